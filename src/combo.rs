@@ -0,0 +1,161 @@
+// src/combo.rs
+use crate::human::HumanDriver;
+use serde::Deserialize;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+use log::warn;
+
+/// 连招中的单个步骤 (JSON 中以 "type" 区分)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum ComboStep {
+    /// 短按并松开一个键
+    KeyClick { key: char },
+    /// 按下一个键且不松开，可与之前按下的键同时保持 (最多 6 个)
+    KeyDown { key: char },
+    /// 只松开一个通过 KeyDown 按下的键，其余键保持按下
+    KeyUp { key: char },
+    /// 松开所有通过 KeyDown 按下的键
+    KeyUpAll,
+    /// 鼠标点击，hold 为按住毫秒数，0 表示随机的自然点击时长
+    MouseClick {
+        #[serde(default = "default_true")]
+        left: bool,
+        #[serde(default)]
+        right: bool,
+        #[serde(default)]
+        hold: u64,
+    },
+    Wait { ms: u64 },
+}
+
+fn default_true() -> bool { true }
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Combo {
+    #[serde(default)]
+    pub name: String,
+    pub steps: Vec<ComboStep>,
+}
+
+impl Combo {
+    pub fn from_file(path: &str) -> Result<Combo, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("无法读取连招文件 {}: {}", path, e))?;
+        serde_json::from_str(&content).map_err(|e| format!("连招文件解析错误 {}: {}", path, e))
+    }
+}
+
+pub struct ComboRunner;
+
+impl ComboRunner {
+    /// 按顺序执行连招；loop_count 为 None 时无限循环
+    /// 每轮结束时如仍有 KeyDown 未松开，会自动松开，避免按键卡住
+    pub fn run(combo: &Combo, human: &mut HumanDriver, loop_count: Option<usize>) {
+        let mut round = 0;
+        while loop_count.is_none_or(|n| round < n) {
+            let mut held: Vec<u8> = Vec::new();
+            let mut held_mod: u8 = 0;
+
+            for step in &combo.steps {
+                match step {
+                    ComboStep::KeyClick { key } => human.key_click(*key),
+                    ComboStep::KeyDown { key } => {
+                        let (code, modifier) = HumanDriver::char_to_keycode_with_mod(*key);
+                        if code == 0 {
+                            warn!("⚠️ [连招] 无法映射按键 {:?}，已跳过", key);
+                            continue;
+                        }
+                        if held.len() >= 6 {
+                            warn!("⚠️ [连招] 同时按下的键超过 6 个，{:?} 已跳过", key);
+                            continue;
+                        }
+                        held.push(code);
+                        held_mod |= modifier;
+                        // 固件的单键帧会叠加到已按下的键上，逐个发送即可组成组合键
+                        human.dispatch(|dev| dev.key_down(code, held_mod));
+                    }
+                    ComboStep::KeyUp { key } => {
+                        let (code, _) = HumanDriver::char_to_keycode_with_mod(*key);
+                        let Some(pos) = held.iter().position(|&k| k == code) else {
+                            warn!("⚠️ [连招] {:?} 未处于按下状态，已跳过", key);
+                            continue;
+                        };
+                        held.remove(pos);
+                        let mut codes = [0u8; 6];
+                        codes[..held.len()].copy_from_slice(&held);
+                        human.dispatch(|dev| dev.key_up_keys(codes));
+                        if held.is_empty() {
+                            held_mod = 0;
+                        }
+                    }
+                    ComboStep::KeyUpAll => {
+                        human.dispatch(|dev| dev.key_up());
+                        held.clear();
+                        held_mod = 0;
+                    }
+                    ComboStep::MouseClick { left, right, hold } => human.click_humanly(*left, *right, *hold),
+                    ComboStep::Wait { ms } => thread::sleep(Duration::from_millis(*ms)),
+                }
+            }
+
+            if !held.is_empty() {
+                human.dispatch(|dev| dev.key_up());
+            }
+            round += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::{HardwareDriver, InputDriver, VecSink};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn bundled_combo_presses_b_with_4_then_b_with_3() {
+        let combo: Combo = serde_json::from_str(include_str!("../combo.json")).unwrap();
+        let keys: Vec<char> = combo
+            .steps
+            .iter()
+            .filter_map(|s| match s {
+                ComboStep::KeyDown { key } => Some(*key),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(keys, ['b', '4', 'b', '3']);
+        // 与旧版硬编码的连招一致：4 = 0x21，3 = 0x20
+        assert_eq!(HumanDriver::char_to_keycode_with_mod('4').0, 0x21);
+        assert_eq!(HumanDriver::char_to_keycode_with_mod('3').0, 0x20);
+    }
+
+    #[test]
+    fn key_down_steps_go_out_as_single_key_frames() {
+        let sink = VecSink::new();
+        let drv = HardwareDriver::builder()
+            .sink(Box::new(sink.clone()))
+            .frame_delay(Duration::ZERO)
+            .split_frame_delay(Duration::ZERO)
+            .build()
+            .unwrap();
+        let device: Arc<Mutex<Box<dyn InputDriver>>> = Arc::new(Mutex::new(Box::new(drv)));
+        let mut human = HumanDriver::new(device, 960, 540);
+        sink.clear();
+
+        let combo: Combo = serde_json::from_str(
+            r#"{ "steps": [ { "type": "KeyDown", "key": "b" }, { "type": "KeyDown", "key": "4" }, { "type": "KeyUpAll" } ] }"#,
+        )
+        .unwrap();
+        ComboRunner::run(&combo, &mut human, Some(1));
+
+        assert_eq!(
+            sink.frames(),
+            vec![
+                vec![0xAA, 0x01, 0x05, 0x00, 0x00, 0, 0, 0, 0x00, 0x00, 0x55],
+                vec![0xAA, 0x01, 0x21, 0x00, 0x00, 0, 0, 0, 0x00, 0x00, 0x55],
+                vec![0xAA, 0x01, 0x00, 0x80, 0x00, 0, 0, 0, 0x00, 0x00, 0x55],
+            ]
+        );
+    }
+}
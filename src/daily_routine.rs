@@ -4,6 +4,7 @@ use crate::nav::NavEngine;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
+use log::{debug, info, warn};
 
 /// 定义单个任务槽位的配置
 struct TaskSlot {
@@ -46,18 +47,28 @@ impl DailyRoutineApp {
             },
         ];
 
+        // 以上坐标按 1920x1080 填写，其他分辨率沿用地图文件的缩放比例
+        let scale = nav.resolution_scale();
+        let slots = slots
+            .into_iter()
+            .map(|slot| {
+                let [x, y] = scale.point([slot.refresh_pos.0 as i32, slot.refresh_pos.1 as i32]);
+                TaskSlot { status_rect: scale.rect(slot.status_rect), refresh_pos: (x as u16, y as u16), ..slot }
+            })
+            .collect();
+
         Self { driver, nav, slots }
     }
 
     /// 执行日活逻辑主入口
     pub fn run(&self) {
-        println!("📅 [Daily] 开始执行日活任务逻辑...");
+        info!("📅 [Daily] 开始执行日活任务逻辑...");
         
         // 最大轮次，防止无限刷新把钱刷光了
         let max_rounds = 10; 
 
         for round in 1..=max_rounds {
-            println!("\n🔄 [Daily] 第 {}/{} 轮扫描...", round, max_rounds);
+            info!("🔄 [Daily] 第 {}/{} 轮扫描...", round, max_rounds);
             
             let mut need_retry = false;
             
@@ -72,16 +83,16 @@ impl DailyRoutineApp {
             }
 
             if !need_retry {
-                println!("✅ [Daily] 所有任务已完成或已领取！");
+                info!("✅ [Daily] 所有任务已完成或已领取！");
                 break;
             }
 
             // 如果本轮有操作（领取或刷新），等待界面动画刷新后继续
-            println!("⏳ 等待任务列表刷新 (2秒)...");
+            info!("⏳ 等待任务列表刷新 (2秒)...");
             thread::sleep(Duration::from_secs(2));
         }
 
-        println!("🏁 [Daily] 日活流程结束。");
+        info!("🏁 [Daily] 日活流程结束。");
     }
 
     /// 处理单个槽位，返回 true 表示进行了操作（需要进入下一轮检查）
@@ -93,7 +104,7 @@ impl DailyRoutineApp {
         // 去除空格和换行，防止 OCR 识别出 "已 完 成" 导致匹配失败
         let clean_text = text.replace(|c: char| c.is_whitespace(), ""); 
 
-        println!("   📝 槽位[{}] 识别结果: [{}]", slot.index, clean_text);
+        debug!("   📝 槽位[{}] 识别结果: [{}]", slot.index, clean_text);
 
         // =========================================================
         // 逻辑判断 (注意顺序：先排除终态，再判断操作)
@@ -102,13 +113,13 @@ impl DailyRoutineApp {
         // 1. 【终态】已完成 / 已领取
         // ⚠️ 必须放在最前面！因为 "已领取" 包含 "领取" 字样
         if clean_text.contains("已完成") || clean_text.contains("已领取") {
-            println!("      -> ✅ 任务已结束，跳过。");
+            info!("      -> ✅ 任务已结束，跳过。");
             return false; // 不做操作
         }
 
         // 2. 【可领取】
         if clean_text.contains("领取") {
-            println!("      -> 🎉 发现可领取奖励，执行领取流程...");
+            info!("      -> 🎉 发现可领取奖励，执行领取流程...");
             if let Ok(mut d) = self.driver.lock() {
                 // A. 点击状态文字中心 (即领取按钮)
                 let cx = (slot.status_rect[0] + slot.status_rect[2]) / 2;
@@ -117,7 +128,7 @@ impl DailyRoutineApp {
                 d.click_humanly(true, false, 0);
 
                 // B. 处理奖励弹窗 (按空格跳过)
-                println!("      -> ⏳ 等待弹窗并按空格跳过...");
+                info!("      -> ⏳ 等待弹窗并按空格跳过...");
                 thread::sleep(Duration::from_millis(1000)); // 等待动画
                 d.key_click(' '); 
                 thread::sleep(Duration::from_millis(1000));
@@ -128,7 +139,7 @@ impl DailyRoutineApp {
 
         // 3. 【未完成】需要刷新
         if clean_text.contains("去完成") || clean_text.contains("未完成") {
-            println!("      -> ⚠️ 任务未完成，点击刷新 ({}, {})...", slot.refresh_pos.0, slot.refresh_pos.1);
+            warn!("      -> ⚠️ 任务未完成，点击刷新 ({}, {})...", slot.refresh_pos.0, slot.refresh_pos.1);
             if let Ok(mut d) = self.driver.lock() {
                 // 点击对应的刷新按钮
                 d.move_to_humanly(slot.refresh_pos.0, slot.refresh_pos.1, 0.5);
@@ -142,11 +153,11 @@ impl DailyRoutineApp {
         
         // 4. 【兜底】识别为空或其他未知状态
         if clean_text.is_empty() {
-             println!("      -> ⚪ 识别为空 (可能是图标/过暗)，暂跳过");
+             info!("      -> ⚪ 识别为空 (可能是图标/过暗)，暂跳过");
              return false;
         }
 
-        println!("      -> ❓ 未知状态，跳过");
+        info!("      -> ❓ 未知状态，跳过");
         false
     }
 }
\ No newline at end of file
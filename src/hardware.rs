@@ -1,26 +1,132 @@
-use byteorder::{LittleEndian, WriteBytesExt};
 // ✨ Added Axis to imports
 use enigo::{
     Direction, Enigo, Key, Keyboard, Mouse, Settings, Coordinate,
     Button, Axis 
 };
+use crate::hid;
+use crate::makcu::{mouse, Key as MakcuKey, MakcuClient, MakcuConfig, MakcuError};
 use serialport::SerialPort;
-use std::io::Write;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 // ==========================================
 // 1. Common Interface (Trait)
 // ==========================================
+/// Bits of the HID mouse button byte, for `InputDriver::mouse_buttons`.
+pub const MOUSE_LEFT: u8 = 0x01;
+pub const MOUSE_RIGHT: u8 = 0x02;
+pub const MOUSE_MIDDLE: u8 = 0x04;
+pub const MOUSE_SIDE1: u8 = 0x08;
+pub const MOUSE_SIDE2: u8 = 0x10;
+
+/// Bits of the HID keyboard modifier byte, for the `modifier` argument of `key_down`.
+/// Defined next to the usage tables in `hid`; re-exported so driver callers keep one import.
+pub use crate::hid::{MOD_ALT, MOD_CTRL, MOD_GUI, MOD_SHIFT};
+
+fn button_mask(left: bool, right: bool) -> u8 {
+    let mut mask = 0;
+    if left { mask |= MOUSE_LEFT; }
+    if right { mask |= MOUSE_RIGHT; }
+    mask
+}
+
+/// Every method returns `Err` when the command did not reach the device,
+/// so callers can notice an unplugged board instead of silently "succeeding".
 pub trait InputDriver: Send + Sync {
-    fn heartbeat(&mut self);
-    fn mouse_abs(&mut self, x: u16, y: u16);
-    fn mouse_move(&mut self, dx: i32, dy: i32, wheel: i8);
-    fn mouse_down(&mut self, left: bool, right: bool);
-    fn mouse_up(&mut self);
-    fn key_down(&mut self, keycode: u8, modifier: u8);
-    fn key_up(&mut self);
-    fn switch_identity(&mut self, index: u8);
+    fn heartbeat(&mut self) -> io::Result<()>;
+    fn mouse_abs(&mut self, x: u16, y: u16) -> io::Result<()>;
+    fn mouse_move(&mut self, dx: i32, dy: i32, wheel: i8) -> io::Result<()>;
+    fn mouse_down(&mut self, left: bool, right: bool) -> io::Result<()>;
+    /// Presses every button set in `mask` (`MOUSE_LEFT | MOUSE_MIDDLE`, ...).
+    fn mouse_buttons(&mut self, mask: u8) -> io::Result<()>;
+    fn mouse_up(&mut self) -> io::Result<()>;
+    fn key_down(&mut self, keycode: u8, modifier: u8) -> io::Result<()>;
+    /// Presses up to six keys together and keeps them held (unused slots are 0).
+    fn key_down_multi(&mut self, keycodes: [u8; 6], modifier: u8) -> io::Result<()>;
+    fn key_up(&mut self) -> io::Result<()>;
+    /// Releases every key not listed in `still_held` and keeps the listed ones down,
+    /// e.g. let go of `5` while `b` stays pressed. All zeros behaves like `key_up`.
+    fn key_up_keys(&mut self, still_held: [u8; 6]) -> io::Result<()>;
+    fn switch_identity(&mut self, index: u8) -> io::Result<()>;
+    /// Screen resolution the driver maps absolute coordinates onto.
+    fn screen_size(&self) -> (u16, u16);
+
+    /// Variants carrying the frame's `delay_ms` field. The firmware waits that many
+    /// milliseconds before applying the report, so the pause is timed on the device
+    /// instead of by a host-side sleep. `None` behaves exactly like the plain method.
+    /// Drivers without a device-side timer emulate it with a sleep.
+    fn mouse_move_delayed(&mut self, dx: i32, dy: i32, wheel: i8, delay_ms: Option<u16>) -> io::Result<()> {
+        host_delay(delay_ms);
+        self.mouse_move(dx, dy, wheel)
+    }
+    fn mouse_down_delayed(&mut self, left: bool, right: bool, delay_ms: Option<u16>) -> io::Result<()> {
+        host_delay(delay_ms);
+        self.mouse_down(left, right)
+    }
+    fn key_down_delayed(&mut self, keycode: u8, modifier: u8, delay_ms: Option<u16>) -> io::Result<()> {
+        host_delay(delay_ms);
+        self.key_down(keycode, modifier)
+    }
+    /// True when the backend can run a whole bezier move on the device through
+    /// `mouse_curve_to`, instead of the caller streaming `mouse_abs` points.
+    fn supports_curve_move(&self) -> bool {
+        false
+    }
+    /// Moves to `(x, y)` along a cubic bezier the device interpolates itself in
+    /// `segments` steps. `ctrl` holds the two control points as offsets from the
+    /// current cursor position. Only meaningful when `supports_curve_move` is true.
+    fn mouse_curve_to(&mut self, _x: u16, _y: u16, _segments: u16, _ctrl: [(i16, i16); 2]) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "driver does not support device-side curves"))
+    }
+    /// Horizontal scroll by `steps` notches (positive scrolls right). The ESP32
+    /// frame protocol only carries a vertical wheel, so the default is `Unsupported`.
+    fn mouse_pan(&mut self, _steps: i16) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "driver does not support horizontal scroll"))
+    }
+    /// Tilt-wheel clicks (the sideways push on the wheel), where the backend has one.
+    fn mouse_tilt(&mut self, _steps: i16) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "driver does not support the tilt wheel"))
+    }
+    /// Where the cursor actually is, as reported by the device. Lets the caller
+    /// correct for absolute moves that land a few pixels off. Drivers that
+    /// cannot read the position back return `Unsupported`.
+    fn cursor_position(&mut self) -> io::Result<(i32, i32)> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "driver cannot read the cursor position"))
+    }
+    /// Re-establishes the link after the device dropped off. Drivers without a
+    /// connection to restore (software input) succeed immediately.
+    fn reconnect(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+    /// Whether a failed write reopens the link by itself before giving up.
+    /// Whoever else calls `reconnect` (the heartbeat thread) turns this off so
+    /// only one side ever reopens the port.
+    fn set_auto_reconnect(&mut self, _enabled: bool) {}
+    /// Releases every key and mouse button. Both releases are attempted even if
+    /// the first one fails; the first error is returned.
+    fn release_all(&mut self) -> io::Result<()> {
+        let keys = self.key_up();
+        let buttons = self.mouse_up();
+        keys.and(buttons)
+    }
+}
+
+/// Records `key` as held. Every driver keeps its held keys in press order, and a
+/// key pressed again while already down keeps its original slot, so `key_up_keys`
+/// leaves the kept keys in the same order on every backend.
+fn note_pressed<T: PartialEq>(held: &mut Vec<T>, key: T) {
+    if !held.contains(&key) {
+        held.push(key);
+    }
+}
+
+fn host_delay(delay_ms: Option<u16>) {
+    if let Some(ms) = delay_ms.filter(|&ms| ms > 0) {
+        thread::sleep(Duration::from_millis(ms as u64));
+    }
 }
 
 // ==========================================
@@ -30,11 +136,14 @@ const FRAME_HEAD: u8 = 0xAA;
 const FRAME_TAIL: u8 = 0x55;
 
 #[repr(u8)]
-enum EventType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
     Keyboard = 0x01,
     MouseRel = 0x02,
     MouseAbs = 0x03,
     System = 0x04,
+    /// Boot-protocol keyboard report; see `build_keyboard_report`.
+    KeyboardReport = 0x05,
 }
 
 #[repr(u8)]
@@ -43,53 +152,461 @@ enum SystemCmd {
     Heartbeat = 0xFF,
 }
 
+/// Builds one wire frame: head, event type, 6 payload bytes, LE `delay_ms`, tail.
+///
+/// Exposed so frames can be logged, replayed or sent over another transport
+/// without going through a `HardwareDriver`.
+pub fn build_frame(event_type: EventType, payload: [u8; 6], delay_ms: u16) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(11);
+    frame.push(FRAME_HEAD);
+    frame.push(event_type as u8);
+    frame.extend_from_slice(&payload);
+    frame.extend_from_slice(&delay_ms.to_le_bytes());
+    frame.push(FRAME_TAIL);
+    frame
+}
+
+/// Builds a frame holding a standard boot-protocol keyboard report: head, event
+/// type, modifier, reserved byte, six usage codes, tail.
+///
+/// The report takes the place of the payload and `delay_ms` bytes, so the frame
+/// keeps the usual 11-byte length. Unlike the single-key `Keyboard` frame, which
+/// adds one key to those already held, a report replaces the whole set of held keys.
+pub fn build_keyboard_report(modifier: u8, keycodes: [u8; 6]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(11);
+    frame.push(FRAME_HEAD);
+    frame.push(EventType::KeyboardReport as u8);
+    frame.push(modifier);
+    frame.push(0x00);
+    frame.extend_from_slice(&keycodes);
+    frame.push(FRAME_TAIL);
+    frame
+}
+
+/// Destination for encoded frames. Lets `HardwareDriver` run against something
+/// other than a real serial port (e.g. `VecSink` when exercising higher layers).
+pub trait FrameSink: Send {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()>;
+
+    /// Reopens the underlying transport. Sinks that cannot reconnect keep the default.
+    fn reconnect(&mut self) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "sink does not support reconnect"))
+    }
+}
+
+/// Writes frames to a serial port and flushes after each one.
+///
+/// Remembers the port name and baud rate so a dead handle (USB adapter dropped
+/// and came back) can be replaced through `reconnect`.
+pub struct SerialSink {
+    port: Option<Box<dyn SerialPort>>,
+    port_name: Option<String>,
+    baud_rate: u32,
+}
+
+impl SerialSink {
+    pub fn new(port: Box<dyn SerialPort>) -> Self {
+        let port_name = port.name();
+        let baud_rate = port.baud_rate().unwrap_or(115200);
+        Self { port: Some(port), port_name, baud_rate }
+    }
+
+    pub fn open(port_name: &str, baud_rate: u32) -> io::Result<Self> {
+        let port = open_serial(port_name, baud_rate)?;
+        Ok(Self { port: Some(port), port_name: Some(port_name.to_string()), baud_rate })
+    }
+}
+
+fn open_serial(port_name: &str, baud_rate: u32) -> io::Result<Box<dyn SerialPort>> {
+    serialport::new(port_name, baud_rate)
+        .timeout(Duration::from_millis(100))
+        .open()
+        .map_err(io::Error::from)
+}
+
+impl FrameSink for SerialSink {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        let port = self
+            .port
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "serial port is closed"))?;
+        port.write_all(frame)?;
+        port.flush()
+    }
+
+    fn reconnect(&mut self) -> io::Result<()> {
+        let name = self
+            .port_name
+            .clone()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "serial port name unknown"))?;
+        // Close the stale handle first; Windows refuses to reopen a port that is still held.
+        self.port = None;
+        self.port = Some(open_serial(&name, self.baud_rate)?);
+        Ok(())
+    }
+}
+
+/// Records every frame in memory. Clone it before handing it to the driver and
+/// read the frames back through `frames()`.
+#[derive(Clone, Default)]
+pub struct VecSink {
+    frames: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl VecSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of all frames sent so far.
+    pub fn frames(&self) -> Vec<Vec<u8>> {
+        self.frames.lock().map(|f| f.clone()).unwrap_or_default()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut f) = self.frames.lock() {
+            f.clear();
+        }
+    }
+}
+
+impl FrameSink for VecSink {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.frames
+            .lock()
+            .map_err(|_| io::Error::other("VecSink mutex poisoned"))?
+            .push(frame.to_vec());
+        Ok(())
+    }
+}
+
+/// One frame captured by `RecordingSink`, with its offset from the start of the recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedFrame {
+    pub offset: Duration,
+    pub bytes: Vec<u8>,
+}
+
+/// Shared handle to the frames a `RecordingSink` has captured. Stays readable
+/// after the sink itself has been moved into a driver.
+#[derive(Clone, Default)]
+pub struct Recording {
+    frames: Arc<Mutex<Vec<RecordedFrame>>>,
+}
+
+impl Recording {
+    pub fn frames(&self) -> Vec<RecordedFrame> {
+        self.frames.lock().map(|f| f.clone()).unwrap_or_default()
+    }
+
+    /// Writes one line per frame: offset in microseconds, then the frame as hex.
+    /// Plain text so two recordings can be compared with an ordinary diff tool.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut out = String::new();
+        for f in self.frames() {
+            let hex: Vec<String> = f.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            out.push_str(&format!("{} {}\n", f.offset.as_micros(), hex.join(" ")));
+        }
+        fs::write(path, out)
+    }
+
+    /// Parses a file written by `save`. Blank lines and `#` comments are skipped.
+    pub fn load(path: &str) -> io::Result<Vec<RecordedFrame>> {
+        let bad = |line: usize, msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, msg));
+        let mut frames = Vec::new();
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let offset = parts
+                .next()
+                .and_then(|t| t.parse::<u64>().ok())
+                .ok_or_else(|| bad(i + 1, "missing offset"))?;
+            let bytes = parts
+                .map(|h| u8::from_str_radix(h, 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| bad(i + 1, "invalid hex byte"))?;
+            frames.push(RecordedFrame { offset: Duration::from_micros(offset), bytes });
+        }
+        Ok(frames)
+    }
+}
+
+/// Passes frames through to another sink and timestamps every frame that was
+/// written successfully, so a session can be saved and replayed byte-for-byte.
+pub struct RecordingSink {
+    inner: Box<dyn FrameSink>,
+    start: Instant,
+    recording: Recording,
+}
+
+impl RecordingSink {
+    pub fn new(inner: Box<dyn FrameSink>) -> Self {
+        Self { inner, start: Instant::now(), recording: Recording::default() }
+    }
+
+    pub fn recording(&self) -> Recording {
+        self.recording.clone()
+    }
+}
+
+impl FrameSink for RecordingSink {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.inner.send(frame)?;
+        let offset = self.start.elapsed();
+        self.recording
+            .frames
+            .lock()
+            .map_err(|_| io::Error::other("Recording mutex poisoned"))?
+            .push(RecordedFrame { offset, bytes: frame.to_vec() });
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> io::Result<()> {
+        self.inner.reconnect()
+    }
+}
+
+/// Re-sends recorded frames verbatim, sleeping so each one goes out at its
+/// original offset from the first frame. Returns the number of frames sent.
+pub fn replay_frames(frames: &[RecordedFrame], sink: &mut dyn FrameSink) -> io::Result<usize> {
+    let base = frames.first().map(|f| f.offset).unwrap_or_default();
+    let start = Instant::now();
+    for f in frames {
+        let due = f.offset.saturating_sub(base);
+        let elapsed = start.elapsed();
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        }
+        sink.send(&f.bytes)?;
+    }
+    Ok(frames.len())
+}
+
+/// Loads a recording saved with `Recording::save` and replays it into `sink`.
+pub fn replay(path: &str, sink: &mut dyn FrameSink) -> io::Result<usize> {
+    replay_frames(&Recording::load(path)?, sink)
+}
+
+/// Default pause after each frame (matches the historical hardcoded 4ms).
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(4);
+
 pub struct HardwareDriver {
-    port: Box<dyn SerialPort>,
+    sink: Box<dyn FrameSink>,
     pub screen_w: u16,
     pub screen_h: u16,
+    frame_delay: Duration,
+    split_frame_delay: Duration,
+    auto_reconnect: bool,
+    // Keys and modifier currently pressed, so `key_up_keys` can press the kept ones again
+    held_keys: Vec<u8>,
+    held_modifier: u8,
 }
 
 impl HardwareDriver {
     pub fn new(port_name: &str, baud_rate: u32, screen_w: u16, screen_h: u16) -> Result<Self, String> {
-        let port = serialport::new(port_name, baud_rate)
-            .timeout(Duration::from_millis(100))
-            .open()
-            .map_err(|e| format!("无法打开串口 {}: {}", port_name, e))?;
+        Self::builder()
+            .port(port_name)
+            .baud(baud_rate)
+            .screen(screen_w, screen_h)
+            .build()
+    }
 
-        Ok(Self { port, screen_w, screen_h })
+    pub fn builder() -> HardwareDriverBuilder {
+        HardwareDriverBuilder::default()
     }
 
-    fn send_raw(&mut self, event_type: EventType, b: [u8; 6], delay_ms: u16) {
-        let mut frame = Vec::with_capacity(11);
-        frame.push(FRAME_HEAD);
-        frame.push(event_type as u8);
-        frame.extend_from_slice(&b);
-        frame.write_u16::<LittleEndian>(delay_ms).unwrap();
-        frame.push(FRAME_TAIL);
+    /// Builds a driver on top of an arbitrary frame sink instead of a serial port.
+    pub fn with_sink(sink: Box<dyn FrameSink>, screen_w: u16, screen_h: u16) -> Self {
+        Self {
+            sink,
+            screen_w,
+            screen_h,
+            frame_delay: DEFAULT_FRAME_DELAY,
+            split_frame_delay: DEFAULT_FRAME_DELAY,
+            auto_reconnect: true,
+            held_keys: Vec::new(),
+            held_modifier: 0,
+        }
+    }
+
+    /// Sets the pause after every frame.
+    ///
+    /// The firmware submits HID reports on a 1ms (1000Hz) polling interval. If serial
+    /// frames arrive faster than that, two of them can land in the same report cycle
+    /// and a press/release transition is lost on the host. The 4ms default leaves a
+    /// few polling cycles of headroom; `Duration::ZERO` disables the pause entirely.
+    pub fn set_frame_delay(&mut self, delay: Duration) {
+        self.frame_delay = delay;
+    }
+
+    /// Sets the pause between the chunks `mouse_move` splits a large delta into.
+    ///
+    /// Relative chunks are increments of one motion with no button transitions to
+    /// lose, so this can be `Duration::ZERO` to keep a 2000px move from being throttled.
+    pub fn set_split_frame_delay(&mut self, delay: Duration) {
+        self.split_frame_delay = delay;
+    }
 
-        let _ = self.port.write_all(&frame);
-        let _ = self.port.flush();
-        thread::sleep(Duration::from_millis(4));
+    fn send_raw(&mut self, event_type: EventType, b: [u8; 6], delay_ms: u16) -> io::Result<()> {
+        self.send_raw_paced(event_type, b, delay_ms, self.frame_delay)
+    }
+
+    fn send_raw_paced(
+        &mut self,
+        event_type: EventType,
+        b: [u8; 6],
+        delay_ms: u16,
+        pause: Duration,
+    ) -> io::Result<()> {
+        self.send_frame(&build_frame(event_type, b, delay_ms), pause)
+    }
+
+    fn send_frame(&mut self, frame: &[u8], pause: Duration) -> io::Result<()> {
+        if let Err(e) = self.sink.send(frame) {
+            if !self.auto_reconnect || self.sink.reconnect().is_err() {
+                return Err(e);
+            }
+            self.sink.send(frame)?;
+        }
+        if !pause.is_zero() {
+            thread::sleep(pause);
+        }
+        Ok(())
+    }
+
+    fn track_pressed(&mut self, keycodes: &[u8], modifier: u8) {
+        for &code in keycodes.iter().filter(|&&k| k != 0) {
+            note_pressed(&mut self.held_keys, code);
+        }
+        self.held_modifier |= modifier;
     }
 }
 
 unsafe impl Sync for HardwareDriver {}
 
+/// Named-option construction for `HardwareDriver`, so screen width/height can't be
+/// swapped positionally and new options don't keep growing `new`'s argument list.
+pub struct HardwareDriverBuilder {
+    port: Option<String>,
+    baud: u32,
+    screen: (u16, u16),
+    frame_delay: Duration,
+    split_frame_delay: Duration,
+    auto_reconnect: bool,
+    sink: Option<Box<dyn FrameSink>>,
+}
+
+impl Default for HardwareDriverBuilder {
+    fn default() -> Self {
+        Self {
+            port: None,
+            baud: 115200,
+            screen: (1920, 1080),
+            frame_delay: DEFAULT_FRAME_DELAY,
+            split_frame_delay: DEFAULT_FRAME_DELAY,
+            auto_reconnect: true,
+            sink: None,
+        }
+    }
+}
+
+impl HardwareDriverBuilder {
+    pub fn port(mut self, port_name: &str) -> Self {
+        self.port = Some(port_name.to_string());
+        self
+    }
+
+    pub fn baud(mut self, baud_rate: u32) -> Self {
+        self.baud = baud_rate;
+        self
+    }
+
+    pub fn screen(mut self, width: u16, height: u16) -> Self {
+        self.screen = (width, height);
+        self
+    }
+
+    pub fn frame_delay(mut self, delay: Duration) -> Self {
+        self.frame_delay = delay;
+        self
+    }
+
+    pub fn split_frame_delay(mut self, delay: Duration) -> Self {
+        self.split_frame_delay = delay;
+        self
+    }
+
+    pub fn auto_reconnect(mut self, enabled: bool) -> Self {
+        self.auto_reconnect = enabled;
+        self
+    }
+
+    /// Sends frames to `sink` instead of opening a serial port; `port`/`baud` are then ignored.
+    pub fn sink(mut self, sink: Box<dyn FrameSink>) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    pub fn build(self) -> Result<HardwareDriver, String> {
+        let sink: Box<dyn FrameSink> = match self.sink {
+            Some(sink) => sink,
+            None => {
+                let port_name = self.port.ok_or_else(|| "未指定串口".to_string())?;
+                let sink = SerialSink::open(&port_name, self.baud)
+                    .map_err(|e| format!("无法打开串口 {}: {}", port_name, e))?;
+                Box::new(sink)
+            }
+        };
+
+        let mut driver = HardwareDriver::with_sink(sink, self.screen.0, self.screen.1);
+        driver.set_frame_delay(self.frame_delay);
+        driver.set_split_frame_delay(self.split_frame_delay);
+        driver.set_auto_reconnect(self.auto_reconnect);
+        Ok(driver)
+    }
+}
+
+/// Makes sure nothing stays pressed on the host when the driver goes away
+/// (early return or panic unwinding), mirroring `MakcuClient`'s `.release()` on drop.
+/// It only runs once the last owner lets go: stop the heartbeat thread first, since
+/// it holds a clone of the driver. Ctrl+C terminates without unwinding, so nothing
+/// is released in that case.
+impl Drop for HardwareDriver {
+    fn drop(&mut self) {
+        // Best effort only: a dead device must not be reopened on the way out.
+        self.auto_reconnect = false;
+        let _ = self.release_all();
+    }
+}
+
 impl InputDriver for HardwareDriver {
-    fn heartbeat(&mut self) {
+    fn screen_size(&self) -> (u16, u16) {
+        (self.screen_w, self.screen_h)
+    }
+
+    fn heartbeat(&mut self) -> io::Result<()> {
         let mut b = [0u8; 6];
         b[0] = SystemCmd::Heartbeat as u8;
-        self.send_raw(EventType::System, b, 0);
+        self.send_raw(EventType::System, b, 0)
+    }
+
+    /// When enabled (the default), a failed write reopens the sink once and
+    /// retries the frame before the error is returned.
+    fn set_auto_reconnect(&mut self, enabled: bool) {
+        self.auto_reconnect = enabled;
     }
 
-    fn switch_identity(&mut self, index: u8) {
+    fn switch_identity(&mut self, index: u8) -> io::Result<()> {
         let mut b = [0u8; 6];
         b[0] = SystemCmd::SetId as u8;
         b[1] = index;
-        self.send_raw(EventType::System, b, 0);
+        self.send_raw(EventType::System, b, 0)
     }
 
-    fn mouse_abs(&mut self, x: u16, y: u16) {
+    fn mouse_abs(&mut self, x: u16, y: u16) -> io::Result<()> {
         let tx = ((x as f32 / self.screen_w as f32) * 32767.0) as u16;
         let ty = ((y as f32 / self.screen_h as f32) * 32767.0) as u16;
         let tx = tx.clamp(10, 32757);
@@ -100,12 +617,19 @@ impl InputDriver for HardwareDriver {
         b[3] = ((tx >> 8) & 0xFF) as u8;
         b[4] = (ty & 0xFF) as u8;
         b[5] = ((ty >> 8) & 0xFF) as u8;
-        self.send_raw(EventType::MouseAbs, b, 0);
+        self.send_raw(EventType::MouseAbs, b, 0)
+    }
+
+    fn mouse_move(&mut self, dx: i32, dy: i32, wheel: i8) -> io::Result<()> {
+        self.mouse_move_delayed(dx, dy, wheel, None)
     }
 
-    fn mouse_move(&mut self, dx: i32, dy: i32, wheel: i8) {
+    fn mouse_move_delayed(&mut self, dx: i32, dy: i32, wheel: i8, delay_ms: Option<u16>) -> io::Result<()> {
+        // The delay belongs to the motion as a whole, so only the first frame carries it
+        let mut delay = delay_ms.unwrap_or(0);
         if wheel != 0 {
-            self.send_raw(EventType::MouseRel, [0, wheel as u8, 0, 0, 0, 0], 0);
+            self.send_raw(EventType::MouseRel, [0, wheel as u8, 0, 0, 0, 0], delay)?;
+            delay = 0;
         }
         let max_step = 127;
         let mut cur_dx = dx;
@@ -118,30 +642,89 @@ impl InputDriver for HardwareDriver {
             let bx = (step_x as i16).to_le_bytes();
             let by = (step_y as i16).to_le_bytes();
             
-            self.send_raw(EventType::MouseRel, [0, 0, bx[0], bx[1], by[0], by[1]], 0);
-            
             cur_dx -= step_x;
             cur_dy -= step_y;
+
+            // Only the final chunk waits the regular frame delay
+            let pause = if cur_dx == 0 && cur_dy == 0 { self.frame_delay } else { self.split_frame_delay };
+            self.send_raw_paced(EventType::MouseRel, [0, 0, bx[0], bx[1], by[0], by[1]], delay, pause)?;
+            delay = 0;
         }
+        Ok(())
     }
 
-    fn mouse_down(&mut self, left: bool, right: bool) {
-        let mut mask = 0;
-        if left { mask |= 0x01; }
-        if right { mask |= 0x02; }
-        self.send_raw(EventType::MouseRel, [mask, 0, 0, 0, 0, 0], 0);
+    fn mouse_down(&mut self, left: bool, right: bool) -> io::Result<()> {
+        self.mouse_buttons(button_mask(left, right))
     }
 
-    fn mouse_up(&mut self) {
-        self.send_raw(EventType::MouseRel, [0, 0, 0, 0, 0, 0], 0);
+    fn mouse_buttons(&mut self, mask: u8) -> io::Result<()> {
+        self.send_raw(EventType::MouseRel, [mask, 0, 0, 0, 0, 0], 0)
     }
 
-    fn key_down(&mut self, keycode: u8, modifier: u8) {
-        self.send_raw(EventType::Keyboard, [keycode, 0x00, modifier, 0, 0, 0], 0);
+    fn mouse_down_delayed(&mut self, left: bool, right: bool, delay_ms: Option<u16>) -> io::Result<()> {
+        self.send_raw(EventType::MouseRel, [button_mask(left, right), 0, 0, 0, 0, 0], delay_ms.unwrap_or(0))
     }
 
-    fn key_up(&mut self) {
-        self.send_raw(EventType::Keyboard, [0, 0x80, 0, 0, 0, 0], 0);
+    fn mouse_up(&mut self) -> io::Result<()> {
+        self.send_raw(EventType::MouseRel, [0, 0, 0, 0, 0, 0], 0)
+    }
+
+    fn key_down(&mut self, keycode: u8, modifier: u8) -> io::Result<()> {
+        self.key_down_multi([keycode, 0, 0, 0, 0, 0], modifier)
+    }
+
+    fn key_down_delayed(&mut self, keycode: u8, modifier: u8, delay_ms: Option<u16>) -> io::Result<()> {
+        self.send_raw(EventType::Keyboard, [keycode, 0x00, modifier, 0, 0, 0], delay_ms.unwrap_or(0))?;
+        self.track_pressed(&[keycode], modifier);
+        Ok(())
+    }
+
+    fn key_down_multi(&mut self, keycodes: [u8; 6], modifier: u8) -> io::Result<()> {
+        let mut keys = keycodes.into_iter().filter(|&k| k != 0);
+        let (first, second) = (keys.next(), keys.next());
+        if second.is_none() {
+            // A single key keeps the legacy Keyboard frame, byte-identical to `key_down`
+            self.send_raw(EventType::Keyboard, [first.unwrap_or(0), 0x00, modifier, 0, 0, 0], 0)?;
+            self.track_pressed(&keycodes, modifier);
+            return Ok(());
+        }
+
+        // A report replaces everything the firmware holds, so it lists the keys
+        // that were already down as well as the new ones (first six in press order).
+        let mut held = self.held_keys.clone();
+        for code in keycodes.into_iter().filter(|&k| k != 0) {
+            note_pressed(&mut held, code);
+        }
+        let mut report = [0u8; 6];
+        for (slot, &code) in report.iter_mut().zip(&held) {
+            *slot = code;
+        }
+        let frame = build_keyboard_report(self.held_modifier | modifier, report);
+        self.send_frame(&frame, self.frame_delay)?;
+        self.track_pressed(&keycodes, modifier);
+        Ok(())
+    }
+
+    fn key_up(&mut self) -> io::Result<()> {
+        self.held_keys.clear();
+        self.held_modifier = 0;
+        self.send_raw(EventType::Keyboard, [0, 0x80, 0, 0, 0, 0], 0)
+    }
+
+    fn key_up_keys(&mut self, still_held: [u8; 6]) -> io::Result<()> {
+        // The frame protocol can only release everything at once, so release all
+        // and press the kept keys again with the modifier they were held with.
+        let keep: Vec<u8> = self.held_keys.iter().copied().filter(|k| still_held.contains(k)).collect();
+        let modifier = self.held_modifier;
+        self.key_up()?;
+        for code in keep {
+            self.key_down(code, modifier)?;
+        }
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> Result<(), String> {
+        self.sink.reconnect().map_err(|e| format!("重新连接串口失败: {}", e))
     }
 }
 
@@ -152,7 +735,41 @@ pub struct SoftwareDriver {
     enigo: Enigo,
     pub screen_w: u16,
     pub screen_h: u16,
-    last_key: Option<Key>,
+    held_keys: Vec<Key>,
+    held_modifier: u8,
+    held_buttons: u8,
+}
+
+const SOFTWARE_BUTTONS: [(u8, Button); 5] = [
+    (MOUSE_LEFT, Button::Left),
+    (MOUSE_RIGHT, Button::Right),
+    (MOUSE_MIDDLE, Button::Middle),
+    (MOUSE_SIDE1, Button::Back),
+    (MOUSE_SIDE2, Button::Forward),
+];
+
+/// HID modifier bit i (usage 0xE0 + i) -> enigo key. Right Alt/GUI have no
+/// cross-platform enigo key of their own, so they share Alt/Meta with the left side.
+const SOFTWARE_MODIFIERS: [(u8, Key); 8] = [
+    (MOD_CTRL, Key::LControl),
+    (MOD_SHIFT, Key::LShift),
+    (MOD_ALT, Key::Alt),
+    (MOD_GUI, Key::Meta),
+    (0x10, Key::RControl),
+    (0x20, Key::RShift),
+    (0x40, Key::Alt),
+    (0x80, Key::Meta),
+];
+
+/// Enigo keys to press for a HID modifier byte, in bit order and without duplicates.
+fn modifier_keys(modifier: u8) -> Vec<Key> {
+    let mut keys = Vec::new();
+    for (bit, key) in SOFTWARE_MODIFIERS {
+        if modifier & bit != 0 {
+            note_pressed(&mut keys, key);
+        }
+    }
+    keys
 }
 
 unsafe impl Sync for SoftwareDriver {}
@@ -163,95 +780,363 @@ impl SoftwareDriver {
             enigo: Enigo::new(&Settings::default()).unwrap(),
             screen_w,
             screen_h,
-            last_key: None,
+            held_keys: Vec::new(),
+            held_modifier: 0,
+            held_buttons: 0,
         }
     }
 
     fn hid_to_enigo(&self, hid: u8) -> Option<Key> {
         match hid {
-            0x04..=0x1D => { 
-                let c = (b'a' + (hid - 0x04)) as char;
-                Some(Key::Unicode(c)) 
-            },
-            0x1E..=0x27 => { 
-                let c = if hid == 0x27 { '0' } else { (b'1' + (hid - 0x1E)) as char };
-                Some(Key::Unicode(c))
-            },
-            0x28 => Some(Key::Return),
-            0x29 => Some(Key::Escape),
-            0x2A => Some(Key::Backspace),
-            0x2B => Some(Key::Tab),
-            0x2C => Some(Key::Space),
-            0x2D => Some(Key::Unicode('-')),
-            0x2E => Some(Key::Unicode('=')),
-            0x2F => Some(Key::Unicode('[')),
-            0x30 => Some(Key::Unicode(']')),
-            0x31 => Some(Key::Unicode('\\')),
-            0x33 => Some(Key::Unicode(';')),
-            0x34 => Some(Key::Unicode('\'')),
-            0x36 => Some(Key::Unicode(',')),
-            0x37 => Some(Key::Unicode('.')),
-            0x38 => Some(Key::Unicode('/')),
-            0xE0 => Some(Key::Control),
-            0xE1 => Some(Key::Shift),
-            0xE2 => Some(Key::Alt),
-            _ => None,
+            hid::KEY_ENTER => Some(Key::Return),
+            hid::KEY_ESCAPE => Some(Key::Escape),
+            hid::KEY_BACKSPACE => Some(Key::Backspace),
+            hid::KEY_TAB => Some(Key::Tab),
+            hid::KEY_SPACE => Some(Key::Space),
+            0xE0..=0xE7 => modifier_keys(1 << (hid - 0xE0)).pop(),
+            // Letters, digits and punctuation share the table in crate::hid
+            _ => crate::hid::usage_to_char(hid).map(Key::Unicode),
         }
     }
 }
 
 impl InputDriver for SoftwareDriver {
-    fn heartbeat(&mut self) {}
-    fn switch_identity(&mut self, _index: u8) {}
+    fn screen_size(&self) -> (u16, u16) {
+        (self.screen_w, self.screen_h)
+    }
+
+    fn heartbeat(&mut self) -> io::Result<()> { Ok(()) }
+    fn switch_identity(&mut self, _index: u8) -> io::Result<()> { Ok(()) }
 
-    fn mouse_abs(&mut self, x: u16, y: u16) {
-        let _ = self.enigo.move_mouse(x as i32, y as i32, Coordinate::Abs);
+    fn mouse_abs(&mut self, x: u16, y: u16) -> io::Result<()> {
+        self.enigo.move_mouse(x as i32, y as i32, Coordinate::Abs).map_err(enigo_err)
     }
 
-    fn mouse_move(&mut self, dx: i32, dy: i32, wheel: i8) {
-        let _ = self.enigo.move_mouse(dx, dy, Coordinate::Rel);
+    fn mouse_move(&mut self, dx: i32, dy: i32, wheel: i8) -> io::Result<()> {
+        self.enigo.move_mouse(dx, dy, Coordinate::Rel).map_err(enigo_err)?;
         if wheel != 0 {
             // ✨ Corrected scroll usage
-            let _ = self.enigo.scroll(-wheel as i32, Axis::Vertical);
+            self.enigo.scroll(-wheel as i32, Axis::Vertical).map_err(enigo_err)?;
         }
+        Ok(())
     }
 
-    fn mouse_down(&mut self, left: bool, right: bool) {
-        if left { let _ = self.enigo.button(Button::Left, Direction::Press); }
-        if right { let _ = self.enigo.button(Button::Right, Direction::Press); }
+    fn mouse_pan(&mut self, steps: i16) -> io::Result<()> {
+        self.enigo.scroll(steps as i32, Axis::Horizontal).map_err(enigo_err)
     }
 
-    fn mouse_up(&mut self) {
-        let _ = self.enigo.button(Button::Left, Direction::Release);
-        let _ = self.enigo.button(Button::Right, Direction::Release);
+    fn mouse_down(&mut self, left: bool, right: bool) -> io::Result<()> {
+        self.mouse_buttons(button_mask(left, right))
     }
 
-    fn key_down(&mut self, keycode: u8, modifier: u8) {
-        if (modifier & 0x02) != 0 || (modifier & 0x20) != 0 {
-            let _ = self.enigo.key(Key::Shift, Direction::Press);
+    fn mouse_buttons(&mut self, mask: u8) -> io::Result<()> {
+        for (bit, button) in SOFTWARE_BUTTONS {
+            if mask & bit != 0 {
+                self.enigo.button(button, Direction::Press).map_err(enigo_err)?;
+            }
         }
+        self.held_buttons |= mask;
+        Ok(())
+    }
 
-        if let Some(key) = self.hid_to_enigo(keycode) {
-            let _ = self.enigo.key(key, Direction::Press);
-            self.last_key = Some(key);
+    fn mouse_up(&mut self) -> io::Result<()> {
+        // Left/right are always released (historical behaviour); extra buttons only if pressed
+        let extra = self.held_buttons & !(MOUSE_LEFT | MOUSE_RIGHT);
+        self.held_buttons = 0;
+        for (bit, button) in SOFTWARE_BUTTONS {
+            if bit & (MOUSE_LEFT | MOUSE_RIGHT | extra) != 0 {
+                self.enigo.button(button, Direction::Release).map_err(enigo_err)?;
+            }
         }
+        Ok(())
     }
 
-    fn key_up(&mut self) {
-        if let Some(key) = self.last_key {
-            let _ = self.enigo.key(key, Direction::Release);
-            self.last_key = None;
+    fn key_down(&mut self, keycode: u8, modifier: u8) -> io::Result<()> {
+        self.key_down_multi([keycode, 0, 0, 0, 0, 0], modifier)
+    }
+
+    fn key_down_multi(&mut self, keycodes: [u8; 6], modifier: u8) -> io::Result<()> {
+        // Modifiers go down before the keys, and only the ones not already held
+        let already = modifier_keys(self.held_modifier);
+        for key in modifier_keys(modifier).into_iter().filter(|k| !already.contains(k)) {
+            self.enigo.key(key, Direction::Press).map_err(enigo_err)?;
         }
-        let _ = self.enigo.key(Key::Shift, Direction::Release);
+        self.held_modifier |= modifier;
+
+        for code in keycodes.into_iter().filter(|&k| k != 0) {
+            if let Some(key) = self.hid_to_enigo(code) {
+                self.enigo.key(key, Direction::Press).map_err(enigo_err)?;
+                note_pressed(&mut self.held_keys, key);
+            }
+        }
+        Ok(())
+    }
+
+    fn key_up(&mut self) -> io::Result<()> {
+        for key in std::mem::take(&mut self.held_keys) {
+            self.enigo.key(key, Direction::Release).map_err(enigo_err)?;
+        }
+        // Release exactly the modifiers that were pressed, in reverse order
+        for key in modifier_keys(std::mem::take(&mut self.held_modifier)).into_iter().rev() {
+            self.enigo.key(key, Direction::Release).map_err(enigo_err)?;
+        }
+        Ok(())
+    }
+
+    fn key_up_keys(&mut self, still_held: [u8; 6]) -> io::Result<()> {
+        let keep: Vec<Key> = still_held
+            .into_iter()
+            .filter(|&k| k != 0)
+            .filter_map(|k| self.hid_to_enigo(k))
+            .collect();
+        let (kept, released): (Vec<Key>, Vec<Key>) =
+            std::mem::take(&mut self.held_keys).into_iter().partition(|k| keep.contains(k));
+        self.held_keys = kept;
+        for key in released {
+            self.enigo.key(key, Direction::Release).map_err(enigo_err)?;
+        }
+        Ok(())
+    }
+}
+
+/// Maps enigo errors onto io::Error so both drivers share one error type.
+fn enigo_err(e: enigo::InputError) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+// ==========================================
+// 4. Makcu Driver (text protocol)
+// ==========================================
+/// `InputDriver` on top of a MAKCU board's text protocol (`.moveto`, `.down`, ...).
+///
+/// Unlike the ESP32 frame path, the firmware can interpolate a bezier itself, so
+/// `HumanDriver` sends one `.moveto` per movement instead of a frame per point.
+pub struct MakcuDriver {
+    client: Option<MakcuClient>,
+    config: MakcuConfig,
+    pub screen_w: u16,
+    pub screen_h: u16,
+    held_keys: Vec<u8>,
+    held_modifiers: u8,
+    held_buttons: u8,
+}
+
+unsafe impl Sync for MakcuDriver {}
+
+impl MakcuDriver {
+    /// `port_name` may be empty or `AUTO` to find the board by VID/PID.
+    pub fn new(port_name: &str, screen_w: u16, screen_h: u16) -> Result<Self, String> {
+        Self::with_config(MakcuConfig::new(port_name).with_screen_size(screen_w, screen_h))
+    }
+
+    pub fn with_config(config: MakcuConfig) -> Result<Self, String> {
+        let client = MakcuClient::new(config.clone()).map_err(|e| format!("无法连接 MAKCU: {}", e))?;
+        Ok(Self::from_client(client, config))
+    }
+
+    /// Wraps an already opened client; `config` is what `reconnect` reopens the port with.
+    pub(crate) fn from_client(client: MakcuClient, config: MakcuConfig) -> Self {
+        Self {
+            client: Some(client),
+            screen_w: config.screen_width,
+            screen_h: config.screen_height,
+            config,
+            held_keys: Vec::new(),
+            held_modifiers: 0,
+            held_buttons: 0,
+        }
+    }
+
+    /// Switches board and host to `rate` (see `MakcuClient::set_baud_verified`) and
+    /// remembers it, so a later `reconnect` reopens the port at the new rate.
+    /// On failure both sides are back on the old rate and the config is unchanged.
+    pub fn set_baud_verified(&mut self, rate: u32) -> Result<String, String> {
+        let reply = self.client().map_err(|e| e.to_string())?.set_baud_verified(rate).map_err(|e| e.to_string())?;
+        self.config.baud_rate = rate;
+        Ok(reply)
+    }
+
+    fn client(&mut self) -> io::Result<&mut MakcuClient> {
+        self.client
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "MAKCU port is closed"))
+    }
+
+    fn set_button(&mut self, bit: u8, state: u8) -> io::Result<()> {
+        let client = self.client()?;
+        let result = match bit {
+            MOUSE_LEFT => client.mouse_left(Some(state)),
+            MOUSE_RIGHT => client.mouse_right(Some(state)),
+            MOUSE_MIDDLE => client.mouse_middle(Some(state)),
+            MOUSE_SIDE1 => client.mouse_side1(Some(state)),
+            _ => client.mouse_side2(Some(state)),
+        };
+        result.map(|_| ()).map_err(makcu_err)
+    }
+
+    fn press_code(&mut self, code: u8, down: bool) -> io::Result<()> {
+        let key = MakcuKey::from_hid_code(code).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("MAKCU 不支持键码 0x{:02X}", code))
+        })?;
+        let client = self.client()?;
+        let result = if down { client.keyboard_down(key) } else { client.keyboard_up(key) };
+        result.map(|_| ()).map_err(makcu_err)
+    }
+}
+
+impl InputDriver for MakcuDriver {
+    fn screen_size(&self) -> (u16, u16) {
+        (self.screen_w, self.screen_h)
+    }
+
+    /// `.version()` doubles as a link check: a silent board counts as a failure.
+    fn heartbeat(&mut self) -> io::Result<()> {
+        let reply = self.client()?.version().map_err(makcu_err)?;
+        if reply.trim().is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "MAKCU did not answer"));
+        }
+        Ok(())
+    }
+
+    fn switch_identity(&mut self, _index: u8) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "MAKCU has no identity slots"))
+    }
+
+    fn mouse_abs(&mut self, x: u16, y: u16) -> io::Result<()> {
+        self.client()?.mouse_moveto(x, y, None, None).map(|_| ()).map_err(makcu_err)
+    }
+
+    fn mouse_move(&mut self, dx: i32, dy: i32, wheel: i8) -> io::Result<()> {
+        if wheel != 0 {
+            self.client()?.mouse_wheel(wheel).map_err(makcu_err)?;
+        }
+        if dx != 0 || dy != 0 {
+            let clamp = |v: i32| v.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+            self.client()?.mouse_move(clamp(dx), clamp(dy), None, None).map_err(makcu_err)?;
+        }
+        Ok(())
+    }
+
+    fn mouse_down(&mut self, left: bool, right: bool) -> io::Result<()> {
+        self.mouse_buttons(button_mask(left, right))
+    }
+
+    fn mouse_buttons(&mut self, mask: u8) -> io::Result<()> {
+        for bit in [MOUSE_LEFT, MOUSE_RIGHT, MOUSE_MIDDLE, MOUSE_SIDE1, MOUSE_SIDE2] {
+            if mask & bit != 0 {
+                self.set_button(bit, 1)?;
+                self.held_buttons |= bit;
+            }
+        }
+        Ok(())
+    }
+
+    fn mouse_up(&mut self) -> io::Result<()> {
+        // Same contract as the other drivers: left/right always, extra buttons only if pressed
+        let release = MOUSE_LEFT | MOUSE_RIGHT | self.held_buttons;
+        self.held_buttons = 0;
+        for bit in [MOUSE_LEFT, MOUSE_RIGHT, MOUSE_MIDDLE, MOUSE_SIDE1, MOUSE_SIDE2] {
+            if release & bit != 0 {
+                self.set_button(bit, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn key_down(&mut self, keycode: u8, modifier: u8) -> io::Result<()> {
+        self.key_down_multi([keycode, 0, 0, 0, 0, 0], modifier)
+    }
+
+    fn key_down_multi(&mut self, keycodes: [u8; 6], modifier: u8) -> io::Result<()> {
+        // Modifier bit i is HID usage 0xE0 + i, pressed before the keys like a real keyboard
+        for i in 0..8 {
+            let bit = 1u8 << i;
+            if modifier & bit != 0 && self.held_modifiers & bit == 0 {
+                self.press_code(0xE0 + i, true)?;
+                self.held_modifiers |= bit;
+            }
+        }
+        for code in keycodes.into_iter().filter(|&k| k != 0) {
+            self.press_code(code, true)?;
+            note_pressed(&mut self.held_keys, code);
+        }
+        Ok(())
+    }
+
+    fn key_up(&mut self) -> io::Result<()> {
+        for code in std::mem::take(&mut self.held_keys) {
+            self.press_code(code, false)?;
+        }
+        for i in 0..8 {
+            if self.held_modifiers & (1u8 << i) != 0 {
+                self.press_code(0xE0 + i, false)?;
+            }
+        }
+        self.held_modifiers = 0;
+        Ok(())
+    }
+
+    fn key_up_keys(&mut self, still_held: [u8; 6]) -> io::Result<()> {
+        if still_held.iter().all(|&k| k == 0) {
+            return self.key_up();
+        }
+        let (kept, released): (Vec<u8>, Vec<u8>) =
+            std::mem::take(&mut self.held_keys).into_iter().partition(|k| still_held.contains(k));
+        self.held_keys = kept;
+        for code in released {
+            self.press_code(code, false)?;
+        }
+        Ok(())
+    }
+
+    fn supports_curve_move(&self) -> bool {
+        true
+    }
+
+    fn mouse_curve_to(&mut self, x: u16, y: u16, segments: u16, ctrl: [(i16, i16); 2]) -> io::Result<()> {
+        let segments = segments.clamp(1, mouse::MAX_SEGMENTS);
+        self.client()?
+            .mouse_moveto(x, y, Some(segments), Some(ctrl))
+            .map(|_| ())
+            .map_err(makcu_err)
+    }
+
+    fn mouse_pan(&mut self, steps: i16) -> io::Result<()> {
+        self.client()?.mouse_pan(steps).map(|_| ()).map_err(makcu_err)
+    }
+
+    fn mouse_tilt(&mut self, steps: i16) -> io::Result<()> {
+        self.client()?.mouse_tilt(steps).map(|_| ()).map_err(makcu_err)
+    }
+
+    fn cursor_position(&mut self) -> io::Result<(i32, i32)> {
+        self.client()?.mouse_getpos_parsed().map_err(makcu_err)
+    }
+
+    fn reconnect(&mut self) -> Result<(), String> {
+        // Drop the stale handle first; Windows refuses to reopen a port that is still held
+        self.client = None;
+        self.client = Some(MakcuClient::new(self.config.clone()).map_err(|e| format!("重新连接 MAKCU 失败: {}", e))?);
+        self.held_keys.clear();
+        self.held_modifiers = 0;
+        self.held_buttons = 0;
+        Ok(())
     }
 }
 
+/// Same as `enigo_err`, for errors coming back from `MakcuClient`.
+fn makcu_err(e: MakcuError) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
 // ==========================================
-// 4. Factory Function
+// 5. Factory Function
 // ==========================================
 pub enum DriverType {
     Hardware,
     Software,
+    /// MAKCU board over its text protocol; `port` may be `AUTO`.
+    Makcu,
 }
 
 pub fn create_driver(
@@ -269,5 +1154,311 @@ pub fn create_driver(
             let drv = SoftwareDriver::new(screen_w, screen_h);
             Ok(Box::new(drv))
         }
+        DriverType::Makcu => {
+            let drv = MakcuDriver::new(port, screen_w, screen_h)?;
+            Ok(Box::new(drv))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::makcu::client::tests::MockPort;
+
+    /// Sink whose writes always fail, like a port whose USB adapter was pulled.
+    struct FailingSink;
+
+    impl FrameSink for FailingSink {
+        fn send(&mut self, _frame: &[u8]) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "device unplugged"))
+        }
+    }
+
+    /// Sink that fails until it is reopened, like an adapter that dropped and came back.
+    #[derive(Clone, Default)]
+    struct DroppedSink {
+        dead: Arc<std::sync::atomic::AtomicBool>,
+        reopened: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl FrameSink for DroppedSink {
+        fn send(&mut self, _frame: &[u8]) -> io::Result<()> {
+            if self.dead.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "device unplugged"));
+            }
+            Ok(())
+        }
+
+        fn reconnect(&mut self) -> io::Result<()> {
+            self.reopened.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.dead.store(false, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn driver_on(sink: Box<dyn FrameSink>) -> HardwareDriver {
+        HardwareDriver::builder()
+            .sink(sink)
+            .frame_delay(Duration::ZERO)
+            .split_frame_delay(Duration::ZERO)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn write_errors_reach_the_caller() {
+        let mut drv = driver_on(Box::new(FailingSink));
+        assert_eq!(drv.mouse_abs(100, 100).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        assert!(drv.mouse_move(5, 5, 0).is_err());
+        assert!(drv.key_down(0x04, 0).is_err());
+        assert!(drv.key_up().is_err());
+    }
+
+    #[test]
+    fn zero_frame_delay_sends_without_sleeping() {
+        let mut paced = HardwareDriver::builder()
+            .sink(Box::new(VecSink::new()))
+            .frame_delay(Duration::from_millis(4))
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        for _ in 0..5 {
+            paced.mouse_abs(100, 100).unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // 2000px is split into 16 relative chunks; none of them may sleep
+        let sink = VecSink::new();
+        let mut drv = driver_on(Box::new(sink.clone()));
+        let start = Instant::now();
+        for _ in 0..50 {
+            drv.mouse_move(2000, 0, 0).unwrap();
+        }
+        assert_eq!(sink.frames().len(), 50 * 16);
+        assert!(start.elapsed() < Duration::from_millis(50), "took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn builder_applies_its_options_to_a_mock_sink() {
+        let sink = VecSink::new();
+        let mut drv = HardwareDriver::builder()
+            .sink(Box::new(sink.clone()))
+            .screen(2560, 1440)
+            .frame_delay(Duration::ZERO)
+            .auto_reconnect(false)
+            .build()
+            .unwrap();
+        assert_eq!(drv.screen_size(), (2560, 1440));
+        assert!(!drv.auto_reconnect);
+
+        // Abs coordinates scale against the configured screen, not the 1920x1080 default
+        drv.mouse_abs(1280, 720).unwrap();
+        let frame = &sink.frames()[0];
+        assert_eq!(u16::from_le_bytes([frame[4], frame[5]]), 16383);
+
+        let err = HardwareDriver::builder().screen(1920, 1080).build().err().unwrap();
+        assert_eq!(err, "未指定串口");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn key_up_keys_releases_all_then_presses_kept_keys_again() {
+        let sink = VecSink::new();
+        let mut drv = driver_on(Box::new(sink.clone()));
+        drv.key_down(0x05, 0).unwrap();
+        drv.key_down(0x22, 0).unwrap();
+        sink.clear();
+
+        drv.key_up_keys([0x05, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!(
+            sink.frames(),
+            vec![
+                build_frame(EventType::Keyboard, [0, 0x80, 0, 0, 0, 0], 0),
+                build_frame(EventType::Keyboard, [0x05, 0, 0, 0, 0, 0], 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_re_pressed_key_keeps_its_place_in_press_order() {
+        let mut held = Vec::new();
+        for key in [0x05, 0x22, 0x05, 0x04] {
+            note_pressed(&mut held, key);
+        }
+        assert_eq!(held, [0x05, 0x22, 0x04]);
+
+        // The frame driver presses the kept keys again in that same order
+        let sink = VecSink::new();
+        let mut drv = driver_on(Box::new(sink.clone()));
+        for key in [0x05, 0x22, 0x05, 0x04] {
+            drv.key_down(key, 0).unwrap();
+        }
+        sink.clear();
+        drv.key_up_keys([0x04, 0x22, 0x05, 0, 0, 0]).unwrap();
+        assert_eq!(
+            sink.frames(),
+            vec![
+                build_frame(EventType::Keyboard, [0, 0x80, 0, 0, 0, 0], 0),
+                build_frame(EventType::Keyboard, [0x05, 0, 0, 0, 0, 0], 0),
+                build_frame(EventType::Keyboard, [0x22, 0, 0, 0, 0, 0], 0),
+                build_frame(EventType::Keyboard, [0x04, 0, 0, 0, 0, 0], 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn delay_ms_goes_into_the_frame_little_endian() {
+        let sink = VecSink::new();
+        let mut drv = driver_on(Box::new(sink.clone()));
+        drv.key_down_delayed(0x04, 0, Some(0x0102)).unwrap();
+        drv.mouse_down_delayed(true, false, Some(300)).unwrap();
+        drv.mouse_move_delayed(5, 0, 0, None).unwrap();
+
+        let frames = sink.frames();
+        assert_eq!(frames[0], build_frame(EventType::Keyboard, [0x04, 0, 0, 0, 0, 0], 0x0102));
+        assert_eq!(frames[0][8..10], [0x02, 0x01]);
+        assert_eq!(frames[1][8..10], [0x2C, 0x01]);
+        assert_eq!(frames[2][8..10], [0, 0]);
+    }
+
+    #[test]
+    fn button_mask_is_the_first_payload_byte() {
+        let sink = VecSink::new();
+        let mut drv = driver_on(Box::new(sink.clone()));
+        drv.mouse_buttons(MOUSE_MIDDLE | MOUSE_SIDE2).unwrap();
+        drv.mouse_down(true, true).unwrap();
+        drv.mouse_up().unwrap();
+
+        let frames = sink.frames();
+        assert_eq!(frames[0], vec![0xAA, EventType::MouseRel as u8, 0x14, 0, 0, 0, 0, 0, 0, 0, 0x55]);
+        assert_eq!(frames[1][2], MOUSE_LEFT | MOUSE_RIGHT);
+        assert_eq!(frames[2][2], 0);
+    }
+
+    #[test]
+    fn software_modifiers_cover_every_bit_of_the_modifier_byte() {
+        assert!(modifier_keys(0).is_empty());
+        assert_eq!(modifier_keys(MOD_CTRL | MOD_SHIFT), [Key::LControl, Key::LShift]);
+        assert_eq!(modifier_keys(MOD_ALT | MOD_GUI), [Key::Alt, Key::Meta]);
+        assert_eq!(modifier_keys(0x10 | 0x20), [Key::RControl, Key::RShift]);
+        // Left and right Alt both map to Alt, which is then pressed and released once
+        assert_eq!(modifier_keys(MOD_ALT | 0x40), [Key::Alt]);
+        assert_eq!(modifier_keys(0xFF).len(), 6);
+    }
+
+    #[test]
+    fn key_down_multi_sends_one_packed_boot_report() {
+        let sink = VecSink::new();
+        let mut drv = driver_on(Box::new(sink.clone()));
+        // W + A + Space with Shift held; the empty slot in the middle is packed away
+        drv.key_down_multi([0x1A, 0, 0x04, 0x2C, 0, 0], MOD_SHIFT).unwrap();
+        assert_eq!(
+            sink.frames(),
+            vec![vec![0xAA, 0x05, 0x02, 0x00, 0x1A, 0x04, 0x2C, 0, 0, 0, 0x55]]
+        );
+
+        // Keys already held stay in the next report, ahead of the new one
+        sink.clear();
+        drv.key_down_multi([0x07, 0x04, 0, 0, 0, 0], 0).unwrap();
+        assert_eq!(
+            sink.frames(),
+            vec![vec![0xAA, 0x05, 0x02, 0x00, 0x1A, 0x04, 0x2C, 0x07, 0, 0, 0x55]]
+        );
+
+        // A single key still goes out as the legacy Keyboard frame
+        sink.clear();
+        drv.key_down_multi([0, 0, 0x16, 0, 0, 0], 0).unwrap();
+        assert_eq!(sink.frames(), vec![build_frame(EventType::Keyboard, [0x16, 0, 0, 0, 0, 0], 0)]);
+    }
+
+    #[test]
+    fn failed_write_reopens_the_sink_once_unless_reconnect_is_owned_elsewhere() {
+        use std::sync::atomic::Ordering;
+
+        let sink = DroppedSink::default();
+        let mut drv = driver_on(Box::new(sink.clone()));
+        sink.dead.store(true, Ordering::SeqCst);
+        drv.mouse_move(5, 5, 0).unwrap();
+        assert_eq!(sink.reopened.load(Ordering::SeqCst), 1);
+
+        // Once the heartbeat owns reconnection, writes fail fast and only an explicit reconnect reopens
+        drv.set_auto_reconnect(false);
+        sink.dead.store(true, Ordering::SeqCst);
+        assert!(drv.mouse_move(5, 5, 0).is_err());
+        assert_eq!(sink.reopened.load(Ordering::SeqCst), 1);
+        InputDriver::reconnect(&mut drv).unwrap();
+        assert_eq!(sink.reopened.load(Ordering::SeqCst), 2);
+        drv.mouse_move(5, 5, 0).unwrap();
+    }
+
+    #[test]
+    fn dropping_the_driver_releases_keys_and_buttons() {
+        let sink = VecSink::new();
+        let mut drv = driver_on(Box::new(sink.clone()));
+        drv.key_down(0x04, 0).unwrap();
+        drv.mouse_down(true, false).unwrap();
+        sink.clear();
+
+        drop(drv);
+        assert_eq!(
+            sink.frames(),
+            vec![
+                build_frame(EventType::Keyboard, [0, 0x80, 0, 0, 0, 0], 0),
+                build_frame(EventType::MouseRel, [0, 0, 0, 0, 0, 0], 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn dropping_a_driver_on_a_dead_device_does_not_reopen_it() {
+        use std::sync::atomic::Ordering;
+
+        let sink = DroppedSink::default();
+        let mut drv = driver_on(Box::new(sink.clone()));
+        drv.key_down(0x04, 0).unwrap();
+        sink.dead.store(true, Ordering::SeqCst);
+
+        drop(drv);
+        assert_eq!(sink.reopened.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn recorded_frames_replay_byte_for_byte() {
+        let live = VecSink::new();
+        let recorder = RecordingSink::new(Box::new(live.clone()));
+        let recording = recorder.recording();
+        let mut drv = driver_on(Box::new(recorder));
+        drv.mouse_abs(100, 200).unwrap();
+        drv.mouse_abs(140, 230).unwrap();
+        drv.mouse_down(true, false).unwrap();
+        drv.mouse_up().unwrap();
+
+        let path = std::env::temp_dir().join(format!("nzm_replay_{}.txt", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        recording.save(&path).unwrap();
+        let mut replayed = VecSink::new();
+        let sent = replay(&path, &mut replayed);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(sent.unwrap(), 4);
+        assert_eq!(replayed.frames(), live.frames());
+    }
+
+    #[test]
+    fn verified_baud_switch_is_kept_for_reconnects() {
+        let port = MockPort::default();
+        let config = MakcuConfig::new("MOCK").with_timeout(50);
+        let client = MakcuClient::from_port(Box::new(port.clone()), config.clone());
+        let mut drv = MakcuDriver::from_client(client, config);
+
+        // .baud() gets no prompt back; .info() answers at the new rate
+        port.reply(b"").reply(b"km.MAKCU v3.2\r\n>>>\r\n");
+        drv.set_baud_verified(4_000_000).unwrap();
+        assert_eq!(drv.config.baud_rate, 4_000_000);
+
+        // a garbled .info() reply rolls back and leaves the config alone
+        port.reply(b"").reply(b"\xff\xfe\r\n>>>\r\n");
+        assert!(drv.set_baud_verified(6_000_000).is_err());
+        assert_eq!(drv.config.baud_rate, 4_000_000);
+    }
+}
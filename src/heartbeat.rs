@@ -0,0 +1,208 @@
+// src/heartbeat.rs
+use crate::hardware::InputDriver;
+use log::{error, info, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// 正常情况下的心跳间隔
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// 连续失败时心跳间隔按 2 倍递增，最长不超过该值
+pub const HEARTBEAT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// 连续失败达到该次数后判定设备丢失，主循环暂停
+pub const HEARTBEAT_FAIL_LIMIT: u32 = 3;
+
+/// 心跳节奏与判定设备丢失的阈值，默认取上面三个常量
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    pub max_backoff: Duration,
+    pub fail_limit: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: HEARTBEAT_INTERVAL,
+            max_backoff: HEARTBEAT_MAX_BACKOFF,
+            fail_limit: HEARTBEAT_FAIL_LIMIT,
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    /// 连续失败 failures 次后到下一次心跳的等待时长
+    fn backoff(&self, failures: u32) -> Duration {
+        match failures {
+            0 => self.interval,
+            n => (self.interval * 2u32.saturating_pow(n - 1)).min(self.max_backoff),
+        }
+    }
+}
+
+/// 心跳线程句柄
+pub struct Heartbeat {
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl Heartbeat {
+    /// 停止心跳线程并等待其退出，释放它持有的驱动引用
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.thread().unpark();
+        let _ = self.handle.join();
+    }
+}
+
+/// 按默认节奏启动后台心跳，见 spawn_heartbeat_with
+pub fn spawn_heartbeat(driver: Arc<Mutex<Box<dyn InputDriver>>>, healthy: Arc<AtomicBool>) -> Heartbeat {
+    spawn_heartbeat_with(driver, healthy, HeartbeatConfig::default())
+}
+
+/// 后台心跳：失败时尝试重连并指数退避，连续失败 fail_limit 次后把 healthy 置为 false，
+/// 恢复后重新置为 true
+/// 心跳线程是唯一负责重连的一方：启动时关闭驱动写失败时的自动重连，避免两边先后重开同一个串口
+/// 返回的句柄用于退出前停止线程；线程持有驱动的 Arc，不停止的话驱动的 Drop (松开所有键) 永远不会执行
+pub fn spawn_heartbeat_with(
+    driver: Arc<Mutex<Box<dyn InputDriver>>>,
+    healthy: Arc<AtomicBool>,
+    config: HeartbeatConfig,
+) -> Heartbeat {
+    if let Ok(mut d) = driver.lock() {
+        d.set_auto_reconnect(false);
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_flag = Arc::clone(&stop);
+    let handle = thread::spawn(move || {
+        let mut failures: u32 = 0;
+        while !stop_flag.load(Ordering::Relaxed) {
+            let ok = match driver.lock() {
+                Ok(mut d) => match d.heartbeat() {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!("⚠️ [心跳] 发送失败 (连续 {} 次): {}，尝试重新连接...", failures + 1, e);
+                        match d.reconnect() {
+                            Ok(()) => {
+                                info!("✅ [心跳] 已重新连接设备");
+                                d.heartbeat().is_ok()
+                            }
+                            Err(e) => {
+                                error!("❌ [心跳] {}", e);
+                                false
+                            }
+                        }
+                    }
+                },
+                Err(_) => false,
+            };
+
+            if ok {
+                if failures >= config.fail_limit {
+                    info!("✅ [心跳] 设备已恢复");
+                }
+                failures = 0;
+                healthy.store(true, Ordering::Relaxed);
+            } else {
+                failures += 1;
+                if failures == config.fail_limit {
+                    error!("❌ [心跳] 连续 {} 次失败，判定设备丢失，暂停自动化", failures);
+                    healthy.store(false, Ordering::Relaxed);
+                }
+            }
+
+            // 分段等待，stop 后能及时退出而不是睡满退避时间
+            let deadline = Instant::now() + config.backoff(failures);
+            while !stop_flag.load(Ordering::Relaxed) && Instant::now() < deadline {
+                thread::park_timeout(deadline.saturating_duration_since(Instant::now()));
+            }
+        }
+    });
+    Heartbeat { stop, handle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::{FrameSink, HardwareDriver};
+    use std::io;
+    use std::sync::atomic::AtomicUsize;
+
+    /// 可拔插的假串口：拔出期间写入和重连都失败，插回后两者都成功
+    #[derive(Clone)]
+    struct UnpluggableSink {
+        plugged: Arc<AtomicBool>,
+        sent: Arc<AtomicUsize>,
+    }
+
+    impl FrameSink for UnpluggableSink {
+        fn send(&mut self, _frame: &[u8]) -> io::Result<()> {
+            self.sent.fetch_add(1, Ordering::SeqCst);
+            if self.plugged.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "device unplugged"))
+            }
+        }
+
+        fn reconnect(&mut self) -> io::Result<()> {
+            if self.plugged.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(io::Error::new(io::ErrorKind::NotFound, "port missing"))
+            }
+        }
+    }
+
+    /// 轮询直到条件成立，超时返回 false
+    fn wait_until(timeout: Duration, mut cond: impl FnMut() -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if cond() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        false
+    }
+
+    #[test]
+    fn backoff_doubles_per_failure_up_to_the_cap() {
+        let config = HeartbeatConfig::default();
+        let secs: Vec<u64> = (0..8).map(|n| config.backoff(n).as_secs()).collect();
+        assert_eq!(secs, [1, 1, 2, 4, 8, 16, 30, 30]);
+        assert_eq!(config.backoff(u32::MAX), HEARTBEAT_MAX_BACKOFF);
+    }
+
+    #[test]
+    fn failing_heartbeats_flip_the_health_flag_and_recovery_restores_it() {
+        let sink = UnpluggableSink { plugged: Arc::new(AtomicBool::new(true)), sent: Arc::new(AtomicUsize::new(0)) };
+        let drv = HardwareDriver::builder()
+            .sink(Box::new(sink.clone()))
+            .frame_delay(Duration::ZERO)
+            .split_frame_delay(Duration::ZERO)
+            .build()
+            .unwrap();
+        let driver: Arc<Mutex<Box<dyn InputDriver>>> = Arc::new(Mutex::new(Box::new(drv)));
+        let healthy = Arc::new(AtomicBool::new(true));
+        let config = HeartbeatConfig { interval: Duration::from_millis(5), max_backoff: Duration::from_millis(20), fail_limit: 3 };
+        let heartbeat = spawn_heartbeat_with(Arc::clone(&driver), Arc::clone(&healthy), config);
+
+        assert!(wait_until(Duration::from_secs(2), || sink.sent.load(Ordering::SeqCst) > 0));
+        assert!(healthy.load(Ordering::Relaxed));
+
+        // 拔掉设备：连续失败 3 次后判定丢失
+        sink.plugged.store(false, Ordering::SeqCst);
+        let unplugged_at = sink.sent.load(Ordering::SeqCst);
+        assert!(wait_until(Duration::from_secs(2), || !healthy.load(Ordering::Relaxed)), "设备丢失后健康标志没有翻转");
+        assert!(sink.sent.load(Ordering::SeqCst) - unplugged_at >= 3);
+
+        // 插回：下一次心跳重连成功，标志恢复
+        sink.plugged.store(true, Ordering::SeqCst);
+        assert!(wait_until(Duration::from_secs(2), || healthy.load(Ordering::Relaxed)), "设备恢复后健康标志没有复位");
+
+        heartbeat.stop();
+        assert_eq!(Arc::strong_count(&driver), 1);
+    }
+}
@@ -0,0 +1,99 @@
+// src/hid.rs
+// USB HID 键盘用法码 (Usage ID) 表，按美式键盘布局
+// human / tower_defense / combo / 软件驱动共用这一张表，避免各自维护导致键码不一致
+
+/// HID 键盘修饰键字节的各位，用于 key_down 的 modifier 参数 (可按位或)
+pub const MOD_CTRL: u8 = 0x01;
+pub const MOD_SHIFT: u8 = 0x02;
+pub const MOD_ALT: u8 = 0x04;
+pub const MOD_GUI: u8 = 0x08;
+
+/// HID 键码：回车
+pub const KEY_ENTER: u8 = 0x28;
+/// HID 键码：ESC
+pub const KEY_ESCAPE: u8 = 0x29;
+/// HID 键码：退格
+pub const KEY_BACKSPACE: u8 = 0x2A;
+/// HID 键码：Tab
+pub const KEY_TAB: u8 = 0x2B;
+/// HID 键码：空格
+pub const KEY_SPACE: u8 = 0x2C;
+
+/// 字符转 (HID 键码, 修饰键)
+/// 大写字母和需要 Shift 的符号返回左 Shift (0x02)；无法映射时返回 None
+pub fn char_to_usage(c: char) -> Option<(u8, u8)> {
+    const SHIFT: u8 = MOD_SHIFT;
+    let usage = match c {
+        'a'..='z' => (c as u8 - b'a' + 0x04, 0),
+        'A'..='Z' => (c as u8 - b'A' + 0x04, SHIFT),
+        // 数字行是 1..9 后接 0，'0' 不能按 '1' 的偏移计算
+        '1'..='9' => (c as u8 - b'1' + 0x1E, 0),
+        '0' => (0x27, 0),
+        '!' => (0x1E, SHIFT),
+        '@' => (0x1F, SHIFT),
+        '#' => (0x20, SHIFT),
+        '$' => (0x21, SHIFT),
+        '%' => (0x22, SHIFT),
+        '^' => (0x23, SHIFT),
+        '&' => (0x24, SHIFT),
+        '*' => (0x25, SHIFT),
+        '(' => (0x26, SHIFT),
+        ')' => (0x27, SHIFT),
+        '\n' => (KEY_ENTER, 0),
+        '\u{1B}' => (KEY_ESCAPE, 0),
+        '\t' => (KEY_TAB, 0),
+        ' ' => (KEY_SPACE, 0),
+        '-' => (0x2D, 0),
+        '_' => (0x2D, SHIFT),
+        '=' => (0x2E, 0),
+        '+' => (0x2E, SHIFT),
+        '[' => (0x2F, 0),
+        '{' => (0x2F, SHIFT),
+        ']' => (0x30, 0),
+        '}' => (0x30, SHIFT),
+        '\\' => (0x31, 0),
+        '|' => (0x31, SHIFT),
+        ';' => (0x33, 0),
+        ':' => (0x33, SHIFT),
+        '\'' => (0x34, 0),
+        '"' => (0x34, SHIFT),
+        '`' => (0x35, 0),
+        '~' => (0x35, SHIFT),
+        ',' => (0x36, 0),
+        '<' => (0x36, SHIFT),
+        '.' => (0x37, 0),
+        '>' => (0x37, SHIFT),
+        '/' => (0x38, 0),
+        '?' => (0x38, SHIFT),
+        _ => return None,
+    };
+    Some(usage)
+}
+
+/// char_to_usage 的逆映射：返回不按 Shift 时该键输入的可打印字符 (字母为小写)
+pub fn usage_to_char(code: u8) -> Option<char> {
+    (' '..='~').find(|&c| char_to_usage(c) == Some((code, 0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digits_letters_and_space_map_to_their_usage_ids() {
+        let digits: Vec<u8> = ('0'..='9').map(|c| char_to_usage(c).unwrap().0).collect();
+        assert_eq!(digits, [0x27, 0x1E, 0x1F, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26]);
+
+        assert_eq!(char_to_usage('a'), Some((0x04, 0)));
+        assert_eq!(char_to_usage('z'), Some((0x1D, 0)));
+        assert_eq!(char_to_usage('Q'), Some((0x14, MOD_SHIFT)));
+        assert_eq!(char_to_usage(' '), Some((KEY_SPACE, 0)));
+        assert_eq!(char_to_usage(')'), Some((0x27, MOD_SHIFT)));
+        assert_eq!(char_to_usage('é'), None);
+
+        assert_eq!(usage_to_char(0x27), Some('0'));
+        assert_eq!(usage_to_char(0x14), Some('q'));
+        assert_eq!(usage_to_char(KEY_SPACE), Some(' '));
+        assert_eq!(usage_to_char(KEY_ENTER), None);
+    }
+}
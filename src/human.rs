@@ -1,60 +1,238 @@
 // src/human.rs
 use crate::hardware::InputDriver;
+use crate::hid::{self, KEY_ESCAPE, KEY_SPACE};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use rand::Rng;
 use rand_distr::{Normal, Distribution};
+use log::{debug, info, warn};
+
+/// 默认轨迹采样密度 (点/秒)
+const DEFAULT_STEPS_PER_SECOND: f32 = 80.0;
+/// 单次移动最少采样点数，保证极短移动也有平滑轨迹
+const MIN_MOVE_STEPS: u32 = 8;
+
+/// 短于该距离 (像素) 的移动不做过冲，避免微调时也"甩过头"
+const MIN_OVERSHOOT_DISTANCE: f32 = 30.0;
+
+/// 设备离线时 dispatch 轮询健康标志的间隔
+const DEVICE_WAIT_POLL: Duration = Duration::from_millis(200);
+
+/// 拟人化行为的可选参数
+#[derive(Debug, Clone)]
+pub struct HumanConfig {
+    /// 是否开启"过冲-回正"：先沿移动方向冲过目标，再快速修正回来
+    pub overshoot: bool,
+    /// 过冲距离范围 (像素)
+    pub overshoot_px_range: (f32, f32),
+    /// 打字时单词之间 (空格后) 的额外停顿范围 (毫秒)
+    pub word_pause_ms: (u64, u64),
+    /// 闭环校正：移动结束后向设备读取真实光标位置 (需驱动支持，如 MAKCU 的 .getpos())，
+    /// 偏差超出容差时补一次相对移动；游戏自带灵敏度导致绝对坐标落点偏移时开启
+    pub closed_loop: bool,
+    /// 闭环校正的容差 (像素)，偏差不超过该值即视为到位
+    pub closed_loop_tolerance_px: i32,
+    /// 闭环校正最多修正的次数
+    pub closed_loop_max_iters: u8,
+    /// 第一个贝塞尔控制点 (路径 20% 处) 的随机偏移范围 (像素)，x / y 各自独立取值
+    pub ctrl1_spread: (f32, f32),
+    /// 第二个贝塞尔控制点 (路径 80% 处) 的随机偏移范围 (像素)
+    pub ctrl2_spread: (f32, f32),
+    /// 按距离缩放偏移：设置后偏移范围乘以 min(移动距离 / 该值, 1)，
+    /// 短距离移动的弧度随之变小，不会绕出大圈；None 时固定使用上面的范围
+    pub spread_reference_px: Option<f32>,
+}
+
+impl Default for HumanConfig {
+    fn default() -> Self {
+        Self {
+            overshoot: false,
+            overshoot_px_range: (5.0, 20.0),
+            word_pause_ms: (120, 380),
+            closed_loop: false,
+            closed_loop_tolerance_px: 1,
+            closed_loop_max_iters: 3,
+            ctrl1_spread: (-40.0, 40.0),
+            ctrl2_spread: (-20.0, 60.0),
+            spread_reference_px: None,
+        }
+    }
+}
 
 pub struct HumanDriver {
     // ✨ 核心修改：使用 Box<dyn InputDriver> 来存储多态驱动
     pub device: Arc<Mutex<Box<dyn InputDriver>>>,
     pub cur_x: f32,
     pub cur_y: f32,
+    home: (f32, f32),
+    steps_per_second: f32,
+    config: HumanConfig,
+    cancel: Option<Arc<AtomicBool>>,
+    // scroll_pixels 中不足一格的余量 (像素)，留到下次累计
+    scroll_remainder: i32,
+    // 屏幕尺寸 (取自驱动)，轨迹点会被限制在屏幕范围内
+    screen: (f32, f32),
+    // 演练模式：轨迹和时序照常计算，但不向设备发送任何指令
+    dry_run: bool,
+    // 驱动能在设备端执行整段贝塞尔 (MAKCU .moveto)，此时每段轨迹只发一条指令
+    device_curve: bool,
+    // 设备健康标志 (由心跳线程维护)，为 false 时 dispatch 暂停发送，等待设备恢复
+    device_health: Option<Arc<AtomicBool>>,
 }
 
 impl HumanDriver {
     /// 初始化拟人化驱动器
     // ✨ 核心修改：参数类型同步更新
     pub fn new(device: Arc<Mutex<Box<dyn InputDriver>>>, start_x: u16, start_y: u16) -> Self {
+        let ((sw, sh), device_curve) = device
+            .lock()
+            .map(|d| (d.screen_size(), d.supports_curve_move()))
+            .unwrap_or(((1920, 1080), false));
         Self {
             device,
             cur_x: start_x as f32,
             cur_y: start_y as f32,
+            home: (start_x as f32, start_y as f32),
+            steps_per_second: DEFAULT_STEPS_PER_SECOND,
+            config: HumanConfig::default(),
+            cancel: None,
+            scroll_remainder: 0,
+            screen: (sw as f32, sh as f32),
+            dry_run: false,
+            device_curve,
+            device_health: None,
         }
     }
 
+    /// 构造时直接指定拟人化参数
+    pub fn with_config(device: Arc<Mutex<Box<dyn InputDriver>>>, start_x: u16, start_y: u16, config: HumanConfig) -> Self {
+        let mut driver = Self::new(device, start_x, start_y);
+        driver.config = config;
+        driver
+    }
+
+    /// 运行中替换拟人化参数
+    pub fn set_config(&mut self, config: HumanConfig) {
+        self.config = config;
+    }
+
+    /// 设置 move_to_humanly 的轨迹采样密度 (点/秒)，非正值会被忽略
+    pub fn set_steps_per_second(&mut self, steps_per_second: f32) {
+        if steps_per_second > 0.0 {
+            self.steps_per_second = steps_per_second;
+        }
+    }
+
+    /// 设置取消标志：置为 true 后，进行中的拟人移动会在下一个采样点前停止
+    /// 传入 None 取消绑定
+    pub fn set_cancel_token(&mut self, token: Option<Arc<AtomicBool>>) {
+        self.cancel = token;
+    }
+
+    /// 绑定设备健康标志：设备离线期间 dispatch 阻塞等待恢复，
+    /// 塔防 / 日常等流程不会在设备丢失时继续盲发键鼠；传入 None 取消绑定
+    pub fn set_device_health(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.device_health = flag;
+    }
+
+    /// 演练模式：所有经过 HumanDriver 的键鼠指令都被丢弃 (光标位置仍按计划更新)
+    pub fn set_dry_run(&mut self, enabled: bool) {
+        self.dry_run = enabled;
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed))
+    }
+
+    /// 屏幕尺寸 (取自驱动)，用于把按 1920x1080 编写的坐标换算到实际分辨率
+    pub fn screen_size(&self) -> (f32, f32) {
+        self.screen
+    }
+
+    /// 当前光标位置 (内部跟踪值)
+    pub fn position(&self) -> (f32, f32) {
+        (self.cur_x, self.cur_y)
+    }
+
+    /// 拟人化地移回构造时传入的起始中心点
+    /// 拟人轨迹的落点带 ±2px 抖动，最后补一帧把光标精确对齐到中心
+    pub fn home(&mut self, duration_sec: f32) {
+        let (hx, hy) = self.home;
+        self.move_to_humanly(hx as u16, hy as u16, duration_sec);
+        if self.is_cancelled() {
+            return;
+        }
+        self.dispatch(|dev| dev.mouse_abs(hx as u16, hy as u16));
+        self.cur_x = hx;
+        self.cur_y = hy;
+    }
+
     // ==========================================
     // 1. 基础输入原子操作 (原子层)
     // ==========================================
 
-    /// 内部辅助：字符转 HID 键码
-    fn char_to_keycode(&self, ch: char) -> u8 {
-        match ch.to_ascii_lowercase() {
-            'a'..='z' => ch.to_ascii_lowercase() as u8 - b'a' + 0x04,
-            '1'..='9' => ch as u8 - b'1' + 0x1E,
-            '0' => 0x27,
-            ' ' => 0x2C,
-            _ => 0,
+    /// 锁定设备执行一条指令，发送失败时打印警告而不是静默丢弃
+    pub fn dispatch<F>(&self, op: F)
+    where
+        F: FnOnce(&mut dyn InputDriver) -> io::Result<()>,
+    {
+        if self.dry_run || !self.wait_for_device() {
+            return;
+        }
+        if let Ok(mut dev) = self.device.lock() {
+            if let Err(e) = op(dev.as_mut()) {
+                warn!("⚠️ [Human] 指令发送失败: {}", e);
+            }
+        }
+    }
+
+    /// 设备离线时阻塞到心跳报告恢复；等待期间被取消则返回 false，这条指令直接丢弃
+    fn wait_for_device(&self) -> bool {
+        let Some(health) = &self.device_health else { return true };
+        if health.load(Ordering::Relaxed) {
+            return true;
+        }
+        warn!("⏸️ [Human] 设备离线，暂停发送指令，等待恢复...");
+        while !health.load(Ordering::Relaxed) {
+            if self.is_cancelled() {
+                return false;
+            }
+            thread::sleep(DEVICE_WAIT_POLL);
         }
+        info!("▶️ [Human] 设备已恢复，继续发送指令");
+        true
+    }
+
+    /// 字符转 (HID 键码, 修饰键)，按美式键盘布局 (查 hid::char_to_usage)
+    /// 大写字母和需要 Shift 的符号返回左 Shift (0x02)；无法映射时键码为 0
+    pub fn char_to_keycode_with_mod(ch: char) -> (u8, u8) {
+        hid::char_to_usage(ch).unwrap_or((0, 0))
     }
 
     /// 🔥 【键盘长按】
     /// 允许指定按下的毫秒数。如果是 0，则执行一次极短的点击。
     pub fn key_hold(&mut self, ch: char, ms: u64) {
-        let keycode = self.char_to_keycode(ch);
+        self.key_hold_mod(ch, 0, ms);
+    }
+
+    /// 【带修饰键的长按】 例如 key_hold_mod('z', MOD_CTRL, 0) 发送 Ctrl+Z
+    /// modifier 为 HID 修饰键位 (MOD_CTRL / MOD_SHIFT / MOD_ALT / MOD_GUI，可按位或)，
+    /// 会与字符本身需要的 Shift 合并
+    pub fn key_hold_mod(&mut self, ch: char, modifier: u8, ms: u64) {
+        let (keycode, char_mod) = Self::char_to_keycode_with_mod(ch);
+        let modifier = modifier | char_mod;
         if keycode != 0 {
-            if let Ok(mut dev) = self.device.lock() {
-                dev.key_down(keycode, 0);
-            }
+            // 修饰键与键码放在同一帧，避免 Shift 与字母之间出现时序错位
+            self.dispatch(|dev| dev.key_down(keycode, modifier));
             
             // 如果 ms 为 0，模拟一个非常短的物理接触
             let hold_time = if ms > 0 { ms } else { rand::thread_rng().gen_range(20..45) };
             thread::sleep(Duration::from_millis(hold_time));
 
-            if let Ok(mut dev) = self.device.lock() {
-                dev.key_up();
-            }
+            self.dispatch(|dev| dev.key_up());
         }
     }
 
@@ -65,23 +243,89 @@ impl HumanDriver {
         self.key_hold(ch, jitter);
     }
 
+    /// 【带修饰键的点击】 (短按)，例如 key_click_mod('a', MOD_SHIFT | MOD_CTRL)
+    pub fn key_click_mod(&mut self, ch: char, modifier: u8) {
+        let jitter = rand::thread_rng().gen_range(35..70);
+        self.key_hold_mod(ch, modifier, jitter);
+    }
+
+    /// 【按扫描码点按】 按下 HID 键码 code，保持 hold_ms 毫秒后松开
+    /// 用于 ESC 这类无法由字符表示的按键
+    pub fn tap_scancode(&mut self, code: u8, hold_ms: u64) {
+        self.dispatch(|dev| dev.key_down(code, 0));
+        thread::sleep(Duration::from_millis(hold_ms));
+        self.dispatch(|dev| dev.key_up());
+    }
+
+    /// 点按 ESC (关闭弹窗 / 返回上一级)
+    pub fn tap_escape(&mut self) {
+        let jitter = rand::thread_rng().gen_range(35..70);
+        self.tap_scancode(KEY_ESCAPE, jitter);
+    }
+
+    /// 点按空格 (跳过动画 / 确认)
+    pub fn tap_space(&mut self) {
+        let jitter = rand::thread_rng().gen_range(35..70);
+        self.tap_scancode(KEY_SPACE, jitter);
+    }
+
     /// 🔥 【模拟鼠标滚轮】
-    /// delta: 120 的倍数，正数为向上滚，负数为向下滚
+    /// delta: 滚动格数，正数为向上滚，负数为向下滚
+    /// 逐格发送单格滚轮事件，格间带随机间隔，看起来像连续拨动滚轮
     pub fn mouse_scroll(&mut self, delta: i32) {
-        if let Ok(mut dev) = self.device.lock() {
-            // 在 lib.rs 中 mouse_move 的第三个参数通常对应滚轮字节
-            dev.mouse_move(0, 0, delta as i8);
+        let notch: i8 = if delta < 0 { -1 } else { 1 };
+        let mut rng = rand::thread_rng();
+
+        for i in 0..delta.unsigned_abs() {
+            if i > 0 {
+                thread::sleep(Duration::from_millis(rng.gen_range(15..40)));
+            }
+            // mouse_move 的第三个参数对应滚轮字节
+            self.dispatch(|dev| dev.mouse_move(0, 0, notch));
         }
         // 滚轮后稍微停顿符合人体工程学
         thread::sleep(Duration::from_millis(100));
     }
 
+    /// 【横向滚动】 steps 为正时向右，用于横向翻页的背包/列表
+    /// 需要驱动支持 (MAKCU .pan() / 软件模拟)；ESP32 帧协议没有横向滚轮，只会打印警告
+    pub fn mouse_pan(&mut self, steps: i16) {
+        if steps == 0 {
+            return;
+        }
+        self.dispatch(|dev| dev.mouse_pan(steps));
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    /// 【滚轮左右拨动】 对应 MAKCU .tilt()，其他驱动只打印警告
+    pub fn mouse_tilt(&mut self, steps: i16) {
+        if steps == 0 {
+            return;
+        }
+        self.dispatch(|dev| dev.mouse_tilt(steps));
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    /// 🔥 【按像素滚动】
+    /// pixels: 期望滚动的像素距离 (正数向上)，px_per_notch: 每格滚轮对应的像素
+    /// 不足一格的余量会累计到下一次调用，连续多次小幅滚动不会丢失距离；返回实际发送的格数
+    pub fn scroll_pixels(&mut self, pixels: i32, px_per_notch: i32) -> i32 {
+        if px_per_notch <= 0 {
+            return 0;
+        }
+        let total = self.scroll_remainder + pixels;
+        let notches = total / px_per_notch;
+        self.scroll_remainder = total - notches * px_per_notch;
+        if notches != 0 {
+            self.mouse_scroll(notches);
+        }
+        notches
+    }
+
     /// 🔥 【相对移动】
     /// 用于在当前位置基础上进行微调或防掉线微动
     pub fn move_relative(&mut self, dx: i32, dy: i32) {
-        if let Ok(mut dev) = self.device.lock() {
-            dev.mouse_move(dx, dy, 0);
-        }
+        self.dispatch(|dev| dev.mouse_move(dx, dy, 0));
         self.cur_x += dx as f32;
         self.cur_y += dy as f32;
     }
@@ -95,50 +339,227 @@ impl HumanDriver {
         let mut rng = rand::thread_rng();
         let start = (self.cur_x, self.cur_y);
         
-        let end = (
+        let end = self.clamp_to_screen((
             target_x as f32 + rng.gen_range(-2.0..2.0),
             target_y as f32 + rng.gen_range(-2.0..2.0)
-        );
+        ));
+
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let dist = (dx * dx + dy * dy).sqrt();
+
+        if self.config.overshoot && dist >= MIN_OVERSHOOT_DISTANCE {
+            // 沿移动方向冲过目标，停顿片刻后用一段近似直线的短轨迹修正回来
+            let (lo, hi) = self.config.overshoot_px_range;
+            let over = if hi > lo { rng.gen_range(lo..hi) } else { lo };
+            let past = (end.0 + dx / dist * over, end.1 + dy / dist * over);
+
+            let (c1, c2) = self.random_ctrl_points(&mut rng, start, past);
+            if !self.trace_bezier(start, c1, c2, past, duration_sec * 0.85) {
+                return;
+            }
 
+            thread::sleep(Duration::from_millis(rng.gen_range(30..70)));
+
+            let c1 = (past.0 + (end.0 - past.0) * 0.33, past.1 + (end.1 - past.1) * 0.33);
+            let c2 = (past.0 + (end.0 - past.0) * 0.66, past.1 + (end.1 - past.1) * 0.66);
+            if !self.trace_bezier(past, c1, c2, end, (duration_sec * 0.15).max(0.05)) {
+                return;
+            }
+        } else {
+            let (c1, c2) = self.random_ctrl_points(&mut rng, start, end);
+            if !self.trace_bezier(start, c1, c2, end, duration_sec) {
+                return;
+            }
+        }
+
+        self.cur_x = end.0;
+        self.cur_y = end.1;
+
+        if self.config.closed_loop {
+            self.correct_position(end);
+        }
+    }
+
+    /// 闭环校正：读回真实光标位置，按偏差补相对移动，直到落在容差内或用完次数
+    /// 驱动不支持读取位置时直接返回
+    fn correct_position(&mut self, target: (f32, f32)) {
+        if self.dry_run {
+            return;
+        }
+        let (tx, ty) = (target.0.round() as i32, target.1.round() as i32);
+        let tol = self.config.closed_loop_tolerance_px.max(0);
+        for _ in 0..self.config.closed_loop_max_iters {
+            if self.is_cancelled() {
+                return;
+            }
+            let pos = match self.device.lock() {
+                Ok(mut dev) => dev.cursor_position(),
+                Err(_) => return,
+            };
+            let (x, y) = match pos {
+                Ok(p) => p,
+                Err(e) => {
+                    debug!("[ClosedLoop] 无法读取光标位置，跳过校正: {}", e);
+                    return;
+                }
+            };
+            let (dx, dy) = (tx - x, ty - y);
+            if dx.abs() <= tol && dy.abs() <= tol {
+                return;
+            }
+            debug!("[ClosedLoop] 实际 ({}, {}) 偏离目标 ({}, {})，修正 ({}, {})", x, y, tx, ty, dx, dy);
+            self.dispatch(|dev| dev.mouse_move(dx, dy, 0));
+        }
+    }
+
+    /// 把坐标限制在 [0, 宽-1] × [0, 高-1]
+    fn clamp_to_screen(&self, p: (f32, f32)) -> (f32, f32) {
+        let max_x = (self.screen.0 - 1.0).max(0.0);
+        let max_y = (self.screen.1 - 1.0).max(0.0);
+        (p.0.clamp(0.0, max_x), p.1.clamp(0.0, max_y))
+    }
+
+    /// 为一段轨迹生成带随机偏移的贝塞尔控制点
+    /// 偏移范围取自 HumanConfig 的 ctrl1_spread / ctrl2_spread，开启 spread_reference_px 时按距离缩小
+    fn random_ctrl_points(&self, rng: &mut impl Rng, start: (f32, f32), end: (f32, f32)) -> ((f32, f32), (f32, f32)) {
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let factor = match self.config.spread_reference_px {
+            Some(reference) if reference > 0.0 => ((dx * dx + dy * dy).sqrt() / reference).min(1.0),
+            _ => 1.0,
+        };
+        let mut offset = |(lo, hi): (f32, f32)| {
+            let (lo, hi) = (lo * factor, hi * factor);
+            if hi > lo { rng.gen_range(lo..hi) } else { lo }
+        };
         let ctrl1 = (
-            start.0 + (end.0 - start.0) * 0.2 + rng.gen_range(-40.0..40.0),
-            start.1 + (end.1 - start.1) * 0.2 + rng.gen_range(-40.0..40.0)
+            start.0 + dx * 0.2 + offset(self.config.ctrl1_spread),
+            start.1 + dy * 0.2 + offset(self.config.ctrl1_spread)
         );
         let ctrl2 = (
-            start.0 + (end.0 - start.0) * 0.8 + rng.gen_range(-20.0..60.0),
-            start.1 + (end.1 - start.1) * 0.8 + rng.gen_range(-20.0..60.0)
+            start.0 + dx * 0.8 + offset(self.config.ctrl2_spread),
+            start.1 + dy * 0.8 + offset(self.config.ctrl2_spread)
         );
+        (ctrl1, ctrl2)
+    }
 
-        let steps = (duration_sec * 80.0) as u32; 
+    /// 沿给定贝塞尔曲线逐点发送绝对坐标
+    /// 被取消标志打断时返回 false，此时 cur_x/cur_y 停在最后一个已发送的点
+    fn trace_bezier(&mut self, start: (f32, f32), ctrl1: (f32, f32), ctrl2: (f32, f32), end: (f32, f32), duration_sec: f32) -> bool {
+        // 采样点数随时长和密度线性增长，并设下限防止短移动"跳帧"
+        let steps = ((duration_sec * self.steps_per_second) as u32).max(MIN_MOVE_STEPS);
+        if self.device_curve {
+            return self.trace_bezier_on_device(start, ctrl1, ctrl2, end, steps, duration_sec);
+        }
         let interval = Duration::from_secs_f32(duration_sec / steps as f32);
 
         for i in 0..=steps {
+            if self.is_cancelled() {
+                return false;
+            }
             let t_linear = i as f32 / steps as f32;
             let t_eased = Self::ease_in_out_cubic(t_linear);
-            let (px, py) = Self::bezier_cubic(t_eased, start, ctrl1, ctrl2, end);
+            // 控制点和过冲点可能落到屏幕外，逐点限制在屏幕范围内再发送
+            let (px, py) = self.clamp_to_screen(Self::bezier_cubic(t_eased, start, ctrl1, ctrl2, end));
             
-            if let Ok(mut dev) = self.device.lock() {
-                dev.mouse_abs(px as u16, py as u16);
-            }
+            self.dispatch(|dev| dev.mouse_abs(px as u16, py as u16));
+            self.cur_x = px;
+            self.cur_y = py;
             thread::sleep(interval);
         }
+        true
+    }
 
-        self.cur_x = end.0;
-        self.cur_y = end.1;
+    /// 同一条贝塞尔交给设备端插值：只发一条 mouse_curve_to，控制点换算为相对起点的偏移
+    /// 设备端按匀速分段，没有 ease_in_out 的加减速；发送后按原时长等待，保持调用方的节奏不变
+    fn trace_bezier_on_device(&mut self, start: (f32, f32), ctrl1: (f32, f32), ctrl2: (f32, f32), end: (f32, f32), steps: u32, duration_sec: f32) -> bool {
+        if self.is_cancelled() {
+            return false;
+        }
+        let (ex, ey) = self.clamp_to_screen(end);
+        let rel = |p: (f32, f32)| ((p.0 - start.0).round() as i16, (p.1 - start.1).round() as i16);
+        let ctrl = [rel(ctrl1), rel(ctrl2)];
+        let segments = steps.min(u16::MAX as u32) as u16;
+        self.dispatch(|dev| dev.mouse_curve_to(ex as u16, ey as u16, segments, ctrl));
+        thread::sleep(Duration::from_secs_f32(duration_sec));
+        self.cur_x = ex;
+        self.cur_y = ey;
+        true
+    }
+
+    /// 【普通点击】等价于 click_humanly(left, right, 0)，按住时长随机取自然值
+    pub fn click(&mut self, left: bool, right: bool) {
+        self.click_humanly(left, right, 0);
     }
 
     /// 【拟人化鼠标点击】
     /// 增加 hold_ms 参数以支持长按点击（如蓄力）
     pub fn click_humanly(&mut self, left: bool, right: bool, hold_ms: u64) {
+        if self.dry_run {
+            debug!("📝 [DryRun] 点击 ({:.0}, {:.0}) left={} right={}", self.cur_x, self.cur_y, left, right);
+            return;
+        }
         let mut rng = rand::thread_rng();
-        if let Ok(mut dev) = self.device.lock() {
-            dev.mouse_down(left, right);
-            
-            let sleep_time = if hold_ms > 0 { hold_ms } else { rng.gen_range(30..75) };
-            thread::sleep(Duration::from_millis(sleep_time));
-            
-            dev.mouse_up();
+        // 与其他指令一样经过 dispatch：设备离线时等待恢复，而不是向断开的串口硬发
+        self.dispatch(|dev| dev.mouse_down(left, right));
+
+        let sleep_time = if hold_ms > 0 { hold_ms } else { rng.gen_range(30..75) };
+        thread::sleep(Duration::from_millis(sleep_time));
+
+        self.dispatch(|dev| dev.mouse_up());
+    }
+
+    /// 【拟人化拖拽】
+    /// 在当前位置按下左键，停顿 hold_ms_before_move 让游戏识别"抓取"，
+    /// 按住不放沿贝塞尔轨迹移动到目标点后再松开。为 0 时使用 60~120ms 的随机停顿
+    pub fn drag_humanly(&mut self, to_x: u16, to_y: u16, duration_sec: f32, hold_ms_before_move: u64) {
+        let mut rng = rand::thread_rng();
+
+        self.dispatch(|dev| dev.mouse_down(true, false));
+        let grab_dwell = if hold_ms_before_move > 0 { hold_ms_before_move } else { rng.gen_range(60..120) };
+        thread::sleep(Duration::from_millis(grab_dwell));
+
+        // 复用拟人轨迹，按键状态在移动期间保持不变，cur_x/cur_y 由其更新
+        self.move_to_humanly(to_x, to_y, duration_sec);
+
+        // 落点后稍作停留再松手，避免游戏把松开判定为"甩出"
+        thread::sleep(Duration::from_millis(rng.gen_range(40..90)));
+        self.dispatch(|dev| dev.mouse_up());
+    }
+
+    /// 【连点】 以每秒 cps 次的频率连续点击，持续 duration，返回实际点击次数
+    /// jitter 为 true 时每次间隔和按住时长随机浮动 ±15%，否则严格等间隔；取消标志置位后立即停止
+    /// 按时间表而不是累加 sleep 调度，长时间连点的总次数不会因误差漂移
+    pub fn autoclick(&mut self, left: bool, right: bool, cps: f32, duration: Duration, jitter: bool) -> u32 {
+        if cps <= 0.0 || !(left || right) {
+            return 0;
         }
+        let mut rng = rand::thread_rng();
+        let period = Duration::from_secs_f32(1.0 / cps);
+        let start = Instant::now();
+        let mut clicks = 0u32;
+
+        while !self.is_cancelled() {
+            let due = start + period.mul_f32(clicks as f32);
+            if due.duration_since(start) >= duration {
+                break;
+            }
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+
+            let factor = if jitter { rng.gen_range(0.85..1.15) } else { 1.0 };
+            // 按住约占周期的 1/3，上限 60ms，保证下一次按下前已经松开
+            let hold = period.mul_f32(0.35 * factor).min(Duration::from_millis(60));
+            let offset = if jitter { period.mul_f32(rng.gen_range(0.0..0.15)) } else { Duration::ZERO };
+            thread::sleep(offset);
+
+            self.dispatch(|dev| dev.mouse_down(left, right));
+            thread::sleep(hold);
+            self.dispatch(|dev| dev.mouse_up());
+            clicks += 1;
+        }
+        clicks
     }
 
     pub fn double_click_humanly(&mut self, left: bool, right: bool, interval_ms: u64) {
@@ -159,13 +580,23 @@ impl HumanDriver {
         let base_delay_ms = 60.0 / (base_wpm * 5.0) * 1000.0;
         let normal_dist = Normal::new(base_delay_ms, base_delay_ms * 0.3).unwrap();
         let mut rng = rand::thread_rng();
+        let (pause_lo, pause_hi) = self.config.word_pause_ms;
+
+        // 每个单词抽一个速度系数：熟悉的词打得快，生僻的词打得慢
+        let mut word_speed: f32 = rng.gen_range(0.75..1.3);
 
         for ch in text.chars() {
             // 直接复用我们新写的 key_click
             self.key_click(ch);
 
             // 字符间的随机停顿
-            let delay = normal_dist.sample(&mut rng).max(10.0) as u64;
+            let mut delay = (normal_dist.sample(&mut rng) * word_speed).max(10.0) as u64;
+
+            if ch == ' ' {
+                // 单词之间停顿更长，并为下一个单词换一个速度
+                delay += if pause_hi > pause_lo { rng.gen_range(pause_lo..pause_hi) } else { pause_lo };
+                word_speed = rng.gen_range(0.75..1.3);
+            }
             thread::sleep(Duration::from_millis(delay));
         }
     }
@@ -186,4 +617,407 @@ impl HumanDriver {
         let y = uu * u * p0.1 + 3.0 * uu * t * p1.1 + 3.0 * u * tt * p2.1 + tt * t * p3.1;
         (x, y)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::{
+        EventType, FrameSink, HardwareDriver, MakcuDriver, RecordingSink, VecSink, MOD_ALT, MOD_CTRL, MOD_SHIFT, MOUSE_LEFT,
+        MOUSE_RIGHT,
+    };
+    use crate::makcu::client::tests::MockPort;
+    use crate::makcu::{MakcuClient, MakcuConfig};
+    use rand::SeedableRng;
+
+    /// 挂在内存 sink 上的 1920x1080 硬件驱动，帧间不做等待
+    fn human_on(sink: &VecSink) -> HumanDriver {
+        let drv = HardwareDriver::builder()
+            .sink(Box::new(sink.clone()))
+            .screen(1920, 1080)
+            .frame_delay(Duration::ZERO)
+            .split_frame_delay(Duration::ZERO)
+            .build()
+            .unwrap();
+        let human = HumanDriver::new(Arc::new(Mutex::new(Box::new(drv))), 960, 540);
+        sink.clear();
+        human
+    }
+
+    /// 挂在脚本化 MAKCU 串口上的 1920x1080 拟人驱动，串口回复由测试预设
+    fn makcu_human(port: &MockPort) -> HumanDriver {
+        let config = MakcuConfig::new("MOCK").with_timeout(50).with_screen_size(1920, 1080);
+        let client = MakcuClient::from_port(Box::new(port.clone()), config.clone());
+        let drv = MakcuDriver::from_client(client, config);
+        HumanDriver::new(Arc::new(Mutex::new(Box::new(drv))), 960, 540)
+    }
+
+    /// 把绝对移动帧还原成屏幕坐标
+    fn abs_point(frame: &[u8]) -> (f32, f32) {
+        let tx = u16::from_le_bytes([frame[4], frame[5]]) as f32;
+        let ty = u16::from_le_bytes([frame[6], frame[7]]) as f32;
+        (tx / 32767.0 * 1920.0, ty / 32767.0 * 1080.0)
+    }
+
+    #[test]
+    fn move_to_humanly_emits_a_path_of_abs_frames_ending_at_the_target() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        human.move_to_humanly(1500, 300, 0.1);
+
+        let frames = sink.frames();
+        assert!(frames.len() > 2);
+        assert!(frames.iter().all(|f| f[1] == EventType::MouseAbs as u8));
+
+        let (x0, y0) = abs_point(&frames[0]);
+        assert!((x0 - 960.0).abs() <= 1.0 && (y0 - 540.0).abs() <= 1.0, "起点 ({}, {})", x0, y0);
+        let (x, y) = abs_point(frames.last().unwrap());
+        assert!((x - 1500.0).abs() <= 3.0 && (y - 300.0).abs() <= 3.0, "终点 ({}, {})", x, y);
+        let (cx, cy) = human.position();
+        assert!((cx - x).abs() <= 1.0 && (cy - y).abs() <= 1.0);
+    }
+
+    #[test]
+    fn moves_into_the_corner_never_wrap_around() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        human.move_to_humanly(5, 5, 0.05);
+        // 从角落附近反复小幅移动：抖动和控制点都可能落到负坐标
+        for _ in 0..20 {
+            sink.clear();
+            human.move_to_humanly(5, 5, 0.02);
+            for frame in sink.frames() {
+                let (x, y) = abs_point(&frame);
+                assert!(x < 100.0 && y < 100.0, "绕到了 ({}, {})", x, y);
+            }
+            let (cx, cy) = human.position();
+            assert!((0.0..10.0).contains(&cx) && (0.0..10.0).contains(&cy), "记录位置 ({}, {})", cx, cy);
+        }
+    }
+
+    #[test]
+    fn abs_frame_count_scales_with_duration_and_density() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        let frames_for = |human: &mut HumanDriver, x: u16, secs: f32| {
+            sink.clear();
+            human.move_to_humanly(x, 540, secs);
+            sink.frames().len()
+        };
+
+        // 默认 80 点/秒，采样点 0..=steps 共 steps + 1 帧
+        assert_eq!(frames_for(&mut human, 1200, 0.2), 17);
+        assert_eq!(frames_for(&mut human, 600, 0.4), 33);
+        // 极短移动不少于 MIN_MOVE_STEPS
+        assert_eq!(frames_for(&mut human, 700, 0.02), MIN_MOVE_STEPS as usize + 1);
+
+        human.set_steps_per_second(160.0);
+        assert_eq!(frames_for(&mut human, 1200, 0.2), 33);
+    }
+
+    #[test]
+    fn typing_sends_shift_with_uppercase_letters_and_symbols() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        human.set_config(HumanConfig { word_pause_ms: (0, 0), ..HumanConfig::default() });
+        human.type_humanly("Hello, World! 123", 2000.0);
+
+        // 每个字符一帧按下一帧松开，按下帧的 [2] 是键码、[4] 是修饰键
+        let frames = sink.frames();
+        let pressed: Vec<(u8, u8)> = frames.chunks(2).map(|pair| (pair[0][2], pair[0][4])).collect();
+        let expected: Vec<(u8, u8)> = vec![
+            (0x0B, MOD_SHIFT), (0x08, 0), (0x0F, 0), (0x0F, 0), (0x12, 0), (0x36, 0), (KEY_SPACE, 0),
+            (0x1A, MOD_SHIFT), (0x12, 0), (0x15, 0), (0x0F, 0), (0x07, 0), (0x1E, MOD_SHIFT), (KEY_SPACE, 0),
+            (0x1E, 0), (0x1F, 0), (0x20, 0),
+        ];
+        assert_eq!(pressed, expected);
+        assert!(frames.chunks(2).all(|pair| pair[1][3] == 0x80));
+    }
+
+    #[test]
+    fn home_returns_exactly_to_the_start_center() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        human.move_to_humanly(300, 900, 0.05);
+        assert_ne!(human.position(), (960.0, 540.0));
+
+        human.home(0.05);
+        assert_eq!(human.position(), (960.0, 540.0));
+        let (x, y) = abs_point(sink.frames().last().unwrap());
+        assert!((x - 960.0).abs() <= 0.1 && (y - 540.0).abs() <= 0.1, "终点 ({}, {})", x, y);
+    }
+
+    #[test]
+    fn cancel_flag_stops_a_move_after_the_current_point() {
+        /// 转发到 VecSink，发出第 n 帧后置位取消标志，模拟移动中途按下停止热键
+        struct CancelAfter {
+            inner: VecSink,
+            remaining: usize,
+            cancel: Arc<AtomicBool>,
+        }
+
+        impl FrameSink for CancelAfter {
+            fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+                self.inner.send(frame)?;
+                self.remaining = self.remaining.saturating_sub(1);
+                if self.remaining == 0 {
+                    self.cancel.store(true, Ordering::Relaxed);
+                }
+                Ok(())
+            }
+        }
+
+        let sink = VecSink::new();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let drv = HardwareDriver::builder()
+            .sink(Box::new(CancelAfter { inner: sink.clone(), remaining: 5, cancel: Arc::clone(&cancel) }))
+            .frame_delay(Duration::ZERO)
+            .build()
+            .unwrap();
+        let mut human = HumanDriver::new(Arc::new(Mutex::new(Box::new(drv))), 960, 540);
+        human.set_cancel_token(Some(Arc::clone(&cancel)));
+        human.move_to_humanly(1800, 100, 0.5);
+
+        // 0.5 秒的移动本应有 41 帧，第 5 帧后停止，光标停在最后发出的点
+        let frames = sink.frames();
+        assert_eq!(frames.len(), 5);
+        let (x, y) = abs_point(frames.last().unwrap());
+        let (cx, cy) = human.position();
+        assert!((cx - x).abs() <= 1.0 && (cy - y).abs() <= 1.0);
+        assert!(cx < 1700.0);
+    }
+
+    #[test]
+    fn spaces_pause_longer_than_letters_while_typing() {
+        let recorder = RecordingSink::new(Box::new(VecSink::new()));
+        let recording = recorder.recording();
+        let drv = HardwareDriver::builder().sink(Box::new(recorder)).frame_delay(Duration::ZERO).build().unwrap();
+        let mut human = HumanDriver::new(Arc::new(Mutex::new(Box::new(drv))), 960, 540);
+        human.set_config(HumanConfig { word_pause_ms: (150, 200), ..HumanConfig::default() });
+        human.type_humanly("abc def ghi jkl", 300.0);
+
+        // 字符 i 的停顿 = 它的松开帧到下一个字符按下帧的间隔
+        let frames = recording.frames();
+        let (mut space, mut letter) = (Vec::new(), Vec::new());
+        for (i, ch) in "abc def ghi jkl".chars().enumerate().take(14) {
+            let gap = frames[2 * i + 2].offset - frames[2 * i + 1].offset;
+            if ch == ' ' { space.push(gap) } else { letter.push(gap) }
+        }
+        let mean = |v: &[Duration]| v.iter().sum::<Duration>() / v.len() as u32;
+        assert!(mean(&space) > mean(&letter) + Duration::from_millis(100), "空格 {:?} / 字母 {:?}", mean(&space), mean(&letter));
+    }
+
+    #[test]
+    fn mouse_scroll_sends_one_single_notch_frame_per_notch() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        human.mouse_scroll(-5);
+
+        let down = vec![0xAA, EventType::MouseRel as u8, 0, (-1i8) as u8, 0, 0, 0, 0, 0x00, 0x00, 0x55];
+        assert_eq!(sink.frames(), vec![down; 5]);
+    }
+
+    #[test]
+    fn scroll_pixels_sends_whole_notches_and_carries_the_remainder() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        let up_notches = |sink: &VecSink| sink.frames().iter().filter(|f| f[1] == EventType::MouseRel as u8 && f[3] == 1).count();
+
+        assert_eq!(human.scroll_pixels(250, 100), 2);
+        assert_eq!(up_notches(&sink), 2);
+        // 上次剩下的 50px 加上这次的 50px 凑满一格
+        assert_eq!(human.scroll_pixels(50, 100), 1);
+        assert_eq!(up_notches(&sink), 3);
+        assert_eq!(human.scroll_pixels(-40, 100), 0);
+        assert_eq!(sink.frames().len(), 3);
+    }
+
+    #[test]
+    fn click_presses_then_releases_the_button() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        human.click(false, true);
+
+        let frames = sink.frames();
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|f| f[1] == EventType::MouseRel as u8));
+        assert_eq!((frames[0][2], frames[1][2]), (0x02, 0x00));
+    }
+
+    #[test]
+    fn autoclick_count_matches_cps_times_duration() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        // (按下帧数, 松开帧数)
+        let presses = |sink: &VecSink, mask: u8| {
+            let frames = sink.frames();
+            let buttons = frames.iter().filter(|f| f[1] == EventType::MouseRel as u8);
+            let down = buttons.clone().filter(|f| f[2] == mask).count();
+            let up = buttons.filter(|f| f[2] == 0).count();
+            (down, up)
+        };
+
+        // 20 次/秒 × 0.5 秒
+        let start = Instant::now();
+        assert_eq!(human.autoclick(true, false, 20.0, Duration::from_millis(500), false), 10);
+        assert_eq!(presses(&sink, MOUSE_LEFT), (10, 10));
+        assert!(start.elapsed() < Duration::from_millis(800), "{:?}", start.elapsed());
+
+        sink.clear();
+        let clicks = human.autoclick(false, true, 20.0, Duration::from_millis(500), true);
+        assert!((9..=11).contains(&clicks), "{}", clicks);
+        assert_eq!(presses(&sink, MOUSE_RIGHT), (clicks as usize, clicks as usize));
+
+        sink.clear();
+        human.set_cancel_token(Some(Arc::new(AtomicBool::new(true))));
+        assert_eq!(human.autoclick(true, false, 20.0, Duration::from_millis(500), false), 0);
+        assert!(sink.frames().is_empty());
+    }
+
+    #[test]
+    fn key_hold_mod_puts_the_modifier_byte_into_the_key_frame() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        human.key_hold_mod('z', MOD_CTRL, 1);
+        // 大写字母自带的 Shift 与传入的修饰键合并
+        human.key_hold_mod('S', MOD_ALT, 1);
+
+        let keyboard = |code: u8, modifier: u8| vec![0xAA, EventType::Keyboard as u8, code, 0, modifier, 0, 0, 0, 0, 0, 0x55];
+        let release = vec![0xAA, EventType::Keyboard as u8, 0, 0x80, 0, 0, 0, 0, 0, 0, 0x55];
+        assert_eq!(
+            sink.frames(),
+            vec![keyboard(0x1D, MOD_CTRL), release.clone(), keyboard(0x16, MOD_ALT | MOD_SHIFT), release]
+        );
+    }
+
+    #[test]
+    fn escape_and_space_taps_press_and_release_their_scancodes() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        human.tap_escape();
+        human.tap_space();
+
+        let down = |code: u8| vec![0xAA, EventType::Keyboard as u8, code, 0, 0, 0, 0, 0, 0x00, 0x00, 0x55];
+        let up = vec![0xAA, EventType::Keyboard as u8, 0, 0x80, 0, 0, 0, 0, 0x00, 0x00, 0x55];
+        assert_eq!(sink.frames(), vec![down(KEY_ESCAPE), up.clone(), down(KEY_SPACE), up]);
+    }
+
+    #[test]
+    fn input_waits_while_the_device_is_offline() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        let health = Arc::new(AtomicBool::new(false));
+        human.set_device_health(Some(Arc::clone(&health)));
+
+        let restore = {
+            let health = Arc::clone(&health);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(300));
+                health.store(true, Ordering::Relaxed);
+            })
+        };
+        let start = Instant::now();
+        human.tap_space();
+        restore.join().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(sink.frames().len(), 2);
+
+        // 离线时被取消：指令丢弃，不会一直阻塞
+        sink.clear();
+        health.store(false, Ordering::Relaxed);
+        human.set_cancel_token(Some(Arc::new(AtomicBool::new(true))));
+        human.tap_space();
+        human.click_humanly(true, false, 10);
+        assert!(sink.frames().is_empty());
+    }
+
+    #[test]
+    fn closed_loop_corrects_a_3px_landing_error() {
+        let port = MockPort::default();
+        let mut human = makcu_human(&port);
+        human.set_config(HumanConfig { closed_loop: true, ..HumanConfig::default() });
+
+        // 第一次读回偏了 3px，补一次相对移动后到位
+        port.reply(b"1203,297\r\n>>>\r\n").reply(b">>>\r\n").reply(b"1200,300\r\n>>>\r\n");
+        human.correct_position((1200.0, 300.0));
+        assert_eq!(port.written(), ".getpos()\r\n.move(-3,3,1)\r\n.getpos()\r\n");
+    }
+
+    #[test]
+    fn makcu_backend_sends_the_whole_curve_as_one_moveto() {
+        let port = MockPort::default();
+        let mut human = makcu_human(&port);
+        port.reply(b">>>\r\n");
+        human.move_to_humanly(1500, 300, 0.05);
+
+        let written = port.written();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 1, "{:?}", written);
+        let args: Vec<i32> = lines[0]
+            .strip_prefix(".moveto(")
+            .and_then(|s| s.strip_suffix(')'))
+            .unwrap()
+            .split(',')
+            .map(|v| v.parse().unwrap())
+            .collect();
+        // x, y, segments, 两个控制点 (相对起点的偏移)
+        assert_eq!(args.len(), 7, "{:?}", args);
+        let (x, y) = (args[0], args[1]);
+        assert!((x - 1500).abs() <= 2 && (y - 300).abs() <= 2, "终点 ({}, {})", x, y);
+        assert!(args[2] >= MIN_MOVE_STEPS as i32);
+        let (cx, cy) = human.position();
+        assert_eq!((cx as i32, cy as i32), (x, y));
+    }
+
+    #[test]
+    fn pan_and_tilt_go_out_as_makcu_commands() {
+        let port = MockPort::default();
+        let mut human = makcu_human(&port);
+        port.reply(b">>>\r\n").reply(b">>>\r\n");
+        human.mouse_pan(3);
+        human.mouse_tilt(-2);
+        assert_eq!(port.written(), ".pan(3)\r\n.tilt(-2)\r\n");
+
+        // 帧协议驱动没有横向滚轮：不发送任何帧
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        human.mouse_pan(3);
+        assert!(sink.frames().is_empty());
+    }
+
+    #[test]
+    fn control_point_spread_grows_with_distance_when_scaled() {
+        let sink = VecSink::new();
+        let mut human = human_on(&sink);
+        human.set_config(HumanConfig { spread_reference_px: Some(400.0), ..HumanConfig::default() });
+        let mut rng = rand::rngs::StdRng::seed_from_u64(99);
+
+        // 控制点 1 相对路径 20% 处的平均偏移 (像素)
+        let mut mean_spread = |human: &HumanDriver, dist: f32| {
+            let (start, end) = ((100.0, 500.0), (100.0 + dist, 500.0));
+            let total: f32 = (0..2000)
+                .map(|_| {
+                    let (c1, _) = human.random_ctrl_points(&mut rng, start, end);
+                    let (ox, oy) = (c1.0 - (start.0 + dist * 0.2), c1.1 - start.1);
+                    assert!(ox.abs() <= 40.0 * (dist / 400.0).min(1.0) && oy.abs() <= 40.0 * (dist / 400.0).min(1.0));
+                    ox.abs()
+                })
+                .sum();
+            total / 2000.0
+        };
+
+        let near = mean_spread(&human, 100.0);
+        let mid = mean_spread(&human, 200.0);
+        let far = mean_spread(&human, 400.0);
+        let beyond = mean_spread(&human, 1200.0);
+        assert!((1.8..2.2).contains(&(mid / near)), "100px {:.2} / 200px {:.2}", near, mid);
+        assert!((1.8..2.2).contains(&(far / mid)), "200px {:.2} / 400px {:.2}", mid, far);
+        // 超过参考距离后不再放大
+        assert!((0.9..1.1).contains(&(beyond / far)), "400px {:.2} / 1200px {:.2}", far, beyond);
+
+        // 关闭距离缩放时偏移范围与距离无关
+        human.set_config(HumanConfig::default());
+        let unscaled = mean_spread(&human, 1200.0);
+        assert!((0.9..1.1).contains(&(mean_spread(&human, 400.0) / unscaled)));
+    }
+}
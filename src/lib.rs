@@ -1,7 +1,14 @@
 // src/lib.rs
 
 pub mod hardware;      // 新增：底层驱动
+pub mod hid;           // HID 键码表
+pub mod heartbeat;     // 心跳与设备健康检测
 pub mod human;         // 拟人化层
 pub mod nav;           // 视觉导航层
 pub mod tower_defense; // 业务逻辑层
-pub mod daily_routine; // 日常任务层
\ No newline at end of file
+pub mod daily_routine; // 日常任务层
+pub mod combo;         // 连招脚本
+pub mod makcu;         // MAKCU 文本协议客户端
+
+// 帧格式对外公开，便于日志/回放/其他传输通道复用
+pub use hardware::{build_frame, build_keyboard_report, EventType};
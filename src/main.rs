@@ -1,11 +1,17 @@
 // src/main.rs
 use clap::Parser;
+use log::{error, info, warn};
+use nzm_cmd::combo::{Combo, ComboRunner};
 use nzm_cmd::daily_routine::DailyRoutineApp;
 use nzm_cmd::hardware::{create_driver, DriverType, InputDriver};
+use nzm_cmd::heartbeat::spawn_heartbeat;
 use nzm_cmd::human::HumanDriver;
 use nzm_cmd::nav::{NavEngine, NavResult};
-use nzm_cmd::tower_defense::TowerDefenseApp;
+use nzm_cmd::tower_defense::{TDConfig, TowerDefenseApp};
 use screenshots::Screen;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
@@ -21,25 +27,104 @@ struct Args {
 
     #[arg(long)]
     test: Option<String>,
+
+    /// `--test combo` 使用的连招文件
+    #[arg(long, default_value = "combo.json")]
+    combo: String,
+
+    /// 日志除控制台外同时追加写入该文件，便于事后排查
+    #[arg(long)]
+    log_file: Option<String>,
+
+    /// 使用 MAKCU 文本协议驱动 (此时 --port 可填 AUTO 按 VID/PID 自动查找)
+    #[arg(long)]
+    makcu: bool,
+
+    /// 塔防演练：只打印拆除/放置/升级/视野移动的计划，不实际操作
+    #[arg(long)]
+    dry_run: bool,
+
+    /// 塔防动作日志目录：每局写入 <场景>_<时间>.actions.json，记录每个任务的按键和坐标
+    #[arg(long)]
+    action_log: Option<String>,
+}
+
+/// 同时写到控制台和日志文件
+struct Tee {
+    file: fs::File,
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stdout().write_all(buf)?;
+        self.file.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()?;
+        self.file.flush()
+    }
+}
+
+/// 设备离线时阻塞，直到心跳线程报告恢复
+fn wait_for_device(healthy: &AtomicBool) {
+    if healthy.load(Ordering::Relaxed) {
+        return;
+    }
+    warn!("⏸️ [主控] 设备离线，暂停自动化，等待心跳恢复...");
+    while !healthy.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_secs(1));
+    }
+    info!("▶️ [主控] 设备已恢复，继续自动化");
+}
+
+/// 默认输出 info 及以上级别，可用 RUST_LOG 调整 (如 RUST_LOG=debug、RUST_LOG=nzm_cmd::nav=debug)
+fn init_logger(log_file: Option<&str>) {
+    let mut builder = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    builder.format(|buf, record| {
+        writeln!(buf, "{} {:<5} {}", chrono::Local::now().format("%H:%M:%S%.3f"), record.level(), record.args())
+    });
+
+    let mut open_err = None;
+    let target = match log_file.map(|path| fs::OpenOptions::new().create(true).append(true).open(path)) {
+        Some(Ok(file)) => env_logger::Target::Pipe(Box::new(Tee { file })),
+        Some(Err(e)) => { open_err = Some(e); env_logger::Target::Stdout }
+        None => env_logger::Target::Stdout,
+    };
+    builder.target(target).init();
+
+    if let (Some(path), Some(e)) = (log_file, open_err) {
+        warn!("⚠️ 无法打开日志文件 {}: {}，仅输出到控制台", path, e);
+    }
 }
 
 fn main() {
     let args = Args::parse();
+    init_logger(args.log_file.as_deref());
 
-    println!("========================================");
-    println!("🚀 NZM_CMD 智能控制中心");
-    println!("📍 端口: {}", args.port);
+    info!("========================================");
+    info!("🚀 NZM_CMD 智能控制中心");
+    info!("📍 端口: {}", args.port);
     if let Some(t) = &args.test {
-        println!("🔧 模式: 测试 ({})", t);
+        info!("🔧 模式: 测试 ({})", t);
     } else {
-        println!("🎯 目标: {}", args.target);
+        info!("🎯 目标: {}", args.target);
     }
-    println!("========================================");
+    info!("========================================");
 
-    let (sw, sh) = (1920, 1080);
+    // 以主显示器的实际分辨率初始化驱动，配置坐标由各模块按 1920x1080 基准换算
+    let (sw, sh) = Screen::all()
+        .ok()
+        .and_then(|screens| screens.first().map(|s| (s.display_info.width as u16, s.display_info.height as u16)))
+        .filter(|&(w, h)| w > 0 && h > 0)
+        .unwrap_or((1920, 1080));
+    info!("🖥️ 屏幕分辨率: {}x{}", sw, sh);
 
     let driver_type = if args.port.to_uppercase() == "SOFT" {
         DriverType::Software
+    } else if args.makcu {
+        DriverType::Makcu
     } else {
         DriverType::Hardware
     };
@@ -47,113 +132,131 @@ fn main() {
     let driver_box: Box<dyn InputDriver> = match create_driver(driver_type, &args.port, sw, sh) {
         Ok(d) => d,
         Err(e) => {
-            println!("⚠️ 警告: 无法初始化驱动 ({})", e);
-            println!("⚠️ 尝试回退到 [软件模拟模式]...");
+            warn!("⚠️ 警告: 无法初始化驱动 ({})", e);
+            warn!("⚠️ 尝试回退到 [软件模拟模式]...");
             create_driver(DriverType::Software, "", sw, sh).unwrap()
         }
     };
 
     let driver_arc: Arc<Mutex<Box<dyn InputDriver>>> = Arc::new(Mutex::new(driver_box));
 
-    let hb = Arc::clone(&driver_arc);
-    thread::spawn(move || loop {
-        if let Ok(mut d) = hb.lock() {
-            d.heartbeat();
-        }
-        thread::sleep(Duration::from_secs(1));
-    });
+    let device_healthy = Arc::new(AtomicBool::new(true));
+    let heartbeat = spawn_heartbeat(Arc::clone(&driver_arc), Arc::clone(&device_healthy));
 
-    let human_driver = Arc::new(Mutex::new(HumanDriver::new(
-        Arc::clone(&driver_arc),
-        sw / 2,
-        sh / 2,
-    )));
+    let mut human = HumanDriver::new(Arc::clone(&driver_arc), sw / 2, sh / 2);
+    // 设备丢失期间所有处理器发出的键鼠指令都暂停，直到心跳报告恢复
+    human.set_device_health(Some(Arc::clone(&device_healthy)));
+    let human_driver = Arc::new(Mutex::new(human));
 
     let engine = Arc::new(NavEngine::new("ui_map.toml", Arc::clone(&human_driver)));
+    for problem in engine.validate_graph() {
+        warn!("⚠️ [地图检查] {}", problem);
+    }
 
     if let Some(mode) = args.test.as_deref() {
-        println!("⏳ 5秒后开始执行 [{}] 测试...", mode);
+        info!("⏳ 5秒后开始执行 [{}] 测试...", mode);
         thread::sleep(Duration::from_secs(5));
         match mode {
             "input" => run_input_test(human_driver),
             "screen" => run_screen_test(),
             "ocr" => run_ocr_test(engine),
             "scroll" => run_scroll_test(human_driver),
-            "combo" => run_combo_test(human_driver), // ✨ 新增这一行
-            _ => println!("❌ 未知测试模式"),
+            "combo" => run_combo_test(human_driver, &args.combo), // ✨ 新增这一行
+            _ => error!("❌ 未知测试模式"),
         }
+        // 先停掉心跳，main 返回时驱动才会被真正释放并松开所有键
+        heartbeat.stop();
         return;
     }
 
-    println!("✅ 引擎就绪，5秒后开始自动化循环...");
+    info!("✅ 引擎就绪，5秒后开始自动化循环...");
     thread::sleep(Duration::from_secs(5));
 
     loop {
-        println!("\n🔄 [主控] 正在导航至: {}...", args.target);
+        wait_for_device(&device_healthy);
+        info!("🔄 [主控] 正在导航至: {}...", args.target);
 
         let nav_result = engine.navigate(&args.target);
 
         match nav_result {
             NavResult::Handover(scene_id, handler_opt) => {
-                println!("⚔️ [主控] 导航成功: [{}]", scene_id);
-
-                let handler_key = handler_opt.as_deref().unwrap_or("td");
+                info!("⚔️ [主控] 导航成功: [{}]", scene_id);
+
+                // 未声明 handler 的托管节点沿用历史行为交给塔防；声明了未知 handler 则不再猜测
+                let handler_key = match handler_opt.as_deref() {
+                    Some(h) => h,
+                    None => {
+                        info!("ℹ️ [路由] 场景 [{}] 未声明 handler，默认按 'td' 处理", scene_id);
+                        "td"
+                    }
+                };
 
                 match handler_key {
                     "daily" => {
-                        println!("📅 [路由] 检测到 'daily' 标记，启动日活模块...");
+                        info!("📅 [路由] 检测到 'daily' 标记，启动日活模块...");
                         let app =
                             DailyRoutineApp::new(Arc::clone(&human_driver), Arc::clone(&engine));
                         app.run();
                     }
-                    "td" | _ => {
-                        println!("🏰 [路由] 启动塔防模块 (Handler: {})...", handler_key);
-                        let mut td_app =
-                            TowerDefenseApp::new(Arc::clone(&human_driver), Arc::clone(&engine));
+                    "td" => {
+                        info!("🏰 [路由] 启动塔防模块 (Handler: {})...", handler_key);
+                        // 存在 td_config.json 时用它覆盖默认的界面坐标
+                        let td_config = if std::path::Path::new("td_config.json").exists() {
+                            TDConfig::from_file("td_config.json").unwrap_or_else(|e| {
+                                warn!("⚠️ {}，使用默认塔防配置", e);
+                                TDConfig::default()
+                            })
+                        } else {
+                            TDConfig::default()
+                        };
+                        let mut td_app = TowerDefenseApp::with_config(
+                            Arc::clone(&human_driver),
+                            Arc::clone(&engine),
+                            td_config,
+                        );
+                        td_app.set_dry_run(args.dry_run);
+                        td_app.set_scene_id(&scene_id);
+                        if let Some(dir) = &args.action_log {
+                            let _ = fs::create_dir_all(dir);
+                            let stamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+                            let path = std::path::Path::new(dir).join(format!("{}_{}.actions.json", scene_id, stamp));
+                            td_app.enable_action_log(&path.to_string_lossy());
+                        }
 
                         let map_file = format!("{}地图.json", scene_id);
                         let strategy_file = format!("{}策略.json", scene_id);
                         let traps_file = "traps_config.json";
 
-                        println!("📂 加载配置: {} | {}", map_file, strategy_file);
+                        info!("📂 加载配置: {} | {}", map_file, strategy_file);
                         td_app.run(&map_file, &strategy_file, traps_file);
                     }
+                    unknown => {
+                        error!("❌ [路由] 未知处理器 '{}' (场景 [{}])，请检查 ui_map.toml 的 handler 字段", unknown, scene_id);
+                    }
                 }
 
-                println!("🎉 本局任务结束，5秒后重新开始循环...");
+                info!("🎉 本局任务结束，5秒后重新开始循环...");
                 thread::sleep(Duration::from_secs(5));
             }
 
             NavResult::Failed => {
-                println!("❌ [主控] 导航失败，执行重置操作 (ESC)...");
-
-                if let Ok(mut human) = human_driver.lock() {
-                    human.key_hold('\u{1B}', 100);
-
-                    if let Ok(mut dev) = human.device.lock() {
-                        dev.key_down(0x29, 0);
-                    }
-                    thread::sleep(Duration::from_millis(100));
-                    if let Ok(mut dev) = human.device.lock() {
-                        dev.key_up();
-                    }
-
-                    thread::sleep(Duration::from_millis(100));
-                    if let Ok(mut dev) = human.device.lock() {
-                        dev.key_down(0x2C, 0); // 空格键扫描码
-                    }
-                    thread::sleep(Duration::from_millis(100));
-                    if let Ok(mut dev) = human.device.lock() {
-                        dev.key_up(); 
+                error!("❌ [主控] 导航失败");
+                // 优先执行卡住界面在 ui_map.toml 中声明的 recovery，没有时才做通用重置
+                if !engine.recover() {
+                    info!("🔄 [主控] 执行通用重置操作 (ESC)...");
+                    if let Ok(mut human) = human_driver.lock() {
+                        human.tap_escape();
+                        thread::sleep(Duration::from_millis(100));
+                        human.tap_space();
                     }
                 }
 
-                println!("⏳ 等待界面重置 (3秒)...");
+                info!("⏳ 等待界面重置 (3秒)...");
                 thread::sleep(Duration::from_secs(3));
             }
 
             NavResult::Success => {
-                println!("✅ [主控] 导航到达终点，等待重置...");
+                info!("✅ [主控] 导航到达终点，等待重置...");
                 thread::sleep(Duration::from_secs(5));
             }
         }
@@ -161,9 +264,9 @@ fn main() {
 }
 
 fn run_input_test(driver: Arc<Mutex<HumanDriver>>) {
-    println!("Testing Mouse & Keyboard...");
+    info!("Testing Mouse & Keyboard...");
     if let Ok(mut d) = driver.lock() {
-        println!("-> 移动鼠标 (矩形轨迹)");
+        info!("-> 移动鼠标 (矩形轨迹)");
         let start_x = 500;
         let start_y = 500;
         d.move_to_humanly(start_x, start_y, 0.5);
@@ -172,23 +275,23 @@ fn run_input_test(driver: Arc<Mutex<HumanDriver>>) {
         d.move_to_humanly(start_x, start_y + 300, 0.5);
         d.move_to_humanly(start_x, start_y, 0.5);
 
-        println!("-> 执行点击 (Click)");
+        info!("-> 执行点击 (Click)");
         d.click_humanly(true, false, 0);
         thread::sleep(Duration::from_millis(500));
 
-        println!("-> 模拟键盘输入 'hello 123'");
+        info!("-> 模拟键盘输入 'hello 123'");
         d.type_humanly("hello 123", 60.0);
     }
-    println!("Done.");
+    info!("Done.");
 }
 
 fn run_screen_test() {
-    println!("Testing Screen Capture...");
+    info!("Testing Screen Capture...");
     let start = Instant::now();
     let screens = Screen::all().unwrap_or_default();
 
     if let Some(screen) = screens.first() {
-        println!(
+        info!(
             "-> 检测到屏幕: {}x{}",
             screen.display_info.width, screen.display_info.height
         );
@@ -196,163 +299,62 @@ fn run_screen_test() {
             Ok(image) => {
                 let path = "debug_screenshot.png";
                 image.save(path).unwrap();
-                println!(
+                info!(
                     "✅ 截图成功! 已保存至: {} (耗时 {}ms)",
                     path,
                     start.elapsed().as_millis()
                 );
             }
-            Err(e) => println!("❌ 截图失败: {}", e),
+            Err(e) => error!("❌ 截图失败: {}", e),
         }
     } else {
-        println!("❌ 未检测到显示器");
+        error!("❌ 未检测到显示器");
     }
 }
 
 fn run_ocr_test(engine: Arc<NavEngine>) {
-    println!("Testing OCR Function...");
+    info!("Testing OCR Function...");
     let rect = [100, 100, 500, 200];
-    println!("-> 正在识别区域: {:?}", rect);
+    info!("-> 正在识别区域: {:?}", rect);
     let start = Instant::now();
     let text = engine.ocr_area(rect);
 
-    println!("----------------------------------------");
-    println!("⏱️ 耗时: {} ms", start.elapsed().as_millis());
-    println!("📝 识别结果: [{}]", text);
-    println!("----------------------------------------");
+    info!("----------------------------------------");
+    info!("⏱️ 耗时: {} ms", start.elapsed().as_millis());
+    info!("📝 识别结果: [{}]", text);
+    info!("----------------------------------------");
 
     if text.is_empty() {
-        println!("⚠️ 警告: 识别结果为空，请确认该区域有文字。");
+        warn!("⚠️ 警告: 识别结果为空，请确认该区域有文字。");
     }
 }
 
 fn run_scroll_test(driver: Arc<Mutex<HumanDriver>>) {
-    println!("Testing Mouse Scroll...");
+    info!("Testing Mouse Scroll...");
     if let Ok(mut d) = driver.lock() {
-        println!("-> 向下滚动 5 格 (Scroll Down)");
+        info!("-> 向下滚动 5 格 (Scroll Down)");
         d.mouse_scroll(-5);
 
         thread::sleep(Duration::from_secs(2));
 
-        println!("-> 向上滚动 5 格 (Scroll Up)");
+        info!("-> 向上滚动 5 格 (Scroll Up)");
         d.mouse_scroll(5);
     }
-    println!("Done.");
+    info!("Done.");
 }
 
-// ✨ 新增 Combo 测试函数
-fn run_combo_test(driver: Arc<Mutex<HumanDriver>>) {
-    println!("Testing Combo Sequence (Loop)... Press Ctrl+C to stop.");
-    // 默认间隔 50ms
-    let delay = Duration::from_millis(40);
-
-    // HID 键码: b=0x05, 4=0x21, 5=0x22
-    let key_b = 0x05;
-    let key_4 = 0x20;
-    let key_5 = 0x21;
-
-    loop {
-        // 锁定 HumanDriver 以获取访问权限
-        if let Ok(mut human) = driver.lock() {
-            // 1. 鼠标左键两下
-            // (click_humanly 内部会有几十毫秒的 hold time)
-            human.click_humanly(true, false, 50);
-            thread::sleep(delay);
-            human.click_humanly(true, false, 0);
-            thread::sleep(delay);
-
-            // 2. 按 b, 按 5
-            if let Ok(mut dev) = human.device.lock() {
-                dev.key_down(key_b, 0);
-            }
-            thread::sleep(delay);
-            if let Ok(mut dev) = human.device.lock() {
-                dev.key_down(key_5, 0);
-            }
-            thread::sleep(delay);
-
-            // 3. 松 b, 松 5
-            if let Ok(mut dev) = human.device.lock() {
-                dev.key_up(); // 释放 (通常是释放所有或最后一个)
-            }
-            thread::sleep(delay);
-            if let Ok(mut dev) = human.device.lock() {
-                dev.key_up(); // 再次释放以防万一
-            }
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            // 4. 鼠标左键两下
-            human.click_humanly(true, false, 0);
-            thread::sleep(delay);
-            human.click_humanly(true, false, 0);
-            thread::sleep(delay);
-
-            // 5. 按 b, 按 4
-            if let Ok(mut dev) = human.device.lock() {
-                dev.key_down(key_b, 0);
-            }
-            thread::sleep(delay);
-            if let Ok(mut dev) = human.device.lock() {
-                dev.key_down(key_4, 0);
-            }
-            thread::sleep(delay);
-
-            // 6. 松 b, 松 4
-            if let Ok(mut dev) = human.device.lock() {
-                dev.key_up();
-            }
-            thread::sleep(delay);
-            if let Ok(mut dev) = human.device.lock() {
-                dev.key_up();
-            }
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
-            thread::sleep(delay);
+// ✨ 新增 Combo 测试函数：连招序列从 JSON 文件读取
+fn run_combo_test(driver: Arc<Mutex<HumanDriver>>, combo_file: &str) {
+    let combo = match Combo::from_file(combo_file) {
+        Ok(c) => c,
+        Err(e) => {
+            error!("❌ {}", e);
+            return;
         }
-        // 循环继续
+    };
+    info!("Testing Combo [{}] ({} 步, Loop)... Press Ctrl+C to stop.", combo.name, combo.steps.len());
+
+    if let Ok(mut human) = driver.lock() {
+        ComboRunner::run(&combo, &mut human, None);
     }
 }
@@ -0,0 +1,256 @@
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use crate::makcu::{
+    client::MakcuClient,
+    config::MakcuConfig,
+    error::{MakcuError, MakcuResult},
+    mouse::{MouseButtons, MouseControl, MouseMoveAck},
+    keyboard::{Key, KeyboardControl},
+    led::{LedTarget, LedMode, LedControl},
+};
+
+/// MakcuClient 的异步版本 (需开启 `async` feature)，命令字符串与同步版共用 MouseControl / KeyboardControl / LedControl
+/// P 默认为 tokio-serial 的串口流，也可以传入任意实现 AsyncRead + AsyncWrite 的端口
+/// 注意：异步版本无法在 Drop 中发送 `.release()`，退出前请自行调用 release
+pub struct AsyncMakcuClient<P = SerialStream> {
+    port: P,
+    config: MakcuConfig,
+    last_response: String,
+}
+
+impl AsyncMakcuClient<SerialStream> {
+    /// port_name 为空或 "AUTO" 时按 VID/PID 自动查找串口；必须在 tokio 运行时内调用
+    pub fn open(mut config: MakcuConfig) -> MakcuResult<Self> {
+        if config.port_name.is_empty() || config.port_name.eq_ignore_ascii_case("AUTO") {
+            config.port_name = MakcuClient::find_port()?;
+        }
+        let port = tokio_serial::new(&config.port_name, config.baud_rate)
+            .timeout(config.timeout_duration())
+            .open_native_async()
+            .map_err(|e| MakcuError::SerialPortError(format!(
+                "无法打开串口 {}: {}",
+                config.port_name, e
+            )))?;
+        Ok(Self::from_port(port, config))
+    }
+}
+
+impl<P: AsyncRead + AsyncWrite + Unpin> AsyncMakcuClient<P> {
+    pub fn from_port(port: P, config: MakcuConfig) -> Self {
+        Self {
+            port,
+            config,
+            last_response: String::new(),
+        }
+    }
+
+    pub async fn send_command(&mut self, command: &str) -> MakcuResult<String> {
+        let timeout = self.config.timeout_duration();
+        self.send_command_with(command, timeout).await
+    }
+
+    /// 发送命令并在 timeout 内等待 `>>>` 提示符；超时返回 TimeoutError，固件报错时返回 CommandFailed
+    pub async fn send_command_with(&mut self, command: &str, timeout: Duration) -> MakcuResult<String> {
+        self.send_command_no_wait(command).await?;
+        let response = self.read_response(timeout).await?;
+        MakcuClient::check_error(&response, &self.config.error_prefixes)?;
+        Ok(response)
+    }
+
+    pub async fn send_command_no_wait(&mut self, command: &str) -> MakcuResult<()> {
+        self.port
+            .write_all(command.as_bytes())
+            .await
+            .map_err(|e| MakcuError::CommandFailed(format!("发送命令失败: {}", e)))?;
+
+        self.port.flush().await.map_err(|e| MakcuError::CommandFailed(format!("刷新失败: {}", e)))?;
+        Ok(())
+    }
+
+    async fn read_response(&mut self, timeout: Duration) -> MakcuResult<String> {
+        let mut buffer = String::new();
+        let read = async {
+            let mut byte = [0u8; 1];
+            loop {
+                match self.port.read(&mut byte).await {
+                    Ok(0) => return Err(MakcuError::DeviceNotConnected),
+                    Ok(_) => {
+                        buffer.push(byte[0] as char);
+                        if buffer.ends_with(">>>\r\n") || buffer.ends_with(">>>\n") {
+                            return Ok(());
+                        }
+                    }
+                    Err(e) => return Err(MakcuError::SerialPortError(format!("读取回复失败: {}", e))),
+                }
+            }
+        };
+
+        match tokio::time::timeout(timeout, read).await {
+            Ok(Ok(())) => {
+                let response = buffer.trim_end_matches(">>>\r\n").trim_end_matches(">>>\n").to_string();
+                self.last_response = response.clone();
+                Ok(response)
+            }
+            Ok(Err(e)) if buffer.is_empty() => Err(e),
+            // 超时或读到一半出错时与同步版一致：已收到的部分里有报错行返回 CommandFailed，否则 TimeoutError
+            _ => {
+                MakcuClient::check_error(&buffer, &self.config.error_prefixes)?;
+                Err(MakcuError::TimeoutError)
+            }
+        }
+    }
+
+    pub fn get_last_response(&self) -> &str {
+        &self.last_response
+    }
+
+    pub fn config(&self) -> &MakcuConfig {
+        &self.config
+    }
+
+    pub async fn info(&mut self) -> MakcuResult<String> {
+        self.send_command(".info()\r\n").await
+    }
+
+    pub async fn version(&mut self) -> MakcuResult<String> {
+        self.send_command(".version()\r\n").await
+    }
+
+    pub async fn release(&mut self) -> MakcuResult<()> {
+        self.send_command_no_wait(".release()\r\n").await
+    }
+
+    pub async fn mouse_click(&mut self, button: MouseButtons, count: u8) -> MakcuResult<String> {
+        let cmd = MouseControl::build_click_command(button, count);
+        self.send_command(&cmd).await
+    }
+
+    pub async fn mouse_button(&mut self, button: MouseButtons, state: u8) -> MakcuResult<String> {
+        let cmd = MouseControl::build_set_button_command(button, state);
+        self.send_command(&cmd).await
+    }
+
+    pub async fn mouse_move(
+        &mut self,
+        dx: i16,
+        dy: i16,
+        segments: Option<u16>,
+        control_points: Option<[(i16, i16); 2]>,
+    ) -> MakcuResult<String> {
+        let cmd = MouseControl::build_move_command(dx, dy, segments, control_points)?;
+        self.send_command(&cmd).await
+    }
+
+    pub async fn mouse_moveto(
+        &mut self,
+        x: u16,
+        y: u16,
+        segments: Option<u16>,
+        control_points: Option<[(i16, i16); 2]>,
+    ) -> MakcuResult<String> {
+        let cmd = MouseControl::build_moveto_command(x, y, segments, control_points)?;
+        self.send_command(&cmd).await
+    }
+
+    /// 同 mouse_move，但解析回复
+    pub async fn mouse_move_checked(
+        &mut self,
+        dx: i16,
+        dy: i16,
+        segments: Option<u16>,
+        control_points: Option<[(i16, i16); 2]>,
+    ) -> MakcuResult<MouseMoveAck> {
+        let cmd = MouseControl::build_move_command(dx, dy, segments, control_points)?;
+        let response = self.send_command(&cmd).await?;
+        MouseControl::parse_move_ack(&cmd, &response)
+    }
+
+    pub async fn mouse_wheel(&mut self, delta: i8) -> MakcuResult<String> {
+        let cmd = MouseControl::build_wheel_command(delta);
+        self.send_command(&cmd).await
+    }
+
+    pub async fn mouse_getpos_parsed(&mut self) -> MakcuResult<(i32, i32)> {
+        let response = self.send_command(&MouseControl::build_getpos_command()).await?;
+        MouseControl::parse_getpos_response(&response)
+    }
+
+    pub async fn keyboard_down(&mut self, key: Key) -> MakcuResult<String> {
+        let cmd = KeyboardControl::build_down_command(key);
+        self.send_command(&cmd).await
+    }
+
+    pub async fn keyboard_up(&mut self, key: Key) -> MakcuResult<String> {
+        let cmd = KeyboardControl::build_up_command(key);
+        self.send_command(&cmd).await
+    }
+
+    pub async fn keyboard_press(
+        &mut self,
+        key: Key,
+        hold_ms: Option<u16>,
+        rand_ms: Option<u8>,
+    ) -> MakcuResult<String> {
+        let cmd = KeyboardControl::build_press_command(key, hold_ms, rand_ms)?;
+        self.send_command(&cmd).await
+    }
+
+    pub async fn keyboard_string(&mut self, text: &str) -> MakcuResult<String> {
+        let cmd = KeyboardControl::build_string_command(text)?;
+        self.send_command(&cmd).await
+    }
+
+    pub async fn led_set(&mut self, target: LedTarget, mode: LedMode) -> MakcuResult<String> {
+        let cmd = LedControl::build_set_command(target, mode);
+        self.send_command(&cmd).await
+    }
+
+    pub async fn led_rgb(&mut self, target: LedTarget, r: u8, g: u8, b: u8) -> MakcuResult<String> {
+        let cmd = LedControl::build_rgb_command(target, r, g, b);
+        self.send_command(&cmd).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, AsyncBufReadExt, BufReader, DuplexStream};
+
+    /// 内存管道两端：一端给客户端，另一端扮演设备
+    fn client_pair() -> (AsyncMakcuClient<DuplexStream>, BufReader<DuplexStream>) {
+        let (port, device) = duplex(256);
+        (AsyncMakcuClient::from_port(port, MakcuConfig::new("MOCK").with_timeout(50)), BufReader::new(device))
+    }
+
+    /// 设备端：读一条命令，按 reply 回复，返回收到的命令
+    async fn answer(device: &mut BufReader<DuplexStream>, reply: &[u8]) -> String {
+        let mut line = String::new();
+        device.read_line(&mut line).await.unwrap();
+        device.get_mut().write_all(reply).await.unwrap();
+        line
+    }
+
+    #[tokio::test]
+    async fn command_is_written_and_the_reply_resolves() {
+        let (mut client, mut device) = client_pair();
+        let (reply, received) = tokio::join!(
+            client.mouse_move(10, -5, None, None),
+            answer(&mut device, b"km.move(10,-5,1)\r\n>>>\r\n"),
+        );
+        assert_eq!(received, ".move(10,-5,1)\r\n");
+        assert_eq!(reply, Ok("km.move(10,-5,1)\r\n".to_string()));
+        assert_eq!(client.get_last_response(), "km.move(10,-5,1)\r\n");
+    }
+
+    #[tokio::test]
+    async fn missing_prompt_times_out_and_error_lines_fail() {
+        let (mut client, mut device) = client_pair();
+        let (reply, _) = tokio::join!(client.version(), answer(&mut device, b"km.MAKCU"));
+        assert_eq!(reply, Err(MakcuError::TimeoutError));
+
+        let (reply, _) = tokio::join!(client.info(), answer(&mut device, b"ERR: busy\r\n>>>\r\n"));
+        assert_eq!(reply, Err(MakcuError::CommandFailed("ERR: busy".to_string())));
+    }
+}
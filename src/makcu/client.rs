@@ -1,59 +1,163 @@
-use serialport::SerialPort;
+use serialport::{ClearBuffer, SerialPort, SerialPortInfo, SerialPortType};
 use std::io::Write;
-use std::thread;
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
 use std::sync::{Arc, Mutex};
 
 use crate::makcu::{
     config::MakcuConfig,
     error::{MakcuError, MakcuResult},
-    mouse::{MouseButtons, MouseControl},
+    mouse::{LockDirection, LockState, MouseAxis, MouseButtons, MouseControl, MouseMoveAck},
     keyboard::{Key, KeyboardControl},
-    led::{LedTarget, LedMode, LedControl},
+    led::{self, LedTarget, LedMode, LedControl},
+    stream::{FrameScanner, StreamEvent, StreamParser},
 };
 
+/// MAKCU 使用的 USB 串口芯片 (CH343) 的 VID/PID
+pub const MAKCU_VID: u16 = 0x1A86;
+pub const MAKCU_PID: u16 = 0x55D3;
+
+/// toggle_dtr 时 DTR/RTS 保持低电平的时长
+const DTR_PULSE: Duration = Duration::from_millis(50);
+/// 复位或清空缓冲区后等待板子稳定的时长
+const OPEN_SETTLE: Duration = Duration::from_millis(100);
+/// 发送 .baud() 后等待固件切换波特率的时长
+const BAUD_SETTLE: Duration = Duration::from_millis(50);
+
 pub struct MakcuClient {
     port: Box<dyn SerialPort>,
     config: MakcuConfig,
     response_buffer: Arc<Mutex<String>>,
+    // 流监听线程的停止标志和句柄，None 表示未在监听；监听期间串口只归该线程读取
+    stream_listener: Mutex<Option<(Arc<AtomicBool>, JoinHandle<()>)>>,
 }
 
 impl MakcuClient {
-    pub fn new(config: MakcuConfig) -> MakcuResult<Self> {
-        let port = serialport::new(&config.port_name, config.baud_rate)
+    /// port_name 为空或 "AUTO" 时按 VID/PID 自动查找串口
+    pub fn new(mut config: MakcuConfig) -> MakcuResult<Self> {
+        if config.port_name.is_empty() || config.port_name.eq_ignore_ascii_case("AUTO") {
+            config.port_name = Self::find_port()?;
+        }
+        let mut port = serialport::new(&config.port_name, config.baud_rate)
             .timeout(config.timeout_duration())
             .open()
             .map_err(|e| MakcuError::SerialPortError(format!(
                 "无法打开串口 {}: {}",
                 config.port_name, e
             )))?;
+        Self::prepare_port(port.as_mut(), &config)?;
+        Ok(Self::from_port(port, config))
+    }
 
-        Ok(Self {
+    /// 用已打开的串口构造客户端，不做 DTR 复位和清空缓冲区
+    pub fn from_port(port: Box<dyn SerialPort>, config: MakcuConfig) -> Self {
+        Self {
             port,
             config,
             response_buffer: Arc::new(Mutex::new(String::new())),
-        })
+            stream_listener: Mutex::new(None),
+        }
+    }
+
+    /// 按配置整理刚打开的串口：先 DTR/RTS 复位，等待稳定后再清空缓冲区，
+    /// 这样复位期间吐出的乱码也会被清掉，第一条命令不会读到垃圾回复
+    fn prepare_port(port: &mut dyn SerialPort, config: &MakcuConfig) -> MakcuResult<()> {
+        let serial_err = |e: serialport::Error| MakcuError::SerialPortError(format!("初始化串口失败: {}", e));
+        if config.toggle_dtr {
+            port.write_data_terminal_ready(false).map_err(serial_err)?;
+            port.write_request_to_send(false).map_err(serial_err)?;
+            thread::sleep(DTR_PULSE);
+            port.write_data_terminal_ready(true).map_err(serial_err)?;
+            port.write_request_to_send(true).map_err(serial_err)?;
+        }
+        if config.toggle_dtr || config.clear_on_open {
+            thread::sleep(OPEN_SETTLE);
+        }
+        if config.clear_on_open {
+            port.clear(ClearBuffer::All).map_err(serial_err)?;
+        }
+        Ok(())
+    }
+
+    /// 枚举系统串口，返回第一个 VID/PID 与 MAKCU 匹配的端口名
+    pub fn find_port() -> MakcuResult<String> {
+        let ports = serialport::available_ports()
+            .map_err(|e| MakcuError::SerialPortError(format!("无法枚举串口: {}", e)))?;
+        Self::select_port(&ports).ok_or(MakcuError::DeviceNotConnected)
+    }
+
+    /// 自动查找串口并生成默认配置
+    pub fn autodetect() -> MakcuResult<MakcuConfig> {
+        Ok(MakcuConfig::new(&Self::find_port()?))
+    }
+
+    fn select_port(ports: &[SerialPortInfo]) -> Option<String> {
+        ports
+            .iter()
+            .find(|p| matches!(&p.port_type, SerialPortType::UsbPort(usb) if usb.vid == MAKCU_VID && usb.pid == MAKCU_PID))
+            .map(|p| p.port_name.clone())
     }
 
     pub fn send_command(&mut self, command: &str) -> MakcuResult<String> {
-        self.port
-            .write_all(command.as_bytes())
-            .map_err(|e| MakcuError::CommandFailed(format!("发送命令失败: {}", e)))?;
+        let timeout = self.config.timeout_duration();
+        self.send_command_with(command, timeout, 0)
+    }
 
-        self.port.flush().map_err(|e| MakcuError::CommandFailed(format!("刷新失败: {}", e)))?;
+    /// 发送命令并在 timeout 内等待回复；回复为空或超时 (TimeoutError) 时最多重发 retries 次，间隔按 20ms 起指数退避
+    /// 固件明确报错 (CommandFailed) 时不重试
+    pub fn send_command_with(&mut self, command: &str, timeout: Duration, retries: u8) -> MakcuResult<String> {
+        self.ensure_not_streaming()?;
+        let mut backoff = Duration::from_millis(20);
+        let mut attempt = 0;
+        loop {
+            self.port
+                .write_all(command.as_bytes())
+                .map_err(|e| MakcuError::CommandFailed(format!("发送命令失败: {}", e)))?;
+
+            self.port.flush().map_err(|e| MakcuError::CommandFailed(format!("刷新失败: {}", e)))?;
+
+            thread::sleep(Duration::from_millis(10));
+
+            match self.read_response(timeout) {
+                Ok(response) if !response.trim().is_empty() || attempt >= retries => {
+                    Self::check_error(&response, &self.config.error_prefixes)?;
+                    return Ok(response);
+                }
+                Ok(_) => {}
+                Err(MakcuError::TimeoutError) if attempt < retries => {}
+                Err(e) => return Err(e),
+            }
 
-        thread::sleep(Duration::from_millis(10));
+            attempt += 1;
+            thread::sleep(backoff);
+            backoff *= 2;
+        }
+    }
 
-        self.read_response()
+    /// 回复中有一行以错误前缀开头时返回 CommandFailed
+    pub(crate) fn check_error(response: &str, prefixes: &[String]) -> MakcuResult<()> {
+        let error_line = response
+            .lines()
+            .map(str::trim)
+            .find(|line| prefixes.iter().any(|p| !p.is_empty() && line.starts_with(p.as_str())));
+        match error_line {
+            Some(line) => Err(MakcuError::CommandFailed(line.to_string())),
+            None => Ok(()),
+        }
     }
 
-    fn read_response(&self) -> MakcuResult<String> {
+    /// 读到 `>>>` 提示符为止，返回提示符之前的内容
+    /// timeout 内没等到提示符时返回 TimeoutError；已收到的部分里有固件报错行时返回 CommandFailed
+    fn read_response(&mut self, timeout: Duration) -> MakcuResult<String> {
         let mut buffer = String::new();
         let start = std::time::Instant::now();
 
-        while start.elapsed() < self.config.timeout_duration() {
+        while start.elapsed() < timeout {
             let mut byte = [0u8; 1];
             match self.port.read(&mut byte) {
+                Ok(0) => continue,
                 Ok(_) => {
                     let ch = byte[0] as char;
                     buffer.push(ch);
@@ -72,7 +176,8 @@ impl MakcuClient {
             }
         }
 
-        Ok(buffer)
+        Self::check_error(&buffer, &self.config.error_prefixes)?;
+        Err(MakcuError::TimeoutError)
     }
 
     pub fn send_command_no_wait(&mut self, command: &str) -> MakcuResult<()> {
@@ -102,6 +207,17 @@ impl MakcuClient {
         Ok(())
     }
 
+    /// 读取一个以 head 开头、tail 结尾的二进制帧，不等待 `>>>` 提示符
+    /// 帧前的噪声被丢弃；max_len 为单帧最大长度 (含帧头帧尾)，config 超时内没有完整帧时返回 TimeoutError
+    pub fn read_exact_frame(&mut self, head: u8, tail: u8, max_len: usize) -> MakcuResult<Vec<u8>> {
+        self.ensure_not_streaming()?;
+        if max_len < 2 {
+            return Err(MakcuError::InvalidParameter("帧长度至少为2 (帧头+帧尾)".to_string()));
+        }
+        let deadline = std::time::Instant::now() + self.config.timeout_duration();
+        FrameScanner::new(head, tail, max_len).read_frame(&mut self.port, deadline)
+    }
+
     pub fn help(&mut self) -> MakcuResult<String> {
         self.send_command(".help()\r\n")
     }
@@ -154,6 +270,46 @@ impl MakcuClient {
         self.send_command(&cmd)
     }
 
+    /// 切换波特率并确认生效：发送 .baud(rate) 后把主机端串口也切到 rate，再用 .info() 验证回复
+    /// 回复为空或含乱码时，按新速率让固件切回原波特率、主机端同步回退，并返回错误
+    pub fn set_baud_verified(&mut self, rate: u32) -> MakcuResult<String> {
+        let old = self.config.baud_rate;
+        self.send_command_no_wait(&format!(".baud({})\r\n", rate))?;
+        thread::sleep(BAUD_SETTLE);
+        self.set_host_baud(rate)?;
+
+        let reason = match self.info() {
+            Ok(reply) if Self::is_sane_reply(&reply) => {
+                self.config.baud_rate = rate;
+                return Ok(reply);
+            }
+            Ok(reply) if reply.trim().is_empty() => "无回复".to_string(),
+            Ok(reply) => format!("回复异常 {:?}", reply),
+            Err(e) => e.to_string(),
+        };
+
+        // 固件可能已经切到新速率：先按新速率让它切回去，再恢复主机端
+        let _ = self.send_command_no_wait(&format!(".baud({})\r\n", old));
+        thread::sleep(BAUD_SETTLE);
+        self.set_host_baud(old)?;
+        let _ = self.port.clear(ClearBuffer::All);
+        Err(MakcuError::CommandFailed(format!("切换波特率到 {} 失败 ({})，已回退到 {}", rate, reason, old)))
+    }
+
+    /// 只修改主机端串口的波特率，并丢弃切换前残留的字节
+    fn set_host_baud(&mut self, rate: u32) -> MakcuResult<()> {
+        self.port
+            .set_baud_rate(rate)
+            .map_err(|e| MakcuError::SerialPortError(format!("无法切换主机波特率到 {}: {}", rate, e)))?;
+        let _ = self.port.clear(ClearBuffer::Input);
+        Ok(())
+    }
+
+    /// 波特率不匹配时读到的是随机字节，正常回复只包含可打印 ASCII 和空白
+    fn is_sane_reply(reply: &str) -> bool {
+        !reply.trim().is_empty() && reply.chars().all(|c| c.is_ascii_graphic() || c.is_ascii_whitespace())
+    }
+
     pub fn bypass(&mut self, mode: Option<u8>) -> MakcuResult<String> {
         let cmd = match mode {
             Some(m) => format!(".bypass({})\r\n", m),
@@ -222,6 +378,29 @@ impl MakcuClient {
         self.send_command(&cmd)
     }
 
+    /// 查询按键当前是否按下
+    pub fn mouse_button_state(&mut self, button: MouseButtons) -> MakcuResult<bool> {
+        let response = self.send_command(&MouseControl::build_get_button_command(button))?;
+        Self::parse_flag(&response)
+    }
+
+    /// 解析 0/1 状态回复，支持 `1`、`left(1)`、`km.left(1)` 等形式，取最后一个非空行
+    fn parse_flag(response: &str) -> MakcuResult<bool> {
+        let malformed = || MakcuError::ParseError(format!("无法解析状态: {:?}", response));
+        let line = response.lines().map(str::trim).rfind(|l| !l.is_empty()).ok_or_else(malformed)?;
+        let line = line.trim_start_matches("km.");
+        let value = match line.split_once('(') {
+            Some((_, rest)) => rest.strip_suffix(')').ok_or_else(malformed)?,
+            None => line,
+        };
+        // 带参数的回显 (如 `isdown(a,1)`) 以最后一个参数为状态
+        match value.rsplit(',').next().unwrap_or(value).trim() {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            _ => Err(malformed()),
+        }
+    }
+
     pub fn mouse_click(&mut self, button: MouseButtons, count: u8) -> MakcuResult<String> {
         let cmd = MouseControl::build_click_command(button, count);
         self.send_command(&cmd)
@@ -278,6 +457,38 @@ impl MakcuClient {
         self.send_command(&cmd)
     }
 
+    /// 沿自动生成的拟人化弧线相对移动 (分段数和控制点见 MouseControl::humanized_arc)
+    pub fn mouse_move_arc(&mut self, dx: i16, dy: i16) -> MakcuResult<String> {
+        let (segments, ctrl) = MouseControl::humanized_arc(dx, dy, &mut rand::thread_rng());
+        self.mouse_move(dx, dy, Some(segments), Some(ctrl))
+    }
+
+    /// 同 mouse_move，但解析回复：固件报错 (如位移超出 HID 范围) 时返回 Err
+    pub fn mouse_move_checked(
+        &mut self,
+        dx: i16,
+        dy: i16,
+        segments: Option<u16>,
+        control_points: Option<[(i16, i16); 2]>,
+    ) -> MakcuResult<MouseMoveAck> {
+        let cmd = MouseControl::build_move_command(dx, dy, segments, control_points)?;
+        let response = self.send_command(&cmd)?;
+        MouseControl::parse_move_ack(&cmd, &response)
+    }
+
+    /// 同 mouse_moveto，但解析回复
+    pub fn mouse_moveto_checked(
+        &mut self,
+        x: u16,
+        y: u16,
+        segments: Option<u16>,
+        control_points: Option<[(i16, i16); 2]>,
+    ) -> MakcuResult<MouseMoveAck> {
+        let cmd = MouseControl::build_moveto_command(x, y, segments, control_points)?;
+        let response = self.send_command(&cmd)?;
+        MouseControl::parse_move_ack(&cmd, &response)
+    }
+
     pub fn mouse_wheel(&mut self, delta: i8) -> MakcuResult<String> {
         let cmd = MouseControl::build_wheel_command(delta);
         self.send_command(&cmd)
@@ -298,11 +509,84 @@ impl MakcuClient {
         self.send_command(&cmd)
     }
 
+    /// 同 mouse_getpos，但把回显解析为坐标
+    pub fn mouse_getpos_parsed(&mut self) -> MakcuResult<(i32, i32)> {
+        let response = self.mouse_getpos()?;
+        MouseControl::parse_getpos_response(&response)
+    }
+
     pub fn mouse_silent(&mut self, x: u16, y: u16) -> MakcuResult<String> {
         let cmd = MouseControl::build_silent_command(x, y);
         self.send_command(&cmd)
     }
 
+    pub fn mouse_catch(&mut self, button: MouseButtons, mode: u8) -> MakcuResult<String> {
+        let cmd = MouseControl::build_catch_command(button, mode);
+        self.send_command(&cmd)
+    }
+
+    /// 硬件层按键重映射，例如把侧键映射为左键
+    pub fn mouse_remap_button(&mut self, src: MouseButtons, dst: MouseButtons) -> MakcuResult<String> {
+        let cmd = MouseControl::build_remap_button_command(src, dst);
+        self.send_command(&cmd)
+    }
+
+    pub fn mouse_reset_button_remap(&mut self) -> MakcuResult<String> {
+        let cmd = MouseControl::build_reset_button_remap_command();
+        self.send_command(&cmd)
+    }
+
+    pub fn mouse_remap_axis(&mut self, invert_x: bool, invert_y: bool, swap_xy: bool) -> MakcuResult<String> {
+        let cmd = MouseControl::build_remap_axis_command(invert_x, invert_y, swap_xy);
+        self.send_command(&cmd)
+    }
+
+    pub fn mouse_reset_axis_remap(&mut self) -> MakcuResult<String> {
+        let cmd = MouseControl::build_reset_axis_remap_command();
+        self.send_command(&cmd)
+    }
+
+    /// 锁定/解锁某个轴 (可只锁一个方向)，state 为 None 时仅查询；返回设备回报的锁定状态
+    pub fn mouse_lock_axis(
+        &mut self,
+        axis: MouseAxis,
+        direction: LockDirection,
+        state: Option<LockState>,
+    ) -> MakcuResult<LockState> {
+        let cmd = match state {
+            Some(s) => MouseControl::build_lock_axis_command(axis, direction, s),
+            None => MouseControl::build_lock_axis_query_command(axis, direction),
+        };
+        if cmd.is_empty() {
+            return Err(MakcuError::InvalidParameter(format!("{:?} 轴不支持锁定", axis)));
+        }
+        let response = self.send_command(&cmd)?;
+        Self::resolve_lock_state(&response, state)
+    }
+
+    /// 锁定/解锁某个按键，state 为 None 时仅查询；返回设备回报的锁定状态
+    pub fn mouse_lock_button(
+        &mut self,
+        button: MouseButtons,
+        state: Option<LockState>,
+    ) -> MakcuResult<LockState> {
+        let cmd = match state {
+            Some(s) => MouseControl::build_lock_button_command(button, s),
+            None => MouseControl::build_lock_button_query_command(button),
+        };
+        let response = self.send_command(&cmd)?;
+        Self::resolve_lock_state(&response, state)
+    }
+
+    // 设置命令的回显里不一定带状态，此时以请求的状态为准；查询命令必须能解析出状态
+    fn resolve_lock_state(response: &str, requested: Option<LockState>) -> MakcuResult<LockState> {
+        match (MouseControl::parse_lock_response(response), requested) {
+            (Some(s), _) => Ok(s),
+            (None, Some(s)) => Ok(s),
+            (None, None) => Err(MakcuError::ParseError(format!("无法解析锁定状态: {:?}", response))),
+        }
+    }
+
     pub fn keyboard_down(&mut self, key: Key) -> MakcuResult<String> {
         let cmd = KeyboardControl::build_down_command(key);
         self.send_command(&cmd)
@@ -313,6 +597,26 @@ impl MakcuClient {
         self.send_command(&cmd)
     }
 
+    /// 组合键 (如 Ctrl+Shift+Esc)：按顺序按下再逆序松开
+    /// 中途发送失败时，已按下的键会由 ComboGuard 逆序松开，不会留下卡住的修饰键
+    pub fn keyboard_combo(&mut self, keys: &[Key]) -> MakcuResult<String> {
+        if keys.is_empty() {
+            return Err(MakcuError::InvalidParameter("组合键不能为空".to_string()));
+        }
+        let mut guard = ComboGuard { client: self, pressed: Vec::with_capacity(keys.len()) };
+        let mut output = String::new();
+
+        for key in keys {
+            output.push_str(&guard.client.keyboard_down(*key)?);
+            guard.pressed.push(*key);
+        }
+        while let Some(&key) = guard.pressed.last() {
+            output.push_str(&guard.client.keyboard_up(key)?);
+            guard.pressed.pop();
+        }
+        Ok(output)
+    }
+
     pub fn keyboard_press(
         &mut self,
         key: Key,
@@ -338,6 +642,12 @@ impl MakcuClient {
         self.send_command(&cmd)
     }
 
+    /// 同 keyboard_isdown，但把回复解析为是否按下
+    pub fn keyboard_isdown_bool(&mut self, key: Key) -> MakcuResult<bool> {
+        let response = self.keyboard_isdown(key)?;
+        Self::parse_flag(&response)
+    }
+
     pub fn keyboard_disable(&mut self, keys: Vec<Key>) -> MakcuResult<String> {
         let cmd = KeyboardControl::build_disable_command(keys);
         self.send_command(&cmd)
@@ -373,11 +683,23 @@ impl MakcuClient {
         self.send_command(&cmd)
     }
 
+    /// 以二进制帧形式查询 LED 状态 (固件回复 0xDE…0xAD 帧而不是文本时使用)，返回含帧头帧尾的原始帧
+    pub fn led_query_frame(&mut self, target: LedTarget) -> MakcuResult<Vec<u8>> {
+        let cmd = LedControl::build_query_command(target);
+        self.send_command_no_wait(&cmd)?;
+        self.read_exact_frame(led::FRAME_HEAD, led::FRAME_TAIL, led::FRAME_MAX_LEN)
+    }
+
     pub fn led_set(&mut self, target: LedTarget, mode: LedMode) -> MakcuResult<String> {
         let cmd = LedControl::build_set_command(target, mode);
         self.send_command(&cmd)
     }
 
+    pub fn led_rgb(&mut self, target: LedTarget, r: u8, g: u8, b: u8) -> MakcuResult<String> {
+        let cmd = LedControl::build_rgb_command(target, r, g, b);
+        self.send_command(&cmd)
+    }
+
     pub fn led_blink(
         &mut self,
         target: LedTarget,
@@ -388,6 +710,41 @@ impl MakcuClient {
         self.send_command(&cmd)
     }
 
+    /// 流监听线程与 read_response 同时读串口会互相抢走字节，监听期间拒绝需要读回复的操作
+    fn ensure_not_streaming(&self) -> MakcuResult<()> {
+        if self.stream_listener.lock().unwrap().is_some() {
+            return Err(MakcuError::SerialPortError("串口正被流监听线程读取，请先调用 stop_stream_listener".to_string()));
+        }
+        Ok(())
+    }
+
+    /// 启动后台线程读取流模式上报 (需先调用 stream_* 开启)，解析后的事件通过通道送出
+    /// 重复调用会先停止上一个监听线程；监听期间 send_command 等需要读回复的方法返回 SerialPortError
+    pub fn start_stream_listener(&self) -> MakcuResult<Receiver<StreamEvent>> {
+        self.stop_stream_listener();
+
+        let reader = self
+            .port
+            .try_clone()
+            .map_err(|e| MakcuError::SerialPortError(format!("无法复制串口句柄: {}", e)))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+
+        let thread_stop = Arc::clone(&stop);
+        let handle = thread::spawn(move || StreamParser::run(reader, tx, thread_stop));
+
+        *self.stream_listener.lock().unwrap() = Some((stop, handle));
+        Ok(rx)
+    }
+
+    /// 停止监听线程并等待它退出 (最多等一次读取超时)，返回后串口重新可用于收发命令
+    pub fn stop_stream_listener(&self) {
+        if let Some((stop, handle)) = self.stream_listener.lock().unwrap().take() {
+            stop.store(true, Ordering::Relaxed);
+            let _ = handle.join();
+        }
+    }
+
     pub fn stream_keyboard(&mut self, mode: u8, period: u16) -> MakcuResult<String> {
         let cmd = format!(".keyboard({},{})\r\n", mode, period);
         self.send_command(&cmd)
@@ -409,9 +766,336 @@ impl MakcuClient {
     }
 }
 
+/// 组合键执行期间记录已按下的键，提前返回时逆序松开
+struct ComboGuard<'a> {
+    client: &'a mut MakcuClient,
+    pressed: Vec<Key>,
+}
+
+impl Drop for ComboGuard<'_> {
+    fn drop(&mut self) {
+        while let Some(key) = self.pressed.pop() {
+            let _ = self.client.send_command_no_wait(&KeyboardControl::build_up_command(key));
+        }
+    }
+}
+
 impl Drop for MakcuClient {
     fn drop(&mut self) {
+        self.stop_stream_listener();
         let _ = self.port.write_all(b".release()\r\n");
         let _ = self.port.flush();
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serialport::{DataBits, FlowControl, Parity, StopBits};
+    use std::collections::VecDeque;
+    use std::io::{self, Read};
+
+    /// 脚本化的假串口：每写入一条以换行结尾的命令，就把下一段预设回复放进接收缓冲区
+    /// 接收缓冲区为空时读取返回 TimedOut，与真实串口读超时一致；克隆出的句柄共享同一份状态
+    #[derive(Clone, Default)]
+    pub(crate) struct MockPort {
+        written: Arc<Mutex<Vec<u8>>>,
+        replies: Arc<Mutex<VecDeque<Vec<u8>>>>,
+        rx: Arc<Mutex<VecDeque<u8>>>,
+        baud: Arc<Mutex<u32>>,
+        // 打开串口后的线路操作 (DTR/RTS 电平、清空缓冲区)，按调用顺序记录
+        line_ops: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MockPort {
+        pub(crate) fn reply(&self, bytes: &[u8]) -> &Self {
+            self.replies.lock().unwrap().push_back(bytes.to_vec());
+            self
+        }
+
+        /// 直接放入接收缓冲区，模拟设备主动上报
+        fn feed(&self, bytes: &[u8]) {
+            self.rx.lock().unwrap().extend(bytes);
+        }
+
+        pub(crate) fn written(&self) -> String {
+            String::from_utf8_lossy(&self.written.lock().unwrap()).into_owned()
+        }
+
+        fn line_ops(&self) -> Vec<String> {
+            self.line_ops.lock().unwrap().clone()
+        }
+    }
+
+    impl Read for MockPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut rx = self.rx.lock().unwrap();
+            if rx.is_empty() {
+                drop(rx);
+                thread::sleep(Duration::from_millis(1));
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "mock timeout"));
+            }
+            let n = buf.len().min(rx.len());
+            for (dst, src) in buf.iter_mut().zip(rx.drain(..n)) {
+                *dst = src;
+            }
+            Ok(n)
+        }
+    }
+
+    impl Write for MockPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.lock().unwrap().extend_from_slice(buf);
+            if buf.ends_with(b"\n") {
+                if let Some(reply) = self.replies.lock().unwrap().pop_front() {
+                    self.feed(&reply);
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SerialPort for MockPort {
+        fn name(&self) -> Option<String> { Some("MOCK".to_string()) }
+        fn baud_rate(&self) -> serialport::Result<u32> { Ok(*self.baud.lock().unwrap()) }
+        fn data_bits(&self) -> serialport::Result<DataBits> { Ok(DataBits::Eight) }
+        fn flow_control(&self) -> serialport::Result<FlowControl> { Ok(FlowControl::None) }
+        fn parity(&self) -> serialport::Result<Parity> { Ok(Parity::None) }
+        fn stop_bits(&self) -> serialport::Result<StopBits> { Ok(StopBits::One) }
+        fn timeout(&self) -> Duration { Duration::from_millis(1) }
+        fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+            *self.baud.lock().unwrap() = baud_rate;
+            Ok(())
+        }
+        fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> { Ok(()) }
+        fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> { Ok(()) }
+        fn set_parity(&mut self, _: Parity) -> serialport::Result<()> { Ok(()) }
+        fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> { Ok(()) }
+        fn set_timeout(&mut self, _: Duration) -> serialport::Result<()> { Ok(()) }
+        fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+            self.line_ops.lock().unwrap().push(format!("rts={}", level));
+            Ok(())
+        }
+        fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+            self.line_ops.lock().unwrap().push(format!("dtr={}", level));
+            Ok(())
+        }
+        fn read_clear_to_send(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_data_set_ready(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_ring_indicator(&mut self) -> serialport::Result<bool> { Ok(false) }
+        fn read_carrier_detect(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn bytes_to_read(&self) -> serialport::Result<u32> { Ok(self.rx.lock().unwrap().len() as u32) }
+        fn bytes_to_write(&self) -> serialport::Result<u32> { Ok(0) }
+        fn clear(&self, _: ClearBuffer) -> serialport::Result<()> {
+            self.line_ops.lock().unwrap().push("clear".to_string());
+            self.rx.lock().unwrap().clear();
+            Ok(())
+        }
+        fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> { Ok(Box::new(self.clone())) }
+        fn set_break(&self) -> serialport::Result<()> { Ok(()) }
+        fn clear_break(&self) -> serialport::Result<()> { Ok(()) }
+    }
+
+    fn client_on(port: &MockPort) -> MakcuClient {
+        MakcuClient::from_port(Box::new(port.clone()), MakcuConfig::new("MOCK").with_timeout(50))
+    }
+
+    #[test]
+    fn open_flags_decide_the_dtr_reset_and_buffer_clear() {
+        let prepare = |config: MakcuConfig| {
+            let port = MockPort::default();
+            port.feed(b"\x00garbage\xff");
+            MakcuClient::prepare_port(&mut port.clone(), &config).unwrap();
+            (port.line_ops(), port.bytes_to_read().unwrap())
+        };
+
+        // 默认与原行为一致：不碰线路也不清空
+        assert_eq!(prepare(MakcuConfig::new("MOCK")), (vec![], 9));
+        assert_eq!(
+            prepare(MakcuConfig::new("MOCK").with_toggle_dtr(true)),
+            (vec!["dtr=false".to_string(), "rts=false".into(), "dtr=true".into(), "rts=true".into()], 9)
+        );
+        assert_eq!(prepare(MakcuConfig::new("MOCK").with_clear_on_open(true)), (vec!["clear".to_string()], 0));
+        // 两者都开时先复位再清空，复位期间的乱码一并清掉
+        let (ops, pending) = prepare(MakcuConfig::new("MOCK").with_toggle_dtr(true).with_clear_on_open(true));
+        assert_eq!(ops.last().map(String::as_str), Some("clear"));
+        assert_eq!((ops.len(), pending), (5, 0));
+    }
+
+    #[test]
+    fn lock_axis_reports_the_state_the_device_echoes() {
+        let port = MockPort::default();
+        port.reply(b"lock_mx+(1)\r\n>>>\r\n").reply(b"0\r\n>>>\r\n");
+        let mut client = client_on(&port);
+
+        let state = client.mouse_lock_axis(MouseAxis::X, LockDirection::Positive, None).unwrap();
+        assert_eq!(state, LockState::Locked);
+        let state = client.mouse_lock_button(MouseButtons::Left, Some(LockState::Locked)).unwrap();
+        // 设备回报仍未锁定时以设备为准
+        assert_eq!(state, LockState::Unlocked);
+        assert_eq!(port.written(), ".lock_mx+()\r\n.lock_ml(1)\r\n");
+
+        let err = client.mouse_lock_axis(MouseAxis::Pan, LockDirection::Both, None).unwrap_err();
+        assert!(matches!(err, MakcuError::InvalidParameter(_)));
+    }
+
+    #[test]
+    fn catch_and_remap_methods_send_the_built_commands() {
+        let port = MockPort::default();
+        for _ in 0..5 {
+            port.reply(b">>>\r\n");
+        }
+        let mut client = client_on(&port);
+
+        client.mouse_catch(MouseButtons::Right, 1).unwrap();
+        client.mouse_remap_button(MouseButtons::Side1, MouseButtons::Left).unwrap();
+        client.mouse_reset_button_remap().unwrap();
+        client.mouse_remap_axis(false, true, false).unwrap();
+        client.mouse_reset_axis_remap().unwrap();
+        assert_eq!(
+            port.written(),
+            ".catch_mr(1)\r\n.remap_button(4,1)\r\n.remap_button(0)\r\n.remap_axis(0,1,0)\r\n.remap_axis(0)\r\n"
+        );
+    }
+
+    #[test]
+    fn getpos_parsed_reads_the_reply_and_keeps_the_raw_method() {
+        let port = MockPort::default();
+        port.reply(b"km.getpos(100,200)\r\n>>>\r\n").reply(b"100,200\r\n>>>\r\n");
+        let mut client = client_on(&port);
+
+        assert_eq!(client.mouse_getpos_parsed(), Ok((100, 200)));
+        assert_eq!(client.mouse_getpos().unwrap(), "100,200\r\n");
+    }
+
+    #[test]
+    fn stream_listener_owns_the_port_until_stopped() {
+        let port = MockPort::default();
+        let mut client = client_on(&port);
+        let rx = client.start_stream_listener().unwrap();
+
+        port.feed(b"km.buttons(3)\r\naxis(5,-2)\r\n>>>\r\nmouse(1,4,0,-1)\r\n");
+        let events: Vec<StreamEvent> = (0..3).map(|_| rx.recv_timeout(Duration::from_secs(1)).unwrap()).collect();
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Buttons(3),
+                StreamEvent::Axis { x: 5, y: -2, wheel: 0 },
+                StreamEvent::Mouse { buttons: 1, x: 4, y: 0, wheel: -1 },
+            ]
+        );
+
+        // 监听期间命令不能去读回复，否则会和监听线程抢字节
+        assert!(matches!(client.info(), Err(MakcuError::SerialPortError(_))));
+        assert!(port.written().is_empty());
+
+        client.stop_stream_listener();
+        port.reply(b"MAKCU\r\n>>>\r\n");
+        assert_eq!(client.info().unwrap(), "MAKCU\r\n");
+    }
+
+    #[test]
+    fn only_err_lines_count_as_firmware_errors_by_default() {
+        let prefixes = MakcuConfig::default().error_prefixes;
+        assert!(MakcuClient::check_error("?\r\n", &prefixes).is_ok());
+        assert!(MakcuClient::check_error("help: ?cmd\r\n", &prefixes).is_ok());
+        assert_eq!(
+            MakcuClient::check_error("ok\r\nERR bad arg\r\n", &prefixes),
+            Err(MakcuError::CommandFailed("ERR bad arg".to_string()))
+        );
+    }
+
+    #[test]
+    fn missing_prompt_is_a_timeout_and_is_retried() {
+        let port = MockPort::default();
+        // 第一次发送设备没有任何回应，重发后正常回复
+        port.reply(b"").reply(b"v3.2\r\n>>>\r\n");
+        let mut client = client_on(&port);
+        assert_eq!(client.send_command_with(".version()\r\n", Duration::from_millis(30), 1).unwrap(), "v3.2\r\n");
+        assert_eq!(port.written(), ".version()\r\n.version()\r\n");
+
+        // 不重试时直接超时；半截回复也不算成功
+        port.reply(b"v3.");
+        assert_eq!(client.send_command(".version()\r\n"), Err(MakcuError::TimeoutError));
+        // 没等到提示符但已经报错时按固件报错处理
+        port.reply(b"ERR unknown\r\n");
+        assert_eq!(client.send_command(".foo()\r\n"), Err(MakcuError::CommandFailed("ERR unknown".to_string())));
+    }
+
+    #[test]
+    fn combo_releases_pressed_keys_when_a_step_fails() {
+        use crate::makcu::keyboard::{ModifierKey, SystemKey};
+        let port = MockPort::default();
+        port.reply(b">>>\r\n").reply(b">>>\r\n").reply(b"ERR busy\r\n>>>\r\n");
+        let mut client = client_on(&port);
+
+        let keys = [
+            Key::Modifier(ModifierKey::LeftCtrl),
+            Key::Modifier(ModifierKey::LeftShift),
+            Key::System(SystemKey::Escape),
+        ];
+        assert_eq!(client.keyboard_combo(&keys), Err(MakcuError::CommandFailed("ERR busy".to_string())));
+        assert_eq!(
+            port.written(),
+            ".down(ctrl)\r\n.down(shift)\r\n.down(escape)\r\n.up(shift)\r\n.up(ctrl)\r\n"
+        );
+    }
+
+    #[test]
+    fn button_and_key_state_replies_parse_to_bool() {
+        let port = MockPort::default();
+        port.reply(b"1\r\n>>>\r\n")
+            .reply(b" 0 \r\n>>>\r\n")
+            .reply(b"km.left(1)\r\n>>>\r\n")
+            .reply(b"isdown(a,0)\r\n>>>\r\n")
+            .reply(b"yes\r\n>>>\r\n");
+        let mut client = client_on(&port);
+
+        assert_eq!(client.mouse_button_state(MouseButtons::Left), Ok(true));
+        assert_eq!(client.mouse_button_state(MouseButtons::Right), Ok(false));
+        assert_eq!(client.mouse_button_state(MouseButtons::Left), Ok(true));
+        assert_eq!(client.keyboard_isdown_bool(Key::Letter('a')), Ok(false));
+        assert!(matches!(client.keyboard_isdown_bool(Key::Letter('a')), Err(MakcuError::ParseError(_))));
+        assert_eq!(port.written(), ".left()\r\n.right()\r\n.left()\r\n.isdown(a)\r\n.isdown(a)\r\n");
+    }
+
+    #[test]
+    fn autodetect_picks_the_port_with_the_makcu_vid_pid() {
+        use serialport::UsbPortInfo;
+        let usb = |name: &str, vid: u16, pid: u16| SerialPortInfo {
+            port_name: name.to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid,
+                pid,
+                serial_number: None,
+                manufacturer: None,
+                product: None,
+            }),
+        };
+        let ports = [
+            SerialPortInfo { port_name: "COM1".to_string(), port_type: SerialPortType::PciPort },
+            usb("COM3", 0x1A86, 0x7523),
+            usb("COM7", MAKCU_VID, MAKCU_PID),
+            usb("COM9", MAKCU_VID, MAKCU_PID),
+        ];
+        assert_eq!(MakcuClient::select_port(&ports), Some("COM7".to_string()));
+        assert_eq!(MakcuClient::select_port(&ports[..2]), None);
+    }
+
+    #[test]
+    fn read_exact_frame_skips_noise_around_a_framed_packet() {
+        let port = MockPort::default();
+        let mut client = client_on(&port);
+        port.feed(b">>>\r\n\x01\x02");
+        port.feed(&[0xDE, 0x03, 0xFF, 0x80, 0x00, 0xAD, 0x55, 0x66]);
+
+        assert_eq!(client.read_exact_frame(0xDE, 0xAD, 16), Ok(vec![0xDE, 0x03, 0xFF, 0x80, 0x00, 0xAD]));
+        // 只剩帧尾之后的噪声，等到超时也没有完整帧
+        assert_eq!(client.read_exact_frame(0xDE, 0xAD, 16), Err(MakcuError::TimeoutError));
+        assert!(matches!(client.read_exact_frame(0xDE, 0xAD, 1), Err(MakcuError::InvalidParameter(_))));
+    }
+}
@@ -7,6 +7,12 @@ pub struct MakcuConfig {
     pub timeout_ms: u64,
     pub screen_width: u16,
     pub screen_height: u16,
+    /// 回复中任一行以这些前缀开头即视为固件报错
+    pub error_prefixes: Vec<String>,
+    /// 打开串口后清空收发缓冲区，丢弃上电/复位时残留的字节
+    pub clear_on_open: bool,
+    /// 打开串口后拉低再拉高 DTR/RTS，并等待板子稳定
+    pub toggle_dtr: bool,
 }
 
 impl Default for MakcuConfig {
@@ -17,6 +23,9 @@ impl Default for MakcuConfig {
             timeout_ms: 100,
             screen_width: 1920,
             screen_height: 1080,
+            error_prefixes: vec!["ERR".to_string()],
+            clear_on_open: false,
+            toggle_dtr: false,
         }
     }
 }
@@ -45,6 +54,21 @@ impl MakcuConfig {
         self
     }
 
+    pub fn with_error_prefixes(mut self, prefixes: &[&str]) -> Self {
+        self.error_prefixes = prefixes.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    pub fn with_clear_on_open(mut self, enable: bool) -> Self {
+        self.clear_on_open = enable;
+        self
+    }
+
+    pub fn with_toggle_dtr(mut self, enable: bool) -> Self {
+        self.toggle_dtr = enable;
+        self
+    }
+
     pub fn timeout_duration(&self) -> Duration {
         Duration::from_millis(self.timeout_ms)
     }
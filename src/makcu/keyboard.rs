@@ -1,5 +1,11 @@
+use crate::hid;
 use crate::makcu::error::{MakcuError, MakcuResult};
 
+/// `.string()` 允许的最大字符数
+const MAX_STRING_CHARS: usize = 256;
+/// 固件命令缓冲区能容纳的 `.string()` 参数字节数 (UTF-8)，中文每字 3 字节，先于字符数上限触发
+const MAX_STRING_BYTES: usize = 512;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Key {
     Letter(char),
@@ -9,6 +15,94 @@ pub enum Key {
     Modifier(ModifierKey),
 }
 
+impl Key {
+    /// 由运行时字符构造按键：字母 (不区分大小写)、数字，以及空格/回车/Tab/Esc/退格
+    pub fn from_char(c: char) -> Option<Key> {
+        match c {
+            'a'..='z' => Some(Key::Letter(c)),
+            'A'..='Z' => Some(Key::Letter(c.to_ascii_lowercase())),
+            '0'..='9' => Some(Key::Number(c)),
+            ' ' => Some(Key::System(SystemKey::Space)),
+            '\n' | '\r' => Some(Key::System(SystemKey::Enter)),
+            '\t' => Some(Key::System(SystemKey::Tab)),
+            '\u{1B}' => Some(Key::System(SystemKey::Escape)),
+            '\u{8}' => Some(Key::System(SystemKey::Backspace)),
+            _ => None,
+        }
+    }
+
+    /// USB HID 键盘用法码 (Usage ID)，与二进制帧通道使用的键码一致
+    pub fn to_hid_code(self) -> Option<u8> {
+        match self {
+            Key::Letter(c) if c.is_ascii_alphabetic() => hid::char_to_usage(c).map(|(code, _)| code),
+            Key::Number(c @ '0'..='9') => hid::char_to_usage(c).map(|(code, _)| code),
+            Key::Function(n @ 1..=12) => Some(0x3A + (n - 1)),
+            Key::Function(n @ 13..=24) => Some(0x68 + (n - 13)),
+            Key::System(s) => Some(match s {
+                SystemKey::Enter => hid::KEY_ENTER,
+                SystemKey::Escape => hid::KEY_ESCAPE,
+                SystemKey::Backspace => hid::KEY_BACKSPACE,
+                SystemKey::Tab => hid::KEY_TAB,
+                SystemKey::Space => hid::KEY_SPACE,
+                SystemKey::PrintScreen => 0x46,
+                SystemKey::ScrollLock => 0x47,
+                SystemKey::Pause => 0x48,
+                SystemKey::Insert => 0x49,
+                SystemKey::Home => 0x4A,
+                SystemKey::PageUp => 0x4B,
+                SystemKey::Delete => 0x4C,
+                SystemKey::End => 0x4D,
+                SystemKey::PageDown => 0x4E,
+                SystemKey::Right => 0x4F,
+                SystemKey::Left => 0x50,
+                SystemKey::Down => 0x51,
+                SystemKey::Up => 0x52,
+                SystemKey::NumLock => 0x53,
+            }),
+            Key::Modifier(m) => Some(match m {
+                ModifierKey::LeftCtrl => 0xE0,
+                ModifierKey::LeftShift => 0xE1,
+                ModifierKey::LeftAlt => 0xE2,
+                ModifierKey::LeftGui => 0xE3,
+                ModifierKey::RightCtrl => 0xE4,
+                ModifierKey::RightShift => 0xE5,
+                ModifierKey::RightAlt => 0xE6,
+                ModifierKey::RightGui => 0xE7,
+            }),
+            _ => None,
+        }
+    }
+
+    /// to_hid_code 的逆映射；修饰键 (0xE0~0xE7) 也能还原，无法表示的键码返回 None
+    pub fn from_hid_code(code: u8) -> Option<Key> {
+        const SYSTEM: [SystemKey; 19] = [
+            SystemKey::Enter, SystemKey::Escape, SystemKey::Backspace, SystemKey::Tab, SystemKey::Space,
+            SystemKey::PrintScreen, SystemKey::ScrollLock, SystemKey::Pause, SystemKey::Insert, SystemKey::Home,
+            SystemKey::PageUp, SystemKey::Delete, SystemKey::End, SystemKey::PageDown, SystemKey::Right,
+            SystemKey::Left, SystemKey::Down, SystemKey::Up, SystemKey::NumLock,
+        ];
+        const MODIFIERS: [ModifierKey; 8] = [
+            ModifierKey::LeftCtrl, ModifierKey::LeftShift, ModifierKey::LeftAlt, ModifierKey::LeftGui,
+            ModifierKey::RightCtrl, ModifierKey::RightShift, ModifierKey::RightAlt, ModifierKey::RightGui,
+        ];
+        match code {
+            0x04..=0x27 => hid::usage_to_char(code).and_then(Key::from_char),
+            0x3A..=0x45 => Some(Key::Function(code - 0x3A + 1)),
+            0x68..=0x73 => Some(Key::Function(code - 0x68 + 13)),
+            0xE0..=0xE7 => Some(Key::Modifier(MODIFIERS[(code - 0xE0) as usize])),
+            _ => SYSTEM.into_iter().map(Key::System).find(|k| k.to_hid_code() == Some(code)),
+        }
+    }
+}
+
+impl TryFrom<char> for Key {
+    type Error = MakcuError;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        Key::from_char(c).ok_or_else(|| MakcuError::InvalidParameter(format!("不支持的按键字符: {:?}", c)))
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SystemKey {
     Enter,
@@ -71,11 +165,41 @@ impl KeyboardControl {
         Ok(cmd)
     }
 
+    /// 组合键的完整命令序列：按顺序依次按下，再按相反顺序松开
+    pub fn build_combo_commands(keys: &[Key]) -> Vec<String> {
+        let downs = keys.iter().map(|k| Self::build_down_command(*k));
+        let ups = keys.iter().rev().map(|k| Self::build_up_command(*k));
+        downs.chain(ups).collect()
+    }
+
     pub fn build_string_command(text: &str) -> MakcuResult<String> {
-        if text.len() > 256 {
-            return Err(MakcuError::InvalidParameter(
-                "字符串长度不能超过256个字符".to_string(),
-            ));
+        if let Some(c) = text.chars().find(|c| c.is_control()) {
+            return Err(MakcuError::InvalidParameter(format!(
+                "字符串不能包含控制字符 {:?} (会破坏命令分帧)",
+                c
+            )));
+        }
+
+        // 固件没有转义语法：逗号和括号会被当成参数分隔/命令结束，反斜杠也会原样进入命令，一律拒绝
+        if let Some(c) = text.chars().find(|c| matches!(c, '\\' | ',' | '(' | ')')) {
+            return Err(MakcuError::InvalidParameter(format!(
+                "字符串不能包含 {:?} (固件会把它当作命令分隔符)，请拆开发送或改用 keyboard_press",
+                c
+            )));
+        }
+
+        let chars = text.chars().count();
+        if chars > MAX_STRING_CHARS {
+            return Err(MakcuError::InvalidParameter(format!(
+                "字符串超过字符数上限: {} 个字符 (上限 {} 个)",
+                chars, MAX_STRING_CHARS
+            )));
+        }
+        if text.len() > MAX_STRING_BYTES {
+            return Err(MakcuError::InvalidParameter(format!(
+                "字符串超过固件缓冲区字节上限: {} 字节 (UTF-8，上限 {} 字节)",
+                text.len(), MAX_STRING_BYTES
+            )));
         }
 
         Ok(format!(".string({})\r\n", text))
@@ -169,3 +293,84 @@ impl KeyboardControl {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTRL_SHIFT_ESC: [Key; 3] = [
+        Key::Modifier(ModifierKey::LeftCtrl),
+        Key::Modifier(ModifierKey::LeftShift),
+        Key::System(SystemKey::Escape),
+    ];
+
+    #[test]
+    fn keys_from_chars_map_to_hid_usage_ids() {
+        let cases = [
+            ('a', Key::Letter('a'), 0x04),
+            ('z', Key::Letter('z'), 0x1D),
+            ('0', Key::Number('0'), 0x27),
+            ('5', Key::Number('5'), 0x22),
+            (' ', Key::System(SystemKey::Space), 0x2C),
+        ];
+        for (c, key, code) in cases {
+            assert_eq!(Key::from_char(c), Some(key));
+            assert_eq!(key.to_hid_code(), Some(code));
+            // 与二进制帧通道的字符表保持一致
+            assert_eq!(crate::hid::char_to_usage(c), Some((code, 0)));
+            assert_eq!(Key::from_hid_code(code), Some(key));
+        }
+
+        assert_eq!(Key::from_char('A'), Some(Key::Letter('a')));
+        assert_eq!(Key::from_char('%'), None);
+        assert_eq!(Key::Function(5).to_hid_code(), Some(0x3E));
+        assert_eq!(Key::from_hid_code(0x3E), Some(Key::Function(5)));
+    }
+
+    #[test]
+    fn combo_presses_in_order_and_releases_in_reverse() {
+        assert_eq!(
+            KeyboardControl::build_combo_commands(&CTRL_SHIFT_ESC),
+            [
+                ".down(ctrl)\r\n",
+                ".down(shift)\r\n",
+                ".down(escape)\r\n",
+                ".up(escape)\r\n",
+                ".up(shift)\r\n",
+                ".up(ctrl)\r\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn string_rejects_framing_characters_instead_of_sending_them() {
+        for text in ["a,b)c", "(x", "a\\b"] {
+            match KeyboardControl::build_string_command(text) {
+                Err(MakcuError::InvalidParameter(msg)) => assert!(msg.contains("分隔符"), "{}", msg),
+                other => panic!("{:?} -> {:?}", text, other),
+            }
+        }
+        assert!(KeyboardControl::build_string_command("ab\r\n.release()").is_err());
+        assert_eq!(KeyboardControl::build_string_command("a.b c!").unwrap(), ".string(a.b c!)\r\n");
+    }
+
+    #[test]
+    fn string_limits_check_both_characters_and_bytes() {
+        let limit_hit = |text: &str| match KeyboardControl::build_string_command(text) {
+            Err(MakcuError::InvalidParameter(msg)) => msg,
+            other => panic!("{:?}", other),
+        };
+
+        // 字符数上限：ASCII 每字 1 字节，256 个正好
+        let ascii = "a".repeat(MAX_STRING_CHARS);
+        assert_eq!(KeyboardControl::build_string_command(&ascii).unwrap(), format!(".string({})\r\n", ascii));
+        assert!(limit_hit(&"a".repeat(MAX_STRING_CHARS + 1)).contains("字符数上限"));
+
+        // 字节上限：130 个中文 (390 字节) 照常发送，170 个 (510 字节) 仍在上限内，171 个 (513 字节) 超出
+        assert!(KeyboardControl::build_string_command(&"中".repeat(130)).is_ok());
+        let fits = "中".repeat(MAX_STRING_BYTES / 3);
+        assert!(KeyboardControl::build_string_command(&format!("{}ab", fits)).is_ok());
+        assert!(limit_hit(&format!("{}abc", fits)).contains("字节上限"));
+        assert!(limit_hit(&"中".repeat(MAX_STRING_BYTES / 3 + 1)).contains("字节上限"));
+    }
+}
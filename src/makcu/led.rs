@@ -1,7 +1,10 @@
 use crate::makcu::error::{MakcuError, MakcuResult};
 
-const FRAME_HEAD: u8 = 0xDE;
-const FRAME_TAIL: u8 = 0xAD;
+/// 二进制 LED 帧的帧头 / 帧尾
+pub const FRAME_HEAD: u8 = 0xDE;
+pub const FRAME_TAIL: u8 = 0xAD;
+/// 二进制 LED 帧的最大长度 (含帧头帧尾)
+pub const FRAME_MAX_LEN: usize = 16;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LedTarget {
@@ -21,6 +24,8 @@ pub enum LedMode {
 pub struct LedState {
     pub target: LedTarget,
     pub mode: LedMode,
+    /// 固件回复了 RGB 颜色时为 Some((r, g, b))；此时 mode 按颜色是否全黑记为 Off / On
+    pub rgb: Option<(u8, u8, u8)>,
 }
 
 pub struct LedControl;
@@ -34,6 +39,10 @@ impl LedControl {
         format!(".led({},{})\r\n", target as u8, mode as u8)
     }
 
+    pub fn build_rgb_command(target: LedTarget, r: u8, g: u8, b: u8) -> String {
+        format!(".led({},{},{},{})\r\n", target as u8, r, g, b)
+    }
+
     pub fn build_blink_command(
         target: LedTarget,
         times: u8,
@@ -67,16 +76,23 @@ impl LedControl {
         let content = response.strip_prefix("led(")?.strip_suffix(")")?;
         let parts: Vec<&str> = content.split(',').collect();
 
-        if parts.len() != 2 {
-            return None;
-        }
-
         let target = match parts[0].trim() {
             "device" | "1" => LedTarget::Device,
             "host" | "2" => LedTarget::Host,
             _ => return None,
         };
 
+        if parts.len() == 4 {
+            let r = parts[1].trim().parse().ok()?;
+            let g = parts[2].trim().parse().ok()?;
+            let b = parts[3].trim().parse().ok()?;
+            let mode = if (r, g, b) == (0, 0, 0) { LedMode::Off } else { LedMode::On };
+            return Some(LedState { target, mode, rgb: Some((r, g, b)) });
+        }
+        if parts.len() != 2 {
+            return None;
+        }
+
         let mode = match parts[1].trim() {
             "off" | "0" => LedMode::Off,
             "on" | "1" => LedMode::On,
@@ -85,6 +101,33 @@ impl LedControl {
             _ => return None,
         };
 
-        Some(LedState { target, mode })
+        Some(LedState { target, mode, rgb: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb_command_and_reply() {
+        assert_eq!(LedControl::build_rgb_command(LedTarget::Device, 255, 0, 0), ".led(1,255,0,0)\r\n");
+        assert_eq!(
+            LedControl::parse_response("led(device,255,0,0)"),
+            Some(LedState { target: LedTarget::Device, mode: LedMode::On, rgb: Some((255, 0, 0)) })
+        );
+        assert_eq!(
+            LedControl::parse_response("km.led(2,0,0,0)").map(|s| (s.mode, s.rgb)),
+            Some((LedMode::Off, Some((0, 0, 0))))
+        );
+        assert_eq!(LedControl::parse_response("led(device,256,0,0)"), None);
+    }
+
+    #[test]
+    fn mode_reply_has_no_rgb() {
+        assert_eq!(
+            LedControl::parse_response("led(host,slow_blink)"),
+            Some(LedState { target: LedTarget::Host, mode: LedMode::SlowBlink, rgb: None })
+        );
     }
 }
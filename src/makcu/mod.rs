@@ -4,10 +4,16 @@ pub mod mouse;
 pub mod keyboard;
 pub mod led;
 pub mod config;
+pub mod stream;
+#[cfg(feature = "async")]
+pub mod async_client;
 
 pub use client::MakcuClient;
+#[cfg(feature = "async")]
+pub use async_client::AsyncMakcuClient;
 pub use error::{MakcuError, MakcuResult};
-pub use mouse::{MouseButtons, MouseAxis};
-pub use keyboard::Key;
-pub use led::{LedTarget, LedMode};
+pub use mouse::{MouseButtons, MouseAxis, LockDirection, LockState, MouseMoveAck};
+pub use keyboard::{Key, SystemKey, ModifierKey};
+pub use led::{LedTarget, LedMode, LedState};
 pub use config::MakcuConfig;
+pub use stream::{FrameScanner, StreamEvent};
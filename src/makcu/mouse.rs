@@ -1,4 +1,15 @@
 use crate::makcu::error::{MakcuError, MakcuResult};
+use rand::Rng;
+use std::fmt;
+
+/// humanized_arc 每段大约覆盖的像素距离
+const ARC_PX_PER_SEGMENT: f32 = 6.0;
+/// humanized_arc 的最少分段数，保证短距离移动也有弧度
+const ARC_MIN_SEGMENTS: u16 = 4;
+/// 固件允许的最大分段数
+pub const MAX_SEGMENTS: u16 = 512;
+/// 控制点随机偏移的上限 (像素)，与 HumanDriver 的贝塞尔控制点偏移一致
+const ARC_MAX_SPREAD: f32 = 40.0;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MouseButtons {
@@ -9,6 +20,26 @@ pub enum MouseButtons {
     Side2 = 5,
 }
 
+impl MouseButtons {
+    /// 由协议中的按键编号 (1~5) 还原按键
+    pub fn from_u8(v: u8) -> Option<MouseButtons> {
+        match v {
+            1 => Some(MouseButtons::Left),
+            2 => Some(MouseButtons::Right),
+            3 => Some(MouseButtons::Middle),
+            4 => Some(MouseButtons::Side1),
+            5 => Some(MouseButtons::Side2),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MouseButtons {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(MouseControl::button_name(*self))
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MouseAxis {
     X,
@@ -31,6 +62,15 @@ pub enum LockDirection {
     Negative,
 }
 
+/// `.move()` / `.moveto()` 的确认回复
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MouseMoveAck {
+    /// 固件回复 ok
+    Ok,
+    /// 固件原样回显了命令 (已去掉换行)，同样表示已执行
+    Echo(String),
+}
+
 pub struct MouseControl;
 
 impl MouseControl {
@@ -91,9 +131,9 @@ impl MouseControl {
         control_points: Option<[(i16, i16); 2]>,
     ) -> MakcuResult<String> {
         let segments = segments.unwrap_or(1);
-        if segments > 512 {
+        if segments > MAX_SEGMENTS {
             return Err(MakcuError::InvalidParameter(
-                "分段数不能超过512".to_string(),
+                format!("分段数不能超过{}", MAX_SEGMENTS),
             ));
         }
 
@@ -110,6 +150,29 @@ impl MouseControl {
         Ok(cmd)
     }
 
+    /// 为相对移动 (dx, dy) 生成拟人化弧线参数：(分段数, 两个相对起点的控制点)
+    /// 控制点位于路径 20% / 80% 处再加随机偏移，与 HumanDriver 的轨迹相同；
+    /// 偏移幅度随距离增长 (上限 40px)，分段数约每 6px 一段，限制在 4..=512
+    pub fn humanized_arc(dx: i16, dy: i16, rng: &mut impl Rng) -> (u16, [(i16, i16); 2]) {
+        let (fx, fy) = (dx as f32, dy as f32);
+        let dist = (fx * fx + fy * fy).sqrt();
+        if dist < 1.0 {
+            return (1, [(0, 0), (dx, dy)]);
+        }
+        let segments = ((dist / ARC_PX_PER_SEGMENT).round() as u16).clamp(ARC_MIN_SEGMENTS, MAX_SEGMENTS);
+        let spread = (dist * 0.15).min(ARC_MAX_SPREAD);
+        let to_i16 = |v: f32| v.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        let ctrl1 = (
+            to_i16(fx * 0.2 + rng.gen_range(-spread..=spread)),
+            to_i16(fy * 0.2 + rng.gen_range(-spread..=spread)),
+        );
+        let ctrl2 = (
+            to_i16(fx * 0.8 + rng.gen_range(-spread * 0.5..=spread * 1.5)),
+            to_i16(fy * 0.8 + rng.gen_range(-spread * 0.5..=spread * 1.5)),
+        );
+        (segments, [ctrl1, ctrl2])
+    }
+
     pub fn build_moveto_command(
         x: u16,
         y: u16,
@@ -117,9 +180,9 @@ impl MouseControl {
         control_points: Option<[(i16, i16); 2]>,
     ) -> MakcuResult<String> {
         let segments = segments.unwrap_or(1);
-        if segments > 512 {
+        if segments > MAX_SEGMENTS {
             return Err(MakcuError::InvalidParameter(
-                "分段数不能超过512".to_string(),
+                format!("分段数不能超过{}", MAX_SEGMENTS),
             ));
         }
 
@@ -153,6 +216,49 @@ impl MouseControl {
         ".getpos()\r\n".to_string()
     }
 
+    /// 解析 `.getpos()` 的回显，支持 `x,y`、`getpos(x,y)`、`km.getpos(x,y)` 等形式，取最后一个非空行
+    pub fn parse_getpos_response(response: &str) -> MakcuResult<(i32, i32)> {
+        let malformed = || MakcuError::ParseError(format!("无法解析坐标: {:?}", response));
+
+        let line = response.lines().map(str::trim).rfind(|l| !l.is_empty()).ok_or_else(malformed)?;
+        let line = line.trim_start_matches("km.");
+        let content = match line.strip_prefix("getpos") {
+            Some(rest) => rest.trim().strip_prefix('(').and_then(|r| r.strip_suffix(')')).ok_or_else(malformed)?,
+            None => line,
+        };
+
+        let (x, y) = content.split_once(',').ok_or_else(malformed)?;
+        let x = x.trim().parse::<i32>().map_err(|_| malformed())?;
+        let y = y.trim().parse::<i32>().map_err(|_| malformed())?;
+        Ok((x, y))
+    }
+
+    /// 解析移动命令的回复：ok / 回显命令视为成功；以 error、err、invalid、fail 开头的行
+    /// (例如位移超出 HID 范围) 返回 CommandFailed；空回复视为超时，其他内容返回 ParseError
+    pub fn parse_move_ack(command: &str, response: &str) -> MakcuResult<MouseMoveAck> {
+        let lines: Vec<&str> = response.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+        if lines.is_empty() {
+            return Err(MakcuError::TimeoutError);
+        }
+
+        let is_error = |l: &str| {
+            let lower = l.to_ascii_lowercase();
+            ["error", "err", "invalid", "fail"].iter().any(|p| lower.starts_with(p))
+        };
+        if let Some(line) = lines.iter().find(|l| is_error(l)) {
+            return Err(MakcuError::CommandFailed(line.to_string()));
+        }
+
+        let sent = command.trim().trim_start_matches("km.").trim_start_matches('.');
+        if let Some(line) = lines.iter().find(|l| l.trim_start_matches("km.").trim_start_matches('.') == sent) {
+            return Ok(MouseMoveAck::Echo(line.to_string()));
+        }
+        if lines.iter().all(|l| l.eq_ignore_ascii_case("ok")) {
+            return Ok(MouseMoveAck::Ok);
+        }
+        Err(MakcuError::ParseError(format!("无法识别的移动回复: {:?}", response)))
+    }
+
     pub fn build_silent_command(x: u16, y: u16) -> String {
         format!(".silent({},{})\r\n", x, y)
     }
@@ -162,11 +268,51 @@ impl MouseControl {
         direction: LockDirection,
         state: LockState,
     ) -> String {
+        match Self::lock_axis_name(axis, direction) {
+            Some(name) => format!(".lock_{}({})\r\n", name, state as u8),
+            None => String::new(),
+        }
+    }
+
+    pub fn build_lock_axis_query_command(axis: MouseAxis, direction: LockDirection) -> String {
+        match Self::lock_axis_name(axis, direction) {
+            Some(name) => format!(".lock_{}()\r\n", name),
+            None => String::new(),
+        }
+    }
+
+    pub fn build_lock_button_command(
+        button: MouseButtons,
+        state: LockState,
+    ) -> String {
+        format!(".lock_{}({})\r\n", Self::lock_button_name(button), state as u8)
+    }
+
+    pub fn build_lock_button_query_command(button: MouseButtons) -> String {
+        format!(".lock_{}()\r\n", Self::lock_button_name(button))
+    }
+
+    /// 解析锁定查询/设置的回显，支持 `1`、`lock_mx(1)`、`km.lock_mx(1)` 等形式，取最后一行
+    pub fn parse_lock_response(response: &str) -> Option<LockState> {
+        let line = response.lines().map(str::trim).rfind(|l| !l.is_empty())?;
+        let line = line.trim_start_matches("km.");
+        let value = match line.strip_prefix("lock_") {
+            Some(rest) => rest.split_once('(')?.1.strip_suffix(')')?,
+            None => line,
+        };
+        match value.trim() {
+            "0" => Some(LockState::Unlocked),
+            "1" => Some(LockState::Locked),
+            _ => None,
+        }
+    }
+
+    fn lock_axis_name(axis: MouseAxis, direction: LockDirection) -> Option<String> {
         let axis_name = match axis {
             MouseAxis::X => "mx",
             MouseAxis::Y => "my",
             MouseAxis::Wheel => "mw",
-            MouseAxis::Pan | MouseAxis::Tilt => return String::new(),
+            MouseAxis::Pan | MouseAxis::Tilt => return None,
         };
 
         let direction_suffix = match direction {
@@ -175,25 +321,17 @@ impl MouseControl {
             LockDirection::Negative => "-",
         };
 
-        format!(
-            ".lock_{}({})\r\n",
-            format!("{}{}", axis_name, direction_suffix),
-            state as u8
-        )
+        Some(format!("{}{}", axis_name, direction_suffix))
     }
 
-    pub fn build_lock_button_command(
-        button: MouseButtons,
-        state: LockState,
-    ) -> String {
-        let button_name = match button {
+    fn lock_button_name(button: MouseButtons) -> &'static str {
+        match button {
             MouseButtons::Left => "ml",
             MouseButtons::Middle => "mm",
             MouseButtons::Right => "mr",
             MouseButtons::Side1 => "ms1",
             MouseButtons::Side2 => "ms2",
-        };
-        format!(".lock_{}({})\r\n", button_name, state as u8)
+        }
     }
 
     pub fn build_catch_command(
@@ -252,3 +390,141 @@ impl MouseControl {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn buttons_round_trip_through_their_wire_value() {
+        let all = [
+            MouseButtons::Left,
+            MouseButtons::Right,
+            MouseButtons::Middle,
+            MouseButtons::Side1,
+            MouseButtons::Side2,
+        ];
+        for b in all {
+            assert_eq!(MouseButtons::from_u8(b as u8), Some(b));
+        }
+        assert_eq!(MouseButtons::from_u8(0), None);
+        assert_eq!(MouseButtons::from_u8(6), None);
+        assert_eq!(MouseButtons::Side1.to_string(), "side1");
+    }
+
+    #[test]
+    fn lock_axis_commands_cover_every_axis_and_direction() {
+        let cases = [
+            (MouseAxis::X, LockDirection::Both, ".lock_mx(1)\r\n"),
+            (MouseAxis::X, LockDirection::Positive, ".lock_mx+(1)\r\n"),
+            (MouseAxis::X, LockDirection::Negative, ".lock_mx-(1)\r\n"),
+            (MouseAxis::Y, LockDirection::Both, ".lock_my(1)\r\n"),
+            (MouseAxis::Y, LockDirection::Positive, ".lock_my+(1)\r\n"),
+            (MouseAxis::Y, LockDirection::Negative, ".lock_my-(1)\r\n"),
+            (MouseAxis::Wheel, LockDirection::Both, ".lock_mw(1)\r\n"),
+            (MouseAxis::Wheel, LockDirection::Positive, ".lock_mw+(1)\r\n"),
+            (MouseAxis::Wheel, LockDirection::Negative, ".lock_mw-(1)\r\n"),
+        ];
+        for (axis, dir, expected) in cases {
+            assert_eq!(MouseControl::build_lock_axis_command(axis, dir, LockState::Locked), expected);
+        }
+        assert_eq!(MouseControl::build_lock_axis_command(MouseAxis::Y, LockDirection::Negative, LockState::Unlocked), ".lock_my-(0)\r\n");
+        assert_eq!(MouseControl::build_lock_axis_query_command(MouseAxis::X, LockDirection::Positive), ".lock_mx+()\r\n");
+        // pan / tilt 没有锁定命令
+        assert_eq!(MouseControl::build_lock_axis_command(MouseAxis::Pan, LockDirection::Both, LockState::Locked), "");
+        assert_eq!(MouseControl::build_lock_axis_query_command(MouseAxis::Tilt, LockDirection::Both), "");
+    }
+
+    #[test]
+    fn lock_button_commands_and_replies() {
+        assert_eq!(MouseControl::build_lock_button_command(MouseButtons::Left, LockState::Locked), ".lock_ml(1)\r\n");
+        assert_eq!(MouseControl::build_lock_button_command(MouseButtons::Side2, LockState::Unlocked), ".lock_ms2(0)\r\n");
+        assert_eq!(MouseControl::build_lock_button_query_command(MouseButtons::Middle), ".lock_mm()\r\n");
+
+        assert_eq!(MouseControl::parse_lock_response("1"), Some(LockState::Locked));
+        assert_eq!(MouseControl::parse_lock_response("lock_mx+(0)\r\n"), Some(LockState::Unlocked));
+        assert_eq!(MouseControl::parse_lock_response(".lock_ml()\r\nkm.lock_ml(1)\r\n"), Some(LockState::Locked));
+        assert_eq!(MouseControl::parse_lock_response("lock_ml(2)"), None);
+        assert_eq!(MouseControl::parse_lock_response(""), None);
+    }
+
+    #[test]
+    fn catch_and_remap_commands() {
+        assert_eq!(MouseControl::build_catch_command(MouseButtons::Left, 0), ".catch_ml(0)\r\n");
+        assert_eq!(MouseControl::build_catch_command(MouseButtons::Side1, 1), ".catch_ms1(1)\r\n");
+        assert_eq!(MouseControl::build_remap_button_command(MouseButtons::Side2, MouseButtons::Left), ".remap_button(5,1)\r\n");
+        assert_eq!(MouseControl::build_remap_axis_command(true, false, true), ".remap_axis(1,0,1)\r\n");
+        assert_eq!(MouseControl::build_remap_axis_command(false, false, false), ".remap_axis(0,0,0)\r\n");
+    }
+
+    #[test]
+    fn getpos_replies_parse_into_coordinates() {
+        assert_eq!(MouseControl::parse_getpos_response("960,540"), Ok((960, 540)));
+        assert_eq!(MouseControl::parse_getpos_response("getpos(12, -3)\r\n"), Ok((12, -3)));
+        assert_eq!(MouseControl::parse_getpos_response(".getpos()\r\nkm.getpos(1919,1079)\r\n\r\n"), Ok((1919, 1079)));
+        for bad in ["", "getpos(1)", "getpos(a,b)", "12;34", "getpos(1,2"] {
+            assert!(
+                matches!(MouseControl::parse_getpos_response(bad), Err(MakcuError::ParseError(_))),
+                "{:?} 应解析失败",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn move_replies_parse_into_ok_echo_or_error() {
+        let cmd = MouseControl::build_move_command(10, -5, None, None).unwrap();
+        assert_eq!(MouseControl::parse_move_ack(&cmd, "ok\r\n"), Ok(MouseMoveAck::Ok));
+        assert_eq!(
+            MouseControl::parse_move_ack(&cmd, "km.move(10,-5,1)\r\n"),
+            Ok(MouseMoveAck::Echo("km.move(10,-5,1)".to_string()))
+        );
+        assert_eq!(
+            MouseControl::parse_move_ack(&cmd, "move(10,-5,1)\r\nERROR: out of range\r\n"),
+            Err(MakcuError::CommandFailed("ERROR: out of range".to_string()))
+        );
+        assert_eq!(MouseControl::parse_move_ack(&cmd, "\r\n"), Err(MakcuError::TimeoutError));
+        assert!(matches!(MouseControl::parse_move_ack(&cmd, "moveto(1,2)"), Err(MakcuError::ParseError(_))));
+    }
+
+    #[test]
+    fn segment_count_is_capped_at_the_firmware_limit() {
+        assert!(MouseControl::build_move_command(10, 10, Some(MAX_SEGMENTS), None).is_ok());
+        assert!(MouseControl::build_moveto_command(10, 10, Some(MAX_SEGMENTS), None).is_ok());
+        assert_eq!(
+            MouseControl::build_move_command(10, 10, Some(MAX_SEGMENTS + 1), None),
+            Err(MakcuError::InvalidParameter("分段数不能超过512".to_string()))
+        );
+        assert!(MouseControl::build_moveto_command(10, 10, Some(MAX_SEGMENTS + 1), None).is_err());
+    }
+
+    #[test]
+    fn humanized_arc_segments_scale_with_distance_and_points_stay_near_the_path() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let segments = |dx: i16, rng: &mut rand::rngs::StdRng| MouseControl::humanized_arc(dx, 0, rng).0;
+        assert_eq!(MouseControl::humanized_arc(0, 0, &mut rng), (1, [(0, 0), (0, 0)]));
+        assert_eq!(segments(3, &mut rng), ARC_MIN_SEGMENTS);
+        assert_eq!(segments(60, &mut rng), 10);
+        assert_eq!(segments(600, &mut rng), 100);
+        assert_eq!(segments(i16::MAX, &mut rng), MAX_SEGMENTS);
+
+        for (dx, dy) in [(5, 0), (40, -30), (300, 400), (-2000, 1500), (i16::MIN, i16::MAX)] {
+            for _ in 0..200 {
+                let (n, [c1, c2]) = MouseControl::humanized_arc(dx, dy, &mut rng);
+                assert!((1..=MAX_SEGMENTS).contains(&n));
+                let (fx, fy) = (dx as f32, dy as f32);
+                let spread = ((fx * fx + fy * fy).sqrt() * 0.15).min(ARC_MAX_SPREAD) + 1.0;
+                let off1 = (c1.0 as f32 - fx * 0.2, c1.1 as f32 - fy * 0.2);
+                let off2 = (c2.0 as f32 - fx * 0.8, c2.1 as f32 - fy * 0.8);
+                assert!(off1.0.abs() <= spread && off1.1.abs() <= spread, "{:?} 的控制点 1 {:?}", (dx, dy), c1);
+                // i16 极值附近会被截断，只检查未截断的分量
+                for (off, c) in [(off2.0, c2.0), (off2.1, c2.1)] {
+                    if c != i16::MIN && c != i16::MAX {
+                        assert!(off >= -spread * 0.5 && off <= spread * 1.5, "{:?} 的控制点 2 {:?}", (dx, dy), c2);
+                    }
+                }
+            }
+        }
+    }
+}
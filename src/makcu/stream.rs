@@ -0,0 +1,133 @@
+use crate::makcu::error::{MakcuError, MakcuResult};
+use std::io::{ErrorKind, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// 设备在流模式 (`.buttons()` / `.axis()` / `.mouse()` / `.keyboard()`) 下周期上报的事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// 按键位掩码：bit0 左键、bit1 右键、bit2 中键、bit3/bit4 侧键
+    Buttons(u8),
+    Axis { x: i32, y: i32, wheel: i32 },
+    Mouse { buttons: u8, x: i32, y: i32, wheel: i32 },
+    Keyboard(String),
+    /// 无法识别的行，原样保留便于调试
+    Other(String),
+}
+
+pub struct StreamParser;
+
+impl StreamParser {
+    /// 解析一行上报，空行和 `>>>` 提示符返回 None
+    pub fn parse_line(line: &str) -> Option<StreamEvent> {
+        let line = line.trim().trim_start_matches(">>>").trim();
+        if line.is_empty() {
+            return None;
+        }
+        let body = line.trim_start_matches("km.");
+
+        let (name, args) = match body.split_once('(') {
+            Some((name, rest)) => match rest.strip_suffix(')') {
+                Some(args) => (name, args),
+                None => return Some(StreamEvent::Other(line.to_string())),
+            },
+            None => return Some(StreamEvent::Other(line.to_string())),
+        };
+        let nums: Option<Vec<i32>> = args.split(',').map(|p| p.trim().parse::<i32>().ok()).collect();
+
+        let event = match (name, nums.as_deref()) {
+            ("buttons", Some(&[mask])) => StreamEvent::Buttons(mask as u8),
+            ("axis", Some(&[x, y])) => StreamEvent::Axis { x, y, wheel: 0 },
+            ("axis", Some(&[x, y, wheel])) => StreamEvent::Axis { x, y, wheel },
+            ("mouse", Some(&[buttons, x, y, wheel])) => StreamEvent::Mouse { buttons: buttons as u8, x, y, wheel },
+            ("keyboard", _) => StreamEvent::Keyboard(args.trim().to_string()),
+            _ => StreamEvent::Other(line.to_string()),
+        };
+        Some(event)
+    }
+
+    /// 持续读取 reader 并按行解析，直到 stop 置位、接收端关闭或读取出错
+    /// 读取超时视为暂时无数据，继续等待
+    pub fn run<R: Read>(mut reader: R, tx: Sender<StreamEvent>, stop: Arc<AtomicBool>) {
+        let mut pending: Vec<u8> = Vec::new();
+        let mut chunk = [0u8; 256];
+
+        while !stop.load(Ordering::Relaxed) {
+            let n = match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock => continue,
+                Err(_) => break,
+            };
+            pending.extend_from_slice(&chunk[..n]);
+
+            while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+                let raw: Vec<u8> = pending.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&raw);
+                if let Some(event) = Self::parse_line(&line) {
+                    if tx.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 从字节流中切出以 head 开头、tail 结尾的二进制帧 (如 LED 帧 0xDE…0xAD)
+/// head 之前的字节视为噪声丢弃；累计 max_len 字节仍未见到 tail 时丢弃这半帧，从下一个 head 重新同步
+/// 协议没有长度字段，载荷中出现的第一个 tail 即视为帧尾
+pub struct FrameScanner {
+    head: u8,
+    tail: u8,
+    max_len: usize,
+    buf: Vec<u8>,
+}
+
+impl FrameScanner {
+    pub fn new(head: u8, tail: u8, max_len: usize) -> Self {
+        Self { head, tail, max_len: max_len.max(2), buf: Vec::new() }
+    }
+
+    /// 送入一个字节，凑成完整帧时返回整帧 (含 head 与 tail)
+    pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        if self.buf.is_empty() {
+            if byte == self.head {
+                self.buf.push(byte);
+            }
+            return None;
+        }
+        self.buf.push(byte);
+        if byte == self.tail {
+            return Some(std::mem::take(&mut self.buf));
+        }
+        if self.buf.len() >= self.max_len {
+            self.buf.clear();
+            if byte == self.head {
+                self.buf.push(byte);
+            }
+        }
+        None
+    }
+
+    /// 持续读取 reader 直到得到一帧；读取超时视为暂时无数据，超过 deadline 返回 TimeoutError
+    /// 与 read_response 一样逐字节读取，帧尾之后的数据留在串口缓冲区里，不会被多读走
+    pub fn read_frame<R: Read + ?Sized>(&mut self, reader: &mut R, deadline: Instant) -> MakcuResult<Vec<u8>> {
+        let mut byte = [0u8; 1];
+        while Instant::now() < deadline {
+            match reader.read(&mut byte) {
+                Ok(0) => continue,
+                Ok(_) => {
+                    if let Some(frame) = self.push(byte[0]) {
+                        return Ok(frame);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(MakcuError::SerialPortError(format!("读取帧失败: {}", e))),
+            }
+        }
+        Err(MakcuError::TimeoutError)
+    }
+}
@@ -1,20 +1,23 @@
 // src/nav.rs
 use crate::human::HumanDriver;
 use serde::Deserialize;
-use std::collections::{HashMap, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
 use std::time::{Duration, Instant};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::io::Cursor;
 
 use screenshots::Screen;
-use windows::Media::Ocr::OcrEngine;
+use windows::Media::Ocr::{OcrEngine, OcrResult};
 use windows::Globalization::Language;
 use windows::Graphics::Imaging::BitmapDecoder;
 use windows::Storage::Streams::{DataWriter, InMemoryRandomAccessStream};
+use log::{debug, error, info, warn};
+use rand::Rng;
 
 // ==========================================
 // 0. 结果枚举
@@ -27,33 +30,161 @@ pub enum NavResult {
     Failed,
 }
 
+/// OCR 识别出的单个词及其屏幕坐标 [x1, y1, x2, y2]
+/// (Windows OCR 不提供置信度，因此只返回文本和位置)
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrWord {
+    pub text: String,
+    pub rect: [i32; 4],
+}
+
+/// 配置文件默认的编写分辨率 (ui_map.toml 未填写 authored_size 时使用)
+pub const DEFAULT_AUTHORED_SIZE: [u32; 2] = [1920, 1080];
+
+/// 分辨率缩放：把按编写分辨率填写的坐标换算到实际屏幕
+/// 横纵分别计算比例，16:10 等非 16:9 屏幕也能对齐到对应位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResolutionScale {
+    pub sx: f32,
+    pub sy: f32,
+}
+
+impl ResolutionScale {
+    pub const IDENTITY: ResolutionScale = ResolutionScale { sx: 1.0, sy: 1.0 };
+
+    /// authored 为配置编写时的分辨率，actual 为实际屏幕；任一维度为 0 时该方向不缩放
+    pub fn new(authored: (f32, f32), actual: (f32, f32)) -> Self {
+        let ratio = |a: f32, b: f32| if a > 0.0 && b > 0.0 { b / a } else { 1.0 };
+        Self { sx: ratio(authored.0, actual.0), sy: ratio(authored.1, actual.1) }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    pub fn x(&self, x: f32) -> f32 { x * self.sx }
+
+    pub fn y(&self, y: f32) -> f32 { y * self.sy }
+
+    pub fn point(&self, p: [i32; 2]) -> [i32; 2] {
+        [self.x(p[0] as f32).round() as i32, self.y(p[1] as f32).round() as i32]
+    }
+
+    /// [x1, y1, x2, y2] 四个角分别取整，例如 1080p 的 [262, 16, 389, 97] 在 1440p 下为 [349, 21, 519, 129]
+    pub fn rect(&self, r: [i32; 4]) -> [i32; 4] {
+        let [x1, y1] = self.point([r[0], r[1]]);
+        let [x2, y2] = self.point([r[2], r[3]]);
+        [x1, y1, x2, y2]
+    }
+}
+
 // ==========================================
 // 1. TOML 配置数据结构
 // ==========================================
 #[derive(Deserialize, Debug, Clone)]
-struct TomlRoot { scenes: Vec<Scene> }
+struct TomlRoot {
+    // ✨ 新增：坐标编写时的分辨率 [宽, 高]，与实际屏幕不同时所有锚点和跳转坐标按比例换算
+    #[serde(default)]
+    authored_size: Option<[u32; 2]>,
+    scenes: Vec<Scene>,
+}
 
 #[derive(Deserialize, Debug, Clone)]
 struct Scene {
     id: String,
     #[serde(default)] logic: String,
+    // ✨ 新增：至少命中多少个锚点才算识别成功，填写后覆盖 logic
+    #[serde(default)]
+    min_matches: Option<usize>,
     #[serde(default)] anchors: Option<Anchors>,
+    // ✨ 新增：识别该场景时只截取这个区域 [x1, y1, x2, y2]，所有锚点必须落在其中
+    #[serde(default)]
+    scan_rect: Option<[i32; 4]>,
     #[serde(default)] transitions: Option<Vec<Transition>>,
     // ✨ 新增：处理该界面的函数代号 (例如 "daily", "td")
     #[serde(default)]
     handler: Option<String>,
+    // ✨ 新增：导航卡在该界面时执行的恢复动作 (关弹窗、点确定等)，代替主程序的 ESC+空格 通用重置
+    #[serde(default)]
+    recovery: Option<Vec<RecoveryAction>>,
+}
+
+impl Scene {
+    /// 解析 logic 得到所需的最少命中数："or" 为 1，"k:N" 为 N，"and" 或留空为全部
+    /// 无法识别时返回 None
+    fn parse_logic(&self, total: usize) -> Option<usize> {
+        let logic = self.logic.trim().to_lowercase();
+        match logic.as_str() {
+            "" | "and" => Some(total),
+            "or" => Some(1),
+            _ => logic.strip_prefix("k:").and_then(|n| n.trim().parse().ok()),
+        }
+    }
+
+    /// 判定通过所需的最少命中锚点数，min_matches 优先；结果限制在 1..=total
+    fn required_matches(&self, total: usize) -> usize {
+        let n = self.min_matches.or_else(|| self.parse_logic(total)).unwrap_or(total);
+        n.clamp(1, total.max(1))
+    }
+
+    /// 列出超出 scan_rect 的锚点描述；未设置 scan_rect 时为空
+    fn anchors_outside_scan_rect(&self) -> Vec<String> {
+        let (Some(area), Some(anchors)) = (self.scan_rect, self.anchors.as_ref()) else { return Vec::new() };
+        let inside = |r: [i32; 4]| r[0] >= area[0] && r[1] >= area[1] && r[2] <= area[2] && r[3] <= area[3];
+        let mut outside = Vec::new();
+        for t in anchors.text.iter().flatten().filter(|t| !inside(t.rect)) {
+            outside.push(format!("文字 \"{}\" {:?}", t.val, t.rect));
+        }
+        for c in anchors.color.iter().flatten() {
+            let r = c.r.unwrap_or(0) as i32;
+            let [x, y] = c.pos;
+            if !inside([x - r, y - r, x + r + 1, y + r + 1]) {
+                outside.push(format!("颜色 {} {:?}", c.val, c.pos));
+            }
+        }
+        for a in anchors.image.iter().flatten().filter(|a| !inside(a.rect)) {
+            outside.push(format!("图片 {} {:?}", a.path, a.rect));
+        }
+        outside
+    }
+
+    /// 把锚点区域、跳转坐标和 scan_rect 换算到实际分辨率；颜色采样半径保持不变
+    fn rescale(&mut self, scale: ResolutionScale) {
+        self.scan_rect = self.scan_rect.map(|r| scale.rect(r));
+        if let Some(anchors) = self.anchors.as_mut() {
+            for t in anchors.text.iter_mut().flatten() { t.rect = scale.rect(t.rect); }
+            for c in anchors.color.iter_mut().flatten() { c.pos = scale.point(c.pos); }
+            for a in anchors.image.iter_mut().flatten() { a.rect = scale.rect(a.rect); }
+        }
+        for t in self.transitions.iter_mut().flatten() { t.coords = scale.point(t.coords); }
+        for r in self.recovery.iter_mut().flatten() { r.coords = r.coords.map(|c| scale.point(c)); }
+    }
+
+    fn anchor_count(&self) -> usize {
+        self.anchors.as_ref().map_or(0, |a| {
+            a.text.as_ref().map_or(0, Vec::len) + a.color.as_ref().map_or(0, Vec::len) + a.image.as_ref().map_or(0, Vec::len)
+        })
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, Default)]
 struct Anchors {
     text: Option<Vec<TextAnchor>>,
     color: Option<Vec<ColorAnchor>>,
+    // ✨ 新增：图片模板锚点，用于没有文字的图标按钮
+    image: Option<Vec<ImageAnchor>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 struct TextAnchor {
     rect: [i32; 4],
     val: String,
+    // ✨ 新增：容错字符数。填写后先做归一化再按编辑距离模糊匹配，不填则精确包含
+    #[serde(default)]
+    fuzz: Option<u8>,
+    // ✨ 新增：命中时计入的分数，默认 1。调高后独特的标题文字可以压过偶然命中的颜色锚点
+    #[serde(default = "default_weight")]
+    weight: u32,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -61,25 +192,484 @@ struct ColorAnchor {
     pos: [i32; 2],
     val: String,
     tol: u8,
+    // ✨ 新增：采样半径。填写后取以 pos 为中心 (2r+1)x(2r+1) 区域的平均色，抵抗抗锯齿和 UI 抖动
+    #[serde(default)]
+    r: Option<u8>,
+    // ✨ 新增：比较方式 "rgb" (默认) 或 "hsv"
+    #[serde(default)]
+    mode: String,
+    // ✨ 新增：hsv 模式下的明度容差 (0-255)，不填则忽略明暗变化
+    #[serde(default)]
+    vtol: Option<u8>,
+    // ✨ 新增：命中时计入的分数，默认 1
+    #[serde(default = "default_weight")]
+    weight: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ImageAnchor {
+    rect: [i32; 4],
+    path: String,
+    #[serde(default = "default_image_threshold")]
+    threshold: f32,
+    // ✨ 新增：命中时计入的分数，默认 1
+    #[serde(default = "default_weight")]
+    weight: u32,
 }
 
+fn default_image_threshold() -> f32 { 0.9 }
+
+fn default_weight() -> u32 { 1 }
+
 #[derive(Deserialize, Debug, Clone)]
 struct Transition {
     target: String,
     coords: [i32; 2],
     #[serde(default = "default_delay")]
     post_delay: u64,
+    // ✨ 新增：点击前的等待 (毫秒)，给按钮的入场动画留出时间，默认 0
+    #[serde(default)]
+    pre_delay: u64,
+    // ✨ 新增：路径代价，越大越不愿意走；不填时由 pre_delay + post_delay 推算
+    #[serde(default)]
+    cost: Option<u32>,
+    // ✨ 新增：点击后的完成判定，"appear" (默认) 等目标界面出现，"disappear" 等当前界面消失
+    #[serde(default)]
+    wait_mode: WaitMode,
+}
+
+/// 跳转点击后如何判断已经离开/到达
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum WaitMode {
+    /// 等目标场景的锚点出现
+    #[default]
+    Appear,
+    /// 等出发场景的锚点消失 (目标界面加载较慢、但旧界面会立即关闭时更可靠)
+    Disappear,
 }
 
 fn default_delay() -> u64 { 500 }
 
+/// 恢复动作：coords 与 key 二选一。coords 沿用跳转的点击坐标格式，
+/// key 为单个字符或 "esc" / "space" / "enter" / "tab"
+#[derive(Deserialize, Debug, Clone)]
+struct RecoveryAction {
+    #[serde(default)]
+    coords: Option<[i32; 2]>,
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default = "default_delay")]
+    post_delay: u64,
+}
+
+/// 恢复动作的按键名 -> 字符；"esc" 没有可打印字符，由调用方单独处理
+fn recovery_key_char(name: &str) -> Option<char> {
+    match name.to_lowercase().as_str() {
+        "space" => Some(' '),
+        "enter" => Some('\n'),
+        "tab" => Some('\t'),
+        other => {
+            let mut chars = other.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if HumanDriver::char_to_keycode_with_mod(c).0 != 0 => Some(c),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// BCP-47 标签的基本形状：以 '-' 分隔、每段 1~8 个 ASCII 字母或数字
+/// 明显不合法的标签不必交给系统 OCR 接口
+fn is_language_tag(tag: &str) -> bool {
+    tag.split('-').all(|sub| (1..=8).contains(&sub.len()) && sub.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+fn is_escape_key(name: &str) -> bool {
+    matches!(name.to_lowercase().as_str(), "esc" | "escape")
+}
+
+impl RecoveryAction {
+    /// 配置问题描述；动作合法时为 None
+    fn problem(&self) -> Option<String> {
+        match (&self.coords, &self.key) {
+            (Some(_), Some(_)) => Some("coords 与 key 只能填一个".to_string()),
+            (None, None) => Some("coords 与 key 至少填一个".to_string()),
+            (None, Some(k)) if !is_escape_key(k) && recovery_key_char(k).is_none() => Some(format!("无法识别的按键 \"{}\"", k)),
+            _ => None,
+        }
+    }
+}
+
+/// 每跳的固定开销 (移动+点击+等待确认)，保证代价相同时仍偏向更少的跳数
+const HOP_BASE_COST: u64 = 1000;
+
+impl Transition {
+    fn cost(&self) -> u64 {
+        self.cost.map(u64::from).unwrap_or(HOP_BASE_COST + self.pre_delay + self.post_delay)
+    }
+}
+
+// ==========================================
+// 2. 匹配算法 (OCR 容错 / 颜色比较)
+// ==========================================
+
+/// 归一化 OCR 文本：全角转半角、去掉空白与标点、字母转小写
+fn normalize_ocr_text(s: &str) -> Vec<char> {
+    s.chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            _ => c,
+        })
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// 近似子串匹配：expected 与 haystack 任意子串的最小编辑距离 (Sellers 算法)
+fn min_substring_distance(haystack: &[char], expected: &[char]) -> usize {
+    // prev[j] = expected[..j] 与以当前位置结尾的某个子串的最小距离
+    let mut prev: Vec<usize> = (0..=expected.len()).collect();
+    let mut best = prev[expected.len()];
+    for &h in haystack {
+        let mut curr = vec![0; expected.len() + 1];
+        for (j, &e) in expected.iter().enumerate() {
+            let sub = prev[j] + usize::from(h != e);
+            curr[j + 1] = sub.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        best = best.min(curr[expected.len()]);
+        prev = curr;
+    }
+    best
+}
+
+/// OCR 字符白名单过滤：只保留 allowed 中出现的字符
+/// 空白一律保留，多次曝光的识别结果以空格拼接，去掉空格会把相邻结果的数字粘在一起
+pub fn filter_charset(text: &str, allowed: &str) -> String {
+    text.chars().filter(|c| c.is_whitespace() || allowed.contains(*c)).collect()
+}
+
+/// 锚点文字匹配：fuzz 为 None 时保持原有的精确包含语义
+fn text_matches(output: &str, expected: &str, fuzz: Option<u8>) -> bool {
+    match fuzz {
+        None => output.contains(expected),
+        Some(k) => {
+            let expected = normalize_ocr_text(expected);
+            if expected.is_empty() { return true; }
+            min_substring_distance(&normalize_ocr_text(output), &expected) <= k as usize
+        }
+    }
+}
+
+/// RGB 转 HSV：h 为角度 [0, 360)，s / v 缩放到 [0, 255]
+fn rgb_to_hsv(rgb: [u8; 3]) -> (f32, f32, f32) {
+    let [r, g, b] = rgb.map(|c| c as f32);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let s = if max == 0.0 { 0.0 } else { delta / max * 255.0 };
+    (h, s, max)
+}
+
+/// 低于该饱和度时色相基本不可信 (接近灰色)，只比较饱和度和明度
+const HSV_GRAY_SATURATION: f32 = 20.0;
+
+/// RGB 模式：三通道绝对差之和不超过 tol * 3
+fn color_matches_rgb(actual: [u8; 3], expected: [u8; 3], tol: u8) -> bool {
+    let diff: i16 = actual.iter().zip(expected).map(|(&a, e)| (a as i16 - e as i16).abs()).sum();
+    diff <= tol as i16 * 3
+}
+
+/// HSV 模式：色相差 (度) 与饱和度差都不超过 tol，明度差单独用 vtol 判定
+/// 这样按钮只是变暗时仍能匹配
+fn color_matches_hsv(actual: [u8; 3], expected: [u8; 3], tol: u8, vtol: Option<u8>) -> bool {
+    let (h1, s1, v1) = rgb_to_hsv(actual);
+    let (h2, s2, v2) = rgb_to_hsv(expected);
+    let tol = tol as f32;
+
+    if s1.max(s2) >= HSV_GRAY_SATURATION {
+        let dh = (h1 - h2).abs();
+        if dh.min(360.0 - dh) > tol { return false; }
+    }
+    if (s1 - s2).abs() > tol { return false; }
+    match vtol {
+        Some(vt) => (v1 - v2).abs() <= vt as f32,
+        None => true,
+    }
+}
+
+/// 模板在 region 的 (ox, oy) 处的归一化互相关，取值 [-1, 1]
+fn ncc_at(region: &image::GrayImage, ox: u32, oy: u32, template: &image::GrayImage) -> f32 {
+    let n = (template.width() * template.height()) as f32;
+    let (mut sum_r, mut sum_t) = (0.0f32, 0.0f32);
+    for (tx, ty, t) in template.enumerate_pixels() {
+        sum_r += region.get_pixel(ox + tx, oy + ty)[0] as f32;
+        sum_t += t[0] as f32;
+    }
+    let (mean_r, mean_t) = (sum_r / n, sum_t / n);
+    let (mut cov, mut var_r, mut var_t) = (0.0f32, 0.0f32, 0.0f32);
+    for (tx, ty, t) in template.enumerate_pixels() {
+        let dr = region.get_pixel(ox + tx, oy + ty)[0] as f32 - mean_r;
+        let dt = t[0] as f32 - mean_t;
+        cov += dr * dt;
+        var_r += dr * dr;
+        var_t += dt * dt;
+    }
+    if var_r == 0.0 || var_t == 0.0 {
+        // 纯色块没有方差：两者完全一致才算匹配
+        return if (mean_r - mean_t).abs() < 1.0 { 1.0 } else { 0.0 };
+    }
+    cov / (var_r * var_t).sqrt()
+}
+
+/// 在 region 内滑动模板，返回最佳 NCC 得分；区域比模板小时返回 0
+fn best_template_score(region: &image::GrayImage, template: &image::GrayImage) -> f32 {
+    if template.width() == 0 || template.height() == 0 { return 0.0; }
+    if region.width() < template.width() || region.height() < template.height() { return 0.0; }
+    let mut best = f32::MIN;
+    for oy in 0..=(region.height() - template.height()) {
+        for ox in 0..=(region.width() - template.width()) {
+            best = best.max(ncc_at(region, ox, oy, template));
+        }
+    }
+    best
+}
+
+/// 估计画面在两帧之间的垂直位移 (像素，内容上移为正)
+/// 取 before 中部一条横带作为地标，在 after 的同一列范围内上下滑动寻找最佳 NCC；
+/// 最佳得分低于 min_score 时认为地标丢失，返回 None
+pub fn estimate_vertical_shift(before: &image::GrayImage, after: &image::GrayImage, min_score: f32) -> Option<i32> {
+    if before.dimensions() != after.dimensions() { return None; }
+    let (w, h) = before.dimensions();
+    let band_h = (h / 6).max(8);
+    if h < band_h * 2 || w < 16 { return None; }
+    let band_y = (h - band_h) / 2;
+    let template = image::imageops::crop_imm(before, 0, band_y, w, band_h).to_image();
+
+    let mut best: Option<(u32, f32)> = None;
+    for oy in 0..=(h - band_h) {
+        let score = ncc_at(after, 0, oy, &template);
+        if best.is_none_or(|(_, s)| score > s) { best = Some((oy, score)); }
+    }
+    let (oy, score) = best?;
+    if score < min_score { return None; }
+    Some(band_y as i32 - oy as i32)
+}
+
+/// 在图上画 2 像素宽的矩形框 [x1, y1, x2, y2]，超出画面的部分忽略
+fn draw_rect_outline(img: &mut image::RgbaImage, rect: [i32; 4], color: image::Rgba<u8>) {
+    let (w, h) = (img.width() as i32, img.height() as i32);
+    let mut put = |x: i32, y: i32| {
+        if x >= 0 && y >= 0 && x < w && y < h { img.put_pixel(x as u32, y as u32, color); }
+    };
+    for t in 0..2 {
+        for x in rect[0]..=rect[2] { put(x, rect[1] + t); put(x, rect[3] - t); }
+        for y in rect[1]..=rect[3] { put(rect[0] + t, y); put(rect[2] - t, y); }
+    }
+}
+
+/// Otsu 自适应阈值：选取使前景/背景类间方差最大的灰度值
+fn otsu_threshold(img: &image::GrayImage) -> u8 {
+    let mut hist = [0u32; 256];
+    for p in img.pixels() { hist[p[0] as usize] += 1; }
+    let total = img.pixels().len() as f64;
+    let sum_all: f64 = hist.iter().enumerate().map(|(i, &c)| i as f64 * c as f64).sum();
+
+    let (mut w_bg, mut sum_bg) = (0.0f64, 0.0f64);
+    let (mut best_t, mut best_var) = (0u8, 0.0f64);
+    for (t, &count) in hist.iter().enumerate() {
+        w_bg += count as f64;
+        if w_bg == 0.0 { continue; }
+        let w_fg = total - w_bg;
+        if w_fg == 0.0 { break; }
+        sum_bg += t as f64 * count as f64;
+        let mean_bg = sum_bg / w_bg;
+        let mean_fg = (sum_all - sum_bg) / w_fg;
+        let var = w_bg * w_fg * (mean_bg - mean_fg).powi(2);
+        if var > best_var { best_var = var; best_t = t as u8; }
+    }
+    best_t
+}
+
 // ==========================================
-// 2. 接口层 (OCR 与 多重图像预处理)
+// 3. 接口层 (OCR 与 多重图像预处理)
 // ==========================================
+
+/// 一次截图的内存副本：同一轮扫描的所有锚点都在这一帧上判定
+/// 可以只是屏幕的一部分 (scene.scan_rect)，此时 origin 为其左上角；对外一律使用屏幕坐标
+struct Frame {
+    img: image::RgbaImage,
+    origin: (i32, i32),
+}
+
+impl Frame {
+    /// 按 [x1, y1, x2, y2] 裁剪，超出画面的部分会被截断；完全越界时返回 None
+    fn crop(&self, rect: [i32; 4]) -> Option<image::RgbaImage> {
+        let (ox, oy) = self.origin;
+        let (fw, fh) = (self.img.width() as i32, self.img.height() as i32);
+        let x1 = (rect[0] - ox).clamp(0, fw);
+        let y1 = (rect[1] - oy).clamp(0, fh);
+        let x2 = (rect[2].max(rect[0] + 1) - ox).clamp(0, fw);
+        let y2 = (rect[3].max(rect[1] + 1) - oy).clamp(0, fh);
+        if x2 <= x1 || y2 <= y1 { return None; }
+        Some(image::imageops::crop_imm(&self.img, x1 as u32, y1 as u32, (x2 - x1) as u32, (y2 - y1) as u32).to_image())
+    }
+
+    fn pixel(&self, x: i32, y: i32) -> Option<[u8; 3]> {
+        let (x, y) = (x - self.origin.0, y - self.origin.1);
+        if x < 0 || y < 0 || x >= self.img.width() as i32 || y >= self.img.height() as i32 { return None; }
+        let p = self.img.get_pixel(x as u32, y as u32);
+        Some([p[0], p[1], p[2]])
+    }
+
+    /// 以 (x, y) 为中心、半径 r 的方块平均色；越界部分不参与平均
+    fn average(&self, x: i32, y: i32, r: u8) -> Option<[u8; 3]> {
+        let r = r as i32;
+        let (mut sum, mut n) = ([0u32; 3], 0u32);
+        for py in (y - r)..=(y + r) {
+            for px in (x - r)..=(x + r) {
+                if let Some(p) = self.pixel(px, py) {
+                    for (acc, v) in sum.iter_mut().zip(p) { *acc += v as u32; }
+                    n += 1;
+                }
+            }
+        }
+        if n == 0 { return None; }
+        Some(sum.map(|v| (v / n) as u8))
+    }
+}
+
+/// 截图来源：默认从显示器截取，测试时可换成内存中的画面
+trait ScreenSource: Send + Sync {
+    /// 整屏截图
+    fn capture(&self) -> Result<image::RgbaImage, String>;
+    /// 截取左上角为 (x, y)、大小 w x h 的区域
+    fn capture_area(&self, x: i32, y: i32, w: u32, h: u32) -> Result<image::RgbaImage, String>;
+}
+
+/// 从显示器截图，index 为 Screen::all() 中的下标
+struct MonitorSource {
+    index: usize,
+}
+
+impl MonitorSource {
+    /// 当前选中的显示器；序号越界时返回 Err
+    fn screen(&self) -> Result<Screen, String> {
+        pick_screen(Screen::all().unwrap_or_default(), self.index)
+    }
+}
+
+/// 按序号从显示器列表中取一个 (与 Screen::all() 的顺序一致)；越界时返回 Err
+fn pick_screen<T>(screens: Vec<T>, index: usize) -> Result<T, String> {
+    screens.into_iter().nth(index).ok_or_else(|| format!("找不到显示器 #{}", index))
+}
+
+impl ScreenSource for MonitorSource {
+    fn capture(&self) -> Result<image::RgbaImage, String> {
+        let captured = self.screen()?.capture().map_err(|e| e.to_string())?;
+        image::RgbaImage::from_raw(captured.width(), captured.height(), captured.into_raw()).ok_or_else(|| "截图数据尺寸不符".to_string())
+    }
+
+    fn capture_area(&self, x: i32, y: i32, w: u32, h: u32) -> Result<image::RgbaImage, String> {
+        // capture() 返回物理像素，而 Screen::capture_area 按逻辑坐标取区域；
+        // 锚点坐标都是物理像素，这里先换算成逻辑坐标，再从结果中裁出请求的那一块
+        let screen = self.screen()?;
+        let area = LogicalArea::from_physical(x, y, w, h, screen.display_info.scale_factor);
+        let captured = screen.capture_area(area.x, area.y, area.w, area.h).map_err(|e| e.to_string())?;
+        let captured = image::RgbaImage::from_raw(captured.width(), captured.height(), captured.into_raw())
+            .ok_or_else(|| "截图数据尺寸不符".to_string())?;
+        let (ox, oy) = area.crop_offset;
+        let w = w.min(captured.width().saturating_sub(ox));
+        let h = h.min(captured.height().saturating_sub(oy));
+        Ok(image::imageops::crop_imm(&captured, ox, oy, w, h).to_image())
+    }
+}
+
+/// 物理像素区域换算成的逻辑坐标区域 (DPI 缩放下 Screen::capture_area 使用逻辑坐标)
+#[derive(Debug, PartialEq)]
+struct LogicalArea {
+    x: i32,
+    y: i32,
+    w: u32,
+    h: u32,
+    /// 请求区域左上角在截图结果 (物理像素) 中的偏移
+    crop_offset: (u32, u32),
+}
+
+impl LogicalArea {
+    /// 起点向下取整、终点向上取整，保证逻辑区域完整覆盖请求的物理区域
+    fn from_physical(x: i32, y: i32, w: u32, h: u32, scale: f32) -> Self {
+        let scale = if scale > 0.0 { scale } else { 1.0 };
+        let to_logical = |start: i32, len: u32| {
+            let lo = (start as f32 / scale).floor() as i32;
+            let hi = ((start + len as i32) as f32 / scale).ceil() as i32;
+            let offset = (start - (lo as f32 * scale).round() as i32).max(0) as u32;
+            (lo, (hi - lo).max(0) as u32, offset)
+        };
+        let (lx, lw, ox) = to_logical(x, w);
+        let (ly, lh, oy) = to_logical(y, h);
+        Self { x: lx, y: ly, w: lw, h: lh, crop_offset: (ox, oy) }
+    }
+}
+
+/// 低于该高度 (像素) 的区域视为小字，预处理时放大 3 倍而不是 2 倍
+const SMALL_TEXT_HEIGHT: u32 = 32;
+
+/// 生成送去 OCR 的图像：不预处理时只有原图；预处理时为放大后的多重曝光版本
+fn ocr_variants(rgba_img: image::RgbaImage, preprocess: bool, otsu: bool) -> Vec<image::DynamicImage> {
+    let (w, h) = rgba_img.dimensions();
+    let dynamic_img = image::DynamicImage::ImageRgba8(rgba_img);
+
+    if !preprocess {
+        return vec![dynamic_img];
+    }
+
+    // 🔥 放大：Lanczos3 采样能有效平滑艺术字边缘；字高很小 (如波次计数) 时放大 3 倍
+    let scale = if h < SMALL_TEXT_HEIGHT { 3 } else { 2 };
+    let scaled_img = dynamic_img.resize(w * scale, h * scale, image::imageops::FilterType::Lanczos3);
+    let binarize = |t: u8| {
+        let mut luma = scaled_img.grayscale().into_luma8();
+        for pixel in luma.pixels_mut() { pixel[0] = if pixel[0] > t { 255 } else { 0 }; }
+        image::DynamicImage::ImageLuma8(luma)
+    };
+
+    // 🔥 多重曝光：策略 A 强二值化 (阈值 200)，策略 B 中等二值化 (阈值 140)，策略 C 原色缩放图
+    let mut variants = vec![binarize(200), binarize(140), scaled_img.clone()];
+
+    // 策略 D (需开启 otsu): Otsu 自适应阈值，应对背景明暗变化时固定阈值失效
+    if otsu {
+        let t = otsu_threshold(&scaled_img.grayscale().into_luma8());
+        variants.push(binarize(t));
+    }
+    variants
+}
+
 struct GameInterface {
     driver: Arc<Mutex<HumanDriver>>,
     ocr_engine: Option<OcrEngine>,
     screenshot_count: AtomicUsize, 
+    // 调试：OCR 截图转储目录，None 时不落盘
+    debug_dump: Option<PathBuf>,
+    // 图片锚点模板缓存 (灰度图)；加载失败记为 None，避免每帧重复读盘
+    templates: Mutex<HashMap<String, Option<image::GrayImage>>>,
+    // 截图来源 (默认为 0 号显示器)，锚点坐标相对于该显示器左上角
+    source: Box<dyn ScreenSource>,
+    // OCR 前是否做放大 + 二值化预处理；关闭时直接识别原图
+    ocr_preprocess: bool,
+    // 预处理时是否额外跑一遍 Otsu 自适应阈值 (每个区域多一次 OCR 调用)，默认关闭
+    ocr_otsu: bool,
+    // 图片模板按编写分辨率截取，加载时按同一比例缩放
+    template_scale: ResolutionScale,
 }
 
 unsafe impl Send for GameInterface {}
@@ -87,51 +677,60 @@ unsafe impl Sync for GameInterface {}
 
 impl GameInterface {
     fn new(driver: Arc<Mutex<HumanDriver>>) -> Self {
-        println!("🚀 初始化 Windows OCR...");
-        let engine = match Language::CreateLanguage(&windows::core::HSTRING::from("zh-Hans")) {
-            Ok(lang) => match OcrEngine::TryCreateFromLanguage(&lang) {
-                Ok(e) => Some(e),
-                Err(_) => OcrEngine::TryCreateFromUserProfileLanguages().ok()
-            },
-            Err(_) => OcrEngine::TryCreateFromUserProfileLanguages().ok(),
-        };
+        info!("🚀 初始化 Windows OCR...");
+        let engine = Self::create_ocr_engine("zh-Hans")
+            .or_else(|| OcrEngine::TryCreateFromUserProfileLanguages().ok());
+        Self::with_source(driver, engine, Box::new(MonitorSource { index: 0 }))
+    }
+
+    fn with_source(driver: Arc<Mutex<HumanDriver>>, ocr_engine: Option<OcrEngine>, source: Box<dyn ScreenSource>) -> Self {
         Self { 
             driver, 
-            ocr_engine: engine,
+            ocr_engine,
             screenshot_count: AtomicUsize::new(0), 
+            debug_dump: None,
+            templates: Mutex::new(HashMap::new()),
+            source,
+            ocr_preprocess: true,
+            ocr_otsu: false,
+            template_scale: ResolutionScale::IDENTITY,
         }
     }
 
-    /// 调用底层 Windows OCR 识别单张图像
-    fn run_windows_ocr(&self, dynamic_img: image::DynamicImage) -> String {
-        if self.ocr_engine.is_none() { return String::new(); }
-        let engine = self.ocr_engine.as_ref().unwrap();
+    /// 按 BCP-47 语言标签 (如 "zh-Hans"、"en-US") 创建 OCR 引擎；系统未安装该语言时返回 None
+    fn create_ocr_engine(tag: &str) -> Option<OcrEngine> {
+        let lang = Language::CreateLanguage(&windows::core::HSTRING::from(tag)).ok()?;
+        if !OcrEngine::IsLanguageSupported(&lang).unwrap_or(false) {
+            return None;
+        }
+        OcrEngine::TryCreateFromLanguage(&lang).ok()
+    }
+
+    /// 调用底层 Windows OCR 识别单张图像，返回原始识别结果
+    fn recognize(&self, dynamic_img: image::DynamicImage) -> Option<OcrResult> {
+        let engine = self.ocr_engine.as_ref()?;
 
         let mut png_buffer = Cursor::new(Vec::new());
-        if dynamic_img.write_to(&mut png_buffer, image::ImageFormat::Png).is_err() { return String::new(); }
+        dynamic_img.write_to(&mut png_buffer, image::ImageFormat::Png).ok()?;
         let png_bytes = png_buffer.into_inner();
 
-        let stream = InMemoryRandomAccessStream::new().unwrap();
-        let writer = DataWriter::CreateDataWriter(&stream).unwrap();
-        if writer.WriteBytes(&png_bytes).is_err() { return String::new(); }
-        if writer.StoreAsync().unwrap().get().is_err() { return String::new(); }
-        if writer.FlushAsync().unwrap().get().is_err() { return String::new(); }
-        if writer.DetachStream().is_err() { return String::new(); }
-        if stream.Seek(0).is_err() { return String::new(); }
-
-        let decoder = match BitmapDecoder::CreateAsync(&stream) {
-             Ok(op) => match op.get() { Ok(d) => d, Err(_) => return String::new() },
-             Err(_) => return String::new(),
-        };
-        let software_bitmap = match decoder.GetSoftwareBitmapAsync() {
-             Ok(op) => match op.get() { Ok(b) => b, Err(_) => return String::new() },
-             Err(_) => return String::new(),
-        };
-        let result = match engine.RecognizeAsync(&software_bitmap) {
-             Ok(op) => match op.get() { Ok(res) => res, Err(_) => return String::new() },
-             Err(_) => return String::new(),
-        };
-        
+        let stream = InMemoryRandomAccessStream::new().ok()?;
+        let writer = DataWriter::CreateDataWriter(&stream).ok()?;
+        writer.WriteBytes(&png_bytes).ok()?;
+        writer.StoreAsync().ok()?.get().ok()?;
+        writer.FlushAsync().ok()?.get().ok()?;
+        writer.DetachStream().ok()?;
+        stream.Seek(0).ok()?;
+
+        let decoder = BitmapDecoder::CreateAsync(&stream).ok()?.get().ok()?;
+        let software_bitmap = decoder.GetSoftwareBitmapAsync().ok()?.get().ok()?;
+        engine.RecognizeAsync(&software_bitmap).ok()?.get().ok()
+    }
+
+    /// 调用底层 Windows OCR 识别单张图像
+    fn run_windows_ocr(&self, dynamic_img: image::DynamicImage) -> String {
+        let result = match self.recognize(dynamic_img) { Some(r) => r, None => return String::new() };
+
         let mut full_text = String::new();
         if let Ok(lines) = result.Lines() {
             for line in lines {
@@ -141,97 +740,255 @@ impl GameInterface {
         full_text.replace(|c: char| c.is_whitespace(), "")
     }
 
+    /// 截取整屏作为本轮扫描的共享帧
+    fn capture_frame(&self) -> Option<Frame> {
+        let img = match self.source.capture() {
+            Ok(img) => img,
+            Err(e) => {
+                warn!("⚠️ [截图] 整屏截图失败: {}", e);
+                return None;
+            }
+        };
+        self.screenshot_count.fetch_add(1, Ordering::Relaxed);
+        Some(Frame { img, origin: (0, 0) })
+    }
+
+    /// 只截取 rect 区域作为帧，锚点仍按屏幕坐标访问
+    fn capture_frame_area(&self, rect: [i32; 4]) -> Option<Frame> {
+        let w = (rect[2] - rect[0]).max(1) as u32;
+        let h = (rect[3] - rect[1]).max(1) as u32;
+        let img = match self.source.capture_area(rect[0], rect[1], w, h) {
+            Ok(img) => img,
+            Err(e) => {
+                warn!("⚠️ [截图] 区域截图失败 {:?}: {}", rect, e);
+                return None;
+            }
+        };
+        self.screenshot_count.fetch_add(1, Ordering::Relaxed);
+        Some(Frame { img, origin: (rect[0], rect[1]) })
+    }
+
+    /// 累计截图次数 (含 scan_rect 区域截图，用于统计扫描开销)
+    pub fn capture_count(&self) -> usize {
+        self.screenshot_count.load(Ordering::Relaxed)
+    }
+
     pub fn get_text_from_area(&self, rect: [i32; 4]) -> String {
+        self.get_text_from_area_with(rect, self.ocr_preprocess)
+    }
+
+    /// 同 get_text_from_area，但本次调用单独指定是否预处理
+    pub fn get_text_from_area_with(&self, rect: [i32; 4], preprocess: bool) -> String {
          let x = rect[0]; 
          let y = rect[1];
          let w = (rect[2] - rect[0]).max(1);
          let h = (rect[3] - rect[1]).max(1);
          
-         let screens = Screen::all().unwrap_or_default();
-         let screen = match screens.first() { Some(s) => s, None => return String::new() };
-         
-         let captured_data = match screen.capture_area(x, y, w as u32, h as u32) {
+         let rgba_img = match self.source.capture_area(x, y, w as u32, h as u32) {
              Ok(img) => img,
-             Err(_) => return String::new(),
+             Err(e) => {
+                 warn!("⚠️ [OCR] 区域截图失败 {:?}: {}", rect, e);
+                 return String::new();
+             }
          };
 
-         // 1. 基础转换
-         let rgba_img = image::RgbaImage::from_raw(captured_data.width(), captured_data.height(), captured_data.into_raw()).unwrap();
-         let dynamic_img = image::DynamicImage::ImageRgba8(rgba_img);
+         let text = self.ocr_image(rgba_img, "area", preprocess);
+         if text.trim().is_empty() {
+             warn!("⚠️ [OCR] 区域 {:?} 未识别到文字", rect);
+         }
+         text
+    }
 
-         // 2. 🔥 2倍放大：Lanczos3 采样能有效平滑艺术字边缘
-         let scaled_img = dynamic_img.resize(w as u32 * 2, h as u32 * 2, image::imageops::FilterType::Lanczos3);
-         
-         // 3. 🔥 多重曝光 OCR 策略
-         let mut results = Vec::new();
+    /// 识别区域内的每个词，并把词框从 (放大后的) 截图坐标换算回屏幕坐标
+    pub fn get_words_from_area(&self, rect: [i32; 4]) -> Vec<OcrWord> {
+        const SCALE: u32 = 2;
+        let (x, y) = (rect[0], rect[1]);
+        let w = (rect[2] - rect[0]).max(1) as u32;
+        let h = (rect[3] - rect[1]).max(1) as u32;
 
-         // 策略 A: 强二值化 (阈值 200)
-         let mut luma_high = scaled_img.grayscale().into_luma8();
-         for pixel in luma_high.pixels_mut() { pixel[0] = if pixel[0] > 200 { 255 } else { 0 }; }
-         results.push(self.run_windows_ocr(image::DynamicImage::ImageLuma8(luma_high)));
+        let rgba_img = match self.source.capture_area(x, y, w, h) { Ok(img) => img, Err(_) => return Vec::new() };
+        // 与 ocr_image 的策略 C 相同：2 倍 Lanczos 放大后直接识别
+        let scaled = image::DynamicImage::ImageRgba8(rgba_img).resize(w * SCALE, h * SCALE, image::imageops::FilterType::Lanczos3);
+        let result = match self.recognize(scaled) { Some(r) => r, None => return Vec::new() };
+
+        let mut words = Vec::new();
+        if let Ok(lines) = result.Lines() {
+            for line in lines {
+                let line_words = match line.Words() { Ok(ws) => ws, Err(_) => continue };
+                for word in line_words {
+                    let (Ok(text), Ok(b)) = (word.Text(), word.BoundingRect()) else { continue };
+                    let s = SCALE as f32;
+                    words.push(OcrWord {
+                        text: text.to_string(),
+                        rect: [
+                            x + (b.X / s) as i32,
+                            y + (b.Y / s) as i32,
+                            x + ((b.X + b.Width) / s).ceil() as i32,
+                            y + ((b.Y + b.Height) / s).ceil() as i32,
+                        ],
+                    });
+                }
+            }
+        }
+        words
+    }
 
-         // 策略 B: 中等二值化 (阈值 140)
-         let mut luma_mid = scaled_img.grayscale().into_luma8();
-         for pixel in luma_mid.pixels_mut() { pixel[0] = if pixel[0] > 140 { 255 } else { 0 }; }
-         results.push(self.run_windows_ocr(image::DynamicImage::ImageLuma8(luma_mid)));
+    /// 开启调试转储时，把送去 OCR 的区域图保存为 <目录>/ocr_<时间戳>_<标签>.png
+    fn dump_debug_image(&self, img: &image::RgbaImage, label: &str) {
+        let dir = match &self.debug_dump { Some(d) => d, None => return };
+        if let Err(e) = fs::create_dir_all(dir) {
+            warn!("⚠️ [OCR] 无法创建调试目录 {:?}: {}", dir, e);
+            return;
+        }
+        // 标签来自锚点文字，去掉文件名中不允许的字符
+        let safe_label: String = label.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+        let stamp = chrono::Local::now().format("%Y%m%d_%H%M%S_%3f");
+        let path = dir.join(format!("ocr_{}_{}.png", stamp, safe_label));
+        if let Err(e) = img.save(&path) {
+            warn!("⚠️ [OCR] 调试截图保存失败 {:?}: {}", path, e);
+        }
+    }
 
-         // 策略 C: 原色缩放图
-         results.push(self.run_windows_ocr(scaled_img.clone()));
+    /// 对已截取的区域图像执行预处理 + 多重曝光 OCR
+    fn ocr_image(&self, rgba_img: image::RgbaImage, label: &str, preprocess: bool) -> String {
+         self.dump_debug_image(&rgba_img, label);
+         let variants = ocr_variants(rgba_img, preprocess, self.ocr_otsu);
 
-         // 4. 合并所有识别到的文本块
-         let final_text = results.join(" ");
-         final_text
+         // 合并所有识别到的文本块
+         let results: Vec<String> = variants.into_iter().map(|img| self.run_windows_ocr(img)).collect();
+         results.join(" ")
     }
 
-    fn check_text_anchor(&self, rect: [i32; 4], expected: &str) -> bool {
-        let output = self.get_text_from_area(rect);
-        output.contains(expected)
+    fn check_text_anchor(&self, frame: &Frame, rect: [i32; 4], expected: &str, fuzz: Option<u8>) -> bool {
+        let output = match frame.crop(rect) {
+            Some(area) => self.ocr_image(area, expected, self.ocr_preprocess),
+            None => return false,
+        };
+        text_matches(&output, expected, fuzz)
     }
 
     pub fn debug_ocr_file(&self, file_path: &str, expected_contain: &str) {
-        println!("📂 [本地测试] 加载: {}", file_path);
+        info!("📂 [本地测试] 加载: {}", file_path);
         if !Path::new(file_path).exists() { return; }
         let dynamic_img = image::open(file_path).expect("加载失败");
         let output = self.run_windows_ocr(dynamic_img);
-        println!("📝 结果: [{}] | 期望: [{}] -> {}", output, expected_contain, output.contains(expected_contain));
+        info!("📝 结果: [{}] | 期望: [{}] -> {}", output, expected_contain, output.contains(expected_contain));
+    }
+
+    fn check_color_anchor(&self, frame: &Frame, anchor: &ColorAnchor) -> bool {
+        let [x, y] = anchor.pos;
+        let sampled = match anchor.r {
+            Some(r) if r > 0 => frame.average(x, y, r),
+            _ => frame.pixel(x, y),
+        };
+        let actual = match sampled { Some(p) => p, None => return false };
+        let expected_rgb = hex::decode(anchor.val.trim_start_matches('#')).unwrap_or(vec![0,0,0]);
+        if expected_rgb.len() < 3 { return false; }
+        let expected = [expected_rgb[0], expected_rgb[1], expected_rgb[2]];
+        match anchor.mode.to_lowercase().as_str() {
+            "hsv" => color_matches_hsv(actual, expected, anchor.tol, anchor.vtol),
+            _ => color_matches_rgb(actual, expected, anchor.tol),
+        }
+    }
+
+    fn load_template(&self, path: &str) -> Option<image::GrayImage> {
+        let mut cache = self.templates.lock().ok()?;
+        let scale = self.template_scale;
+        cache.entry(path.to_string())
+            .or_insert_with(|| match image::open(path) {
+                Ok(img) if scale.is_identity() => Some(img.into_luma8()),
+                Ok(img) => {
+                    let w = (scale.x(img.width() as f32).round() as u32).max(1);
+                    let h = (scale.y(img.height() as f32).round() as u32).max(1);
+                    Some(image::imageops::resize(&img.into_luma8(), w, h, image::imageops::FilterType::Triangle))
+                }
+                Err(e) => { warn!("⚠️ [模板] 加载失败 {}: {}", path, e); None }
+            })
+            .clone()
     }
 
-    fn check_color_anchor(&self, pos: [i32; 2], expected_hex: &str, tolerance: u8) -> bool {
-        let x = pos[0]; let y = pos[1];
-        let screens = Screen::all().unwrap_or_default();
-        let screen = match screens.first() { Some(s) => s, None => return false };
-        let image = match screen.capture_area(x, y, 1, 1) { Ok(img) => img, Err(_) => return false };
-        let data = image.as_raw();
-        if data.len() < 3 { return false; }
-        let (r, g, b) = (data[0], data[1], data[2]);
-        let expected_rgb = hex::decode(expected_hex.trim_start_matches('#')).unwrap_or(vec![0,0,0]);
-        let diff = (r as i16 - expected_rgb[0] as i16).abs() + (g as i16 - expected_rgb[1] as i16).abs() + (b as i16 - expected_rgb[2] as i16).abs();
-        diff <= (tolerance as i16 * 3)
+    fn check_image_anchor(&self, frame: &Frame, anchor: &ImageAnchor) -> bool {
+        let template = match self.load_template(&anchor.path) { Some(t) => t, None => return false };
+        let region = match frame.crop(anchor.rect) {
+            Some(area) => image::DynamicImage::ImageRgba8(area).into_luma8(),
+            None => return false,
+        };
+        best_template_score(&region, &template) >= anchor.threshold
+    }
+
+    fn perform_key(&self, name: &str) {
+        if let Ok(mut bot) = self.driver.lock() {
+            if is_escape_key(name) {
+                bot.tap_escape();
+            } else if let Some(c) = recovery_key_char(name) {
+                bot.key_click(c);
+            }
+        }
     }
 
     fn perform_click(&self, x: i32, y: i32) {
         if let Ok(mut bot) = self.driver.lock() {
             bot.move_to_humanly(x as u16, y as u16, 0.6);
-            bot.click_humanly(true, false, 0); 
+            bot.click(true, false);
         }
     }
 }
 
 // ==========================================
-// 3. 导航引擎
+// 4. 导航引擎
 // ==========================================
+/// 单步失败时默认的重试点击次数；默认不重试，与原先的导航行为一致，需要时用 set_step_retries 开启
+const DEFAULT_STEP_RETRIES: u8 = 0;
+/// 跳转点击默认的随机偏移半径 (像素)
+const DEFAULT_CLICK_JITTER: u16 = 4;
+/// 一次导航中允许"意外落点后重新规划"的最大次数，防止在两个界面间来回打转
+const MAX_REPLANS: u32 = 3;
+
 pub struct NavEngine {
     scenes: HashMap<String, Scene>,
     interface: GameInterface,
+    step_retries: u8,
+    // 单次 navigate 的总时限，None 表示不限制 (仅受每步超时约束)
+    nav_timeout: Option<Duration>,
+    // 跳转点击的随机偏移半径 (像素)，避免每次都点同一个像素
+    click_jitter: u16,
+    // 配置坐标到实际屏幕的缩放比例
+    scale: ResolutionScale,
 }
 
 impl NavEngine {
     pub fn new(file_path: &str, driver: Arc<Mutex<HumanDriver>>) -> Self {
         let content = fs::read_to_string(file_path).expect("无法读取 TOML");
         let root: TomlRoot = toml::from_str(&content).expect("TOML 解析错误");
+        Self::with_interface(root, GameInterface::new(driver))
+    }
+
+    fn with_interface(root: TomlRoot, mut interface: GameInterface) -> Self {
+        let [aw, ah] = root.authored_size.unwrap_or(DEFAULT_AUTHORED_SIZE);
+        let actual = interface.driver.lock().map(|d| d.screen_size()).unwrap_or((aw as f32, ah as f32));
+        let scale = ResolutionScale::new((aw as f32, ah as f32), actual);
+        if !scale.is_identity() {
+            info!("📐 [分辨率] 地图按 {}x{} 编写，实际 {}x{}，坐标按 ({:.3}, {:.3}) 缩放", aw, ah, actual.0, actual.1, scale.sx, scale.sy);
+        }
         let mut map = HashMap::new();
-        for s in root.scenes { map.insert(s.id.clone(), s); }
-        Self { scenes: map, interface: GameInterface::new(driver) }
+        for mut s in root.scenes {
+            s.rescale(scale);
+            map.insert(s.id.clone(), s);
+        }
+        interface.template_scale = scale;
+        Self {
+            scenes: map,
+            interface,
+            step_retries: DEFAULT_STEP_RETRIES,
+            nav_timeout: None,
+            click_jitter: DEFAULT_CLICK_JITTER,
+            scale,
+        }
+    }
+
+    /// 地图坐标 (authored_size) 到实际屏幕的缩放比例，业务模块可用它换算自己的硬编码坐标
+    pub fn resolution_scale(&self) -> ResolutionScale {
+        self.scale
     }
 
     pub fn test_ocr_on_file(&self, filename: &str, expected: &str) {
@@ -242,120 +999,470 @@ impl NavEngine {
         self.interface.get_text_from_area(rect)
     }
 
-    fn get_match_score(&self, target_id: &str) -> usize {
-        if let Some(scene) = self.scenes.get(target_id) {
-            if scene.anchors.is_none() { return 0; }
-            let anchors = scene.anchors.as_ref().unwrap();
-            let mut score = 0;
-            let mut total_checks = 0;
-            if let Some(texts) = &anchors.text {
-                for t in texts {
-                    total_checks += 1;
-                    if self.interface.check_text_anchor(t.rect, &t.val) { score += 1; }
-                }
-            }
-            if let Some(colors) = &anchors.color {
-                for c in colors {
-                    total_checks += 1;
-                    if self.interface.check_color_anchor(c.pos, &c.val, c.tol) { score += 1; }
-                }
-            }
-            let passed = match scene.logic.to_lowercase().as_str() {
-                "or" => score > 0,              
-                _ => score == total_checks && total_checks > 0, 
-            };
-            if passed { return score; }
-        }
-        0
+    /// 同 ocr_area，但只保留 charset 中的字符 (Windows OCR 不支持白名单，这里做后置过滤)
+    /// 例如识别波次时传入 "0123456789/波次"，去掉误读出的杂字
+    pub fn ocr_area_charset(&self, rect: [i32; 4], charset: &str) -> String {
+        filter_charset(&self.interface.get_text_from_area(rect), charset)
     }
 
-    pub fn identify_current_scene(&self, hint: Option<&str>) -> Option<String> {
-        println!("👀 扫描当前界面...");
-        if let Some(target_id) = hint {
-            if self.get_match_score(target_id) > 0 {
-                println!("✅ 命中预期目标: [{}]", target_id);
-                return Some(target_id.to_string());
-            }
-        }
-        let mut best_match: Option<String> = None;
-        let mut max_score = 0;
-        for (id, _) in &self.scenes {
-            if let Some(h) = hint { if h == id { continue; } }
-            let score = self.get_match_score(id);
-            if score > 0 && score > max_score {
-                max_score = score;
-                best_match = Some(id.clone());
-            }
+    /// 同 ocr_area，但本次调用单独指定是否做放大 + 二值化预处理
+    pub fn ocr_area_with(&self, rect: [i32; 4], preprocess: bool) -> String {
+        self.interface.get_text_from_area_with(rect, preprocess)
+    }
+
+    /// 设置默认是否对 OCR 截图做放大 + 二值化预处理 (默认开启)
+    pub fn set_ocr_preprocess(&mut self, enabled: bool) {
+        self.interface.ocr_preprocess = enabled;
+    }
+
+    /// 预处理时追加一遍 Otsu 自适应阈值识别 (默认关闭)，背景明暗多变、固定阈值识别不出时开启
+    pub fn set_ocr_otsu(&mut self, enabled: bool) {
+        self.interface.ocr_otsu = enabled;
+    }
+
+    /// 截一帧并判断 pos 处 (可选半径平均) 的颜色是否与 hex 在容差内一致 (RGB 模式)
+    pub fn check_color(&self, pos: [i32; 2], hex: &str, tol: u8, radius: Option<u8>) -> bool {
+        let frame = match self.interface.capture_frame() { Some(f) => f, None => return false };
+        let anchor = ColorAnchor { pos, val: hex.to_string(), tol, r: radius, mode: String::new(), vtol: None, weight: 1 };
+        self.interface.check_color_anchor(&frame, &anchor)
+    }
+
+    /// 识别区域内的每个词及其屏幕坐标，便于定位数字/按钮后就近点击
+    pub fn ocr_area_detailed(&self, rect: [i32; 4]) -> Vec<OcrWord> {
+        self.interface.get_words_from_area(rect)
+    }
+
+    /// 截取屏幕区域并转为灰度图，供地标比对 (如视野卷动标定)
+    pub fn capture_gray(&self, rect: [i32; 4]) -> Option<image::GrayImage> {
+        let frame = self.interface.capture_frame()?;
+        let crop = frame.crop(rect)?;
+        Some(image::DynamicImage::ImageRgba8(crop).to_luma8())
+    }
+
+    /// 所有场景 ID (按字母序)
+    pub fn scenes_list(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.scenes.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// 检查场景图：返回悬空的跳转目标 (目标场景未定义) 和没有任何入边的场景
+    /// 结果为空表示配置自洽；入口场景 (如大厅) 没有入边属正常情况，仅作提示
+    pub fn validate_graph(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut has_inbound: HashMap<&str, bool> = self.scenes.keys().map(|id| (id.as_str(), false)).collect();
+
+        for id in self.scenes_list() {
+            let scene = &self.scenes[&id];
+            let total = scene.anchor_count();
+            match scene.min_matches.or_else(|| scene.parse_logic(total)) {
+                None => problems.push(format!("无法识别的 logic: [{}] \"{}\" (可用 and / or / k:N)", id, scene.logic)),
+                Some(n) if total > 0 && (n == 0 || n > total) => {
+                    problems.push(format!("锚点数不足: [{}] 要求命中 {} 个，但只有 {} 个锚点", id, n, total))
+                }
+                _ => {}
+            }
+            for anchor in scene.anchors_outside_scan_rect() {
+                problems.push(format!("锚点超出 scan_rect: [{}] {} 不在 {:?} 内", id, anchor, scene.scan_rect.unwrap_or_default()));
+            }
+            for (i, r) in scene.recovery.iter().flatten().enumerate() {
+                if let Some(p) = r.problem() {
+                    problems.push(format!("恢复动作无效: [{}] 第 {} 步 {}", id, i + 1, p));
+                }
+            }
+            for t in scene.transitions.iter().flatten() {
+                match has_inbound.get_mut(t.target.as_str()) {
+                    Some(flag) => *flag = true,
+                    None => problems.push(format!("悬空跳转: [{}] -> [{}] (目标场景不存在)", id, t.target)),
+                }
+            }
+        }
+
+        let mut orphans: Vec<&str> = has_inbound.into_iter().filter(|(_, v)| !v).map(|(k, _)| k).collect();
+        orphans.sort();
+        for id in orphans {
+            problems.push(format!("孤立场景: [{}] (没有任何跳转指向它)", id));
+        }
+        problems
+    }
+
+    /// 设置单次导航的总时限；超过后无论进行到哪一步都返回 Failed
+    pub fn set_nav_timeout(&mut self, timeout: Option<Duration>) {
+        self.nav_timeout = timeout;
+    }
+
+    /// 设置跳转点击的随机偏移半径 (像素)，点击点在以 coords 为圆心的圆内均匀分布；0 表示精确点击
+    /// 半径应小于按钮的一半尺寸，保证偏移后仍落在按钮上
+    pub fn set_click_jitter(&mut self, radius: u16) {
+        self.click_jitter = radius;
+    }
+
+    /// 在 coords 周围 click_jitter 半径内随机取一点
+    fn jittered(&self, coords: [i32; 2]) -> (i32, i32) {
+        if self.click_jitter == 0 {
+            return (coords[0], coords[1]);
+        }
+        let mut rng = rand::thread_rng();
+        // 半径取 sqrt 保证圆内均匀分布，而不是向圆心聚集
+        let r = self.click_jitter as f32 * rng.gen::<f32>().sqrt();
+        let theta = rng.gen_range(0.0..std::f32::consts::TAU);
+        (coords[0] + (r * theta.cos()).round() as i32, coords[1] + (r * theta.sin()).round() as i32)
+    }
+
+    /// 设置单步超时后仍停留在原界面时的重试点击次数 (0 表示不重试)
+    pub fn set_step_retries(&mut self, retries: u8) {
+        self.step_retries = retries;
+    }
+
+    /// 设置 OCR 调试截图的转储目录，None (默认) 表示不保存
+    /// 文件名带时间戳和锚点文字，多个区域连续识别时不会互相覆盖；导航失败时还会保存标注了锚点的整屏截图
+    pub fn set_debug_dump(&mut self, dir: Option<PathBuf>) {
+        self.interface.debug_dump = dir;
+    }
+
+    /// 切换 OCR 识别语言 (BCP-47 标签，如 "en-US")，重建引擎后后续识别都沿用新引擎
+    /// 该语言不可用时返回 false，原引擎保持不变
+    pub fn set_ocr_language(&mut self, bcp47: &str) -> bool {
+        if !is_language_tag(bcp47) {
+            warn!("⚠️ [OCR] \"{}\" 不是有效的语言标签，保持原引擎", bcp47);
+            return false;
+        }
+        match GameInterface::create_ocr_engine(bcp47) {
+            Some(engine) => {
+                info!("🔤 [OCR] 已切换识别语言: {}", bcp47);
+                self.interface.ocr_engine = Some(engine);
+                true
+            }
+            None => {
+                warn!("⚠️ [OCR] 语言 {} 不可用，保持原引擎", bcp47);
+                false
+            }
+        }
+    }
+
+    /// 系统已安装、可用于 OCR 的语言标签列表
+    pub fn available_ocr_languages() -> Vec<String> {
+        let langs = match OcrEngine::AvailableRecognizerLanguages() { Ok(l) => l, Err(_) => return Vec::new() };
+        langs.into_iter().filter_map(|l| l.LanguageTag().ok()).map(|t| t.to_string()).collect()
+    }
+
+    /// 选择截图的显示器 (默认 0 即主屏)，锚点坐标相对于该显示器
+    pub fn set_monitor_index(&mut self, index: usize) {
+        self.interface.source = Box::new(MonitorSource { index });
+    }
+
+    /// 引擎启动以来的截图次数 (整屏与 scan_rect 区域截图都计入)
+    pub fn capture_count(&self) -> usize {
+        self.interface.capture_count()
+    }
+
+    /// 单次截图并对目标场景打分；场景设置了 scan_rect 时只截取该区域
+    fn get_match_score(&self, target_id: &str) -> usize {
+        self.try_match_score(target_id).unwrap_or(0)
+    }
+
+    /// 同 get_match_score，但截图失败时返回 None，便于与"未命中"区分
+    fn try_match_score(&self, target_id: &str) -> Option<usize> {
+        let scan_rect = self.scenes.get(target_id).and_then(|s| s.scan_rect);
+        let frame = match scan_rect {
+            Some(rect) => self.interface.capture_frame_area(rect),
+            None => self.interface.capture_frame(),
+        };
+        frame.map(|f| self.score_scene(&f, target_id))
+    }
+
+    /// 在给定帧上对场景的全部锚点打分，不再额外截图
+    /// 通过判定按命中个数 (logic / min_matches)，返回值为命中锚点的 weight 之和
+    fn score_scene(&self, frame: &Frame, target_id: &str) -> usize {
+        if let Some(scene) = self.scenes.get(target_id) {
+            if scene.anchors.is_none() { return 0; }
+            let anchors = scene.anchors.as_ref().unwrap();
+            let mut score = 0;
+            let mut hits = 0;
+            let mut total_checks = 0;
+            if let Some(texts) = &anchors.text {
+                for t in texts {
+                    total_checks += 1;
+                    if self.interface.check_text_anchor(frame, t.rect, &t.val, t.fuzz) { hits += 1; score += t.weight as usize; }
+                }
+            }
+            if let Some(colors) = &anchors.color {
+                for c in colors {
+                    total_checks += 1;
+                    if self.interface.check_color_anchor(frame, c) { hits += 1; score += c.weight as usize; }
+                }
+            }
+            if let Some(images) = &anchors.image {
+                for a in images {
+                    total_checks += 1;
+                    if self.interface.check_image_anchor(frame, a) { hits += 1; score += a.weight as usize; }
+                }
+            }
+            let passed = total_checks > 0 && hits >= scene.required_matches(total_checks);
+            // weight 全为 0 时仍需返回非零分数，否则会被当作未命中
+            if passed { return score.max(1); }
+        }
+        0
+    }
+
+    pub fn identify_current_scene(&self, hint: Option<&str>) -> Option<String> {
+        debug!("👀 扫描当前界面...");
+        // 整轮扫描共用一帧，保证各场景在同一画面上比较
+        let frame = match self.interface.capture_frame() {
+            Some(f) => f,
+            None => { error!("❌ 截图失败"); return None; }
+        };
+        if let Some(target_id) = hint {
+            if self.score_scene(&frame, target_id) > 0 {
+                info!("✅ 命中预期目标: [{}]", target_id);
+                return Some(target_id.to_string());
+            }
+        }
+        let ranked = self.rank_scenes(&frame);
+        if let [(first, s1), (second, s2), ..] = ranked.as_slice() {
+            if s1 == s2 {
+                warn!("⚠️ 场景 [{}] 与 [{}] 得分相同 ({})，锚点可能重叠", first, second, s1);
+            }
+        }
+        let best = ranked.into_iter().next();
+        if let Some((id, score)) = &best { info!("✅ 定位: [{}] (得分: {})", id, score); }
+        best.map(|(id, _)| id)
+    }
+
+    /// 对所有场景打分，返回通过判定的 (场景 id, 得分)，按得分从高到低排序 (同分按 id)
+    /// 多个场景同时通过说明锚点有重叠，调用方可据此告警或要求唯一匹配
+    pub fn identify_all(&self) -> Vec<(String, usize)> {
+        match self.interface.capture_frame() {
+            Some(frame) => self.rank_scenes(&frame),
+            None => { error!("❌ 截图失败"); Vec::new() }
         }
-        if let Some(id) = &best_match { println!("✅ 定位: [{}] (得分: {})", id, max_score); }
-        best_match
+    }
+
+    fn rank_scenes(&self, frame: &Frame) -> Vec<(String, usize)> {
+        let mut ranked: Vec<(String, usize)> = self
+            .scenes
+            .keys()
+            .map(|id| (id.clone(), self.score_scene(frame, id)))
+            .filter(|(_, score)| *score > 0)
+            .collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked
     }
 
     fn wait_for_scene(&self, target_id: &str, timeout_ms: u64) -> bool {
         let start = Instant::now();
-        println!("    👀 确认进入 [{}]...", target_id);
+        debug!("    👀 确认进入 [{}]...", target_id);
         while start.elapsed().as_millis() < timeout_ms as u128 {
             if self.get_match_score(target_id) > 0 {
-                println!("    ✅ 确认到达 (耗时 {}ms)", start.elapsed().as_millis());
+                info!("    ✅ 确认到达 (耗时 {}ms)", start.elapsed().as_millis());
                 return true;
             }
             thread::sleep(Duration::from_millis(200));
         }
-        println!("    ⚠️ 等待超时 [{}]", target_id);
+        warn!("    ⚠️ 等待超时 [{}]", target_id);
         false
     }
 
+    /// 等待场景 scene_id 不再匹配 (锚点消失)，在 timeout_ms 内确认消失返回 true
+    /// 截图失败不算消失；没有锚点的场景无法判定，直接返回 false
+    pub fn wait_for_scene_gone(&self, scene_id: &str, timeout_ms: u64) -> bool {
+        if self.scenes.get(scene_id).is_none_or(|s| s.anchor_count() == 0) {
+            warn!("    ⚠️ [{}] 没有锚点，无法判断是否消失", scene_id);
+            return false;
+        }
+        let start = Instant::now();
+        debug!("    👀 等待 [{}] 消失...", scene_id);
+        while start.elapsed().as_millis() < timeout_ms as u128 {
+            if self.try_match_score(scene_id) == Some(0) {
+                info!("    ✅ [{}] 已消失 (耗时 {}ms)", scene_id, start.elapsed().as_millis());
+                return true;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        warn!("    ⚠️ 等待 [{}] 消失超时", scene_id);
+        false
+    }
+
+    /// 导航失败时的现场转储 (需开启 debug_dump)：保存整屏截图 fail_<时间戳>_<场景>.png，
+    /// 期望场景的锚点框命中画绿色、未命中画红色；每个锚点的期望值与实际识别结果写入同名 .txt
+    fn dump_failure(&self, expected: Option<&str>) {
+        let dir = match &self.interface.debug_dump { Some(d) => d, None => return };
+        let frame = match self.interface.capture_frame() { Some(f) => f, None => return };
+        if let Err(e) = fs::create_dir_all(dir) {
+            warn!("⚠️ [Dump] 无法创建调试目录 {:?}: {}", dir, e);
+            return;
+        }
+        let (hit, miss) = (image::Rgba([0, 255, 0, 255]), image::Rgba([255, 0, 0, 255]));
+        let mut canvas = frame.img.clone();
+        let mut notes = vec![format!("期望场景: {}", expected.unwrap_or("(未知)"))];
+
+        let anchors = expected.and_then(|id| self.scenes.get(id)).and_then(|s| s.anchors.as_ref());
+        if let Some(anchors) = anchors {
+            for (i, t) in anchors.text.iter().flatten().enumerate() {
+                let ocr = frame.crop(t.rect).map(|a| self.interface.ocr_image(a, &t.val, self.interface.ocr_preprocess)).unwrap_or_default();
+                let ok = text_matches(&ocr, &t.val, t.fuzz);
+                draw_rect_outline(&mut canvas, t.rect, if ok { hit } else { miss });
+                notes.push(format!("[文字#{}] {:?} 期望 \"{}\" 识别 \"{}\" -> {}", i + 1, t.rect, t.val, ocr, if ok { "命中" } else { "未命中" }));
+            }
+            for (i, c) in anchors.color.iter().flatten().enumerate() {
+                let ok = self.interface.check_color_anchor(&frame, c);
+                let r = c.r.unwrap_or(0) as i32 + 3;
+                let [x, y] = c.pos;
+                draw_rect_outline(&mut canvas, [x - r, y - r, x + r, y + r], if ok { hit } else { miss });
+                let actual = frame.average(x, y, c.r.unwrap_or(0)).map(|p| format!("#{:02X}{:02X}{:02X}", p[0], p[1], p[2]));
+                notes.push(format!("[颜色#{}] {:?} 期望 {} 实际 {} -> {}", i + 1, c.pos, c.val, actual.unwrap_or_default(), if ok { "命中" } else { "未命中" }));
+            }
+            for (i, a) in anchors.image.iter().flatten().enumerate() {
+                let ok = self.interface.check_image_anchor(&frame, a);
+                draw_rect_outline(&mut canvas, a.rect, if ok { hit } else { miss });
+                notes.push(format!("[图片#{}] {:?} 模板 {} -> {}", i + 1, a.rect, a.path, if ok { "命中" } else { "未命中" }));
+            }
+        }
+
+        let safe_label: String = expected.unwrap_or("unknown").chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+        let stamp = chrono::Local::now().format("%Y%m%d_%H%M%S_%3f");
+        let path = dir.join(format!("fail_{}_{}.png", stamp, safe_label));
+        match canvas.save(&path) {
+            Ok(()) => info!("🖼️ [Dump] 失败现场已保存: {:?}", path),
+            Err(e) => warn!("⚠️ [Dump] 失败截图保存失败 {:?}: {}", path, e),
+        }
+        if let Err(e) = fs::write(path.with_extension("txt"), notes.join("\n")) {
+            warn!("⚠️ [Dump] 失败说明保存失败: {}", e);
+        }
+    }
+
     pub fn navigate(&self, target_id: &str) -> NavResult {
-        let start_id = match self.identify_current_scene(None) {
+        let started = Instant::now();
+        let deadline = self.nav_timeout.map(|t| started + t);
+        let mut current = match self.identify_current_scene(None) {
             Some(id) => id,
-            None => { println!("❌ 无法定位起点"); return NavResult::Failed; }
-        };
-        if start_id == target_id {
-            println!("✅ 已在目标位置");
-            return NavResult::Success;
-        }
-        println!("🤖 规划路径: [{}] -> [{}]", start_id, target_id);
-        let path = match self.find_path(&start_id, target_id) {
-            Some(p) => p,
-            None => { println!("❌ 无路可走"); return NavResult::Failed; }
+            None => {
+                error!("❌ 无法定位起点");
+                self.dump_failure(None);
+                return NavResult::Failed;
+            }
         };
-        for (i, step) in path.iter().enumerate() {
-            println!("\n➡️  [步骤 {}/{}] 点击 -> [{}]", i+1, path.len(), step.target);
-            self.interface.perform_click(step.coords[0], step.coords[1]);
-            
-            // ✨ 核心修改：检查是否需要移交控制权
-            // 如果 TOML 里写了 handler = "xxx"，或者它是无锚点的虚拟节点，则移交
-            let (should_handover, handler_name) = if let Some(s) = self.scenes.get(&step.target) {
-                // 如果有 handler 字段，或者没有锚点，都视为需要移交
-                (s.handler.is_some() || s.anchors.is_none(), s.handler.clone())
-            } else { 
-                (false, None) 
+        let mut replans = 0;
+
+        'plan: loop {
+            if current == target_id {
+                info!("✅ 已在目标位置");
+                return NavResult::Success;
+            }
+            info!("🤖 规划路径: [{}] -> [{}]", current, target_id);
+            let path = match self.find_path(&current, target_id) {
+                Some(p) => p,
+                None => { error!("❌ 无路可走"); return NavResult::Failed; }
             };
 
-            if should_handover {
-                println!("🚀 到达托管节点 [{}]，触发处理器: {:?}", step.target, handler_name);
-                thread::sleep(Duration::from_millis(step.post_delay));
-                // 将 handler 名称一并返回给 main
-                return NavResult::Handover(step.target.clone(), handler_name);
+            let mut prev = current.clone();
+            for (i, step) in path.iter().enumerate() {
+                let mut attempts = 0;
+                loop {
+                    if deadline.is_some_and(|d| Instant::now() >= d) {
+                        info!("⏰ 导航总超时 (已用 {}ms)，卡在 [{}] -> [{}]", started.elapsed().as_millis(), prev, step.target);
+                        self.dump_failure(Some(&step.target));
+                        return NavResult::Failed;
+                    }
+                    info!("➡️  [步骤 {}/{}] 点击 -> [{}]", i+1, path.len(), step.target);
+                    if step.pre_delay > 0 {
+                        thread::sleep(Duration::from_millis(step.pre_delay));
+                    }
+                    let (cx, cy) = self.jittered(step.coords);
+                    self.interface.perform_click(cx, cy);
+
+                    // ✨ 核心修改：检查是否需要移交控制权
+                    // 如果 TOML 里写了 handler = "xxx"，或者它是无锚点的虚拟节点，则移交
+                    let (should_handover, handler_name) = if let Some(s) = self.scenes.get(&step.target) {
+                        // 如果有 handler 字段，或者没有锚点，都视为需要移交
+                        (s.handler.is_some() || s.anchors.is_none(), s.handler.clone())
+                    } else { 
+                        (false, None) 
+                    };
+
+                    if should_handover {
+                        info!("🚀 到达托管节点 [{}]，触发处理器: {:?}", step.target, handler_name);
+                        thread::sleep(Duration::from_millis(step.post_delay));
+                        // 将 handler 名称一并返回给 main
+                        return NavResult::Handover(step.target.clone(), handler_name);
+                    }
+
+                    let mut timeout = if step.post_delay < 2000 { 2000 } else { step.post_delay };
+                    // 每步超时不能超过总时限的剩余部分
+                    if let Some(d) = deadline {
+                        timeout = timeout.min(d.saturating_duration_since(Instant::now()).as_millis() as u64);
+                    }
+                    let done = match step.wait_mode {
+                        WaitMode::Disappear if self.scenes.get(&prev).is_some_and(|s| s.anchor_count() > 0) => {
+                            // 旧界面消失只说明点击生效了：目标界面可能还在加载，也可能弹出了别的窗口，
+                            // 仍要在剩余时间内确认目标出现，否则交给下面的重新定位
+                            let wait_started = Instant::now();
+                            self.wait_for_scene_gone(&prev, timeout)
+                                && self.wait_for_scene(&step.target, timeout.saturating_sub(wait_started.elapsed().as_millis() as u64))
+                        }
+                        _ => self.wait_for_scene(&step.target, timeout),
+                    };
+                    if done { break; }
+
+                    // 超时后重新定位：点击可能被加载动画吞掉，也可能跳到了别的界面
+                    match self.identify_current_scene(Some(&prev)) {
+                        Some(id) if id == step.target => break,
+                        Some(id) if id == prev && attempts < self.step_retries => {
+                            attempts += 1;
+                            info!("🔁 仍停留在 [{}]，重试点击 ({}/{})", prev, attempts, self.step_retries);
+                        }
+                        Some(id) if id != prev && replans < MAX_REPLANS => {
+                            replans += 1;
+                            info!("🧭 意外到达 [{}]，重新规划路径 ({}/{})", id, replans, MAX_REPLANS);
+                            current = id;
+                            continue 'plan;
+                        }
+                        _ => {
+                            error!("❌ 导航中断: 未能进入 [{}]", step.target);
+                            self.dump_failure(Some(&step.target));
+                            return NavResult::Failed;
+                        }
+                    }
+                }
+                prev = step.target.clone();
+                thread::sleep(Duration::from_millis(300));
             }
+            info!("✅ 导航完成");
+            return NavResult::Success;
+        }
+    }
 
-            let timeout = if step.post_delay < 2000 { 2000 } else { step.post_delay };
-            if !self.wait_for_scene(&step.target, timeout) {
-                println!("❌ 导航中断: 未能进入 [{}]", step.target);
-                return NavResult::Failed;
+    /// 导航失败后的场景级恢复：识别当前界面，执行它在 recovery 中声明的动作
+    /// 返回 false 表示无法定位当前界面或该界面没有声明恢复动作，调用方应回退到通用重置
+    pub fn recover(&self) -> bool {
+        let Some(id) = self.identify_current_scene(None) else { return false };
+        let Some(actions) = self.scenes.get(&id).and_then(|s| s.recovery.as_ref()).filter(|a| !a.is_empty()) else {
+            return false;
+        };
+        info!("🩹 [恢复] 在 [{}] 执行 {} 步恢复动作", id, actions.len());
+        for action in actions {
+            match (&action.coords, &action.key) {
+                (Some(c), _) => {
+                    let (x, y) = self.jittered(*c);
+                    self.interface.perform_click(x, y);
+                }
+                (None, Some(k)) => self.interface.perform_key(k),
+                (None, None) => continue,
             }
-            thread::sleep(Duration::from_millis(300));
+            thread::sleep(Duration::from_millis(action.post_delay));
         }
-        println!("✅ 导航完成");
-        NavResult::Success
+        true
     }
 
+    /// Dijkstra 寻路：最小化路径上各跳 cost 之和
     fn find_path(&self, start: &str, target: &str) -> Option<Vec<Transition>> {
         if start == target { return Some(vec![]); }
-        let mut queue = VecDeque::from([start.to_string()]);
+        let mut heap = BinaryHeap::from([Reverse((0u64, start.to_string()))]);
         let mut came_from: HashMap<String, (String, Transition)> = HashMap::new();
-        let mut visited = vec![start.to_string()];
-        while let Some(curr) = queue.pop_front() {
+        let mut dist: HashMap<String, u64> = HashMap::from([(start.to_string(), 0)]);
+        while let Some(Reverse((d, curr))) = heap.pop() {
+            // 已有更短路径到达该节点，跳过过期的堆元素
+            if dist.get(&curr).is_some_and(|&best| d > best) { continue; }
             if curr == target {
                 let mut path = vec![];
                 let mut p = target.to_string();
@@ -367,8 +1474,11 @@ impl NavEngine {
             if let Some(scene) = self.scenes.get(&curr) {
                 if let Some(trans) = &scene.transitions {
                     for t in trans {
-                        if !visited.contains(&t.target) {
-                            visited.push(t.target.clone()); queue.push_back(t.target.clone()); came_from.insert(t.target.clone(), (curr.clone(), t.clone()));
+                        let next = d + t.cost();
+                        if dist.get(&t.target).is_none_or(|&best| next < best) {
+                            dist.insert(t.target.clone(), next);
+                            heap.push(Reverse((next, t.target.clone())));
+                            came_from.insert(t.target.clone(), (curr.clone(), t.clone()));
                         }
                     }
                 }
@@ -376,4 +1486,920 @@ impl NavEngine {
         }
         None
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::hardware::{EventType, HardwareDriver, InputDriver, RecordingSink, VecSink};
+    use crate::hid;
+
+    /// 内存中的"屏幕"：测试可随时替换画面，并统计被截图的次数
+    #[derive(Clone)]
+    pub(crate) struct FakeScreen {
+        frame: Arc<Mutex<image::RgbaImage>>,
+        captures: Arc<AtomicUsize>,
+    }
+
+    impl FakeScreen {
+        pub(crate) fn new() -> Self {
+            Self {
+                frame: Arc::new(Mutex::new(image::RgbaImage::from_pixel(1920, 1080, image::Rgba([0, 0, 0, 255])))),
+                captures: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        /// 以 (x, y) 为中心画一个 5x5 的纯色块
+        pub(crate) fn paint(&self, x: u32, y: u32, rgb: [u8; 3]) {
+            let mut img = self.frame.lock().unwrap();
+            for py in y - 2..=y + 2 {
+                for px in x - 2..=x + 2 {
+                    img.put_pixel(px, py, image::Rgba([rgb[0], rgb[1], rgb[2], 255]));
+                }
+            }
+        }
+
+        fn captures(&self) -> usize {
+            self.captures.load(Ordering::Relaxed)
+        }
+    }
+
+    impl ScreenSource for FakeScreen {
+        fn capture(&self) -> Result<image::RgbaImage, String> {
+            self.captures.fetch_add(1, Ordering::Relaxed);
+            Ok(self.frame.lock().unwrap().clone())
+        }
+
+        fn capture_area(&self, x: i32, y: i32, w: u32, h: u32) -> Result<image::RgbaImage, String> {
+            self.captures.fetch_add(1, Ordering::Relaxed);
+            Ok(image::imageops::crop_imm(&*self.frame.lock().unwrap(), x as u32, y as u32, w, h).to_image())
+        }
+    }
+
+    /// 1920x1080、输出到内存 sink 的拟人驱动
+    pub(crate) fn test_driver() -> (Arc<Mutex<HumanDriver>>, VecSink) {
+        let sink = VecSink::new();
+        let hw = HardwareDriver::builder()
+            .sink(Box::new(sink.clone()))
+            .screen(1920, 1080)
+            .frame_delay(Duration::ZERO)
+            .split_frame_delay(Duration::ZERO)
+            .build()
+            .unwrap();
+        let device: Arc<Mutex<Box<dyn InputDriver>>> = Arc::new(Mutex::new(Box::new(hw)));
+        (Arc::new(Mutex::new(HumanDriver::new(device, 960, 540))), sink)
+    }
+
+    /// 用 TOML 文本和假屏幕构造引擎 (不创建 OCR 引擎，测试只用颜色/图片锚点)
+    pub(crate) fn engine_on(toml_text: &str, screen: &FakeScreen) -> NavEngine {
+        engine_with_sink(toml_text, screen).0
+    }
+
+    /// 同 engine_on，另外返回驱动写入键鼠帧的 sink
+    fn engine_with_sink(toml_text: &str, screen: &FakeScreen) -> (NavEngine, VecSink) {
+        let root: TomlRoot = toml::from_str(toml_text).unwrap();
+        let (driver, sink) = test_driver();
+        let engine = NavEngine::with_interface(root, GameInterface::with_source(driver, None, Box::new(screen.clone())));
+        (engine, sink)
+    }
+
+    /// 记录日志的测试 logger：各测试并行运行，每个线程只收集自己发出的日志
+    struct CaptureLog;
+
+    thread_local! {
+        static CAPTURED: std::cell::RefCell<Vec<(log::Level, String)>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    impl log::Log for CaptureLog {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            CAPTURED.with(|c| c.borrow_mut().push((record.level(), record.args().to_string())));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// 运行 f 并返回期间本线程发出的日志
+    fn capture_logs(f: impl FnOnce()) -> Vec<(log::Level, String)> {
+        static INSTALL: std::sync::Once = std::sync::Once::new();
+        INSTALL.call_once(|| {
+            log::set_logger(&CaptureLog).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+        CAPTURED.with(|c| c.borrow_mut().clear());
+        f();
+        CAPTURED.with(|c| c.take())
+    }
+
+    #[test]
+    fn empty_ocr_result_is_logged_as_a_warning() {
+        // 没有 OCR 引擎，识别结果必然为空
+        let engine = engine_on("scenes = []", &FakeScreen::new());
+        let mut text = None;
+        let logs = capture_logs(|| text = Some(engine.ocr_area([100, 100, 300, 150])));
+
+        assert_eq!(text.as_deref().map(str::trim), Some(""));
+        assert!(
+            logs.iter().any(|(level, msg)| *level == log::Level::Warn && msg.contains("未识别到文字")),
+            "{:?}",
+            logs
+        );
+    }
+
+    #[test]
+    fn one_capture_serves_every_anchor_of_a_scene() {
+        let screen = FakeScreen::new();
+        for x in [100, 200, 300] {
+            screen.paint(x, 100, [255, 0, 0]);
+        }
+        let engine = engine_on(
+            r##"
+            [[scenes]]
+            id = "lobby"
+            [scenes.anchors]
+            color = [
+                { pos = [100, 100], val = "#FF0000", tol = 10 },
+                { pos = [200, 100], val = "#FF0000", tol = 10 },
+                { pos = [300, 100], val = "#FF0000", tol = 10 },
+            ]
+            "##,
+            &screen,
+        );
+
+        assert_eq!(engine.get_match_score("lobby"), 3);
+        assert_eq!(screen.captures(), 1);
+        assert_eq!(engine.capture_count(), 1);
+    }
+
+    #[test]
+    fn find_path_prefers_a_cheaper_multi_hop_route_over_a_costly_direct_one() {
+        let screen = FakeScreen::new();
+        let engine = engine_on(
+            r##"
+            [[scenes]]
+            id = "lobby"
+            transitions = [
+                { target = "shop", coords = [100, 100], cost = 9000 },
+                { target = "menu", coords = [200, 100], cost = 1000 },
+                { target = "bag", coords = [300, 100], cost = 1500 },
+            ]
+
+            [[scenes]]
+            id = "menu"
+            transitions = [{ target = "shop", coords = [400, 100], cost = 1000 }]
+
+            [[scenes]]
+            id = "bag"
+            transitions = [{ target = "shop", coords = [500, 100], cost = 1000 }]
+
+            [[scenes]]
+            id = "shop"
+            "##,
+            &screen,
+        );
+
+        let route = |from: &str, to: &str| -> Vec<String> {
+            engine.find_path(from, to).unwrap().into_iter().map(|t| t.target).collect()
+        };
+        // 直达 9000 > 经菜单 2000 < 经背包 2500
+        assert_eq!(route("lobby", "shop"), ["menu", "shop"]);
+        assert_eq!(route("menu", "shop"), ["shop"]);
+        assert!(engine.find_path("shop", "lobby").is_none());
+    }
+
+    #[test]
+    fn ocr_debug_images_are_only_written_when_dumping_is_enabled() {
+        let dir = std::env::temp_dir().join(format!("nzm_ocr_dump_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let screen = FakeScreen::new();
+        let mut engine = engine_on(
+            r##"
+            [[scenes]]
+            id = "lobby"
+            [scenes.anchors]
+            text = [{ rect = [100, 100, 300, 140], val = "开始游戏" }]
+            "##,
+            &screen,
+        );
+
+        engine.get_match_score("lobby");
+        assert!(!dir.exists());
+
+        engine.set_debug_dump(Some(dir.clone()));
+        engine.get_match_score("lobby");
+        let files: Vec<String> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().to_string()).collect();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(files.len(), 1);
+        assert!(files[0].starts_with("ocr_") && files[0].ends_with("_开始游戏.png"), "{}", files[0]);
+    }
+
+    #[test]
+    fn a_failed_navigation_dumps_an_annotated_screenshot() {
+        let dir = std::env::temp_dir().join(format!("nzm_fail_dump_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let screen = FakeScreen::new();
+        screen.paint(100, 100, [255, 0, 0]);
+        let mut engine = engine_on(
+            r##"
+            [[scenes]]
+            id = "lobby"
+            transitions = [{ target = "shop", coords = [960, 540], post_delay = 0 }]
+            [scenes.anchors]
+            color = [{ pos = [100, 100], val = "#FF0000", tol = 10 }]
+
+            [[scenes]]
+            id = "shop"
+            [scenes.anchors]
+            color = [{ pos = [300, 300], val = "#00FF00", tol = 10 }]
+            "##,
+            &screen,
+        );
+        engine.set_click_jitter(0);
+        engine.set_nav_timeout(Some(Duration::from_millis(300)));
+        engine.set_debug_dump(Some(dir.clone()));
+
+        // 商店始终不出现
+        assert_eq!(engine.navigate("shop"), NavResult::Failed);
+
+        let files: Vec<PathBuf> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+        let png = files.iter().find(|p| p.extension().is_some_and(|e| e == "png")).cloned();
+        let notes = png.as_ref().and_then(|p| fs::read_to_string(p.with_extension("txt")).ok());
+        let img = png.as_ref().and_then(|p| image::open(p).ok()).map(|i| i.to_rgba8());
+        let _ = fs::remove_dir_all(&dir);
+
+        let png = png.expect("没有保存失败截图");
+        assert!(png.file_name().unwrap().to_string_lossy().ends_with("_shop.png"), "{:?}", png);
+        let img = img.unwrap();
+        assert!(img.width() > 0 && img.height() > 0);
+        // 未命中的锚点框画成红色
+        assert_eq!(img.get_pixel(297, 300), &image::Rgba([255, 0, 0, 255]));
+        let notes = notes.unwrap();
+        assert!(notes.contains("期望场景: shop"), "{}", notes);
+        assert!(notes.contains("[颜色#1] [300, 300] 期望 #00FF00 实际 #000000 -> 未命中"), "{}", notes);
+    }
+
+    #[test]
+    fn charset_filter_keeps_only_whitelisted_characters() {
+        const WAVE: &str = "0123456789/波次";
+        // 常见误读：S/I/日 混进斜杠两侧，边框读成 | 和 】
+        assert_eq!(filter_charset("|12SI/30日 波次】", WAVE), "12/30 波次");
+        assert_eq!(filter_charset("第 3 波次：Ｏ", WAVE), " 3 波次");
+        // 空白保留，多次曝光拼接的结果不会粘在一起
+        assert_eq!(filter_charset("7/30波次 7/3O波次", WAVE), "7/30波次 7/3波次");
+        assert_eq!(filter_charset("abc", WAVE), "");
+        assert_eq!(filter_charset("12/30", ""), "");
+    }
+
+    #[test]
+    fn fuzzy_text_matching_tolerates_ocr_noise_within_fuzz() {
+        // 全角数字/字母转半角，空白和标点去掉，字母转小写
+        assert_eq!(normalize_ocr_text("波次 １２/３０"), "波次1230".chars().collect::<Vec<_>>());
+        assert_eq!(normalize_ocr_text("Ｗａｖｅ，\u{3000}OK!"), "waveok".chars().collect::<Vec<_>>());
+
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(min_substring_distance(&chars("第波次1开始"), &chars("波次1")), 0);
+        assert_eq!(min_substring_distance(&chars("波欠1"), &chars("波次1")), 1);
+        assert_eq!(min_substring_distance(&chars("xx"), &chars("波次1")), 3);
+
+        // 不填 fuzz：精确包含，空格差异也算不匹配
+        assert!(text_matches("当前波次1", "波次1", None));
+        assert!(!text_matches("当前波次 1", "波次1", None));
+        // fuzz = 1：全角、空白差异和一个误读字符都能容忍
+        assert!(text_matches("当前波次 １", "波次1", Some(1)));
+        assert!(text_matches("开始游截", "开始游戏", Some(1)));
+        assert!(text_matches("波次O", "波次0", Some(1)));
+        // 超出容错：两个字符错误
+        assert!(!text_matches("开台游截", "开始游戏", Some(1)));
+        assert!(text_matches("开台游截", "开始游戏", Some(2)));
+    }
+
+    #[test]
+    fn averaging_a_radius_tolerates_a_noisy_center_pixel() {
+        let screen = FakeScreen::new();
+        screen.paint(500, 500, [255, 0, 0]);
+        screen.frame.lock().unwrap().put_pixel(500, 500, image::Rgba([0, 0, 0, 255]));
+        let engine = engine_on("scenes = []", &screen);
+
+        // 单点采样正好取到噪点；5x5 平均后噪点只占 1/25
+        assert!(!engine.check_color([500, 500], "#FF0000", 10, None));
+        assert!(engine.check_color([500, 500], "#FF0000", 10, Some(2)));
+
+        let frame = Frame { img: screen.frame.lock().unwrap().clone(), origin: (0, 0) };
+        assert_eq!(frame.average(500, 500, 2), Some([244, 0, 0]));
+        // 越界部分不参与平均
+        assert_eq!(frame.average(0, 0, 2), Some([0, 0, 0]));
+        assert_eq!(frame.average(-10, -10, 2), None);
+    }
+
+    #[test]
+    fn hsv_mode_matches_a_dimmed_button_but_not_a_hue_shift() {
+        let blue = [60, 120, 220];
+        let dimmed = [30, 60, 110];
+        // RGB 模式把变暗当成不同颜色；HSV 只比较色相和饱和度
+        assert!(!color_matches_rgb(dimmed, blue, 10));
+        assert!(color_matches_hsv(dimmed, blue, 10, None));
+        // 设置 vtol 后明度差也要在容差内
+        assert!(!color_matches_hsv(dimmed, blue, 10, Some(50)));
+        assert!(color_matches_hsv([50, 100, 185], blue, 10, Some(50)));
+        // 同样亮度但色相偏到绿色
+        assert!(!color_matches_hsv([60, 220, 120], blue, 10, None));
+
+        // 接近灰色时色相不可信，只比较饱和度
+        assert!(color_matches_hsv([130, 128, 126], [128, 128, 128], 10, None));
+        assert!(!color_matches_hsv([160, 128, 96], [128, 128, 128], 10, None));
+    }
+
+    #[test]
+    fn monitor_index_picks_that_screen_from_the_list() {
+        // 代替 Screen::all() 的假列表：(名称, 左上角在虚拟桌面中的位置)
+        let screens = || vec![("主屏", (0, 0)), ("副屏", (1920, 0)), ("竖屏", (-1080, -400))];
+        assert_eq!(pick_screen(screens(), 0), Ok(("主屏", (0, 0))));
+        assert_eq!(pick_screen(screens(), 1), Ok(("副屏", (1920, 0))));
+        assert_eq!(pick_screen(screens(), 2), Ok(("竖屏", (-1080, -400))));
+        assert_eq!(pick_screen(screens(), 3), Err("找不到显示器 #3".to_string()));
+    }
+
+    #[test]
+    fn validate_graph_reports_dangling_targets_and_unreachable_scenes() {
+        let screen = FakeScreen::new();
+        let engine = engine_on(
+            r##"
+            [[scenes]]
+            id = "lobby"
+            transitions = [
+                { target = "shop", coords = [100, 100] },
+                { target = "shpo", coords = [200, 100] },
+            ]
+
+            [[scenes]]
+            id = "shop"
+            transitions = [{ target = "lobby", coords = [300, 100] }]
+
+            [[scenes]]
+            id = "settings"
+            "##,
+            &screen,
+        );
+
+        assert_eq!(engine.scenes_list(), ["lobby", "settings", "shop"]);
+        assert_eq!(
+            engine.validate_graph(),
+            ["悬空跳转: [lobby] -> [shpo] (目标场景不存在)", "孤立场景: [settings] (没有任何跳转指向它)"]
+        );
+    }
+
+    #[test]
+    fn validate_graph_reports_anchors_outside_scan_rect_and_bad_recovery() {
+        let screen = FakeScreen::new();
+        let engine = engine_on(
+            r##"
+            [[scenes]]
+            id = "lobby"
+            scan_rect = [0, 0, 400, 300]
+            transitions = [{ target = "lobby", coords = [100, 100] }]
+            recovery = [
+                { key = "esc" },
+                { coords = [960, 700], key = "space" },
+                { key = "f13" },
+            ]
+            [scenes.anchors]
+            color = [
+                { pos = [100, 100], val = "#FF0000", tol = 10 },
+                { pos = [398, 100], val = "#00FF00", tol = 10, r = 3 },
+            ]
+            text = [{ rect = [200, 250, 500, 290], val = "开始游戏" }]
+            "##,
+            &screen,
+        );
+
+        assert_eq!(
+            engine.validate_graph(),
+            [
+                "锚点超出 scan_rect: [lobby] 文字 \"开始游戏\" [200, 250, 500, 290] 不在 [0, 0, 400, 300] 内",
+                "锚点超出 scan_rect: [lobby] 颜色 #00FF00 [398, 100] 不在 [0, 0, 400, 300] 内",
+                "恢复动作无效: [lobby] 第 2 步 coords 与 key 只能填一个",
+                "恢复动作无效: [lobby] 第 3 步 无法识别的按键 \"f13\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn an_unknown_ocr_language_is_rejected_and_keeps_the_current_engine() {
+        let screen = FakeScreen::new();
+        let mut engine = engine_on("scenes = []", &screen);
+        assert!(is_language_tag("zh-Hans") && is_language_tag("en-US"));
+        for tag in ["", "en_US", "zh--Hans", "不是语言", "toolongsubtag-x"] {
+            assert!(!engine.set_ocr_language(tag), "{:?}", tag);
+            assert!(engine.interface.ocr_engine.is_none());
+        }
+
+        // 形状合法但系统没有的语言要问过 Windows OCR 才知道
+        #[cfg(windows)]
+        {
+            // 系统装有 OCR 语言时先切到第一个，确保"保持原引擎"是对一个真实引擎而言
+            if let Some(tag) = NavEngine::available_ocr_languages().first() {
+                assert!(engine.set_ocr_language(tag));
+            }
+            let current_tag = |engine: &NavEngine| {
+                let ocr = engine.interface.ocr_engine.as_ref()?;
+                Some(ocr.RecognizerLanguage().ok()?.LanguageTag().ok()?.to_string())
+            };
+            let before = current_tag(&engine);
+            assert!(!engine.set_ocr_language("xx-Nope"));
+            assert_eq!(current_tag(&engine), before);
+        }
+    }
+
+    #[test]
+    fn identify_all_lists_every_scene_that_matches_the_screen() {
+        let screen = FakeScreen::new();
+        screen.paint(100, 100, [255, 0, 0]);
+        screen.paint(200, 100, [0, 255, 0]);
+        let engine = engine_on(
+            r##"
+            [[scenes]]
+            id = "lobby"
+            [scenes.anchors]
+            color = [
+                { pos = [100, 100], val = "#FF0000", tol = 10 },
+                { pos = [200, 100], val = "#00FF00", tol = 10 },
+            ]
+
+            [[scenes]]
+            id = "lobby_popup"
+            logic = "or"
+            [scenes.anchors]
+            color = [
+                { pos = [100, 100], val = "#FF0000", tol = 10 },
+                { pos = [300, 100], val = "#0000FF", tol = 10 },
+            ]
+
+            [[scenes]]
+            id = "shop"
+            [scenes.anchors]
+            color = [{ pos = [300, 100], val = "#0000FF", tol = 10 }]
+            "##,
+            &screen,
+        );
+
+        assert_eq!(engine.identify_all(), [("lobby".to_string(), 2), ("lobby_popup".to_string(), 1)]);
+        assert_eq!(engine.identify_current_scene(None).as_deref(), Some("lobby"));
+    }
+
+    #[test]
+    fn k_of_n_logic_passes_only_with_enough_anchors_hit() {
+        // 三个锚点中只有前两个出现在屏幕上
+        let screen = FakeScreen::new();
+        screen.paint(100, 100, [255, 0, 0]);
+        screen.paint(200, 100, [255, 0, 0]);
+        let scene = |id: &str, logic: &str| {
+            format!(
+                r##"
+                [[scenes]]
+                id = "{}"
+                {}
+                [scenes.anchors]
+                color = [
+                    {{ pos = [100, 100], val = "#FF0000", tol = 10 }},
+                    {{ pos = [200, 100], val = "#FF0000", tol = 10 }},
+                    {{ pos = [300, 100], val = "#FF0000", tol = 10 }},
+                ]
+                "##,
+                id, logic
+            )
+        };
+        let toml_text = [
+            scene("two_of_three", r#"logic = "k:2""#),
+            scene("all_three", r#"logic = "k:3""#),
+            scene("min_two", "min_matches = 2"),
+            scene("typo", r#"logic = "most""#),
+        ]
+        .concat();
+        let engine = engine_on(&toml_text, &screen);
+
+        let passed: Vec<String> = engine.identify_all().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(passed, ["min_two", "two_of_three"]);
+
+        let logic_problems: Vec<String> =
+            engine.validate_graph().into_iter().filter(|p| !p.starts_with("孤立场景")).collect();
+        assert_eq!(logic_problems, [r#"无法识别的 logic: [typo] "most" (可用 and / or / k:N)"#]);
+    }
+
+    #[test]
+    fn one_heavy_anchor_outranks_two_light_ones() {
+        let screen = FakeScreen::new();
+        for x in [100, 200, 300] {
+            screen.paint(x, 100, [255, 0, 0]);
+        }
+        let engine = engine_on(
+            r##"
+            [[scenes]]
+            id = "generic"
+            [scenes.anchors]
+            color = [
+                { pos = [100, 100], val = "#FF0000", tol = 10 },
+                { pos = [200, 100], val = "#FF0000", tol = 10 },
+            ]
+
+            [[scenes]]
+            id = "title"
+            [scenes.anchors]
+            color = [{ pos = [300, 100], val = "#FF0000", tol = 10, weight = 5 }]
+            "##,
+            &screen,
+        );
+
+        assert_eq!(engine.identify_all(), [("title".to_string(), 5), ("generic".to_string(), 2)]);
+        assert_eq!(engine.identify_current_scene(None).as_deref(), Some("title"));
+    }
+
+    /// 16x16 的斜向渐变图标，水平翻转后与原图负相关
+    fn icon(flipped: bool) -> image::GrayImage {
+        image::GrayImage::from_fn(16, 16, |x, y| {
+            let x = if flipped { 15 - x } else { x };
+            image::Luma([(x * 12 + y * 3) as u8])
+        })
+    }
+
+    #[test]
+    fn image_anchor_matches_identical_crop_and_rejects_a_different_one() {
+        assert!(best_template_score(&icon(false), &icon(false)) > 0.999);
+        assert!(best_template_score(&icon(true), &icon(false)) < 0.0);
+
+        let path = std::env::temp_dir().join(format!("nzm_nav_icon_{}.png", std::process::id()));
+        icon(false).save(&path).unwrap();
+        let screen = FakeScreen::new();
+        let engine = engine_on(
+            &format!(
+                r#"
+                [[scenes]]
+                id = "shop"
+                [scenes.anchors]
+                image = [{{ rect = [500, 300, 520, 320], path = '{}' }}]
+                "#,
+                path.display()
+            ),
+            &screen,
+        );
+        let blit = |img: &image::GrayImage| {
+            let rgba = image::DynamicImage::ImageLuma8(img.clone()).into_rgba8();
+            image::imageops::replace(&mut *screen.frame.lock().unwrap(), &rgba, 502, 302);
+        };
+
+        blit(&icon(false));
+        assert!(engine.get_match_score("shop") > 0);
+        blit(&icon(true));
+        assert_eq!(engine.get_match_score("shop"), 0);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn otsu_threshold_splits_a_two_tone_image() {
+        let img = image::GrayImage::from_fn(20, 10, |x, _| image::Luma([if x < 10 { 40 } else { 210 }]));
+        let t = otsu_threshold(&img);
+        assert!((40..210).contains(&t), "阈值 {}", t);
+    }
+
+    #[test]
+    fn small_faint_text_only_survives_preprocessing_with_otsu() {
+        // 12px 高的小字：灰色 (120) 笔画落在稍暗 (80) 的底色上，固定阈值 140/200 都分不开
+        let fixture = image::RgbaImage::from_fn(60, 12, |x, y| {
+            let stroke = (3..9).contains(&y) && x % 6 < 2;
+            let v = if stroke { 120 } else { 80 };
+            image::Rgba([v, v, v, 255])
+        });
+        let has_text = |img: &image::DynamicImage| {
+            let luma = img.to_luma8();
+            let white = luma.pixels().filter(|p| p[0] == 255).count();
+            white > 0 && white < luma.pixels().len()
+        };
+
+        let raw = ocr_variants(fixture.clone(), false, true);
+        assert_eq!(raw.len(), 1);
+        assert_eq!(raw[0].to_rgba8(), fixture);
+        assert!(!has_text(&raw[0]));
+
+        let fixed = ocr_variants(fixture.clone(), true, false);
+        assert_eq!(fixed.len(), 3);
+        assert_eq!((fixed[0].width(), fixed[0].height()), (180, 36));
+        assert!(!has_text(&fixed[0]) && !has_text(&fixed[1]));
+
+        let with_otsu = ocr_variants(fixture, true, true);
+        assert_eq!(with_otsu.len(), 4);
+        assert!(has_text(&with_otsu[3]));
+    }
+
+    #[test]
+    fn capture_area_converts_physical_pixels_to_logical_coordinates() {
+        assert_eq!(
+            LogicalArea::from_physical(262, 16, 127, 81, 1.0),
+            LogicalArea { x: 262, y: 16, w: 127, h: 81, crop_offset: (0, 0) }
+        );
+        // 150% 缩放：物理 (300, 150) 正好落在逻辑 (200, 100)
+        assert_eq!(
+            LogicalArea::from_physical(300, 150, 90, 45, 1.5),
+            LogicalArea { x: 200, y: 100, w: 60, h: 30, crop_offset: (0, 0) }
+        );
+        // 落在逻辑像素中间时多截一点，再按偏移裁掉
+        assert_eq!(
+            LogicalArea::from_physical(301, 151, 90, 45, 1.5),
+            LogicalArea { x: 200, y: 100, w: 61, h: 31, crop_offset: (1, 1) }
+        );
+    }
+
+    #[test]
+    fn resolution_scale_maps_1080p_rects_onto_1440p() {
+        let scale = ResolutionScale::new((1920.0, 1080.0), (2560.0, 1440.0));
+        assert_eq!(scale.rect([262, 16, 389, 97]), [349, 21, 519, 129]);
+        assert_eq!(scale.point([960, 540]), [1280, 720]);
+
+        assert!(ResolutionScale::new((1920.0, 1080.0), (1920.0, 1080.0)).is_identity());
+        // 未知尺寸 (0) 的方向不缩放
+        assert_eq!(ResolutionScale::new((0.0, 1080.0), (2560.0, 1440.0)).point([100, 300]), [100, 400]);
+    }
+
+    #[test]
+    fn recover_runs_the_actions_declared_by_the_stuck_scene() {
+        let screen = FakeScreen::new();
+        screen.paint(100, 100, [255, 0, 0]);
+        let (mut engine, sink) = engine_with_sink(
+            r##"
+            [[scenes]]
+            id = "reward_popup"
+            recovery = [
+                { key = "esc", post_delay = 0 },
+                { coords = [960, 700], post_delay = 0 },
+            ]
+            [scenes.anchors]
+            color = [{ pos = [100, 100], val = "#FF0000", tol = 10 }]
+
+            [[scenes]]
+            id = "lobby"
+            [scenes.anchors]
+            color = [{ pos = [300, 300], val = "#00FF00", tol = 10 }]
+            "##,
+            &screen,
+        );
+        engine.set_click_jitter(0);
+        sink.clear();
+
+        assert!(engine.recover());
+        let frames = sink.frames();
+        let key = |code: u8, flag: u8| vec![0xAA, EventType::Keyboard as u8, code, flag, 0, 0, 0, 0, 0x00, 0x00, 0x55];
+        assert_eq!(frames[..2], [key(hid::KEY_ESCAPE, 0), key(0, 0x80)]);
+
+        let last_abs = frames.iter().rev().find(|f| f[1] == EventType::MouseAbs as u8).unwrap();
+        let x = u16::from_le_bytes([last_abs[4], last_abs[5]]) as f32 / 32767.0 * 1920.0;
+        let y = u16::from_le_bytes([last_abs[6], last_abs[7]]) as f32 / 32767.0 * 1080.0;
+        assert!((x - 960.0).abs() <= 3.0 && (y - 700.0).abs() <= 3.0, "点击位置 ({}, {})", x, y);
+        let buttons: Vec<u8> = frames.iter().filter(|f| f[1] == EventType::MouseRel as u8).map(|f| f[2]).collect();
+        assert_eq!(buttons, [1, 0]);
+
+        // 卡在没有声明 recovery 的场景：交回调用方做通用重置
+        let screen = FakeScreen::new();
+        screen.paint(300, 300, [0, 255, 0]);
+        let (engine, sink) = engine_with_sink(
+            r##"
+            [[scenes]]
+            id = "lobby"
+            [scenes.anchors]
+            color = [{ pos = [300, 300], val = "#00FF00", tol = 10 }]
+            "##,
+            &screen,
+        );
+        sink.clear();
+        assert!(!engine.recover());
+        assert!(sink.frames().is_empty());
+    }
+
+    /// sink 中左键按下的次数 (每次点击一帧)
+    fn left_clicks(sink: &VecSink) -> usize {
+        sink.frames().iter().filter(|f| f[1] == EventType::MouseRel as u8 && f[2] == 1).count()
+    }
+
+    #[test]
+    fn a_click_eaten_by_a_loading_animation_is_retried() {
+        let screen = FakeScreen::new();
+        screen.paint(100, 100, [255, 0, 0]);
+        let (mut engine, sink) = engine_with_sink(
+            r##"
+            [[scenes]]
+            id = "lobby"
+            transitions = [{ target = "shop", coords = [960, 540], post_delay = 0 }]
+            [scenes.anchors]
+            color = [{ pos = [100, 100], val = "#FF0000", tol = 10 }]
+
+            [[scenes]]
+            id = "shop"
+            [scenes.anchors]
+            color = [{ pos = [300, 300], val = "#00FF00", tol = 10 }]
+            "##,
+            &screen,
+        );
+        engine.set_step_retries(1);
+        sink.clear();
+
+        // 第一次点击被吞掉，第二次点击后商店才出现
+        let flaky = {
+            let (screen, sink) = (screen.clone(), sink.clone());
+            thread::spawn(move || {
+                let give_up = Instant::now() + Duration::from_secs(10);
+                while left_clicks(&sink) < 2 && Instant::now() < give_up {
+                    thread::sleep(Duration::from_millis(20));
+                }
+                screen.paint(100, 100, [0, 0, 0]);
+                screen.paint(300, 300, [0, 255, 0]);
+            })
+        };
+        assert_eq!(engine.navigate("shop"), NavResult::Success);
+        flaky.join().unwrap();
+        assert_eq!(left_clicks(&sink), 2);
+    }
+
+    #[test]
+    fn handover_carries_the_handler_declared_in_toml() {
+        let screen = FakeScreen::new();
+        screen.paint(100, 100, [255, 0, 0]);
+        let engine = engine_on(
+            r##"
+            [[scenes]]
+            id = "lobby"
+            transitions = [
+                { target = "daily_board", coords = [960, 540], post_delay = 0 },
+                { target = "td_room", coords = [960, 700], post_delay = 0 },
+            ]
+            [scenes.anchors]
+            color = [{ pos = [100, 100], val = "#FF0000", tol = 10 }]
+
+            [[scenes]]
+            id = "daily_board"
+            handler = "daily"
+
+            [[scenes]]
+            id = "td_room"
+            "##,
+            &screen,
+        );
+
+        assert_eq!(engine.navigate("daily_board"), NavResult::Handover("daily_board".into(), Some("daily".into())));
+        // 没有写 handler 的虚拟节点也会移交，但不附带处理器
+        assert_eq!(engine.navigate("td_room"), NavResult::Handover("td_room".into(), None));
+    }
+
+    #[test]
+    fn navigation_gives_up_at_the_overall_deadline() {
+        let screen = FakeScreen::new();
+        screen.paint(100, 100, [255, 0, 0]);
+        let mut engine = engine_on(
+            r##"
+            [[scenes]]
+            id = "lobby"
+            transitions = [{ target = "shop", coords = [960, 540], post_delay = 5000 }]
+            [scenes.anchors]
+            color = [{ pos = [100, 100], val = "#FF0000", tol = 10 }]
+
+            [[scenes]]
+            id = "shop"
+            [scenes.anchors]
+            color = [{ pos = [300, 300], val = "#00FF00", tol = 10 }]
+            "##,
+            &screen,
+        );
+        // 商店永远不出现：单步 5 秒超时加 5 次重试本会卡住半分钟
+        engine.set_step_retries(5);
+        engine.set_nav_timeout(Some(Duration::from_millis(1500)));
+
+        let start = Instant::now();
+        assert_eq!(engine.navigate("shop"), NavResult::Failed);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(1500) && elapsed < Duration::from_millis(3000), "耗时 {:?}", elapsed);
+    }
+
+    /// delay 后在 (x, y) 画上 rgb 色块 (黑色即擦除)
+    #[test]
+    fn jittered_clicks_spread_out_but_stay_within_the_radius() {
+        let screen = FakeScreen::new();
+        let mut engine = engine_on("scenes = []", &screen);
+        engine.set_click_jitter(6);
+
+        let points: Vec<(i32, i32)> = (0..200).map(|_| engine.jittered([960, 540])).collect();
+        for &(x, y) in &points {
+            let dist = (((x - 960).pow(2) + (y - 540).pow(2)) as f32).sqrt();
+            // 取整最多再偏出半个像素对角线
+            assert!(dist <= 6.0 + 0.71, "({}, {}) 距离 {:.2}", x, y, dist);
+        }
+        let distinct: std::collections::HashSet<_> = points.iter().collect();
+        assert!(distinct.len() > 20, "200 次点击只有 {} 个不同落点", distinct.len());
+        assert!(points.windows(2).any(|w| w[0] != w[1]));
+
+        engine.set_click_jitter(0);
+        assert!((0..20).all(|_| engine.jittered([960, 540]) == (960, 540)));
+    }
+
+    #[test]
+    fn pre_delay_holds_the_click_back() {
+        // 返回 (导航结果, 第一帧鼠标移动的时刻, 左键按下的时刻)，时刻从驱动创建算起
+        let run = |pre_delay: u64| {
+            let recorder = RecordingSink::new(Box::new(VecSink::new()));
+            let recording = recorder.recording();
+            let hw = HardwareDriver::builder()
+                .sink(Box::new(recorder))
+                .screen(1920, 1080)
+                .frame_delay(Duration::ZERO)
+                .split_frame_delay(Duration::ZERO)
+                .build()
+                .unwrap();
+            let device: Arc<Mutex<Box<dyn InputDriver>>> = Arc::new(Mutex::new(Box::new(hw)));
+            let driver = Arc::new(Mutex::new(HumanDriver::new(device, 960, 540)));
+            let toml_text = format!(
+                r##"
+                [[scenes]]
+                id = "lobby"
+                transitions = [{{ target = "battle", coords = [960, 700], pre_delay = {}, post_delay = 0 }}]
+                [scenes.anchors]
+                color = [{{ pos = [100, 100], val = "#FF0000", tol = 10 }}]
+
+                [[scenes]]
+                id = "battle"
+                handler = "battle"
+                "##,
+                pre_delay
+            );
+            let screen = FakeScreen::new();
+            screen.paint(100, 100, [255, 0, 0]);
+            let root: TomlRoot = toml::from_str(&toml_text).unwrap();
+            let engine = NavEngine::with_interface(root, GameInterface::with_source(driver, None, Box::new(screen)));
+
+            let result = engine.navigate("battle");
+            let frames = recording.frames();
+            let first_move = frames.first().unwrap().offset;
+            let press = frames.iter().find(|f| f.bytes[1] == EventType::MouseRel as u8 && f.bytes[2] == 1).unwrap().offset;
+            (result, first_move, press)
+        };
+
+        let (result, first_move, press) = run(400);
+        assert_eq!(result, NavResult::Handover("battle".into(), Some("battle".into())));
+        assert!(first_move >= Duration::from_millis(400), "移动在 pre_delay 结束前就开始了: {:?}", first_move);
+        assert!(press > first_move);
+
+        let (_, first_move, _) = run(0);
+        assert!(first_move < Duration::from_millis(400), "{:?}", first_move);
+    }
+
+    fn paint_later(screen: &FakeScreen, delay_ms: u64, x: u32, y: u32, rgb: [u8; 3]) -> thread::JoinHandle<()> {
+        let screen = screen.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(delay_ms));
+            screen.paint(x, y, rgb);
+        })
+    }
+
+    const DISAPPEAR_MAP: &str = r##"
+        [[scenes]]
+        id = "lobby"
+        transitions = [{ target = "shop", coords = [960, 540], post_delay = 0, wait_mode = "disappear" }]
+        [scenes.anchors]
+        color = [{ pos = [100, 100], val = "#FF0000", tol = 10 }]
+
+        [[scenes]]
+        id = "shop"
+        [scenes.anchors]
+        color = [{ pos = [300, 300], val = "#00FF00", tol = 10 }]
+        "##;
+
+    #[test]
+    fn wait_for_scene_gone_returns_once_the_anchor_is_removed() {
+        let screen = FakeScreen::new();
+        screen.paint(100, 100, [255, 0, 0]);
+        let engine = engine_on(DISAPPEAR_MAP, &screen);
+
+        assert!(!engine.wait_for_scene_gone("lobby", 300));
+        let erase = paint_later(&screen, 300, 100, 100, [0, 0, 0]);
+        let start = Instant::now();
+        assert!(engine.wait_for_scene_gone("lobby", 3000));
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        erase.join().unwrap();
+        // 没有锚点的场景无法判断
+        assert!(!engine.wait_for_scene_gone("missing", 100));
+    }
+
+    #[test]
+    fn disappear_step_still_waits_for_the_target_to_load() {
+        let screen = FakeScreen::new();
+        screen.paint(100, 100, [255, 0, 0]);
+        let mut engine = engine_on(DISAPPEAR_MAP, &screen);
+        engine.set_click_jitter(0);
+
+        // 大厅很快关掉，商店 1 秒后才加载出来
+        let erase = paint_later(&screen, 200, 100, 100, [0, 0, 0]);
+        let load = paint_later(&screen, 1000, 300, 300, [0, 255, 0]);
+        let start = Instant::now();
+        assert_eq!(engine.navigate("shop"), NavResult::Success);
+        assert!(start.elapsed() >= Duration::from_millis(1000), "目标出现前就返回了");
+        erase.join().unwrap();
+        load.join().unwrap();
+    }
+}
@@ -1,19 +1,23 @@
+use crate::hid;
 use crate::human::HumanDriver;
-use crate::nav::NavEngine;
+use crate::nav::{estimate_vertical_shift, NavEngine, ResolutionScale};
 use regex::Regex;
-use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
+use log::{debug, error, info, warn};
 
 // ==========================================
 // 1. 数据结构协议
 // ==========================================
 
 // ✨ 新增：预备阶段动作定义 (用于 MapMeta)
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type")]
 pub enum PrepAction {
     KeyDown { key: char },
@@ -48,13 +52,42 @@ pub enum InitAction {
     },
 }
 
-#[derive(Debug, Clone)]
+// ✨ 新增：支持从 JSON/TOML 文件加载，缺省字段回落到 Default
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct TDConfig {
     pub hud_check_rect: [i32; 4],
     pub hud_wave_loop_rect: [i32; 4],
     pub safe_zone: [i32; 4],
+    // 以上 rect、安全区以及地图像素都按这个分辨率编写 (默认 1920x1080)；
+    // 实际屏幕不同时，发出点击和截图前按比例换算，内部计算仍在编写分辨率下进行
     pub screen_width: f32,
     pub screen_height: f32,
+    // ✨ 新增：装备栏可携带的陷阱数量
+    pub loadout_slots: usize,
+    // ✨ 新增：每次识别波次的 OCR 采样次数，>1 时取多数值
+    pub wave_vote_samples: usize,
+    // ✨ 新增：波次 OCR 的字符白名单 (如 "0123456789/波次")，设置后先过滤掉其他字符再做正则匹配
+    pub wave_ocr_charset: Option<String>,
+    // ✨ 新增：放置校验失败后的重试次数
+    pub place_retries: u8,
+    // ✨ 新增：进图调整视野后实测一次卷动速度，替代默认的 move_speed
+    pub calibrate_scroll: bool,
+    // ✨ 新增：进度存档目录。设置后每个波次阶段结束时写入 <目录>/<地图名>.progress.json，
+    // 程序重启后自动从中恢复，避免重复放置
+    pub progress_dir: Option<String>,
+    // ✨ 新增：卡死看门狗 (秒)。波次能正常识别但超过该时长仍未推进时，退出塔防交回主程序重新导航；0 表示关闭 (默认)
+    pub stall_timeout_secs: u64,
+    // ✨ 新增：目标点超出安全区的容忍距离 (像素)。越界不超过该值时夹到安全区边缘，超过则不执行该任务
+    pub clamp_tolerance_px: f32,
+    // ✨ 新增：目标点越界时先移动一次视野再判断，仍越界才跳过
+    pub rescroll_out_of_zone: bool,
+    // ✨ 新增：拆除时连按 'E' 的次数 (第二下防止第一下被吞，部分陷阱需要二次确认)
+    pub demolish_key_repeats: u8,
+    // ✨ 新增：连按 'E' 之间的间隔 (毫秒)
+    pub demolish_key_gap_ms: u64,
+    // ✨ 新增：放置前"目标键 -> 干扰键 -> 目标键"强刷陷阱时每次按键之间的间隔 (毫秒)
+    pub build_swap_gap_ms: u64,
 }
 
 impl Default for TDConfig {
@@ -65,6 +98,33 @@ impl Default for TDConfig {
             safe_zone: [200, 200, 1720, 880],
             screen_width: 1920.0,
             screen_height: 1080.0,
+            loadout_slots: 4,
+            wave_vote_samples: 1,
+            wave_ocr_charset: None,
+            place_retries: 1,
+            calibrate_scroll: false,
+            progress_dir: None,
+            stall_timeout_secs: 0,
+            clamp_tolerance_px: 10.0,
+            rescroll_out_of_zone: true,
+            demolish_key_repeats: 2,
+            demolish_key_gap_ms: 100,
+            build_swap_gap_ms: 120,
+        }
+    }
+}
+
+impl TDConfig {
+    /// 从文件加载配置：扩展名为 .toml 时按 TOML 解析，否则按 JSON 解析
+    pub fn from_file(path: &str) -> Result<TDConfig, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("无法读取配置 {}: {}", path, e))?;
+        let is_toml = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+        if is_toml {
+            toml::from_str(&content).map_err(|e| format!("TOML 解析错误 {}: {}", path, e))
+        } else {
+            serde_json::from_str(&content).map_err(|e| format!("JSON 解析错误 {}: {}", path, e))
         }
     }
 }
@@ -77,8 +137,23 @@ pub struct TrapConfigItem {
     pub b_type: String, // "Floor", "Wall", "Ceiling"
     #[serde(default)]
     pub grid_index: [i32; 2], // [col, row]
+    // ✨ 新增：切出该陷阱的按键，不填则按装备栏位置推算 ('4', '5', '6' ...)
+    #[serde(default)]
+    pub hotkey: Option<char>,
+    // ✨ 新增：放置后校验。目标点颜色与 verify_color 匹配才算放置成功，不填则不校验
+    #[serde(default)]
+    pub verify_color: Option<String>,
+    #[serde(default = "default_verify_tol")]
+    pub verify_tol: u8,
+    #[serde(default)]
+    pub verify_radius: Option<u8>,
 }
 
+fn default_verify_tol() -> u8 { 30 }
+
+// 装备栏位置 -> 默认快捷键
+const LOADOUT_KEYS: [char; 6] = ['4', '5', '6', '7', '8', '9'];
+
 // ✨ 修改：MapMeta 增加 prep_actions
 #[derive(Deserialize, Debug, Clone)]
 pub struct MapMeta {
@@ -86,6 +161,9 @@ pub struct MapMeta {
     pub offset_x: f32,
     pub offset_y: f32,
     pub bottom: f32,
+    // ✨ 新增：地图在 X 轴方向的总像素宽度，不填 (0) 表示不需要横向卷动
+    #[serde(default)]
+    pub right: f32,
     #[serde(default)]
     pub prep_actions: Vec<PrepAction>,
 }
@@ -139,11 +217,144 @@ pub struct MapBuildingsExport {
     pub demolishes: Vec<DemolishEvent>,
 }
 
+/// 对局进度存档，用于进程崩溃/重启后接着当前对局继续
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ProgressSnapshot {
+    pub map_name: String,
+    /// 对局所在的导航场景 id，不同场景 (如普通/炼狱) 的存档互不套用
+    #[serde(default)]
+    pub scene_id: String,
+    /// 保存时间 (Unix 秒)
+    #[serde(default)]
+    pub saved_at: i64,
+    pub last_confirmed_wave: i32,
+    pub placed_uids: BTreeSet<usize>,
+    pub completed_upgrade_keys: BTreeSet<String>,
+    pub completed_demolish_uids: BTreeSet<usize>,
+}
+
+impl ProgressSnapshot {
+    pub fn from_file(path: &str) -> Result<ProgressSnapshot, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("无法读取进度存档 {}: {}", path, e))?;
+        serde_json::from_str(&content).map_err(|e| format!("进度存档解析错误 {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| format!("进度存档序列化失败: {}", e))?;
+        fs::write(path, content).map_err(|e| format!("无法写入进度存档 {}: {}", path, e))
+    }
+
+    /// 存档能否套用到当前对局：地图和场景一致、保存时间在 RESUME_MAX_AGE_SECS 以内、
+    /// 当前波次不早于存档波次且最多领先一波。不能套用时返回原因
+    pub fn check_resumable(&self, map_name: &str, scene_id: &str, detected_wave: i32, now: i64) -> Result<(), String> {
+        if self.map_name != map_name {
+            return Err(format!("存档地图 [{}] 与当前地图 [{}] 不符", self.map_name, map_name));
+        }
+        if self.scene_id != scene_id {
+            return Err(format!("存档场景 [{}] 与当前场景 [{}] 不符", self.scene_id, scene_id));
+        }
+        let age = now - self.saved_at;
+        if !(0..=RESUME_MAX_AGE_SECS).contains(&age) {
+            return Err(format!("存档保存于 {} 秒前 (有效期 {} 秒)，视为上一局遗留", age, RESUME_MAX_AGE_SECS));
+        }
+        let wave = self.last_confirmed_wave;
+        if detected_wave < wave || detected_wave > wave + 1 {
+            return Err(format!("当前第 {} 波与存档第 {} 波不连续", detected_wave, wave));
+        }
+        Ok(())
+    }
+}
+
+/// 进度存档的有效期 (秒)。每个任务完成后都会存档，崩溃重启应在这段时间内回到对局；
+/// 更旧的存档只可能来自已经结束的对局，新开一局时不能套用
+pub const RESUME_MAX_AGE_SECS: i64 = 600;
+
+/// 动作日志里的动作类型
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionKind {
+    Demolish,
+    Place,
+    Upgrade,
+}
+
+/// 动作日志中的一条：一个实际执行 (或因越界跳过) 的任务
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ActionLogEntry {
+    /// 本地时间 "YYYY-MM-DD HH:MM:SS.mmm"
+    pub time: String,
+    pub wave: i32,
+    pub is_late: bool,
+    pub kind: ActionKind,
+    pub uid: Option<usize>,
+    pub name: String,
+    /// 切陷阱 / 升级用的按键，拆除为 None
+    pub key: Option<char>,
+    /// 地图像素坐标，升级为 None
+    pub map: Option<[f32; 2]>,
+    /// 按当时视野换算并夹到安全区后的屏幕坐标 (编写分辨率下)，越界跳过时为 None
+    pub screen: Option<[f32; 2]>,
+    /// false 表示越界跳过或放置校验失败
+    pub ok: bool,
+}
+
+/// 单次运行的动作日志，每个波次阶段结束时整体写入 path (JSON 数组)
+struct ActionLog {
+    path: String,
+    wave: i32,
+    is_late: bool,
+    entries: Vec<ActionLogEntry>,
+}
+
+impl ActionLog {
+    fn flush(&self) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(&self.entries).map_err(|e| format!("动作日志序列化失败: {}", e))?;
+        fs::write(&self.path, content).map_err(|e| format!("无法写入动作日志 {}: {}", self.path, e))
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct WaveStatus {
     pub current_wave: i32,
 }
 
+// ✨ 新增：外部控制运行状态 (通过 state_handle 共享的 AtomicU8)
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Running = 0,
+    Paused = 1,
+    Stopped = 2,
+}
+
+impl RunState {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            1 => RunState::Paused,
+            2 => RunState::Stopped,
+            _ => RunState::Running,
+        }
+    }
+}
+
+/// dry_run 模式下记录的计划动作 (屏幕坐标已按当前视野换算)
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlannedAction {
+    Demolish { uid: usize, screen_x: f32, screen_y: f32 },
+    Place { uid: usize, name: String, key: char, screen_x: f32, screen_y: f32 },
+    Upgrade { name: String, key: char, hold_ms: u64 },
+    /// 视野移动的长按
+    KeyHold { key: char, ms: u64 },
+    /// 单击按键 (开局的 O / N、开战的 G)
+    KeyClick { key: char },
+    /// 缩放视野的滚轮：count 帧，每帧 delta
+    Wheel { delta: i8, count: u32 },
+    /// 地图配置的赛前准备动作
+    Prep(PrepAction),
+    /// 选装备等界面上的鼠标点击 (屏幕坐标)
+    Click { x: u16, y: u16 },
+}
+
 #[derive(Clone)]
 enum TaskAction {
     Demolish(DemolishEvent),
@@ -157,16 +368,37 @@ struct ScheduledTask {
     map_y: f32,
     map_x: f32,
     priority: u8,
+    // 放置前必须已完成的拆除 uid (与本阶段的拆除共用格子时由冲突预检填入)
+    requires_demolish: Vec<usize>,
 }
 
-// 辅助函数：将字符转换为 HID 键码
-fn get_hid_code(c: char) -> u8 {
-    match c.to_ascii_lowercase() {
-        'a'..='z' => c.to_ascii_lowercase() as u8 - b'a' + 0x04,
-        '0'..='9' => c as u8 - b'1' + 0x1E,
-        ' ' => 0x2C,
-        _ => 0,
+/// 两个占地 [grid_x, grid_y, width, height] 是否有公共格子
+fn footprints_overlap(a: [usize; 4], b: [usize; 4]) -> bool {
+    a[0] < b[0] + b[2] && b[0] < a[0] + a[2] && a[1] < b[1] + b[3] && b[1] < a[1] + a[3]
+}
+
+/// 标定时按住方向键的时长
+const CALIBRATE_HOLD_MS: u64 = 400;
+
+/// 由位移和按键时长换算卷动速度 (px/s)；位移过小或速度超出合理范围时返回 None
+fn scroll_speed_from_shift(shift_px: i32, hold_ms: u64) -> Option<f32> {
+    if hold_ms == 0 || shift_px.unsigned_abs() < 5 {
+        return None;
+    }
+    let speed = shift_px.unsigned_abs() as f32 / (hold_ms as f32 / 1000.0);
+    (50.0..=3000.0).contains(&speed).then_some(speed)
+}
+
+// 辅助函数：多数表决，出现次数相同时取最早出现的值
+fn majority_vote(values: &[i32]) -> Option<i32> {
+    let mut best: Option<(i32, usize)> = None;
+    for &v in values {
+        let count = values.iter().filter(|&&x| x == v).count();
+        if best.is_none_or(|(_, c)| count > c) {
+            best = Some((v, count));
+        }
     }
+    best.map(|(v, _)| v)
 }
 
 // ==========================================
@@ -177,6 +409,8 @@ pub struct TowerDefenseApp {
     nav: Arc<NavEngine>,
     config: TDConfig,
     map_meta: Option<MapMeta>,
+    map_name: String,
+    scene_id: String,
 
     strategy_buildings: Vec<BuildingExport>,
     strategy_upgrades: Vec<UpgradeEvent>,
@@ -188,21 +422,48 @@ pub struct TowerDefenseApp {
 
     last_confirmed_wave: i32,
     last_wave_change_time: Instant,
+    pending_resume: Option<ProgressSnapshot>,
 
     trap_lookup: HashMap<String, TrapConfigItem>,
     active_loadout: Vec<String>,
 
+    camera_offset_x: f32,
     camera_offset_y: f32,
     move_speed: f32,
+
+    state: Arc<AtomicU8>,
+
+    dry_run: bool,
+    planned: Vec<PlannedAction>,
+
+    // 编写分辨率 (screen_width x screen_height) 到实际屏幕的缩放
+    scale: ResolutionScale,
+
+    action_log: Option<ActionLog>,
 }
 
 impl TowerDefenseApp {
     pub fn new(driver: Arc<Mutex<HumanDriver>>, nav: Arc<NavEngine>) -> Self {
+        Self::with_config(driver, nav, TDConfig::default())
+    }
+
+    /// 使用自定义界面配置 (如其他分辨率的 rect / 安全区) 创建
+    pub fn with_config(driver: Arc<Mutex<HumanDriver>>, nav: Arc<NavEngine>, config: TDConfig) -> Self {
+        let authored = (config.screen_width, config.screen_height);
+        let scale = driver
+            .lock()
+            .map(|d| ResolutionScale::new(authored, d.screen_size()))
+            .unwrap_or(ResolutionScale::IDENTITY);
+        if !scale.is_identity() {
+            info!("📐 [分辨率] 塔防配置按 {}x{} 编写，坐标按 ({:.3}, {:.3}) 缩放", authored.0, authored.1, scale.sx, scale.sy);
+        }
         Self {
             driver,
             nav,
-            config: TDConfig::default(),
+            config,
             map_meta: None,
+            map_name: String::new(),
+            scene_id: String::new(),
             strategy_buildings: Vec::new(),
             strategy_upgrades: Vec::new(),
             strategy_demolishes: Vec::new(),
@@ -211,10 +472,175 @@ impl TowerDefenseApp {
             completed_demolish_uids: HashSet::new(),
             last_confirmed_wave: 0,
             last_wave_change_time: Instant::now(),
+            pending_resume: None,
             trap_lookup: HashMap::new(),
             active_loadout: Vec::new(),
+            camera_offset_x: 0.0,
             camera_offset_y: 0.0,
             move_speed: 300.0,
+            state: Arc::new(AtomicU8::new(RunState::Running as u8)),
+            dry_run: false,
+            planned: Vec::new(),
+            scale,
+            action_log: None,
+        }
+    }
+
+    /// 编写分辨率下的屏幕坐标 -> 实际屏幕坐标
+    fn to_screen(&self, x: f32, y: f32) -> (u16, u16) {
+        (self.scale.x(x).round() as u16, self.scale.y(y).round() as u16)
+    }
+
+    /// 演练模式：拆除/放置/升级/视野移动、赛前准备、选装备和开战的 G 只打印并记录计划 (坐标、按键、时长)，
+    /// 不发送任何键鼠指令；进图点击、波次检测和结算跳过照常执行，便于在真实对局中核对策略坐标
+    pub fn set_dry_run(&mut self, enabled: bool) {
+        self.dry_run = enabled;
+    }
+
+    /// 对局所在的导航场景 id，写入进度存档；恢复时只接受同一场景的存档
+    pub fn set_scene_id(&mut self, id: &str) {
+        self.scene_id = id.to_string();
+    }
+
+    /// 开启动作日志：记录每个执行的任务 (类型、按键、地图/屏幕坐标、时间)，
+    /// 每个波次阶段结束时覆盖写入 path，用于排查某一波为什么建错 (例如两个任务夹到了同一点)
+    pub fn enable_action_log(&mut self, path: &str) {
+        self.action_log = Some(ActionLog { path: path.to_string(), wave: 0, is_late: false, entries: Vec::new() });
+    }
+
+    /// 目前为止记录的动作 (未开启动作日志时为空)
+    pub fn action_log_entries(&self) -> &[ActionLogEntry] {
+        self.action_log.as_ref().map_or(&[], |log| log.entries.as_slice())
+    }
+
+    /// target 为 (uid, 地图坐标)，升级没有具体位置时为 None
+    fn log_action(
+        &mut self,
+        kind: ActionKind,
+        name: &str,
+        key: Option<char>,
+        target: Option<(usize, (f32, f32))>,
+        screen: Option<(f32, f32)>,
+        ok: bool,
+    ) {
+        let Some(log) = self.action_log.as_mut() else { return };
+        log.entries.push(ActionLogEntry {
+            time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            wave: log.wave,
+            is_late: log.is_late,
+            kind,
+            uid: target.map(|(uid, _)| uid),
+            name: name.to_string(),
+            key,
+            map: target.map(|(_, (x, y))| [x, y]),
+            screen: screen.map(|(x, y)| [x, y]),
+            ok,
+        });
+    }
+
+    fn flush_action_log(&self) {
+        let Some(log) = self.action_log.as_ref() else { return };
+        match log.flush() {
+            Ok(()) => debug!("🧾 [ActionLog] 已写入 {} 条 -> {}", log.entries.len(), log.path),
+            Err(e) => warn!("⚠️ [ActionLog] {}", e),
+        }
+    }
+
+    /// dry_run 模式下按执行顺序记录的动作
+    pub fn planned_actions(&self) -> &[PlannedAction] {
+        &self.planned
+    }
+
+    /// 视野移动用的长按，dry_run 时只记录
+    fn hold_key(&mut self, key: char, ms: u64) {
+        if self.dry_run {
+            info!("📝 [DryRun] 长按 '{}' {}ms", key, ms);
+            self.planned.push(PlannedAction::KeyHold { key, ms });
+            return;
+        }
+        if let Ok(mut human) = self.driver.lock() {
+            human.key_hold(key, ms);
+        }
+    }
+
+    /// 单击按键，dry_run 时只记录
+    fn tap_key(&mut self, key: char) {
+        if self.dry_run {
+            info!("📝 [DryRun] 按 '{}'", key);
+            self.planned.push(PlannedAction::KeyClick { key });
+            return;
+        }
+        if let Ok(mut human) = self.driver.lock() {
+            human.key_click(key);
+        }
+    }
+
+    /// 移动到 (x, y) 后左键单击，dry_run 时只记录
+    fn click_at(&mut self, x: u16, y: u16) {
+        if self.dry_run {
+            info!("📝 [DryRun] 点击 ({}, {})", x, y);
+            self.planned.push(PlannedAction::Click { x, y });
+            return;
+        }
+        if let Ok(mut human) = self.driver.lock() {
+            human.move_to_humanly(x, y, 0.4);
+            human.click_humanly(true, false, 0);
+        }
+    }
+
+    /// 等界面响应上一个操作；dry_run 没有发出操作，不用等
+    fn settle(&self, ms: u64) {
+        if !self.dry_run {
+            thread::sleep(Duration::from_millis(ms));
+        }
+    }
+
+    /// 运行状态句柄：在其他线程 (如热键) 写入 RunState 即可暂停/停止主循环
+    pub fn state_handle(&self) -> Arc<AtomicU8> {
+        Arc::clone(&self.state)
+    }
+
+    pub fn set_state(&self, state: RunState) {
+        self.state.store(state as u8, Ordering::SeqCst);
+    }
+
+    pub fn run_state(&self) -> RunState {
+        RunState::from_u8(self.state.load(Ordering::SeqCst))
+    }
+
+    /// 检查点：暂停时原地等待；返回 false 表示已被要求停止
+    fn checkpoint(&self) -> bool {
+        let mut announced = false;
+        loop {
+            match self.run_state() {
+                RunState::Running => return true,
+                RunState::Stopped => return false,
+                RunState::Paused => {
+                    if !announced {
+                        info!("⏸️ [Control] 已暂停，等待恢复...");
+                        announced = true;
+                    }
+                    thread::sleep(Duration::from_millis(200));
+                }
+            }
+        }
+    }
+
+    /// 检查点 + 停止处理：被要求停止时松开按键鼠标并返回 true
+    fn stop_requested(&self) -> bool {
+        if self.checkpoint() {
+            return false;
+        }
+        info!("🛑 [Control] 收到停止指令，退出塔防模块");
+        self.release_inputs();
+        true
+    }
+
+    /// 停止时松开所有按键和鼠标，避免游戏里残留按住状态
+    fn release_inputs(&self) {
+        if let Ok(d) = self.driver.lock() {
+            d.dispatch(|dev| dev.key_up());
+            d.dispatch(|dev| dev.mouse_up());
         }
     }
 
@@ -224,77 +650,152 @@ impl TowerDefenseApp {
                 self.strategy_buildings = data.buildings;
                 self.strategy_upgrades = data.upgrades;
                 self.strategy_demolishes = data.demolishes;
-                println!(
+                info!(
                     "🏗️ 策略加载成功: 建{} | 升{} | 拆{}",
                     self.strategy_buildings.len(),
                     self.strategy_upgrades.len(),
                     self.strategy_demolishes.len()
                 );
             } else {
-                println!("❌ 策略 JSON 解析失败");
+                error!("❌ 策略 JSON 解析失败");
             }
         }
     }
 
     pub fn recognize_wave_status(&self, rect: [i32; 4], use_tab: bool) -> Option<WaveStatus> {
-        const KEY_TAB: u8 = 0x2B;
+        const SAMPLE_INTERVAL_MS: u64 = 80;
         if use_tab {
             if let Ok(driver) = self.driver.lock() {
-                if let Ok(mut dev) = driver.device.lock() {
-                    dev.key_down(KEY_TAB, 0);
-                }
+                driver.dispatch(|dev| dev.key_down(hid::KEY_TAB, 0));
             }
             thread::sleep(Duration::from_millis(500));
         }
 
-        let text: String = self.nav.ocr_area(rect);
+        // 多次采样取多数，过滤界面闪烁造成的单次误读
+        let samples = self.config.wave_vote_samples.max(1);
+        let mut reads = Vec::with_capacity(samples);
+        for i in 0..samples {
+            if i > 0 {
+                thread::sleep(Duration::from_millis(SAMPLE_INTERVAL_MS));
+            }
+            let rect = self.scale.rect(rect);
+            let text: String = match &self.config.wave_ocr_charset {
+                Some(charset) => self.nav.ocr_area_charset(rect, charset),
+                None => self.nav.ocr_area(rect),
+            };
+            if let Some(num) = Self::parse_wave_text(&text, use_tab) {
+                reads.push(num);
+            }
+        }
 
         if use_tab {
             if let Ok(driver) = self.driver.lock() {
-                if let Ok(mut dev) = driver.device.lock() {
-                    dev.key_up();
-                }
+                driver.dispatch(|dev| dev.key_up());
             }
             thread::sleep(Duration::from_millis(500));
-            if let Ok(driver) = self.driver.lock() {
-                if let Ok(mut dev) = driver.device.lock() {
-                    dev.key_down(KEY_TAB, 0);
-                }
-            }
-            thread::sleep(Duration::from_millis(100));
-            if let Ok(driver) = self.driver.lock() {
-                if let Ok(mut dev) = driver.device.lock() {
-                    dev.key_up();
-                }
+            if let Ok(mut driver) = self.driver.lock() {
+                driver.key_hold('\t', 100);
             }
         }
 
+        let val = majority_vote(&reads)?;
+        if samples > 1 {
+            debug!("🗳️ [OCR Vote] 采样 {:?} -> 第 {} 波", reads, val);
+        }
+        Some(WaveStatus { current_wave: val })
+    }
+
+    /// 从单次 OCR 文本中解析波次号
+    fn parse_wave_text(text: &str, use_tab: bool) -> Option<i32> {
         if text.is_empty() {
             return None;
         }
 
-        println!(
+        info!(
             "🔍 [OCR Debug] 原始文本: 「{}」 (Mode: {})",
             text.trim(),
             if use_tab { "TAB" } else { "HUD" }
         );
 
-        let val = if use_tab {
+        if use_tab {
             let re = Regex::new(r"(\d+)[/\dSI日]+.*波次").ok()?;
-            re.captures(&text).and_then(|caps| {
+            re.captures(text).and_then(|caps| {
                 let num = caps.get(1)?.as_str().parse::<i32>().ok()?;
-                println!("✅ [OCR Match] TAB 模式匹配成功: 第 {} 波", num);
+                debug!("✅ [OCR Match] TAB 模式匹配成功: 第 {} 波", num);
                 Some(num)
-            })?
+            })
         } else {
             let re = Regex::new(r"波次\s*(\d+)").ok()?;
-            re.captures(&text).and_then(|caps| {
+            re.captures(text).and_then(|caps| {
                 let num = caps.get(1)?.as_str().parse::<i32>().ok()?;
-                println!("✅ [OCR Match] HUD 模式匹配成功: 第 {} 波", num);
+                debug!("✅ [OCR Match] HUD 模式匹配成功: 第 {} 波", num);
                 Some(num)
-            })?
-        };
-        Some(WaveStatus { current_wave: val })
+            })
+        }
+    }
+
+    /// 当前对局进度
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            map_name: self.map_name.clone(),
+            scene_id: self.scene_id.clone(),
+            saved_at: chrono::Utc::now().timestamp(),
+            last_confirmed_wave: self.last_confirmed_wave,
+            placed_uids: self.placed_uids.iter().copied().collect(),
+            completed_upgrade_keys: self.completed_upgrade_keys.iter().cloned().collect(),
+            completed_demolish_uids: self.completed_demolish_uids.iter().copied().collect(),
+        }
+    }
+
+    /// 读取进度存档，在 run 检测到开局波次后再决定是否套用 (条件见 ProgressSnapshot::check_resumable)
+    pub fn resume_from(&mut self, path: &str) -> Result<(), String> {
+        self.pending_resume = Some(ProgressSnapshot::from_file(path)?);
+        Ok(())
+    }
+
+    /// 按地图名区分的存档路径；未配置 progress_dir 或地图名未知时为 None
+    fn progress_path(&self) -> Option<String> {
+        let dir = self.config.progress_dir.as_ref()?;
+        if self.map_name.is_empty() {
+            return None;
+        }
+        Some(Path::new(dir).join(format!("{}.progress.json", self.map_name)).to_string_lossy().into_owned())
+    }
+
+    fn save_progress(&self) {
+        // 演练时记录的“已放置”并未真正发生，不能写进存档
+        if self.dry_run {
+            return;
+        }
+        let Some(path) = self.progress_path() else { return };
+        if let Some(dir) = Path::new(&path).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        match self.snapshot().save(&path) {
+            Ok(()) => debug!("💾 [Progress] 已保存进度 (第 {} 波) -> {}", self.last_confirmed_wave, path),
+            Err(e) => warn!("⚠️ [Progress] {}", e),
+        }
+    }
+
+    fn apply_pending_resume(&mut self, detected_wave: i32) {
+        let Some(snap) = self.pending_resume.take() else { return };
+        let now = chrono::Utc::now().timestamp();
+        if let Err(reason) = snap.check_resumable(&self.map_name, &self.scene_id, detected_wave, now) {
+            warn!("⚠️ [Progress] {}，放弃恢复", reason);
+            return;
+        }
+        let wave = snap.last_confirmed_wave;
+        info!(
+            "♻️ [Progress] 恢复进度: 第 {} 波，已放置 {} 个，已升级 {} 项，已拆除 {} 个",
+            wave,
+            snap.placed_uids.len(),
+            snap.completed_upgrade_keys.len(),
+            snap.completed_demolish_uids.len()
+        );
+        self.last_confirmed_wave = wave;
+        self.placed_uids = snap.placed_uids.into_iter().collect();
+        self.completed_upgrade_keys = snap.completed_upgrade_keys.into_iter().collect();
+        self.completed_demolish_uids = snap.completed_demolish_uids.into_iter().collect();
     }
 
     fn validate_wave_transition(&mut self, detected_wave: i32) -> bool {
@@ -303,7 +804,7 @@ impl TowerDefenseApp {
         let is_next_wave = detected_wave == self.last_confirmed_wave + 1;
         let is_long_enough = elapsed >= 60 || self.last_confirmed_wave == 0;
         if is_next_wave && is_long_enough {
-            println!(
+            info!(
                 "✅ [Monitor] 新波次: {} -> {}",
                 self.last_confirmed_wave, detected_wave
             );
@@ -315,23 +816,51 @@ impl TowerDefenseApp {
         }
     }
 
+    /// 看门狗：last_confirmed_wave 已超过 stall_timeout_secs 未推进
+    fn is_stalled(&self) -> bool {
+        self.config.stall_timeout_secs > 0
+            && self.last_wave_change_time.elapsed() >= Duration::from_secs(self.config.stall_timeout_secs)
+    }
+
     fn are_tasks_in_current_view(&self, tasks: &[ScheduledTask]) -> bool {
-        let [_, sz_y1, _, sz_y2] = self.config.safe_zone;
+        let [sz_x1, sz_y1, sz_x2, sz_y2] = self.config.safe_zone;
         let view_top = self.camera_offset_y;
         let safe_map_top = view_top + sz_y1 as f32;
         let safe_map_bottom = view_top + sz_y2 as f32;
+        let view_left = self.camera_offset_x;
+        let safe_map_left = view_left + sz_x1 as f32;
+        let safe_map_right = view_left + sz_x2 as f32;
+        let check_x = self.max_scroll_x() > 0.0;
 
         for task in tasks {
             if task.map_y < safe_map_top || task.map_y > safe_map_bottom {
                 return false;
             }
+            if check_x && (task.map_x < safe_map_left || task.map_x > safe_map_right) {
+                return false;
+            }
         }
         true
     }
 
+    /// 地图总宽度；地图文件未提供 right 时视为与屏幕同宽
+    fn map_width(&self) -> f32 {
+        match &self.map_meta {
+            Some(meta) if meta.right > 0.0 => meta.right,
+            _ => self.config.screen_width,
+        }
+    }
+
+    fn max_scroll_x(&self) -> f32 {
+        (self.map_width() - self.config.screen_width).max(0.0)
+    }
+
     pub fn execute_wave_phase(&mut self, wave: i32, is_late: bool) {
+        if self.run_state() == RunState::Stopped {
+            return;
+        }
         let phase_name = if is_late { "后期" } else { "前期" };
-        println!(
+        info!(
             "🚀 优化执行第 {} 波 [{}] (拆除优先模式)...",
             wave, phase_name
         );
@@ -352,6 +881,7 @@ impl TowerDefenseApp {
                     map_y: py,
                     map_x: px,
                     priority: 0,
+                    requires_demolish: Vec::new(),
                 });
             }
         }
@@ -367,6 +897,7 @@ impl TowerDefenseApp {
                     map_y: py,
                     map_x: px,
                     priority: 1,
+                    requires_demolish: Vec::new(),
                 });
             }
         }
@@ -383,6 +914,7 @@ impl TowerDefenseApp {
                     map_y: 0.0,
                     map_x: 0.0,
                     priority: 2,
+                    requires_demolish: Vec::new(),
                 });
             }
         }
@@ -390,9 +922,14 @@ impl TowerDefenseApp {
         if demolish_tasks.is_empty() && build_upgrade_tasks.is_empty() {
             return;
         }
+        self.link_demolish_dependencies(wave, is_late, &mut build_upgrade_tasks);
+        if let Some(log) = self.action_log.as_mut() {
+            log.wave = wave;
+            log.is_late = is_late;
+        }
 
         if !demolish_tasks.is_empty() {
-            println!(
+            info!(
                 "🧹 [Step 1] 正在执行全图拆除任务 ({}个)...",
                 demolish_tasks.len()
             );
@@ -400,13 +937,49 @@ impl TowerDefenseApp {
         }
 
         if !build_upgrade_tasks.is_empty() {
-            println!(
+            info!(
                 "🏗️ [Step 2] 正在执行建造与升级任务 ({}个)...",
                 build_upgrade_tasks.len()
             );
             build_upgrade_tasks.sort_by(|a, b| a.priority.cmp(&b.priority));
             self.dispatch_tasks_by_region(build_upgrade_tasks);
         }
+        self.flush_action_log();
+    }
+
+    /// 冲突预检：放置任务与本阶段的拆除共用格子时，记下依赖的拆除 uid，放置前拆除未完成就先补做拆除
+    /// (拆除整体先于建造执行，这里防的是拆除被跳过后直接叠放)；
+    /// 排序无法解决的冲突只打印警告：同阶段两个放置重叠，或前期放置压在同波后期才拆除的建筑上
+    fn link_demolish_dependencies(&self, wave: i32, is_late: bool, tasks: &mut [ScheduledTask]) {
+        let footprint = |b: &BuildingExport| [b.grid_x, b.grid_y, b.width, b.height];
+        let demolishes: Vec<&DemolishEvent> = self.strategy_demolishes.iter().filter(|d| d.wave_num == wave).collect();
+
+        for task in tasks.iter_mut() {
+            let TaskAction::Place(b) = &task.action else { continue };
+            for d in demolishes.iter().filter(|d| footprints_overlap(footprint(b), [d.grid_x, d.grid_y, d.width, d.height])) {
+                if d.is_late == is_late {
+                    task.requires_demolish.push(d.uid);
+                } else if d.is_late {
+                    warn!(
+                        "⚠️ [Conflict] 第 {} 波前期放置 {} (uid {}) 与后期拆除 {} (uid {}) 占用同一格子，放置时格子仍被占用",
+                        wave, b.name, b.uid, d.name, d.uid
+                    );
+                }
+            }
+        }
+
+        let places: Vec<&BuildingExport> = tasks
+            .iter()
+            .filter_map(|t| match &t.action { TaskAction::Place(b) => Some(b), _ => None })
+            .collect();
+        for (i, a) in places.iter().enumerate() {
+            for b in places.iter().skip(i + 1).filter(|b| footprints_overlap(footprint(a), footprint(b))) {
+                warn!(
+                    "⚠️ [Conflict] 第 {} 波 [{}] 放置 {} (uid {}) 与 {} (uid {}) 占地重叠，后放置的会失败",
+                    wave, if is_late { "后期" } else { "前期" }, a.name, a.uid, b.name, b.uid
+                );
+            }
+        }
     }
 
     fn dispatch_tasks_by_region(&mut self, tasks: Vec<ScheduledTask>) {
@@ -414,42 +987,78 @@ impl TowerDefenseApp {
         let map_h = meta.bottom;
         let screen_h = self.config.screen_height;
         let mid_point = (map_h - screen_h) / 2.0;
+        let mid_x = self.map_width() / 2.0;
+        // 地图不比屏幕宽时不需要横向分区，保持原来的上下两批
+        let split_x = self.max_scroll_x() > 0.0;
 
-        let (mut upper, mut lower): (Vec<_>, Vec<_>) = tasks
+        let (upper, lower): (Vec<_>, Vec<_>) = tasks
             .into_iter()
             .partition(|t| t.map_y <= mid_point + screen_h / 2.0);
 
-        if !upper.is_empty() {
-            upper.sort_by(|a, b| {
-                a.map_y
-                    .partial_cmp(&b.map_y)
-                    .unwrap()
-                    .then(a.priority.cmp(&b.priority))
-            });
-            if self.are_tasks_in_current_view(&upper) {
-                println!("✨ 上半区任务在视野内，直接执行");
-                self.process_task_batch(upper, false);
+        for (half, top) in [(upper, true), (lower, false)] {
+            let groups: Vec<(Vec<ScheduledTask>, Option<bool>)> = if split_x {
+                let (left, right): (Vec<_>, Vec<_>) = half.into_iter().partition(|t| t.map_x <= mid_x);
+                vec![(left, Some(true)), (right, Some(false))]
             } else {
-                self.align_camera_to_edge(true);
-                self.process_task_batch(upper, true);
+                vec![(half, None)]
+            };
+
+            for (mut group, left) in groups {
+                if group.is_empty() {
+                    continue;
+                }
+                // 每组之前都要对齐视野，停止指令不能等到整个阶段做完
+                if self.stop_requested() {
+                    return;
+                }
+                group.sort_by(|a, b| {
+                    let by_y = if top { a.map_y.partial_cmp(&b.map_y) } else { b.map_y.partial_cmp(&a.map_y) };
+                    by_y.unwrap().then(a.priority.cmp(&b.priority))
+                });
+                let region = format!(
+                    "{}{}",
+                    if top { "上半区" } else { "下半区" },
+                    match left { Some(true) => "左侧", Some(false) => "右侧", None => "" }
+                );
+                if self.are_tasks_in_current_view(&group) {
+                    info!("✨ {}任务在视野内，直接执行", region);
+                    self.process_task_batch(group, false);
+                } else {
+                    self.align_camera_to_edge(top);
+                    if let Some(l) = left {
+                        self.align_camera_to_edge_x(l);
+                    }
+                    self.process_task_batch(group, true);
+                }
             }
         }
+    }
 
-        if !lower.is_empty() {
-            lower.sort_by(|a, b| {
-                b.map_y
-                    .partial_cmp(&a.map_y)
-                    .unwrap()
-                    .then(a.priority.cmp(&b.priority))
-            });
-            if self.are_tasks_in_current_view(&lower) {
-                println!("✨ 下半区任务在视野内，直接执行");
-                self.process_task_batch(lower, false);
-            } else {
-                self.align_camera_to_edge(false);
-                self.process_task_batch(lower, true);
+    /// 放置前补做同格子上还没完成的拆除 (拆除阶段因越界等原因跳过时)，保证先拆后建
+    /// 返回 Some(是否补做了拆除)；补做后仍未完成时把这次放置记为失败并返回 None
+    fn finish_pending_demolishes(&mut self, b: &BuildingExport, task: &ScheduledTask) -> Option<bool> {
+        let mut redone = false;
+        for &uid in &task.requires_demolish {
+            if self.completed_demolish_uids.contains(&uid) {
+                continue;
+            }
+            let pos = self
+                .strategy_demolishes
+                .iter()
+                .find(|d| d.uid == uid)
+                .and_then(|d| self.get_absolute_map_pixel(d.grid_x, d.grid_y, d.width, d.height));
+            if let Some((x, y)) = pos {
+                info!("🔁 [Conflict] {} (uid {}) 所在格子的拆除 (uid {}) 未完成，先补做拆除", b.name, b.uid, uid);
+                self.perform_demolish_action(x, y, uid);
+                redone = true;
+            }
+            if !self.completed_demolish_uids.contains(&uid) {
+                warn!("⚠️ [Conflict] {} (uid {}) 所在格子的拆除 (uid {}) 仍未完成，本次不放置", b.name, b.uid, uid);
+                self.log_action(ActionKind::Place, &b.name, None, Some((b.uid, (task.map_x, task.map_y))), None, false);
+                return None;
             }
         }
+        Some(redone)
     }
 
     fn process_task_batch(&mut self, tasks: Vec<ScheduledTask>, force_initial_refresh: bool) {
@@ -457,12 +1066,22 @@ impl TowerDefenseApp {
         let mut is_first_task = true;
 
         for task in tasks {
+            if !self.checkpoint() {
+                return;
+            }
             if let TaskAction::Upgrade(u) = &task.action {
                 self.execute_single_upgrade(u);
                 continue;
             }
+            let mut demolished_first = false;
+            if let TaskAction::Place(b) = &task.action {
+                match self.finish_pending_demolishes(b, &task) {
+                    Some(redone) => demolished_first = redone,
+                    None => continue,
+                }
+            }
 
-            let mut screen_moved = self.smart_move_camera(task.map_y);
+            let mut screen_moved = self.smart_move_camera(task.map_x, task.map_y) || demolished_first;
             if is_first_task && force_initial_refresh {
                 screen_moved = true;
                 is_first_task = false;
@@ -487,14 +1106,66 @@ impl TowerDefenseApp {
 
 // src/tower_defense.rs
 
-    fn perform_demolish_action(&mut self, map_x: f32, map_y: f32, uid: usize) {
+    /// 把地图坐标换算为安全区内的屏幕坐标
+    /// 越界不超过 clamp_tolerance_px 时夹到安全区边缘；越界更多时返回 None，而不是悄悄落在边缘上
+    fn map_to_safe_screen(&self, map_x: f32, map_y: f32) -> Option<(f32, f32)> {
         let [sz_x1, sz_y1, sz_x2, sz_y2] = self.config.safe_zone;
-        let screen_x = (map_x - 0.0).clamp(sz_x1 as f32, sz_x2 as f32);
-        let screen_y = (map_y - self.camera_offset_y).clamp(sz_y1 as f32, sz_y2 as f32);
+        let raw_x = map_x - self.camera_offset_x;
+        let raw_y = map_y - self.camera_offset_y;
+        let x = raw_x.clamp(sz_x1 as f32, sz_x2 as f32);
+        let y = raw_y.clamp(sz_y1 as f32, sz_y2 as f32);
+        let tol = self.config.clamp_tolerance_px;
+        if (x - raw_x).abs() > tol || (y - raw_y).abs() > tol {
+            None
+        } else {
+            Some((x, y))
+        }
+    }
+
+    /// 取任务在屏幕上的落点，bool 表示是否为此移动了视野
+    /// 越界时按 rescroll_out_of_zone 先移动视野再试一次；仍越界则打印警告并返回 None，
+    /// 调用方跳过该任务且不记录为已完成
+    fn locate_on_screen(&mut self, map_x: f32, map_y: f32, label: &str) -> Option<((f32, f32), bool)> {
+        if let Some(p) = self.map_to_safe_screen(map_x, map_y) {
+            return Some((p, false));
+        }
+        if self.config.rescroll_out_of_zone && self.smart_move_camera(map_x, map_y) {
+            if let Some(p) = self.map_to_safe_screen(map_x, map_y) {
+                debug!("   -> {} 超出安全区，已移动视野", label);
+                return Some((p, true));
+            }
+        }
+        warn!(
+            "⚠️ [SafeZone] {} 的屏幕坐标 ({:.0}, {:.0}) 超出安全区 {:?}，跳过本次操作",
+            label,
+            map_x - self.camera_offset_x,
+            map_y - self.camera_offset_y,
+            self.config.safe_zone
+        );
+        None
+    }
+
+    fn perform_demolish_action(&mut self, map_x: f32, map_y: f32, uid: usize) {
+        let ((screen_x, screen_y), _) = match self.locate_on_screen(map_x, map_y, &format!("拆除 uid {}", uid)) {
+            Some(p) => p,
+            None => {
+                self.log_action(ActionKind::Demolish, "", None, Some((uid, (map_x, map_y))), None, false);
+                return;
+            }
+        };
+        self.log_action(ActionKind::Demolish, "", None, Some((uid, (map_x, map_y))), Some((screen_x, screen_y)), true);
+
+        if self.dry_run {
+            info!("📝 [DryRun] 拆除 uid {} -> 屏幕 ({:.0}, {:.0})", uid, screen_x, screen_y);
+            self.planned.push(PlannedAction::Demolish { uid, screen_x, screen_y });
+            self.completed_demolish_uids.insert(uid);
+            return;
+        }
 
+        let (target_x, target_y) = self.to_screen(screen_x, screen_y);
         if let Ok(mut driver) = self.driver.lock() {
             // 1. 移动到位后强制停顿，确保准星彻底对齐格子
-            driver.move_to_humanly(screen_x as u16, screen_y as u16, 0.4);
+            driver.move_to_humanly(target_x, target_y, 0.4);
             thread::sleep(Duration::from_millis(50));
 
             // 2. 点击选中 (增加 hold 时间到 60ms，防止点击过快游戏未响应)
@@ -503,18 +1174,18 @@ impl TowerDefenseApp {
             // 3. 等待选中框出现的延迟 (从 150ms 增加到 250ms)
             thread::sleep(Duration::from_millis(150));
 
-            // 4. 🔥 双击 'E' 拆除 (Double Tap)
-            // 第一下 E：执行拆除
-            driver.key_click('e');
-            
-            // 间隔 100ms
-            thread::sleep(Duration::from_millis(100));
-            
-            // 第二下 E：保险措施 (防止第一下被吞，或者部分陷阱需要二次确认)
-            driver.key_click('e');
+            // 4. 🔥 连按 'E' 拆除 (默认双击)
+            // 第一下 E 执行拆除，之后的是保险措施 (防止第一下被吞，或者部分陷阱需要二次确认)
+            for i in 0..self.config.demolish_key_repeats.max(1) {
+                if i > 0 {
+                    thread::sleep(Duration::from_millis(self.config.demolish_key_gap_ms));
+                }
+                driver.key_click('e');
+            }
         }
         
         self.completed_demolish_uids.insert(uid);
+        self.save_progress();
         
         // 动作后摇 (稍微缩短一点，因为我们已经多按了一次E)
         thread::sleep(Duration::from_millis(200));
@@ -531,61 +1202,114 @@ impl TowerDefenseApp {
         name: &str,
         uid: usize,
     ) {
-        let [sz_x1, sz_y1, sz_x2, sz_y2] = self.config.safe_zone;
-        let screen_x = (map_x - 0.0).clamp(sz_x1 as f32, sz_x2 as f32);
-        let screen_y = (map_y - self.camera_offset_y).clamp(sz_y1 as f32, sz_y2 as f32);
+        let ((screen_x, screen_y), rescrolled) =
+            match self.locate_on_screen(map_x, map_y, &format!("{} (uid {})", name, uid)) {
+                Some(p) => p,
+                None => {
+                    self.log_action(ActionKind::Place, name, None, Some((uid, (map_x, map_y))), None, false);
+                    return;
+                }
+            };
+        let screen_moved = screen_moved || rescrolled;
         let key = self.get_trap_key(name);
+        let mut attempt: u8 = 0;
+        let log_place = |app: &mut Self, ok: bool| {
+            app.log_action(ActionKind::Place, name, Some(key), Some((uid, (map_x, map_y))), Some((screen_x, screen_y)), ok)
+        };
 
-        if let Ok(mut d) = self.driver.lock() {
-            // 1. 移动鼠标
-            d.move_to_humanly(screen_x as u16, screen_y as u16, 0.35);
+        if self.dry_run {
+            log_place(self, true);
+            info!("📝 [DryRun] 放置 {} (uid {}) 按键 '{}' -> 屏幕 ({:.0}, {:.0})", name, uid, key, screen_x, screen_y);
+            self.planned.push(PlannedAction::Place { uid, name: name.to_string(), key, screen_x, screen_y });
+            self.placed_uids.insert(uid);
+            *last_key = Some(key);
+            return;
+        }
 
-            // [稳定性] 移动到位后强制停顿，等待鼠标“落稳”
-            thread::sleep(Duration::from_millis(50));
+        let (target_x, target_y) = self.to_screen(screen_x, screen_y);
+        loop {
+            if let Ok(mut d) = self.driver.lock() {
+                // 1. 移动鼠标
+                d.move_to_humanly(target_x, target_y, 0.35);
 
-            // 🔥 [核心修复] 判定条件增加 `last_key.is_none()`
-            // 含义：如果是本批次的第一座塔（无论是否移动了视野），或者刚刚移动过视野，
-            // 都强制执行“三连击”切枪逻辑，确保陷阱切出率 100%。
-            if screen_moved || last_key.is_none() {
-                let swap_key = if key == '4' { '5' } else { '4' };
-                
-                // 执行：目标键 -> 干扰键 -> 目标键 (强刷状态)
-                d.key_click(key);
-                thread::sleep(Duration::from_millis(120));
-                d.key_click(swap_key);
-                thread::sleep(Duration::from_millis(120));
-                d.key_click(key);
-
-                // 等待陷阱虚影完全浮现
-                thread::sleep(Duration::from_millis(250));
-                *last_key = Some(key);
-            } else if Some(key) != *last_key {
-                // 如果不是第一座，且类型变了（原地换塔），则单次按键切换
-                d.key_click(key);
-                *last_key = Some(key);
-                thread::sleep(Duration::from_millis(250));
-            } else {
-                // 同种塔连续放置，仅需微小延迟
+                // [稳定性] 移动到位后强制停顿，等待鼠标“落稳”
                 thread::sleep(Duration::from_millis(50));
+
+                // 🔥 [核心修复] 判定条件增加 `last_key.is_none()`
+                // 含义：如果是本批次的第一座塔（无论是否移动了视野），或者刚刚移动过视野，
+                // 都强制执行“三连击”切枪逻辑，确保陷阱切出率 100%。
+                if screen_moved || last_key.is_none() {
+                    let swap_key = if key == '4' { '5' } else { '4' };
+                    
+                    // 执行：目标键 -> 干扰键 -> 目标键 (强刷状态)
+                    let gap = Duration::from_millis(self.config.build_swap_gap_ms);
+                    d.key_click(key);
+                    thread::sleep(gap);
+                    d.key_click(swap_key);
+                    thread::sleep(gap);
+                    d.key_click(key);
+
+                    // 等待陷阱虚影完全浮现
+                    thread::sleep(Duration::from_millis(250));
+                    *last_key = Some(key);
+                } else if Some(key) != *last_key {
+                    // 如果不是第一座，且类型变了（原地换塔），则单次按键切换
+                    d.key_click(key);
+                    *last_key = Some(key);
+                    thread::sleep(Duration::from_millis(250));
+                } else {
+                    // 同种塔连续放置，仅需微小延迟
+                    thread::sleep(Duration::from_millis(50));
+                }
+
+                // 执行双击放置
+                d.double_click_humanly(true, false, 150);
             }
 
-            // 执行双击放置
-            d.double_click_humanly(true, false, 150);
+            // 动作后摇
+            thread::sleep(Duration::from_millis(250));
+
+            if self.verify_placement(name, screen_x, screen_y) {
+                self.placed_uids.insert(uid);
+                self.save_progress();
+                log_place(self, true);
+                return;
+            }
+            if attempt >= self.config.place_retries {
+                log_place(self, false);
+                // 不记录 uid，避免把失败的放置当成已完成
+                error!("❌ [Build] {} (uid {}) 放置校验失败，未记录为已放置", name, uid);
+                return;
+            }
+            attempt += 1;
+            info!("🔁 [Build] {} (uid {}) 放置校验失败，重试 ({}/{})", name, uid, attempt, self.config.place_retries);
+            // 重试时强制重新切出陷阱
+            *last_key = None;
         }
-        self.placed_uids.insert(uid);
+    }
 
-        // 动作后摇
-        thread::sleep(Duration::from_millis(250));
+    /// 放置后校验：陷阱配置了 verify_color 时采样目标点颜色，未配置则视为成功
+    fn verify_placement(&self, name: &str, screen_x: f32, screen_y: f32) -> bool {
+        let item = match self.trap_lookup.get(name) { Some(i) => i, None => return true };
+        let hex = match &item.verify_color { Some(h) => h, None => return true };
+        let (x, y) = self.to_screen(screen_x, screen_y);
+        self.nav.check_color([x as i32, y as i32], hex, item.verify_tol, item.verify_radius)
     }
 
     fn execute_single_upgrade(&mut self, u: &UpgradeEvent) {
+        const UPGRADE_HOLD_MS: u64 = 1500;
         let key = self.get_trap_key(&u.building_name);
-        if let Ok(mut d) = self.driver.lock() {
-            println!("   -> 长按 '{}' (800ms) 以升级: {}", key, u.building_name);
-            d.key_hold(key, 1500);
+        self.log_action(ActionKind::Upgrade, &u.building_name, Some(key), None, None, true);
+        if self.dry_run {
+            info!("📝 [DryRun] 升级 {} -> 长按 '{}' {}ms", u.building_name, key, UPGRADE_HOLD_MS);
+            self.planned.push(PlannedAction::Upgrade { name: u.building_name.clone(), key, hold_ms: UPGRADE_HOLD_MS });
+        } else if let Ok(mut d) = self.driver.lock() {
+            debug!("   -> 长按 '{}' ({}ms) 以升级: {}", key, UPGRADE_HOLD_MS, u.building_name);
+            d.key_hold(key, UPGRADE_HOLD_MS);
         }
         let key_str = format!("{}-{}-{}", u.building_name, u.wave_num, u.is_late);
         self.completed_upgrade_keys.insert(key_str);
+        self.save_progress();
         thread::sleep(Duration::from_millis(400));
     }
 
@@ -593,17 +1317,59 @@ impl TowerDefenseApp {
         let meta = self.map_meta.as_ref().unwrap();
         let max_scroll_y = (meta.bottom - self.config.screen_height).max(0.0);
 
+        info!("🔄 强制归零: {}", if top { "顶部" } else { "底部" });
+        self.hold_key(if top { 'w' } else { 's' }, 2500);
+        self.camera_offset_y = if top { 0.0 } else { max_scroll_y };
+        thread::sleep(Duration::from_millis(500));
+    }
+
+    /// 实测视野卷动速度：按住 's' 固定时长，比对前后两帧中部地标的位移，更新 move_speed
+    /// 地标丢失或结果明显不合理时保留原值
+    pub fn calibrate_scroll(&mut self) {
+        if self.dry_run {
+            info!("📝 [DryRun] 跳过视野速度标定，沿用 move_speed = {:.1}", self.move_speed);
+            return;
+        }
+        let rect = self.scale.rect(self.config.safe_zone);
+        let before = match self.nav.capture_gray(rect) {
+            Some(img) => img,
+            None => {
+                warn!("⚠️ [标定] 截图失败，沿用 move_speed = {:.1}", self.move_speed);
+                return;
+            }
+        };
         if let Ok(mut human) = self.driver.lock() {
-            let key = if top { 'w' } else { 's' };
-            println!("🔄 强制归零: {}", if top { "顶部" } else { "底部" });
-            human.key_hold(key, 2500);
+            human.key_hold('s', CALIBRATE_HOLD_MS);
         }
-        self.camera_offset_y = if top { 0.0 } else { max_scroll_y };
+        thread::sleep(Duration::from_millis(300));
+        let after = self.nav.capture_gray(rect);
+        // 截图位移是实际像素，换回编写分辨率下的像素再折算速度
+        let shift = after
+            .and_then(|a| estimate_vertical_shift(&before, &a, 0.6))
+            .map(|px| (px as f32 / self.scale.sy).round() as i32);
+
+        match shift.and_then(|px| scroll_speed_from_shift(px, CALIBRATE_HOLD_MS)) {
+            Some(speed) => {
+                info!("📏 [标定] 位移 {}px / {}ms -> move_speed {:.1} (原 {:.1})", shift.unwrap(), CALIBRATE_HOLD_MS, speed, self.move_speed);
+                self.move_speed = speed;
+            }
+            None => warn!("⚠️ [标定] 未能可靠测得位移，沿用 move_speed = {:.1}", self.move_speed),
+        }
+        // 回到顶部，保持与 setup_view 之后一致的视野状态
+        self.align_camera_to_edge(true);
+    }
+
+    fn align_camera_to_edge_x(&mut self, left: bool) {
+        let max_scroll_x = self.max_scroll_x();
+
+        info!("🔄 强制归零: {}", if left { "左侧" } else { "右侧" });
+        self.hold_key(if left { 'a' } else { 'd' }, 2500);
+        self.camera_offset_x = if left { 0.0 } else { max_scroll_x };
         thread::sleep(Duration::from_millis(500));
     }
 
     fn scroll_camera_by_pixels(
-        &self,
+        &mut self,
         direction: char,
         pixels: f32,
         time_resolution_ms: u64,
@@ -615,13 +1381,54 @@ impl TowerDefenseApp {
         let units = (raw_ms + time_resolution_ms / 2) / time_resolution_ms;
         let final_ms = units.max(1) * time_resolution_ms;
 
-        if let Ok(mut human) = self.driver.lock() {
-            human.key_hold(direction, final_ms);
-        }
+        self.hold_key(direction, final_ms);
         (final_ms as f32 / 1000.0) * self.move_speed
     }
 
-    fn smart_move_camera(&mut self, target_map_y: f32) -> bool {
+    /// 按需移动视野使目标进入安全区，返回视野是否发生了移动
+    fn smart_move_camera(&mut self, target_map_x: f32, target_map_y: f32) -> bool {
+        let moved_y = self.smart_move_camera_y(target_map_y);
+        let moved_x = self.smart_move_camera_x(target_map_x);
+        moved_y || moved_x
+    }
+
+    fn smart_move_camera_x(&mut self, target_map_x: f32) -> bool {
+        let max_scroll_x = self.max_scroll_x();
+        if max_scroll_x <= 0.0 {
+            return false;
+        }
+        let [z_x1, _, z_x2, _] = self.config.safe_zone;
+        let safe_center_screen_x = (z_x1 + z_x2) as f32 / 2.0;
+
+        let ideal_cam_x = (target_map_x - safe_center_screen_x).clamp(0.0, max_scroll_x);
+        let delta = ideal_cam_x - self.camera_offset_x;
+
+        if delta.abs() < 90.0 {
+            return false;
+        }
+
+        let mid_scroll = max_scroll_x / 2.0;
+        const SCROLL_RES: u64 = 100;
+
+        if ideal_cam_x <= mid_scroll {
+            self.align_camera_to_edge_x(true);
+            if ideal_cam_x > 10.0 {
+                let moved = self.scroll_camera_by_pixels('d', ideal_cam_x, SCROLL_RES);
+                self.camera_offset_x += moved;
+            }
+        } else {
+            self.align_camera_to_edge_x(false);
+            let dist_left = max_scroll_x - ideal_cam_x;
+            if dist_left > 10.0 {
+                let moved = self.scroll_camera_by_pixels('a', dist_left, SCROLL_RES);
+                self.camera_offset_x -= moved;
+            }
+        }
+        thread::sleep(Duration::from_millis(200));
+        true
+    }
+
+    fn smart_move_camera_y(&mut self, target_map_y: f32) -> bool {
         let [_, z_y1, _, z_y2] = self.config.safe_zone;
         let screen_h = self.config.screen_height;
         let safe_center_screen_y = (z_y1 + z_y2) as f32 / 2.0;
@@ -660,6 +1467,7 @@ impl TowerDefenseApp {
     pub fn load_map_terrain(&mut self, path: &str) {
         if let Ok(c) = fs::read_to_string(path) {
             if let Ok(data) = serde_json::from_str::<MapTerrainExport>(&c) {
+                self.map_name = data.map_name;
                 self.map_meta = Some(data.meta);
             }
         }
@@ -676,108 +1484,123 @@ impl TowerDefenseApp {
     }
 
     pub fn setup_view(&mut self) {
-        println!("🔭 对齐左上角边界...");
-        if let Ok(mut human) = self.driver.lock() {
-            human.key_click('o');
-            thread::sleep(Duration::from_secs(2));
+        info!("🔭 对齐左上角边界...");
+        self.tap_key('o');
+        self.settle(2000);
+        self.zoom_out_fully();
+        for _ in 1..=2 {
+            self.hold_key('w', 200);
+            self.settle(50);
+            self.hold_key('a', 200);
+            self.settle(50);
+        }
+        self.hold_key('w', 200);
+        self.hold_key('a', 200);
+        self.camera_offset_x = 0.0;
+        self.camera_offset_y = 0.0;
+    }
+
+    /// 缩放要一直滚到底：沿用原来 40 帧、每帧 -120 的整段滚轮，逐格平滑滚动会拖上几分钟
+    fn zoom_out_fully(&mut self) {
+        if self.dry_run {
+            info!("📝 [DryRun] 滚轮缩放 40 帧，每帧 -120");
+            self.planned.push(PlannedAction::Wheel { delta: -120, count: 40 });
+            return;
+        }
+        if let Ok(human) = self.driver.lock() {
             for _ in 1..=4 {
                 for _ in 0..10 {
-                    human.mouse_scroll(-120);
+                    human.dispatch(|dev| dev.mouse_move(0, 0, -120));
                     thread::sleep(Duration::from_millis(30));
                 }
                 thread::sleep(Duration::from_millis(100));
             }
-            for _ in 1..=2 {
-                human.key_hold('w', 200);
-                thread::sleep(Duration::from_millis(50));
-                human.key_hold('a', 200);
-                thread::sleep(Duration::from_millis(50));
-            }
-            human.key_hold('w', 200);
-            human.key_hold('a', 200);
         }
-        self.camera_offset_y = 0.0;
     }
 
-    pub fn execute_prep_logic(&self) {
-        println!("🔧 执行赛前准备...");
+    pub fn execute_prep_logic(&mut self) {
+        info!("🔧 执行赛前准备...");
 
         if let Some(meta) = &self.map_meta {
             if !meta.prep_actions.is_empty() {
-                println!("   -> 加载自定义战术动作 ({} 步)", meta.prep_actions.len());
-                if let Ok(human) = self.driver.lock() {
-                    if let Ok(mut dev) = human.device.lock() {
-                        for action in &meta.prep_actions {
-                            match action {
-                                PrepAction::KeyDown { key } => {
-                                    let code = get_hid_code(*key);
-                                    if code != 0 {
-                                        dev.key_down(code, 0);
-                                    }
-                                }
-                                PrepAction::KeyUpAll => {
-                                    dev.key_up();
-                                }
-                                PrepAction::Wait { ms } => {
-                                    drop(dev);
-                                    thread::sleep(Duration::from_millis(*ms));
-                                    dev = human.device.lock().unwrap();
-                                }
-                                PrepAction::Log { msg } => {
-                                    println!("   [Prep] {}", msg);
+                info!("   -> 加载自定义战术动作 ({} 步)", meta.prep_actions.len());
+                if self.dry_run {
+                    info!("📝 [DryRun] 赛前准备动作: {:?}", meta.prep_actions);
+                    let actions = meta.prep_actions.iter().cloned().map(PlannedAction::Prep);
+                    self.planned.extend(actions);
+                } else if let Ok(human) = self.driver.lock() {
+                    for action in &meta.prep_actions {
+                        match action {
+                            PrepAction::KeyDown { key } => {
+                                // 地图配置里的字母不区分大小写
+                                if let Some((code, modifier)) = hid::char_to_usage(key.to_ascii_lowercase()) {
+                                    human.dispatch(|dev| dev.key_down(code, modifier));
                                 }
                             }
+                            PrepAction::KeyUpAll => {
+                                human.dispatch(|dev| dev.key_up());
+                            }
+                            PrepAction::Wait { ms } => {
+                                thread::sleep(Duration::from_millis(*ms));
+                            }
+                            PrepAction::Log { msg } => {
+                                info!("   [Prep] {}", msg);
+                            }
                         }
-                        dev.key_up();
                     }
+                    human.dispatch(|dev| dev.key_up());
                 }
             }
         }
 
-        if let Ok(mut human) = self.driver.lock() {
-            human.key_click('n');
-            thread::sleep(Duration::from_millis(500));
-        }
+        self.tap_key('n');
+        self.settle(500);
 
         self.select_loadout();
 
-        if let Ok(mut human) = self.driver.lock() {
-            human.key_click('n');
-            thread::sleep(Duration::from_millis(500));
-        }
+        self.tap_key('n');
+        self.settle(500);
     }
 
-    pub fn select_loadout(&self) {
+    pub fn select_loadout(&mut self) {
         const GRID_START_X: i32 = 520;
         const GRID_START_Y: i32 = 330;
         const GRID_STEP_X: i32 = 170;
         const GRID_STEP_Y: i32 = 205;
 
-        for name in self.active_loadout.iter().take(4) {
-            if let Some(config) = self.trap_lookup.get(name) {
-                let (tab_x, tab_y) = match config.b_type.as_str() {
-                    "Wall" => (172, 375),
-                    "Ceiling" => (172, 462),
-                    _ => (172, 294),
-                };
-
-                if let Ok(mut d) = self.driver.lock() {
-                    d.move_to_humanly(tab_x, tab_y, 0.4);
-                    d.click_humanly(true, false, 0);
-                    thread::sleep(Duration::from_millis(350));
+        if self.active_loadout.len() > self.config.loadout_slots {
+            warn!(
+                "⚠️ [Loadout] 策略需要 {} 种陷阱，但装备栏只有 {} 格，多出的将被忽略: {:?}",
+                self.active_loadout.len(),
+                self.config.loadout_slots,
+                &self.active_loadout[self.config.loadout_slots..]
+            );
+        }
 
-                    let col = config.grid_index[0];
-                    let row = config.grid_index[1];
-                    let target_x = GRID_START_X + col * GRID_STEP_X;
-                    let target_y = GRID_START_Y + row * GRID_STEP_Y;
+        let names: Vec<String> = self.active_loadout.iter().take(self.config.loadout_slots).cloned().collect();
+        for name in names {
+            let Some(config) = self.trap_lookup.get(&name) else {
+                warn!("⚠️ [Config Error] 未找到陷阱配置: {}", name);
+                continue;
+            };
+            let (tab_x, tab_y) = match config.b_type.as_str() {
+                "Wall" => (172.0, 375.0),
+                "Ceiling" => (172.0, 462.0),
+                _ => (172.0, 294.0),
+            };
+            let tab = self.to_screen(tab_x, tab_y);
+
+            let col = config.grid_index[0];
+            let row = config.grid_index[1];
+            let cell = self.to_screen(
+                (GRID_START_X + col * GRID_STEP_X) as f32,
+                (GRID_START_Y + row * GRID_STEP_Y) as f32,
+            );
 
-                    d.move_to_humanly(target_x as u16, target_y as u16, 0.4);
-                    d.click_humanly(true, false, 0);
-                }
-                thread::sleep(Duration::from_millis(400));
-            } else {
-                println!("⚠️ [Config Error] 未找到陷阱配置: {}", name);
-            }
+            self.click_at(tab.0, tab.1);
+            self.settle(350);
+            self.click_at(cell.0, cell.1);
+            self.settle(400);
         }
     }
 
@@ -795,17 +1618,21 @@ impl TowerDefenseApp {
     }
 
     fn get_trap_key(&self, name: &str) -> char {
+        // 配置里显式绑定的按键优先
+        if let Some(key) = self.trap_lookup.get(name).and_then(|c| c.hotkey) {
+            return key;
+        }
         let index = self
             .active_loadout
             .iter()
             .position(|t| t == name)
             .unwrap_or(0);
-        match index {
-            0 => '4',
-            1 => '5',
-            2 => '6',
-            3 => '7',
-            _ => '1',
+        match LOADOUT_KEYS.get(index) {
+            Some(&key) => key,
+            None => {
+                warn!("⚠️ [Loadout] 陷阱 {} 超出默认按键范围，请在配置中指定 hotkey", name);
+                '1'
+            }
         }
     }
 
@@ -814,6 +1641,15 @@ impl TowerDefenseApp {
         self.load_trap_config(trap_p);
         self.load_strategy(strategy_p);
 
+        if self.pending_resume.is_none() {
+            if let Some(path) = self.progress_path().filter(|p| Path::new(p).exists()) {
+                match self.resume_from(&path) {
+                    Ok(()) => info!("📂 [Progress] 发现进度存档: {}", path),
+                    Err(e) => warn!("⚠️ [Progress] {}", e),
+                }
+            }
+        }
+
         let mut seen = HashSet::new();
         let mut derived_loadout = Vec::new();
 
@@ -831,25 +1667,31 @@ impl TowerDefenseApp {
         }
 
         if derived_loadout.is_empty() {
-            println!("⚠️ 警告: 策略中未发现已知陷阱，装备栏将为空！");
+            warn!("⚠️ 警告: 策略中未发现已知陷阱，装备栏将为空！");
         } else {
-            println!("📋 自动分析策略，生成装备列表: {:?}", derived_loadout);
+            info!("📋 自动分析策略，生成装备列表: {:?}", derived_loadout);
         }
         self.active_loadout = derived_loadout;
 
+        let (start_x, start_y) = self.to_screen(1700.0, 950.0);
+        let (confirm_x, confirm_y) = self.to_screen(1110.0, 670.0);
         if let Ok(mut human) = self.driver.lock() {
-            println!("👆 点击游戏入口...");
-            human.move_to_humanly(1700, 950, 0.5);
+            info!("👆 点击游戏入口...");
+            human.move_to_humanly(start_x, start_y, 0.5);
             human.click_humanly(true, false, 0);
-            human.move_to_humanly(1110, 670, 0.5);
+            human.move_to_humanly(confirm_x, confirm_y, 0.5);
             human.click_humanly(true, false, 0);
         }
 
-        println!("⏳ 等待战斗开始...");
+        info!("⏳ 等待战斗开始...");
         loop {
+            if self.stop_requested() {
+                return;
+            }
             if let Some(status) = self.recognize_wave_status(self.config.hud_check_rect, false) {
                 if status.current_wave > 0 {
-                    println!("🎮 战斗开始! 初始波次: {}", status.current_wave);
+                    info!("🎮 战斗开始! 初始波次: {}", status.current_wave);
+                    self.apply_pending_resume(status.current_wave);
                     self.last_wave_change_time = Instant::now();
                     break;
                 }
@@ -859,10 +1701,16 @@ impl TowerDefenseApp {
 
         self.execute_prep_logic();
         self.setup_view();
+        if self.config.calibrate_scroll {
+            self.calibrate_scroll();
+        }
 
-        println!("🤖 自动化监控中...");
+        info!("🤖 自动化监控中...");
         let mut no_wave_count = 0;
         loop {
+            if self.stop_requested() {
+                return;
+            }
             // 尝试检测波次 (带 Tab 切换)
             // 我们把结果存下来，以便处理 "未检测到" 的情况
             let wave_status_opt = self.recognize_wave_status(self.config.hud_wave_loop_rect, true);
@@ -873,50 +1721,56 @@ impl TowerDefenseApp {
                 if self.validate_wave_transition(status.current_wave) {
                     let current_wave = status.current_wave;
                     self.execute_wave_phase(current_wave, false);
-                    println!("🔔 波次 {} 前期完成，按 G 开战", current_wave);
-                    if let Ok(mut d) = self.driver.lock() {
-                        d.key_click('g');
+                    self.save_progress();
+                    if self.stop_requested() {
+                        return;
                     }
+                    info!("🔔 波次 {} 前期完成，按 G 开战", current_wave);
+                    self.tap_key('g');
                     thread::sleep(Duration::from_secs(1));
+                    if self.stop_requested() {
+                        return;
+                    }
                     self.execute_wave_phase(current_wave, true);
+                    self.save_progress();
+                } else if self.is_stalled() {
+                    // 波次读得到却一直不变：多半卡在了某个非结算界面，交给 main 的导航重置处理
+                    warn!(
+                        "⚠️ [Monitor] 波次停留在 {} 已超过 {} 秒，判定为卡死，退出塔防模块",
+                        self.last_confirmed_wave, self.config.stall_timeout_secs
+                    );
+                    self.release_inputs();
+                    break;
                 }
             } else {
                 // === 情况 B: 未检测到波次 (可能是结算界面) ===
                 no_wave_count += 1;
-                println!(
+                warn!(
                     "⚠️ [Monitor] 未检测到波次信息 ({}/2)，尝试跳过结算...",
                     no_wave_count
                 );
 
                 if let Ok(mut d) = self.driver.lock() {
-                    println!("   -> 点击空格 (Space) + 双击 ESC");
+                    info!("   -> 点击空格 (Space) + 双击 ESC");
 
-                    // 直接操作底层设备发送 HID 码 0x29 (ESC)
-                    if let Ok(mut dev) = d.device.lock() {
-                        // 第一次 ESC
-                        dev.key_down(0x29, 0);
-                        thread::sleep(Duration::from_millis(100)); // 按下持续时间
-                        dev.key_up();
-
-                        thread::sleep(Duration::from_millis(300)); // 两次按键间隔
-                    }
+                    d.tap_escape();
+                    thread::sleep(Duration::from_millis(300)); // 两次按键间隔
 
                     // 点击空格 (跳过结算动画)
-                    d.key_click(' ');
+                    d.tap_space();
                     thread::sleep(Duration::from_millis(500));
 
-                    if let Ok(mut dev) = d.device.lock() {
-                        // 第二次 ESC
-                        dev.key_down(0x29, 0);
-                        thread::sleep(Duration::from_millis(100));
-                        dev.key_up();
-                    }
+                    d.tap_escape();
                 }
 
                 // 2. 检查退出条件
                 if no_wave_count >= 3 {
-                    println!("🏁 连续 2 次未检测到波次，判定为游戏结束。");
-                    println!("🔄 退出当前循环，返回主程序...");
+                    info!("🏁 连续 2 次未检测到波次，判定为游戏结束。");
+                    info!("🔄 退出当前循环，返回主程序...");
+                    // 对局已结束，存档不再有效，避免下一局误恢复
+                    if let Some(path) = self.progress_path() {
+                        let _ = fs::remove_file(path);
+                    }
                     break; // 跳出 loop，函数结束，控制权交还给 main 的 loop
                 }
             }
@@ -925,3 +1779,412 @@ impl TowerDefenseApp {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::{EventType, VecSink};
+    use crate::nav::tests::{engine_on, test_driver, FakeScreen};
+
+    /// 1920x1080 的塔防实例：键鼠帧写入返回的 VecSink，导航引擎用空白假屏幕
+    fn td_app() -> (TowerDefenseApp, VecSink) {
+        td_app_on(&FakeScreen::new())
+    }
+
+    /// 同 td_app，导航引擎截图取自 screen
+    fn td_app_on(screen: &FakeScreen) -> (TowerDefenseApp, VecSink) {
+        let (driver, sink) = test_driver();
+        let nav = Arc::new(engine_on("scenes = []", screen));
+        (TowerDefenseApp::new(driver, nav), sink)
+    }
+
+    /// 两座第 1 波前期的地板陷阱，都在初始视野的安全区内
+    fn load_two_building_strategy(app: &mut TowerDefenseApp) {
+        app.map_meta = Some(serde_json::from_str(
+            r#"{"grid_pixel_size": 40, "offset_x": 0, "offset_y": 0, "bottom": 1080,
+                "prep_actions": [{"type": "KeyDown", "key": "w"}, {"type": "KeyUpAll"}]}"#,
+        ).unwrap());
+        app.strategy_buildings = serde_json::from_str(
+            r#"[{"uid": 1, "name": "炮台", "grid_x": 10, "grid_y": 8, "width": 1, "height": 1, "wave_num": 1},
+                {"uid": 2, "name": "冰冻", "grid_x": 20, "grid_y": 10, "width": 1, "height": 1, "wave_num": 1}]"#,
+        ).unwrap();
+        for (name, col) in [("炮台", 0), ("冰冻", 1)] {
+            let item: TrapConfigItem =
+                serde_json::from_str(&format!(r#"{{"name": "{}", "grid_index": [{}, 0]}}"#, name, col)).unwrap();
+            app.trap_lookup.insert(name.to_string(), item);
+        }
+        app.active_loadout = vec!["炮台".into(), "冰冻".into()];
+    }
+
+    #[test]
+    fn dry_run_records_the_plan_without_sending_a_single_frame() {
+        let (mut app, sink) = td_app();
+        load_two_building_strategy(&mut app);
+        app.set_dry_run(true);
+
+        app.execute_prep_logic();
+        app.setup_view();
+        app.calibrate_scroll();
+        app.execute_wave_phase(1, false);
+        app.tap_key('g');
+
+        assert!(sink.frames().is_empty(), "dry run sent {} frames", sink.frames().len());
+        let planned = app.planned_actions();
+        assert_eq!(
+            planned[..5],
+            [
+                PlannedAction::Prep(PrepAction::KeyDown { key: 'w' }),
+                PlannedAction::Prep(PrepAction::KeyUpAll),
+                PlannedAction::KeyClick { key: 'n' },
+                PlannedAction::Click { x: 172, y: 294 },
+                PlannedAction::Click { x: 520, y: 330 },
+            ]
+        );
+        assert!(planned.contains(&PlannedAction::Wheel { delta: -120, count: 40 }));
+        let places: Vec<(usize, f32, f32)> = planned
+            .iter()
+            .filter_map(|a| match a {
+                PlannedAction::Place { uid, screen_x, screen_y, .. } => Some((*uid, *screen_x, *screen_y)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(places, [(1, 420.0, 340.0), (2, 820.0, 420.0)]);
+        assert_eq!(planned.last(), Some(&PlannedAction::KeyClick { key: 'g' }));
+    }
+
+    #[test]
+    fn action_log_records_each_task_with_its_key_and_coordinates() {
+        let (mut app, _sink) = td_app();
+        load_two_building_strategy(&mut app);
+        app.set_dry_run(true);
+        let path = std::env::temp_dir().join(format!("nzm_actions_{}.json", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        app.enable_action_log(&path);
+
+        app.execute_wave_phase(1, false);
+        let written = fs::read_to_string(&path);
+        let _ = fs::remove_file(&path);
+        let entries: Vec<ActionLogEntry> = serde_json::from_str(&written.unwrap()).unwrap();
+
+        assert_eq!(entries, app.action_log_entries());
+        let summary: Vec<_> = entries.iter().map(|e| (e.kind, e.uid, e.key, e.map, e.screen, e.ok)).collect();
+        assert_eq!(
+            summary,
+            [
+                (ActionKind::Place, Some(1), Some('4'), Some([420.0, 340.0]), Some([420.0, 340.0]), true),
+                (ActionKind::Place, Some(2), Some('5'), Some([820.0, 420.0]), Some([820.0, 420.0]), true),
+            ]
+        );
+        assert!(entries.iter().all(|e| e.wave == 1 && !e.is_late));
+    }
+
+    #[test]
+    fn a_place_on_a_demolished_cell_always_runs_after_the_demolish() {
+        // 炮台 (uid 1) 的格子上同一阶段还有一个要拆的旧炮台 (uid 9)
+        let overlapping = || {
+            let (mut app, _sink) = td_app();
+            load_two_building_strategy(&mut app);
+            app.strategy_demolishes = serde_json::from_str(
+                r#"[{"uid": 9, "name": "旧炮台", "grid_x": 10, "grid_y": 8, "width": 1, "height": 1, "wave_num": 1, "is_late": false}]"#,
+            ).unwrap();
+            app.set_dry_run(true);
+            app
+        };
+        let order = |app: &TowerDefenseApp| -> Vec<(&'static str, usize)> {
+            app.planned_actions()
+                .iter()
+                .filter_map(|a| match a {
+                    PlannedAction::Demolish { uid, .. } => Some(("demolish", *uid)),
+                    PlannedAction::Place { uid, .. } => Some(("place", *uid)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let mut app = overlapping();
+        app.execute_wave_phase(1, false);
+        assert_eq!(order(&app), [("demolish", 9), ("place", 1), ("place", 2)]);
+
+        // 拆除阶段没做成 (这里直接跳过拆除阶段)：放置前先补做同格子的拆除
+        let mut app = overlapping();
+        let b = app.strategy_buildings[0].clone();
+        let (map_x, map_y) = app.get_absolute_map_pixel(b.grid_x, b.grid_y, b.width, b.height).unwrap();
+        let mut tasks = vec![ScheduledTask { action: TaskAction::Place(b), map_y, map_x, priority: 1, requires_demolish: Vec::new() }];
+        app.link_demolish_dependencies(1, false, &mut tasks);
+        app.process_task_batch(tasks, false);
+        assert_eq!(order(&app), [("demolish", 9), ("place", 1)]);
+    }
+
+    #[test]
+    fn demolish_presses_e_as_often_as_configured() {
+        let (mut app, sink) = td_app();
+        load_two_building_strategy(&mut app);
+        app.config.demolish_key_gap_ms = 0;
+        let e_presses = |app: &mut TowerDefenseApp, repeats: u8| {
+            app.config.demolish_key_repeats = repeats;
+            sink.clear();
+            app.perform_demolish_action(420.0, 340.0, 9);
+            sink.frames()
+                .iter()
+                .filter(|f| f[1] == EventType::Keyboard as u8 && f[2] == 0x08)
+                .count()
+        };
+
+        assert_eq!(e_presses(&mut app, 2), 2);
+        assert_eq!(e_presses(&mut app, 4), 4);
+    }
+
+    #[test]
+    fn td_config_round_trips_through_json_and_toml_files() {
+        let config = TDConfig {
+            safe_zone: [100, 120, 1800, 960],
+            place_retries: 3,
+            wave_ocr_charset: Some("0123456789/波次".into()),
+            stall_timeout_secs: 90,
+            ..TDConfig::default()
+        };
+        let dir = std::env::temp_dir();
+        let json_path = dir.join(format!("nzm_td_{}.json", std::process::id())).to_string_lossy().to_string();
+        let toml_path = dir.join(format!("nzm_td_{}.toml", std::process::id())).to_string_lossy().to_string();
+        fs::write(&json_path, serde_json::to_string(&config).unwrap()).unwrap();
+        fs::write(&toml_path, toml::to_string(&config).unwrap()).unwrap();
+        let from_json = TDConfig::from_file(&json_path);
+        let from_toml = TDConfig::from_file(&toml_path);
+        let _ = fs::remove_file(&json_path);
+        let _ = fs::remove_file(&toml_path);
+
+        let expected = serde_json::to_value(&config).unwrap();
+        assert_eq!(serde_json::to_value(from_json.unwrap()).unwrap(), expected);
+        assert_eq!(serde_json::to_value(from_toml.unwrap()).unwrap(), expected);
+
+        // 只写了部分字段的 TOML，其余回落到默认值
+        fs::write(&toml_path, "place_retries = 5\n").unwrap();
+        let partial = TDConfig::from_file(&toml_path);
+        let _ = fs::remove_file(&toml_path);
+        let partial = partial.unwrap();
+        assert_eq!(partial.place_retries, 5);
+        assert_eq!(partial.safe_zone, TDConfig::default().safe_zone);
+    }
+
+    #[test]
+    fn each_trap_in_a_five_slot_loadout_gets_its_own_key() {
+        let (mut app, _sink) = td_app();
+        app.active_loadout = ["炮台", "冰冻", "地刺", "火墙", "电网"].map(String::from).to_vec();
+        let keys: Vec<char> = app.active_loadout.iter().map(|name| app.get_trap_key(name)).collect();
+        assert_eq!(keys, ['4', '5', '6', '7', '8']);
+
+        // 配置里显式绑定的 hotkey 优先于装备栏位置
+        let item: TrapConfigItem = serde_json::from_str(r#"{"name": "电网", "hotkey": "3"}"#).unwrap();
+        app.trap_lookup.insert("电网".into(), item);
+        assert_eq!(app.get_trap_key("电网"), '3');
+    }
+
+    #[test]
+    fn majority_vote_filters_a_single_misread() {
+        assert_eq!(majority_vote(&[5, 5, 7]), Some(5));
+        assert_eq!(majority_vote(&[7, 5, 5]), Some(5));
+        // 票数相同时取最早读到的值
+        assert_eq!(majority_vote(&[7, 5]), Some(7));
+        assert_eq!(majority_vote(&[5, 7, 7, 5]), Some(5));
+        assert_eq!(majority_vote(&[]), None);
+    }
+
+    #[test]
+    fn stop_exits_before_the_next_group_and_releases_inputs() {
+        let released = |sink: &VecSink| {
+            let frames = sink.frames();
+            let key_up = frames.iter().any(|f| f[1] == EventType::Keyboard as u8 && f[3] == 0x80);
+            let mouse_up = frames.iter().any(|f| f[1] == EventType::MouseRel as u8 && f[2] == 0);
+            key_up && mouse_up
+        };
+
+        let (mut app, sink) = td_app();
+        load_two_building_strategy(&mut app);
+        app.set_dry_run(true);
+        let tasks: Vec<ScheduledTask> = app
+            .strategy_buildings
+            .clone()
+            .into_iter()
+            .map(|b| {
+                let (map_x, map_y) = app.get_absolute_map_pixel(b.grid_x, b.grid_y, b.width, b.height).unwrap();
+                ScheduledTask { action: TaskAction::Place(b), map_y, map_x, priority: 1, requires_demolish: Vec::new() }
+            })
+            .collect();
+        app.set_state(RunState::Stopped);
+        app.dispatch_tasks_by_region(tasks);
+        assert!(app.planned_actions().is_empty(), "{:?}", app.planned_actions());
+        assert!(released(&sink));
+
+        // 主循环在等待开局时收到停止也立即返回
+        let (mut app, sink) = td_app();
+        app.set_state(RunState::Stopped);
+        app.run("missing_terrain.json", "missing_strategy.json", "missing_traps.json");
+        assert!(released(&sink));
+    }
+
+    #[test]
+    fn a_placement_that_fails_verification_is_not_recorded_as_placed() {
+        // 冰冻的落点上出现了陷阱的颜色，炮台的落点上没有
+        let screen = FakeScreen::new();
+        screen.paint(820, 420, [0, 200, 255]);
+        let (mut app, _sink) = td_app_on(&screen);
+        load_two_building_strategy(&mut app);
+        for item in app.trap_lookup.values_mut() {
+            item.verify_color = Some("#00C8FF".into());
+        }
+        app.config.place_retries = 0;
+        app.config.build_swap_gap_ms = 0;
+        app.enable_action_log(&std::env::temp_dir().join("nzm_unused_actions.json").to_string_lossy());
+
+        let mut last_key = None;
+        app.perform_build_action(&mut last_key, false, 420.0, 340.0, "炮台", 1);
+        app.perform_build_action(&mut last_key, false, 820.0, 420.0, "冰冻", 2);
+
+        assert!(!app.placed_uids.contains(&1));
+        assert!(app.placed_uids.contains(&2));
+        let results: Vec<_> = app.action_log_entries().iter().map(|e| (e.uid, e.ok)).collect();
+        assert_eq!(results, [(Some(1), false), (Some(2), true)]);
+    }
+
+    #[test]
+    fn tasks_beyond_the_screen_width_scroll_the_view_sideways() {
+        let (mut app, sink) = td_app();
+        load_two_building_strategy(&mut app);
+        // 两屏宽的地图：右上角一座，左下角一座
+        app.map_meta.as_mut().unwrap().right = 3840.0;
+        app.strategy_buildings = serde_json::from_str(
+            r#"[{"uid": 1, "name": "炮台", "grid_x": 80, "grid_y": 8, "width": 1, "height": 1, "wave_num": 1},
+                {"uid": 2, "name": "冰冻", "grid_x": 10, "grid_y": 20, "width": 1, "height": 1, "wave_num": 1}]"#,
+        ).unwrap();
+        app.set_dry_run(true);
+
+        app.execute_wave_phase(1, false);
+
+        assert!(sink.frames().is_empty());
+        let steps: Vec<_> = app
+            .planned_actions()
+            .iter()
+            .filter_map(|a| match a {
+                PlannedAction::KeyHold { key: key @ ('a' | 'd'), .. } => Some(format!("hold {}", key)),
+                PlannedAction::Place { uid, screen_x, .. } => Some(format!("place {} @ {}", uid, screen_x)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(steps, ["hold d", "place 1 @ 1300", "hold a", "place 2 @ 420"]);
+    }
+
+    #[test]
+    fn scroll_speed_is_measured_from_the_landmark_shift() {
+        // 带纹理的地图，视野向下卷动 40px 后地标整体上移 40px
+        let map = |x: u32, y: u32| image::Luma([((x * 73 + y * 151) ^ (y * y * 7)) as u8]);
+        let before = image::GrayImage::from_fn(200, 240, map);
+        let after = image::GrayImage::from_fn(200, 240, |x, y| map(x, y + 40));
+        let shift = estimate_vertical_shift(&before, &after, 0.6);
+        assert_eq!(shift, Some(40));
+        assert_eq!(estimate_vertical_shift(&after, &before, 0.6), Some(-40));
+
+        assert_eq!(scroll_speed_from_shift(40, CALIBRATE_HOLD_MS), Some(100.0));
+        assert_eq!(scroll_speed_from_shift(-40, CALIBRATE_HOLD_MS), Some(100.0));
+        // 位移太小、按键时长为 0 或速度离谱时不采信
+        assert_eq!(scroll_speed_from_shift(3, CALIBRATE_HOLD_MS), None);
+        assert_eq!(scroll_speed_from_shift(40, 0), None);
+        assert_eq!(scroll_speed_from_shift(2000, CALIBRATE_HOLD_MS), None);
+    }
+
+    #[test]
+    fn a_task_outside_the_safe_zone_is_reported_instead_of_clamped() {
+        let (mut app, sink) = td_app();
+        load_two_building_strategy(&mut app);
+        app.config.rescroll_out_of_zone = false;
+        app.set_dry_run(true);
+        app.enable_action_log(&std::env::temp_dir().join("nzm_unused_actions.json").to_string_lossy());
+
+        let mut last_key = None;
+        // 安全区 [200, 200, 1720, 880]：x=1900 越界 180px，远超 10px 容差
+        app.perform_build_action(&mut last_key, false, 1900.0, 500.0, "炮台", 1);
+        // 越界 6px 仍在容差内，夹到边缘照常放置
+        app.perform_build_action(&mut last_key, false, 1726.0, 500.0, "冰冻", 2);
+
+        assert!(sink.frames().is_empty());
+        assert!(!app.placed_uids.contains(&1));
+        assert!(app.placed_uids.contains(&2));
+        let places: Vec<_> = app
+            .planned_actions()
+            .iter()
+            .filter_map(|a| match a {
+                PlannedAction::Place { uid, screen_x, .. } => Some((*uid, *screen_x)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(places, [(2, 1720.0)]);
+        let results: Vec<_> = app.action_log_entries().iter().map(|e| (e.uid, e.screen, e.ok)).collect();
+        assert_eq!(results, [(Some(1), None, false), (Some(2), Some([1720.0, 500.0]), true)]);
+    }
+
+    #[test]
+    fn a_wave_counter_stuck_past_the_timeout_trips_the_watchdog() {
+        let (mut app, _sink) = td_app();
+        let stuck_since = Instant::now().checked_sub(Duration::from_secs(6)).unwrap();
+        app.last_confirmed_wave = 3;
+        app.last_wave_change_time = stuck_since;
+        // 默认关闭
+        assert!(!app.is_stalled());
+
+        app.config.stall_timeout_secs = 5;
+        assert!(app.is_stalled());
+        // 同一波次再次读到不会重置计时
+        assert!(!app.validate_wave_transition(3));
+        assert!(app.is_stalled());
+
+        app.config.stall_timeout_secs = 10;
+        assert!(!app.is_stalled());
+    }
+
+    fn snapshot(saved_at: i64) -> ProgressSnapshot {
+        ProgressSnapshot {
+            map_name: "空间站".into(),
+            scene_id: "空间站普通".into(),
+            saved_at,
+            last_confirmed_wave: 5,
+            placed_uids: [1, 2, 3].into_iter().collect(),
+            completed_upgrade_keys: ["炮台-3-false".to_string()].into_iter().collect(),
+            completed_demolish_uids: [7].into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn progress_snapshot_round_trips_through_a_file() {
+        let snap = snapshot(1_700_000_000);
+        let path = std::env::temp_dir().join(format!("nzm_progress_{}.json", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        snap.save(&path).unwrap();
+        let loaded = ProgressSnapshot::from_file(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.unwrap(), snap);
+    }
+
+    #[test]
+    fn only_a_fresh_snapshot_of_the_same_match_is_resumed() {
+        let now = 1_700_000_000;
+        let snap = snapshot(now - 30);
+        assert!(snap.check_resumable("空间站", "空间站普通", 5, now).is_ok());
+        assert!(snap.check_resumable("空间站", "空间站普通", 6, now).is_ok());
+
+        // 新开一局回到前几波，或者跳过了一波以上
+        assert!(snap.check_resumable("空间站", "空间站普通", 1, now).is_err());
+        assert!(snap.check_resumable("空间站", "空间站普通", 7, now).is_err());
+        // 其他场景 / 其他地图
+        assert!(snap.check_resumable("空间站", "空间站炼狱", 5, now).is_err());
+        assert!(snap.check_resumable("雪地", "空间站普通", 5, now).is_err());
+        // 上一局遗留的旧存档，即使波次对得上也不恢复
+        let stale = snapshot(now - RESUME_MAX_AGE_SECS - 1);
+        assert!(stale.check_resumable("空间站", "空间站普通", 5, now).is_err());
+
+        // 旧格式存档没有 scene_id / saved_at，一律视为过期
+        let legacy: ProgressSnapshot = serde_json::from_str(
+            r#"{"map_name":"空间站","last_confirmed_wave":5,"placed_uids":[1],"completed_upgrade_keys":[],"completed_demolish_uids":[]}"#,
+        )
+        .unwrap();
+        assert!(legacy.check_resumable("空间站", "", 5, now).is_err());
+    }
+}
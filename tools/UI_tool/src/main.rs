@@ -2,7 +2,7 @@
 
 use eframe::egui::{self, Color32, Pos2, Rect, RichText, Sense, Stroke, Vec2};
 use screenshots::Screen;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::time::Instant;
 
@@ -16,33 +16,257 @@ use windows::Storage::Streams::{DataWriter, InMemoryRandomAccessStream};
 // 1. 数据结构
 // ==========================================
 #[derive(Clone, PartialEq)]
-enum RecognitionLogic { AND, OR }
+enum RecognitionLogic { AND, OR, AtLeast(usize) }
 
 #[derive(Clone, PartialEq)]
 enum ElementKind {
-    TextAnchor { text: String },
-    ColorAnchor { color_hex: String, tolerance: u8 },
-    Button { target: String, post_delay: u32 },
+    TextAnchor { text: String, weight: u32 },
+    ColorAnchor { color_hex: String, tolerance: u8, radius: u8, weight: u32 },
+    Button { target: String, post_delay: u32, pre_delay: u32, wait_gone: bool },
 }
 
+/// 画布上当前拖拽的用途
+#[derive(Clone, Copy)]
+enum DragMode {
+    /// 在空白处拖出新框
+    NewRect,
+    /// 拖动元素主体：grab 为按下点相对元素左上角的偏移 (图片坐标)
+    Move { idx: usize, grab: Vec2 },
+    /// 拖动角点缩放：anchor 为固定不动的对角点 (图片坐标)
+    Resize { idx: usize, anchor: Pos2 },
+}
+
+/// 角点手柄的边长 (屏幕像素)
+const HANDLE_SIZE: f32 = 8.0;
+
+/// 画布缩放上限 (相对适应窗口的倍数)
+const MAX_ZOOM: f32 = 40.0;
+/// 放大镜显示的像素半径与每个像素的显示边长
+const LOUPE_RADIUS: i64 = 5;
+const LOUPE_CELL: f32 = 10.0;
+
+/// 左上、右上、左下、右下；第 i 个角的对角为 3 - i
+fn corners(r: Rect) -> [Pos2; 4] { [r.left_top(), r.right_top(), r.left_bottom(), r.right_bottom()] }
+
 #[derive(Clone)]
 struct UIElementDraft {
     pos_or_rect: Rect,
     kind: ElementKind,
+    /// 导入时该锚点/跳转的原始表 (新建的为空)，导出时保留 fuzz、mode/vtol、cost 等工具不编辑的字段
+    raw: toml::Table,
+}
+
+impl UIElementDraft {
+    fn new(pos_or_rect: Rect, kind: ElementKind) -> Self {
+        Self { pos_or_rect, kind, raw: toml::Table::new() }
+    }
+}
+
+/// 单个场景的编辑状态，对应 ui_map.toml 中的一个 [[scenes]]
+#[derive(Clone)]
+struct SceneDraft {
+    scene_id: String,
+    scene_name: String,
+    logic: RecognitionLogic,
+    handler: Option<String>,
+    drafts: Vec<UIElementDraft>,
+    /// 导入时场景的原始表，导出时保留 scan_rect、recovery、图片锚点等工具不编辑的字段
+    raw: toml::Table,
+}
+
+impl SceneDraft {
+    fn new(id: &str, name: &str) -> Self {
+        Self { scene_id: id.into(), scene_name: name.into(), logic: RecognitionLogic::AND, handler: None, drafts: Vec::new(), raw: toml::Table::new() }
+    }
+}
+
+// 导入和导出共用同一套结构，生成的 TOML 一定能被原样导入
+// 这些结构只描述工具能编辑的字段；其余字段保存在各 draft 的 raw 表里，导出时合并回去
+#[derive(Serialize, Deserialize)]
+struct TomlScene {
+    id: String,
+    name: String,
+    logic: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_matches: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    handler: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    anchors: Option<TomlAnchors>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transitions: Option<Vec<TomlTransition>>,
+}
+#[derive(Serialize, Deserialize)]
+struct TomlAnchors {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<Vec<TomlTextAnchor>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<Vec<TomlColorAnchor>>,
+}
+#[derive(Serialize, Deserialize)]
+struct TomlTextAnchor { rect: [i32; 4], val: String, #[serde(default = "default_weight", skip_serializing_if = "is_default_weight")] weight: u32 }
+#[derive(Serialize, Deserialize)]
+struct TomlColorAnchor { pos: [i32; 2], val: String, tol: u8, #[serde(default, skip_serializing_if = "is_zero")] r: u8, #[serde(default = "default_weight", skip_serializing_if = "is_default_weight")] weight: u32 }
+#[derive(Serialize, Deserialize)]
+struct TomlTransition {
+    target: String,
+    coords: [i32; 2],
+    post_delay: u32,
+    #[serde(default, skip_serializing_if = "is_zero_u32")]
+    pre_delay: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wait_mode: Option<String>,
+}
+
+fn is_zero(v: &u8) -> bool { *v == 0 }
+fn is_zero_u32(v: &u32) -> bool { *v == 0 }
+fn default_weight() -> u32 { 1 }
+fn is_default_weight(v: &u32) -> bool { *v == 1 }
+
+/// 以导入时的原始表为底，把 owned 中的键替换成工具生成的值 (生成结果里没有的键删除)，其余字段原样保留
+fn merge_owned<T: Serialize>(raw: &toml::Table, owned: &[&str], generated: &T) -> Result<toml::Table, String> {
+    let toml::Value::Table(mut generated) = toml::Value::try_from(generated).map_err(|e| e.to_string())? else {
+        return Err("生成结果不是表".into());
+    };
+    let mut out = raw.clone();
+    for key in owned {
+        match generated.remove(*key) {
+            Some(v) => { out.insert(key.to_string(), v); }
+            None => { out.remove(*key); }
+        }
+    }
+    Ok(out)
+}
+
+/// 原始表中数组 list 的第 i 个表；不存在时为空表
+fn raw_item(list: Option<&toml::Value>, i: usize) -> toml::Table {
+    list.and_then(|v| v.as_array()).and_then(|a| a.get(i)).and_then(|v| v.as_table()).cloned().unwrap_or_default()
+}
+
+fn table_array(items: Vec<toml::Table>) -> toml::Value {
+    toml::Value::Array(items.into_iter().map(toml::Value::Table).collect())
+}
+
+/// 把全部场景合并进导入时的根表 (保留 authored_size 等顶层字段) 并输出 TOML
+fn scenes_to_toml(root: &toml::Table, scenes: &[SceneDraft]) -> Result<String, String> {
+    let mut root = root.clone();
+    let scenes = scenes.iter().map(scene_to_toml).collect::<Result<Vec<_>, _>>()?;
+    root.insert("scenes".into(), table_array(scenes));
+    toml::to_string(&root).map_err(|e| e.to_string())
+}
+
+/// 解析 TOML，返回根表和场景列表
+fn scenes_from_toml(content: &str) -> Result<(toml::Table, Vec<SceneDraft>), String> {
+    let root: toml::Table = toml::from_str(content).map_err(|e| e.to_string())?;
+    let mut scenes = Vec::new();
+    for raw in root.get("scenes").and_then(|v| v.as_array()).into_iter().flatten() {
+        let raw = raw.as_table().cloned().ok_or("scenes 中的元素必须是表")?;
+        let scene: TomlScene = toml::Value::Table(raw.clone()).try_into().map_err(|e: toml::de::Error| e.to_string())?;
+        scenes.push(scene_from_toml(&scene, raw));
+    }
+    Ok((root, scenes))
 }
 
-#[derive(Deserialize)]
-struct TomlRoot { scenes: Vec<TomlScene> }
-#[derive(Deserialize)]
-struct TomlScene { id: String, name: String, logic: String, anchors: Option<TomlAnchors>, transitions: Option<Vec<TomlTransition>> }
-#[derive(Deserialize)]
-struct TomlAnchors { text: Option<Vec<TomlTextAnchor>>, color: Option<Vec<TomlColorAnchor>> }
-#[derive(Deserialize)]
-struct TomlTextAnchor { rect: [i32; 4], val: String }
-#[derive(Deserialize)]
-struct TomlColorAnchor { pos: [i32; 2], val: String, tol: u8 }
-#[derive(Deserialize)]
-struct TomlTransition { target: String, coords: [i32; 2], post_delay: u32 }
+fn scene_to_toml(scene: &SceneDraft) -> Result<toml::Table, String> {
+    let (mut texts, mut colors, mut transitions) = (Vec::new(), Vec::new(), Vec::new());
+    for d in scene.drafts.iter() {
+        let r = d.pos_or_rect;
+        match &d.kind {
+            ElementKind::TextAnchor { text, weight } => texts.push(merge_owned(&d.raw, &["rect", "val", "weight"], &TomlTextAnchor {
+                rect: [r.min.x as i32, r.min.y as i32, r.max.x as i32, r.max.y as i32],
+                val: text.clone(),
+                weight: *weight,
+            })?),
+            ElementKind::ColorAnchor { color_hex, tolerance, radius, weight } => colors.push(merge_owned(&d.raw, &["pos", "val", "tol", "r", "weight"], &TomlColorAnchor {
+                pos: [r.min.x as i32, r.min.y as i32],
+                val: color_hex.clone(),
+                tol: *tolerance,
+                r: *radius,
+                weight: *weight,
+            })?),
+            ElementKind::Button { target, post_delay, pre_delay, wait_gone } => {
+                // 工具只能切换 "disappear"，原文件里其他取值原样保留
+                let kept_mode = d.raw.get("wait_mode").and_then(|v| v.as_str()).filter(|m| *m != "disappear");
+                transitions.push(merge_owned(&d.raw, &["target", "coords", "post_delay", "pre_delay", "wait_mode"], &TomlTransition {
+                    target: target.clone(),
+                    coords: [r.center().x as i32, r.center().y as i32],
+                    post_delay: *post_delay,
+                    pre_delay: *pre_delay,
+                    wait_mode: if *wait_gone { Some("disappear".to_string()) } else { kept_mode.map(String::from) },
+                })?);
+            }
+        }
+    }
+
+    // 原文件用 min_matches 表达的 k-of-n 继续写回 min_matches，logic 保持原样
+    let raw_logic = scene.raw.get("logic").and_then(|v| v.as_str()).unwrap_or_default();
+    let (logic, min_matches) = match scene.logic {
+        RecognitionLogic::AND => ("and".to_string(), None),
+        RecognitionLogic::OR => ("or".to_string(), None),
+        RecognitionLogic::AtLeast(k) if scene.raw.contains_key("min_matches") => (raw_logic.to_string(), Some(k)),
+        RecognitionLogic::AtLeast(k) => (format!("k:{}", k), None),
+    };
+    let generated = TomlScene {
+        id: scene.scene_id.clone(),
+        name: scene.scene_name.clone(),
+        logic,
+        min_matches,
+        handler: scene.handler.clone(),
+        anchors: None,
+        transitions: None,
+    };
+    let mut out = merge_owned(&scene.raw, &["id", "name", "logic", "min_matches", "handler"], &generated)?;
+
+    // anchors 里的 image 等工具不编辑的列表保留，只替换 text / color
+    let mut anchors = scene.raw.get("anchors").and_then(|v| v.as_table()).cloned().unwrap_or_default();
+    for (key, items) in [("text", texts), ("color", colors)] {
+        if items.is_empty() { anchors.remove(key); } else { anchors.insert(key.into(), table_array(items)); }
+    }
+    if anchors.is_empty() { out.remove("anchors"); } else { out.insert("anchors".into(), toml::Value::Table(anchors)); }
+    if transitions.is_empty() { out.remove("transitions"); } else { out.insert("transitions".into(), table_array(transitions)); }
+    Ok(out)
+}
+
+fn scene_from_toml(scene: &TomlScene, raw: toml::Table) -> SceneDraft {
+    let mut draft = SceneDraft::new(&scene.id, &scene.name);
+    let logic = scene.logic.trim().to_lowercase();
+    let k = scene.min_matches.or_else(|| logic.strip_prefix("k:").and_then(|n| n.trim().parse().ok()));
+    draft.logic = match k {
+        Some(k) => RecognitionLogic::AtLeast(k),
+        None if logic == "or" => RecognitionLogic::OR,
+        None => RecognitionLogic::AND,
+    };
+    draft.handler = scene.handler.clone();
+    let raw_anchors = raw.get("anchors").and_then(|v| v.as_table());
+    if let Some(anchors) = &scene.anchors {
+        for (i, t) in anchors.text.iter().flatten().enumerate() {
+            let rect = Rect::from_min_max(Pos2::new(t.rect[0] as f32, t.rect[1] as f32), Pos2::new(t.rect[2] as f32, t.rect[3] as f32));
+            let mut d = UIElementDraft::new(rect, ElementKind::TextAnchor { text: t.val.clone(), weight: t.weight });
+            d.raw = raw_item(raw_anchors.and_then(|a| a.get("text")), i);
+            draft.drafts.push(d);
+        }
+        for (i, c) in anchors.color.iter().flatten().enumerate() {
+            let pos = Pos2::new(c.pos[0] as f32, c.pos[1] as f32);
+            let rect = Rect::from_min_max(pos, pos + Vec2::splat(1.0));
+            let mut d = UIElementDraft::new(rect, ElementKind::ColorAnchor { color_hex: c.val.clone(), tolerance: c.tol, radius: c.r, weight: c.weight });
+            d.raw = raw_item(raw_anchors.and_then(|a| a.get("color")), i);
+            draft.drafts.push(d);
+        }
+    }
+    for (i, t) in scene.transitions.iter().flatten().enumerate() {
+        let rect = Rect::from_center_size(Pos2::new(t.coords[0] as f32, t.coords[1] as f32), Vec2::splat(20.0));
+        let mut d = UIElementDraft::new(rect, ElementKind::Button {
+            target: t.target.clone(),
+            post_delay: t.post_delay,
+            pre_delay: t.pre_delay,
+            wait_gone: t.wait_mode.as_deref() == Some("disappear"),
+        });
+        d.raw = raw_item(raw.get("transitions"), i);
+        draft.drafts.push(d);
+    }
+    draft.raw = raw;
+    draft
+}
 
 // ==========================================
 // 2. 编辑器状态
@@ -55,17 +279,22 @@ struct MapBuilderTool {
     ocr_engine: Option<OcrEngine>,
     ocr_test_result: String, 
 
-    scene_id: String,
-    scene_name: String,
-    logic: RecognitionLogic,
+    scenes: Vec<SceneDraft>,
+    current_scene: usize,
     
     start_pos: Option<Pos2>,
     current_rect: Option<Rect>,
+    selected: Option<usize>,
+    drag_mode: Option<DragMode>,
+    zoom: f32,
+    pan: Vec2,
     is_color_picker_mode: bool,
+    pick_radius: u8,
     capture_timer: Option<Instant>, 
 
-    drafts: Vec<UIElementDraft>,
     toml_content: String,
+    /// 导入时的根表，导出时保留 authored_size 等 scenes 以外的顶层字段
+    toml_root: toml::Table,
     status_msg: String,
 }
 
@@ -84,115 +313,139 @@ impl MapBuilderTool {
             img_size: Vec2::ZERO,
             ocr_engine: engine,          
             ocr_test_result: String::new(), 
-            scene_id: "lobby_01".into(),
-            scene_name: "游戏主界面".into(),
-            logic: RecognitionLogic::AND,
+            scenes: vec![SceneDraft::new("lobby_01", "游戏主界面")],
+            current_scene: 0,
             start_pos: None,
             current_rect: None,
+            selected: None,
+            drag_mode: None,
+            zoom: 1.0,
+            pan: Vec2::ZERO,
             is_color_picker_mode: false,
+            pick_radius: 0,
             capture_timer: None,
-            drafts: Vec::new(),
             toml_content: String::new(),
+            toml_root: toml::Table::new(),
             status_msg: status.into(),
         }
     }
 
+    /// 截图和打开图片共用：更新原图、尺寸和显示纹理
+    fn set_image(&mut self, ctx: &egui::Context, image: image::RgbaImage) {
+        self.img_size = Vec2::new(image.width() as f32, image.height() as f32);
+        let color_img = egui::ColorImage::from_rgba_unmultiplied(
+            [image.width() as usize, image.height() as usize], 
+            image.as_flat_samples().as_slice()
+        );
+        self.texture = Some(ctx.load_texture("shot", color_img, egui::TextureOptions::NEAREST));
+        self.raw_image = Some(image);
+        self.start_pos = None;
+        self.current_rect = None;
+        self.zoom = 1.0;
+        self.pan = Vec2::ZERO;
+    }
+
     fn capture_immediate(&mut self, ctx: &egui::Context) {
         let screens = Screen::all().unwrap();
         if let Some(screen) = screens.first() {
             if let Ok(image) = screen.capture() {
-                self.img_size = Vec2::new(image.width() as f32, image.height() as f32);
-                self.raw_image = Some(image.clone()); 
-                let color_img = egui::ColorImage::from_rgba_unmultiplied(
-                    [image.width() as usize, image.height() as usize], 
-                    image.as_flat_samples().as_slice()
-                );
-                self.texture = Some(ctx.load_texture("shot", color_img, Default::default()));
+                self.set_image(ctx, image);
                 self.status_msg = "截图成功".into();
             }
         }
     }
 
-    fn pick_color(&self, p: Pos2) -> String {
-        if let Some(img) = &self.raw_image {
-            let x = p.x as u32;
-            let y = p.y as u32;
-            if x < img.width() && y < img.height() {
-                let pixel = img.get_pixel(x, y);
-                return format!("#{:02X}{:02X}{:02X}", pixel[0], pixel[1], pixel[2]);
+    /// 从磁盘加载截图，可在游戏未运行时离线制作场景 (保留当前已画的元素)
+    fn open_image(&mut self, ctx: &egui::Context) {
+        let Some(path) = rfd::FileDialog::new().add_filter("图片", &["png", "jpg", "jpeg", "bmp"]).pick_file() else { return; };
+        match image::open(&path) {
+            Ok(img) => {
+                self.set_image(ctx, img.to_rgba8());
+                self.status_msg = format!("已打开图片: {}", path.display());
             }
+            Err(e) => { self.status_msg = format!("打开图片失败: {}", e); }
         }
-        "#FFFFFF".into()
     }
 
-    fn build_toml(&mut self) {
-        let logic_str = if self.logic == RecognitionLogic::AND { "and" } else { "or" };
-        let mut toml = format!("[[scenes]]\nid = \"{}\"\nname = \"{}\"\nlogic = \"{}\"\n\n", self.scene_id, self.scene_name, logic_str);
-        toml.push_str("[scenes.anchors]\n");
-        toml.push_str("text = [\n");
-        for d in self.drafts.iter() {
-            if let ElementKind::TextAnchor { text } = &d.kind {
-                toml.push_str(&format!("  {{ rect = [{}, {}, {}, {}], val = \"{}\" }},\n",
-                    d.pos_or_rect.min.x as i32, d.pos_or_rect.min.y as i32, d.pos_or_rect.max.x as i32, d.pos_or_rect.max.y as i32, text));
+    /// 取色：radius 为 0 时取单点，否则取 (2r+1)x(2r+1) 方块的平均色 (与主程序的 r 字段一致)
+    fn pick_color(&self, p: Pos2, radius: u8) -> String {
+        if let Some(img) = &self.raw_image {
+            let (cx, cy, r) = (p.x as i64, p.y as i64, radius as i64);
+            let (mut sum, mut n) = ([0u32; 3], 0u32);
+            for y in (cy - r)..=(cy + r) {
+                for x in (cx - r)..=(cx + r) {
+                    if x < 0 || y < 0 || x >= img.width() as i64 || y >= img.height() as i64 { continue; }
+                    let pixel = img.get_pixel(x as u32, y as u32);
+                    for (acc, v) in sum.iter_mut().zip(pixel.0.iter()) { *acc += *v as u32; }
+                    n += 1;
+                }
             }
-        }
-        toml.push_str("]\ncolor = [\n");
-        for d in self.drafts.iter() {
-            if let ElementKind::ColorAnchor { color_hex, tolerance } = &d.kind {
-                toml.push_str(&format!("  {{ pos = [{}, {}], val = \"{}\", tol = {} }},\n",
-                    d.pos_or_rect.min.x as i32, d.pos_or_rect.min.y as i32, color_hex, tolerance));
+            if n > 0 {
+                return format!("#{:02X}{:02X}{:02X}", sum[0] / n, sum[1] / n, sum[2] / n);
             }
         }
-        toml.push_str("]\n\n# --- 动作步骤 ---\n");
-        for d in self.drafts.iter() {
-            if let ElementKind::Button { target, post_delay } = &d.kind {
-                toml.push_str("[[scenes.transitions]]\n");
-                toml.push_str(&format!("target = \"{}\"\n", target));
-                toml.push_str(&format!("coords = [{}, {}]\n", d.pos_or_rect.center().x as i32, d.pos_or_rect.center().y as i32));
-                toml.push_str(&format!("post_delay = {}\n\n", post_delay));
+        "#FFFFFF".into()
+    }
+
+    /// 当前正在编辑的场景
+    fn scene(&mut self) -> &mut SceneDraft {
+        &mut self.scenes[self.current_scene]
+    }
+
+    /// 依次输出全部场景；只有一个场景时与旧版单场景输出等价
+    fn build_toml(&mut self) {
+        match scenes_to_toml(&self.toml_root, &self.scenes) {
+            Ok(content) => {
+                self.toml_content = content;
+                self.status_msg = format!("TOML 已生成 ({} 个场景)", self.scenes.len());
             }
+            Err(e) => { self.status_msg = format!("生成失败: {}", e); }
         }
-        self.toml_content = toml;
-        self.status_msg = "TOML 已生成".into();
     }
 
     fn import_toml(&mut self) {
         if self.toml_content.trim().is_empty() { self.status_msg = "导入失败：内容为空".into(); return; }
-        match toml::from_str::<TomlRoot>(&self.toml_content) {
-            Ok(root) => {
-                if let Some(scene) = root.scenes.first() {
-                    self.scene_id = scene.id.clone();
-                    self.scene_name = scene.name.clone();
-                    self.logic = if scene.logic.to_lowercase() == "or" { RecognitionLogic::OR } else { RecognitionLogic::AND };
-                    self.drafts.clear();
-                    if let Some(anchors) = &scene.anchors {
-                        if let Some(texts) = &anchors.text {
-                            for t in texts {
-                                let rect = Rect::from_min_max(Pos2::new(t.rect[0] as f32, t.rect[1] as f32), Pos2::new(t.rect[2] as f32, t.rect[3] as f32));
-                                self.drafts.push(UIElementDraft { pos_or_rect: rect, kind: ElementKind::TextAnchor { text: t.val.clone() } });
-                            }
-                        }
-                        if let Some(colors) = &anchors.color {
-                            for c in colors {
-                                let pos = Pos2::new(c.pos[0] as f32, c.pos[1] as f32);
-                                let rect = Rect::from_min_max(pos, pos + Vec2::splat(1.0));
-                                self.drafts.push(UIElementDraft { pos_or_rect: rect, kind: ElementKind::ColorAnchor { color_hex: c.val.clone(), tolerance: c.tol } });
-                            }
-                        }
-                    }
-                    if let Some(transitions) = &scene.transitions {
-                        for t in transitions {
-                            let rect = Rect::from_center_size(Pos2::new(t.coords[0] as f32, t.coords[1] as f32), Vec2::splat(20.0));
-                            self.drafts.push(UIElementDraft { pos_or_rect: rect, kind: ElementKind::Button { target: t.target.clone(), post_delay: t.post_delay } });
-                        }
-                    }
-                    self.status_msg = format!("成功导入场景：{}", self.scene_id);
-                }
+        match scenes_from_toml(&self.toml_content) {
+            Ok((root, scenes)) => {
+                if scenes.is_empty() { self.status_msg = "导入失败：没有任何场景".into(); return; }
+                self.toml_root = root;
+                self.scenes = scenes;
+                self.current_scene = 0;
+                self.current_rect = None;
+                self.selected = None;
+                self.status_msg = format!("成功导入 {} 个场景", self.scenes.len());
             },
             Err(e) => { self.status_msg = format!("解析失败: {}", e); }
         }
     }
 
+    /// 总是按当前编辑的场景重新生成后再写入，不会写出文本框里过期的内容
+    fn save_toml_file(&mut self) {
+        let content = match scenes_to_toml(&self.toml_root, &self.scenes) {
+            Ok(content) => content,
+            Err(e) => { self.status_msg = format!("生成失败: {}", e); return; }
+        };
+        self.toml_content = content;
+        let Some(path) = rfd::FileDialog::new().add_filter("TOML", &["toml"]).set_file_name("ui_map.toml").save_file() else { return; };
+        match fs::write(&path, &self.toml_content) {
+            Ok(_) => { self.status_msg = format!("已保存到 {}", path.display()); }
+            Err(e) => { self.status_msg = format!("保存失败: {}", e); }
+        }
+    }
+
+    /// 读取文件到文本框并立即导入，失败时保留原有场景
+    fn load_toml_file(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("TOML", &["toml"]).pick_file() else { return; };
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                self.toml_content = content;
+                self.import_toml();
+                self.status_msg = format!("{} ({})", self.status_msg, path.display());
+            }
+            Err(e) => { self.status_msg = format!("读取失败: {}", e); }
+        }
+    }
+
     fn perform_ocr(&mut self, rect: Rect) {
         if self.ocr_engine.is_none() {
             self.ocr_test_result = "OCR 引擎未初始化".into();
@@ -278,8 +531,9 @@ impl eframe::App for MapBuilderTool {
             if start_time.elapsed().as_secs_f32() >= 3.0 {
                 self.capture_immediate(ctx);
                 self.capture_timer = None; 
-                self.drafts.clear(); 
+                self.scene().drafts.clear(); 
                 self.current_rect = None;
+                self.selected = None;
             } else {
                 ctx.request_repaint(); 
             }
@@ -295,38 +549,83 @@ impl eframe::App for MapBuilderTool {
                     let remaining = 3.0 - self.capture_timer.unwrap().elapsed().as_secs_f32();
                     ui.add(egui::ProgressBar::new(remaining / 3.0).text(format!("倒计时：{:.1}s", remaining)));
                 } else {
-                    if ui.button("📸 3秒延时截图").clicked() { self.capture_timer = Some(Instant::now()); }
+                    ui.horizontal(|ui| {
+                        if ui.button("📸 3秒延时截图").clicked() { self.capture_timer = Some(Instant::now()); }
+                        if ui.button("📁 打开图片").clicked() { self.open_image(ctx); }
+                    });
                 }
             });
 
             ui.separator();
-            ui.horizontal(|ui| { ui.label("ID:"); ui.text_edit_singleline(&mut self.scene_id); });
-            ui.horizontal(|ui| { ui.label("名称:"); ui.text_edit_singleline(&mut self.scene_name); });
+            ui.horizontal(|ui| {
+                ui.label(format!("场景 ({}):", self.scenes.len()));
+                if ui.button("➕ 新建").clicked() {
+                    let n = self.scenes.len() + 1;
+                    self.scenes.push(SceneDraft::new(&format!("scene_{:02}", n), "新场景"));
+                    self.current_scene = self.scenes.len() - 1;
+                    self.current_rect = None;
+                    self.selected = None;
+                }
+                if ui.add_enabled(self.scenes.len() > 1, egui::Button::new("🗑 删除当前")).clicked() {
+                    self.scenes.remove(self.current_scene);
+                    self.current_scene = self.current_scene.min(self.scenes.len() - 1);
+                    self.current_rect = None;
+                    self.selected = None;
+                }
+            });
+            egui::ScrollArea::vertical().id_source("scene_scroll").max_height(100.0).show(ui, |ui| {
+                for i in 0..self.scenes.len() {
+                    let label = format!("{} ({})", self.scenes[i].scene_name, self.scenes[i].scene_id);
+                    if ui.selectable_label(i == self.current_scene, label).clicked() && i != self.current_scene {
+                        self.current_scene = i;
+                        self.current_rect = None;
+                        self.selected = None;
+                    }
+                }
+            });
+
+            let scene = &mut self.scenes[self.current_scene];
+            ui.horizontal(|ui| { ui.label("ID:"); ui.text_edit_singleline(&mut scene.scene_id); });
+            ui.horizontal(|ui| { ui.label("名称:"); ui.text_edit_singleline(&mut scene.scene_name); });
             ui.horizontal(|ui| { 
                 ui.label("逻辑:"); 
-                ui.radio_value(&mut self.logic, RecognitionLogic::AND, "AND"); 
-                ui.radio_value(&mut self.logic, RecognitionLogic::OR, "OR"); 
+                ui.radio_value(&mut scene.logic, RecognitionLogic::AND, "AND"); 
+                ui.radio_value(&mut scene.logic, RecognitionLogic::OR, "OR"); 
+                let is_k = matches!(scene.logic, RecognitionLogic::AtLeast(_));
+                if ui.radio(is_k, "至少K个").clicked() && !is_k { scene.logic = RecognitionLogic::AtLeast(2); }
+                if let RecognitionLogic::AtLeast(k) = &mut scene.logic {
+                    ui.add(egui::DragValue::new(k).clamp_range(1..=20).prefix("K:"));
+                }
             });
 
             ui.separator();
-            ui.checkbox(&mut self.is_color_picker_mode, "🧪 吸管取色模式");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.is_color_picker_mode, "🧪 吸管取色模式");
+                ui.add(egui::DragValue::new(&mut self.pick_radius).clamp_range(0..=10).prefix("半径:"));
+            });
+            ui.horizontal(|ui| {
+                ui.label(format!("缩放: {:.1}x", self.zoom));
+                if ui.button("🔍 适应窗口").clicked() { self.zoom = 1.0; self.pan = Vec2::ZERO; }
+                ui.label(RichText::new("滚轮缩放 / 中键拖动").weak());
+            });
 
             if let Some(rect) = self.current_rect {
                 ui.group(|ui| {
                     ui.label(RichText::new("已选中目标：").color(Color32::from_rgb(0, 255, 255)).strong());
                     
                     if self.is_color_picker_mode {
-                        let color = self.pick_color(rect.min);
+                        let color = self.pick_color(rect.min, self.pick_radius);
                         ui.label(format!("HEX: {}", color));
                         if ui.button("📌 添加颜色锚点").clicked() {
-                            self.drafts.push(UIElementDraft { pos_or_rect: rect, kind: ElementKind::ColorAnchor { color_hex: color, tolerance: 15 } });
+                            let draft = UIElementDraft::new(rect, ElementKind::ColorAnchor { color_hex: color, tolerance: 15, radius: self.pick_radius, weight: 1 });
+                            self.scene().drafts.push(draft);
                             self.current_rect = None;
                         }
                     } else {
                         ui.horizontal(|ui| {
                             if ui.button("⚓ 添加 Text 锚点").clicked() {
                                 let val = if self.ocr_test_result.is_empty() || self.ocr_test_result.contains("...") { "Text".to_string() } else { self.ocr_test_result.clone() };
-                                self.drafts.push(UIElementDraft { pos_or_rect: rect, kind: ElementKind::TextAnchor { text: val } });
+                                self.scene().drafts.push(UIElementDraft::new(rect, ElementKind::TextAnchor { text: val, weight: 1 }));
                                 self.current_rect = None;
                             }
                             if ui.button("🔍 区域 OCR 测试").clicked() {
@@ -339,7 +638,7 @@ impl eframe::App for MapBuilderTool {
                         }
 
                         if ui.button("🖱️ 添加 Button 跳转").clicked() {
-                            self.drafts.push(UIElementDraft { pos_or_rect: rect, kind: ElementKind::Button { target: "next".into(), post_delay: 500 } });
+                            self.scene().drafts.push(UIElementDraft::new(rect, ElementKind::Button { target: "next".into(), post_delay: 500, pre_delay: 0, wait_gone: false }));
                             self.current_rect = None;
                         }
                     }
@@ -349,23 +648,31 @@ impl eframe::App for MapBuilderTool {
             ui.separator();
             egui::ScrollArea::vertical().id_source("list_scroll").max_height(200.0).show(ui, |ui| {
                 let mut del = None;
-                for (i, d) in self.drafts.iter_mut().enumerate() {
+                let drafts = &mut self.scenes[self.current_scene].drafts;
+                for (i, d) in drafts.iter_mut().enumerate() {
                     ui.horizontal(|ui| {
                         match &mut d.kind {
-                            ElementKind::TextAnchor { text } => { ui.label("⚓"); ui.text_edit_singleline(text); }
-                            ElementKind::ColorAnchor { color_hex, tolerance } => {
+                            ElementKind::TextAnchor { text, weight } => {
+                                ui.label("⚓"); ui.text_edit_singleline(text);
+                                ui.add(egui::DragValue::new(weight).prefix("W:"));
+                            }
+                            ElementKind::ColorAnchor { color_hex, tolerance, radius, weight } => {
                                 ui.label("🧪"); ui.label(color_hex.as_str());
                                 ui.add(egui::DragValue::new(tolerance).prefix("T:"));
+                                ui.add(egui::DragValue::new(radius).clamp_range(0..=10).prefix("R:"));
+                                ui.add(egui::DragValue::new(weight).prefix("W:"));
                             }
-                            ElementKind::Button { target, post_delay } => {
+                            ElementKind::Button { target, post_delay, pre_delay, wait_gone } => {
                                 ui.label("🖱️"); ui.text_edit_singleline(target);
+                                ui.add(egui::DragValue::new(pre_delay).prefix("pre:"));
                                 ui.add(egui::DragValue::new(post_delay).prefix("ms:"));
+                                ui.checkbox(wait_gone, "等消失").on_hover_text("点击后等当前界面消失，而不是等目标界面出现");
                             }
                         }
                         if ui.button("❌").clicked() { del = Some(i); }
                     });
                 }
-                if let Some(i) = del { self.drafts.remove(i); }
+                if let Some(i) = del { drafts.remove(i); self.selected = None; }
             });
 
             ui.separator();
@@ -373,6 +680,10 @@ impl eframe::App for MapBuilderTool {
                 if ui.button("📤 生成 TOML").clicked() { self.build_toml(); }
                 if ui.button("📥 导入 TOML").clicked() { self.import_toml(); }
             });
+            ui.horizontal(|ui| {
+                if ui.button("💾 保存到文件").clicked() { self.save_toml_file(); }
+                if ui.button("📂 从文件加载").clicked() { self.load_toml_file(); }
+            });
             
             egui::ScrollArea::vertical().id_source("toml_scroll").show(ui, |ui| {
                 ui.add(egui::TextEdit::multiline(&mut self.toml_content).font(egui::TextStyle::Monospace).desired_width(f32::INFINITY));
@@ -383,29 +694,87 @@ impl eframe::App for MapBuilderTool {
             let (resp, painter) = ui.allocate_painter(ui.available_size(), Sense::drag());
             if let Some(tex) = &self.texture {
                 let painter_size = resp.rect.size();
-                let scale = (painter_size.x / self.img_size.x).min(painter_size.y / self.img_size.y);
+                let fit = (painter_size.x / self.img_size.x).min(painter_size.y / self.img_size.y);
+                // 滚轮以光标为中心缩放：保持光标下的图片像素位置不变
+                if let Some(hover) = resp.hover_pos() {
+                    let scroll = ui.input(|i| i.scroll_delta.y);
+                    if scroll != 0.0 {
+                        let old_scale = fit * self.zoom;
+                        self.zoom = (self.zoom * (scroll * 0.002).exp()).clamp(1.0, MAX_ZOOM);
+                        let img_pt = (hover - (resp.rect.min + self.pan)) / old_scale;
+                        self.pan = (hover - img_pt * fit * self.zoom) - resp.rect.min;
+                    }
+                }
+                if resp.dragged_by(egui::PointerButton::Middle) { self.pan += resp.drag_delta(); }
+                if self.zoom <= 1.0 { self.pan = Vec2::ZERO; }
+                let scale = fit * self.zoom;
                 let draw_size = self.img_size * scale;
-                let draw_rect = Rect::from_min_size(resp.rect.min, draw_size);
+                let draw_rect = Rect::from_min_size(resp.rect.min + self.pan, draw_size);
                 painter.image(tex.id(), draw_rect, Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)), Color32::WHITE);
 
                 let to_screen = |p: Pos2| draw_rect.min + (p.to_vec2() * scale);
                 let from_screen = |p: Pos2| { let v = (p - draw_rect.min) / scale; Pos2::new(v.x, v.y) };
 
-                for d in &self.drafts {
+                let drafts = &mut self.scenes[self.current_scene].drafts;
+                let selected = self.selected.filter(|&i| i < drafts.len());
+                let is_color = |d: &UIElementDraft| matches!(d.kind, ElementKind::ColorAnchor{..});
+                for (i, d) in drafts.iter().enumerate() {
                     let color = match d.kind {
                         ElementKind::TextAnchor{..} => Color32::GREEN,
                         ElementKind::ColorAnchor{..} => Color32::from_rgb(255, 165, 0),
                         ElementKind::Button{..} => Color32::BLUE,
                     };
-                    painter.rect_stroke(Rect::from_min_max(to_screen(d.pos_or_rect.min), to_screen(d.pos_or_rect.max)), 2.0, Stroke::new(2.0, color));
+                    let screen_rect = Rect::from_min_max(to_screen(d.pos_or_rect.min), to_screen(d.pos_or_rect.max));
+                    let width = if selected == Some(i) { 3.5 } else { 2.0 };
+                    painter.rect_stroke(screen_rect, 2.0, Stroke::new(width, color));
+                    // 颜色锚点只有一个像素，不提供缩放手柄
+                    if selected == Some(i) && !is_color(d) {
+                        for c in corners(screen_rect) {
+                            painter.rect_filled(Rect::from_center_size(c, Vec2::splat(HANDLE_SIZE)), 0.0, Color32::WHITE);
+                        }
+                    }
                 }
 
-                if resp.drag_started() {
-                    if let Some(p) = resp.interact_pointer_pos() { self.start_pos = Some(from_screen(p)); }
+                if resp.drag_started_by(egui::PointerButton::Primary) {
+                    if let Some(p) = resp.interact_pointer_pos() {
+                        // 先测选中元素的角点，再从上层往下测元素主体，都没命中才画新框
+                        // 吸管模式下只能拖动颜色锚点，避免在文字框内取色时误拖动文字框
+                        let handle = selected.filter(|&i| !is_color(&drafts[i])).and_then(|i| {
+                            let r = drafts[i].pos_or_rect;
+                            let screen_corners = corners(Rect::from_min_max(to_screen(r.min), to_screen(r.max)));
+                            screen_corners.iter().position(|c| c.distance(p) <= HANDLE_SIZE).map(|k| DragMode::Resize { idx: i, anchor: corners(r)[3 - k] })
+                        });
+                        let picker = self.is_color_picker_mode;
+                        let body = || drafts.iter().enumerate().rev()
+                            .filter(|(_, d)| !picker || is_color(d))
+                            .find(|(_, d)| Rect::from_min_max(to_screen(d.pos_or_rect.min), to_screen(d.pos_or_rect.max)).expand(3.0).contains(p))
+                            .map(|(i, d)| DragMode::Move { idx: i, grab: from_screen(p) - d.pos_or_rect.min });
+                        let mode = handle.or_else(body).unwrap_or(DragMode::NewRect);
+                        match mode {
+                            DragMode::NewRect => { self.selected = None; self.start_pos = Some(from_screen(p)); }
+                            DragMode::Move { idx, .. } | DragMode::Resize { idx, .. } => { self.selected = Some(idx); self.current_rect = None; }
+                        }
+                        self.drag_mode = Some(mode);
+                    }
                 }
+                if resp.dragged_by(egui::PointerButton::Primary) {
+                    if let (Some(mode), Some(p)) = (self.drag_mode, resp.interact_pointer_pos()) {
+                        match mode {
+                            DragMode::Move { idx, grab } => {
+                                let r = &mut drafts[idx].pos_or_rect;
+                                *r = Rect::from_min_size((from_screen(p) - grab).round(), r.size());
+                            }
+                            DragMode::Resize { idx, anchor } => {
+                                drafts[idx].pos_or_rect = Rect::from_two_pos(anchor, from_screen(p).round());
+                            }
+                            DragMode::NewRect => {}
+                        }
+                    }
+                }
+                if resp.drag_released() { self.drag_mode = None; }
                 if let (Some(start), Some(curr_raw)) = (self.start_pos, resp.interact_pointer_pos()) {
                     let curr = from_screen(curr_raw);
-                    let rect = if self.is_color_picker_mode { Rect::from_min_max(curr, curr + Vec2::splat(1.0)) } else { Rect::from_two_pos(start, curr) };
+                    let rect = if self.is_color_picker_mode { Rect::from_min_max(curr.floor(), curr.floor() + Vec2::splat(1.0)) } else { Rect::from_two_pos(start, curr) };
                     painter.rect_stroke(Rect::from_min_max(to_screen(rect.min), to_screen(rect.max)), 0.0, Stroke::new(1.5, Color32::RED));
                     if resp.drag_released() { 
                         self.current_rect = Some(rect); 
@@ -413,8 +782,39 @@ impl eframe::App for MapBuilderTool {
                         self.ocr_test_result.clear(); 
                     }
                 }
+
+                // 吸管模式下在光标旁显示放大镜，直接按原图像素采样，与画布缩放无关
+                if self.is_color_picker_mode {
+                    if let (Some(hover), Some(img)) = (resp.hover_pos(), &self.raw_image) {
+                        let center = from_screen(hover).floor();
+                        let (cx, cy) = (center.x as i64, center.y as i64);
+                        let in_image = |x: i64, y: i64| x >= 0 && y >= 0 && x < img.width() as i64 && y < img.height() as i64;
+                        let box_size = LOUPE_CELL * (2 * LOUPE_RADIUS + 1) as f32;
+                        let mut origin = hover + Vec2::splat(24.0);
+                        if origin.x + box_size > resp.rect.max.x { origin.x = hover.x - 24.0 - box_size; }
+                        if origin.y + box_size + 20.0 > resp.rect.max.y { origin.y = hover.y - 44.0 - box_size; }
+                        let loupe = Rect::from_min_size(origin, Vec2::splat(box_size));
+                        painter.rect_filled(loupe, 0.0, Color32::BLACK);
+                        for dy in -LOUPE_RADIUS..=LOUPE_RADIUS {
+                            for dx in -LOUPE_RADIUS..=LOUPE_RADIUS {
+                                if !in_image(cx + dx, cy + dy) { continue; }
+                                let px = img.get_pixel((cx + dx) as u32, (cy + dy) as u32);
+                                let offset = Vec2::new((dx + LOUPE_RADIUS) as f32, (dy + LOUPE_RADIUS) as f32) * LOUPE_CELL;
+                                painter.rect_filled(Rect::from_min_size(origin + offset, Vec2::splat(LOUPE_CELL)), 0.0, Color32::from_rgb(px[0], px[1], px[2]));
+                            }
+                        }
+                        let mid = Rect::from_min_size(origin + Vec2::splat(LOUPE_RADIUS as f32 * LOUPE_CELL), Vec2::splat(LOUPE_CELL));
+                        painter.rect_stroke(mid, 0.0, Stroke::new(1.5, Color32::RED));
+                        painter.rect_stroke(loupe, 0.0, Stroke::new(1.0, Color32::WHITE));
+                        if in_image(cx, cy) {
+                            let px = img.get_pixel(cx as u32, cy as u32);
+                            let label = format!("({}, {}) #{:02X}{:02X}{:02X}", cx, cy, px[0], px[1], px[2]);
+                            painter.text(loupe.left_bottom() + Vec2::new(0.0, 4.0), egui::Align2::LEFT_TOP, label, egui::FontId::monospace(12.0), Color32::WHITE);
+                        }
+                    }
+                }
             } else {
-                ui.centered_and_justified(|ui| ui.label("点击左侧『3秒延时截图』开始工作"));
+                ui.centered_and_justified(|ui| ui.label("点击左侧『3秒延时截图』或『打开图片』开始工作"));
             }
         });
     }
@@ -423,4 +823,81 @@ impl eframe::App for MapBuilderTool {
 fn main() -> eframe::Result<()> {
     let opts = eframe::NativeOptions { viewport: egui::ViewportBuilder::default().with_inner_size([1400.0, 900.0]), ..Default::default() };
     eframe::run_native("MINKE UI Mapper Pro", opts, Box::new(|cc| Box::new(MapBuilderTool::new(cc))))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 包含工具不编辑的字段：顶层 authored_size、scan_rect、recovery、fuzz、mode/vtol、图片锚点、cost
+    const MAP: &str = r##"
+authored_size = [1920, 1080]
+
+[[scenes]]
+id = "lobby"
+name = "大厅"
+logic = "and"
+min_matches = 2
+scan_rect = [0, 0, 800, 600]
+handler = "daily"
+
+[scenes.anchors]
+[[scenes.anchors.text]]
+rect = [10, 10, 120, 40]
+val = "开始游戏"
+fuzz = 1
+
+[[scenes.anchors.color]]
+pos = [50, 60]
+val = "#FF8800"
+tol = 20
+mode = "hsv"
+vtol = 30
+
+[[scenes.anchors.image]]
+rect = [200, 200, 240, 240]
+path = "icons/gear.png"
+
+[[scenes.transitions]]
+target = "shop"
+coords = [400, 300]
+post_delay = 800
+cost = 5
+wait_mode = "appear"
+
+[[scenes.recovery]]
+key = "esc"
+"##;
+
+    #[test]
+    fn generate_import_generate_is_stable() {
+        let (root, scenes) = scenes_from_toml(MAP).unwrap();
+        let first = scenes_to_toml(&root, &scenes).unwrap();
+        let (root, scenes) = scenes_from_toml(&first).unwrap();
+        assert_eq!(scenes_to_toml(&root, &scenes).unwrap(), first);
+    }
+
+    #[test]
+    fn fields_the_tool_does_not_model_survive_an_edit() {
+        let (root, mut scenes) = scenes_from_toml(MAP).unwrap();
+        scenes[0].scene_name = "主界面".into();
+        if let ElementKind::TextAnchor { text, .. } = &mut scenes[0].drafts[0].kind {
+            *text = "开始".into();
+        }
+        let out: toml::Table = toml::from_str(&scenes_to_toml(&root, &scenes).unwrap()).unwrap();
+        let scene = out["scenes"][0].as_table().unwrap();
+
+        assert_eq!(out["authored_size"], toml::Value::try_from([1920, 1080]).unwrap());
+        assert_eq!(scene["name"].as_str(), Some("主界面"));
+        assert_eq!(scene["min_matches"].as_integer(), Some(2));
+        assert_eq!(scene["logic"].as_str(), Some("and"));
+        assert!(scene.contains_key("scan_rect") && scene.contains_key("recovery"));
+        let anchors = scene["anchors"].as_table().unwrap();
+        assert_eq!(anchors["text"][0]["val"].as_str(), Some("开始"));
+        assert_eq!(anchors["text"][0]["fuzz"].as_integer(), Some(1));
+        assert_eq!(anchors["color"][0]["mode"].as_str(), Some("hsv"));
+        assert_eq!(anchors["color"][0]["vtol"].as_integer(), Some(30));
+        assert_eq!(anchors["image"][0]["path"].as_str(), Some("icons/gear.png"));
+        assert_eq!(scene["transitions"][0]["cost"].as_integer(), Some(5));
+        assert_eq!(scene["transitions"][0]["wait_mode"].as_str(), Some("appear"));
+    }
+}